@@ -0,0 +1,192 @@
+use anyhow::{Context, bail};
+use api::application::manifest_service::Plan;
+use api::domain::entities::{Function, LogEntry, Trigger, TriggerKind};
+use reqwest::multipart::{Form, Part};
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use serde::Deserialize;
+use std::path::Path;
+
+/// Thin wrapper over the Fluor HTTP API.
+pub struct Client {
+    http: reqwest::Client,
+    url: String,
+    token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LoginResponse {
+    token: String,
+}
+
+impl Client {
+    pub fn new(url: &str, token: Option<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            token,
+        }
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self.http.request(method, format!("{}{}", self.url, path));
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    async fn send(request: RequestBuilder) -> anyhow::Result<Response> {
+        let response = request
+            .send()
+            .await
+            .context("Request to Fluor API failed")?;
+        Self::check(response).await
+    }
+
+    /// Turns non-2xx responses into errors carrying the body.
+    async fn check(response: Response) -> anyhow::Result<Response> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let body = response.text().await.unwrap_or_default();
+        if body.is_empty() {
+            bail!("{}", status)
+        }
+        bail!("{}: {}", status, body)
+    }
+
+    pub async fn login(&self, email: &str, password: &str) -> anyhow::Result<String> {
+        let request = self
+            .request(Method::POST, "/login")
+            .json(&serde_json::json!({ "email": email, "password": password }));
+        let response: LoginResponse = Self::send(request).await?.json().await?;
+        Ok(response.token)
+    }
+
+    pub async fn find_function(&self, name: &str) -> anyhow::Result<Option<Function>> {
+        let response = self
+            .request(Method::GET, &format!("/functions/{}", name))
+            .send()
+            .await
+            .context("Request to Fluor API failed")?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(Self::check(response).await?.json().await?))
+    }
+
+    /// Uploads `wasm` with the definition, updating the function rather than
+    /// creating it when `update` is set.
+    pub async fn deploy(
+        &self,
+        function: &Function,
+        wasm: &Path,
+        update: bool,
+    ) -> anyhow::Result<Function> {
+        let bytes =
+            std::fs::read(wasm).with_context(|| format!("Failed to read {}", wasm.display()))?;
+        let form = Form::new()
+            .part(
+                "function",
+                Part::text(serde_json::to_string(function)?).mime_str("application/json")?,
+            )
+            .part(
+                "file",
+                Part::bytes(bytes).file_name(format!("{}.wasm", function.name)),
+            );
+
+        let request = if update {
+            self.request(Method::PUT, &format!("/functions/{}", function.name))
+        } else {
+            self.request(Method::POST, "/functions")
+        };
+        Ok(Self::send(request.multipart(form)).await?.json().await?)
+    }
+
+    /// Calls the function through the gateway, on its first plain HTTP trigger.
+    pub async fn invoke(&self, function: &str, body: String) -> anyhow::Result<String> {
+        let trigger = self
+            .triggers()
+            .await?
+            .into_iter()
+            .find(|t| {
+                t.function_name == function && t.kind == TriggerKind::Http && !t.path.contains('{')
+            })
+            .with_context(|| {
+                format!(
+                    "Function '{}' has no HTTP trigger; add one or use --async",
+                    function
+                )
+            })?;
+        let method = Method::from_bytes(trigger.method.to_uppercase().as_bytes())
+            .with_context(|| format!("Trigger '{}' has an invalid method", trigger.name))?;
+        let request = self
+            .request(method, &format!("/function{}", trigger.path))
+            .body(body);
+        Ok(Self::send(request).await?.text().await?)
+    }
+
+    pub async fn invoke_async(&self, function: &str, body: String) -> anyhow::Result<()> {
+        let request = self
+            .request(
+                Method::POST,
+                &format!("/functions/{}/invocations", function),
+            )
+            .body(body);
+        Self::send(request).await?;
+        Ok(())
+    }
+
+    /// Most recent log lines of the function, newest first.
+    pub async fn logs(&self, function: &str) -> anyhow::Result<Vec<LogEntry>> {
+        let request = self.request(
+            Method::GET,
+            &format!("/telemetry/functions/{}/logs", function),
+        );
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    pub async fn triggers(&self) -> anyhow::Result<Vec<Trigger>> {
+        Ok(Self::send(self.request(Method::GET, "/triggers"))
+            .await?
+            .json()
+            .await?)
+    }
+
+    pub async fn create_trigger(&self, trigger: &Trigger) -> anyhow::Result<Trigger> {
+        let request = self.request(Method::POST, "/triggers").json(trigger);
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    pub async fn delete_trigger(&self, name: &str) -> anyhow::Result<()> {
+        Self::send(self.request(Method::DELETE, &format!("/triggers/{}", name))).await?;
+        Ok(())
+    }
+
+    pub async fn rollback(&self, function: &str) -> anyhow::Result<Function> {
+        let request = self.request(Method::POST, &format!("/functions/{}/rollback", function));
+        Ok(Self::send(request).await?.json().await?)
+    }
+
+    /// Sends a manifest to `/apply`, or to `/plan` when `dry_run` is set, along
+    /// with the binaries it names in `artifacts`.
+    pub async fn apply(
+        &self,
+        manifest: String,
+        content_type: &str,
+        artifacts: Vec<(String, Vec<u8>)>,
+        dry_run: bool,
+    ) -> anyhow::Result<Plan> {
+        let path = if dry_run { "/plan" } else { "/apply" };
+        // Artifact names are matched verbatim, so they must not be escaped
+        let mut form = Form::new()
+            .percent_encode_noop()
+            .part("manifest", Part::text(manifest).mime_str(content_type)?);
+        for (name, bytes) in artifacts {
+            form = form.part("artifact", Part::bytes(bytes).file_name(name));
+        }
+        let request = self.request(Method::POST, path).multipart(form);
+        Ok(Self::send(request).await?.json().await?)
+    }
+}
@@ -0,0 +1,75 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_URL: &str = "http://localhost:8080";
+
+/// What `fluor login` remembers between invocations.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct Config {
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/fluor/config.toml`, falling back to `~/.config`.
+    pub fn default_path() -> PathBuf {
+        let base = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .unwrap_or_else(|| PathBuf::from("."));
+        base.join("fluor").join("config.toml")
+    }
+
+    /// Reads the config at `path`; a missing file is an empty config.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(content) => toml::from_str(&content)
+                .with_context(|| format!("Invalid config file {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    /// Writes the config, readable by its owner only since it holds the token.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        fs::write(path, toml::to_string(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_config_round_trip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("fluor").join("config.toml");
+        assert_eq!(Config::load(&path).unwrap(), Config::default());
+
+        let config = Config {
+            url: Some("http://fluor.test".to_string()),
+            token: Some("secret".to_string()),
+        };
+        config.save(&path).unwrap();
+        assert_eq!(Config::load(&path).unwrap(), config);
+
+        fs::write(&path, "url = 3").unwrap();
+        assert!(Config::load(&path).is_err());
+    }
+}
@@ -0,0 +1,498 @@
+//! `fluor`, the command-line client of the API.
+
+pub mod client;
+pub mod config;
+
+use anyhow::Context;
+use api::application::manifest_service::{
+    ChangeAction, Manifest, ManifestFormat, Plan, ResourceKind,
+};
+use api::domain::entities::{Function, Language, LogEntry, Trigger, TriggerKind};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use client::Client;
+use config::Config;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::collections::{BTreeSet, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Parser, Debug)]
+#[command(name = "fluor", version, about = "Deploy and manage Fluor functions")]
+pub struct Cli {
+    /// API address; defaults to the one saved by `login`.
+    #[arg(long, global = true, env = "FLUOR_URL")]
+    pub url: Option<String>,
+    /// Config file holding the API address and token.
+    #[arg(long, global = true, env = "FLUOR_CONFIG")]
+    pub config: Option<PathBuf>,
+    #[arg(long, short, global = true, value_enum, default_value_t = Output::Human)]
+    pub output: Output,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    Human,
+    Json,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Signs in and saves the token for later commands.
+    Login {
+        #[arg(long, env = "FLUOR_EMAIL")]
+        email: String,
+        #[arg(long, env = "FLUOR_PASSWORD", hide_env_values = true)]
+        password: String,
+    },
+    /// Uploads a Wasm binary, creating the function or updating it.
+    Deploy(DeployArgs),
+    /// Calls a function through its HTTP trigger.
+    Invoke {
+        function: String,
+        /// Request body.
+        #[arg(long, short, default_value = "")]
+        data: String,
+        /// Queue the invocation instead of waiting for its result.
+        #[arg(long = "async")]
+        background: bool,
+    },
+    /// Prints a function's recent logs.
+    Logs {
+        function: String,
+        /// Keep polling for new lines.
+        #[arg(long, short)]
+        follow: bool,
+        /// Seconds between polls with --follow.
+        #[arg(long, default_value_t = 2)]
+        interval: u64,
+    },
+    #[command(subcommand)]
+    Triggers(TriggerCommand),
+    /// Restores the function as it was before its last update.
+    Rollback { function: String },
+    /// Makes the deployment match a manifest file (YAML, TOML or JSON).
+    Apply {
+        manifest: PathBuf,
+        /// Only print the changes that would be made.
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct DeployArgs {
+    /// Wasm binary to upload.
+    pub wasm: PathBuf,
+    /// Function name; defaults to the definition's, then to the binary's file name.
+    #[arg(long)]
+    pub name: Option<String>,
+    /// JSON function definition; the flags below override its fields.
+    #[arg(long, short = 'f')]
+    pub definition: Option<PathBuf>,
+    #[arg(long, value_parser = serde_value::<Language>)]
+    pub language: Option<Language>,
+    #[arg(long)]
+    pub cpu: Option<String>,
+    #[arg(long)]
+    pub memory: Option<String>,
+    /// Environment variable as KEY=VALUE; repeatable.
+    #[arg(long = "env", short, value_parser = parse_env)]
+    pub env: Vec<(String, String)>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TriggerCommand {
+    /// Routes requests on a path to a function.
+    Add {
+        name: String,
+        #[arg(long)]
+        function: String,
+        #[arg(long)]
+        path: String,
+        #[arg(long, default_value = "GET")]
+        method: String,
+        #[arg(long, value_parser = serde_value::<TriggerKind>, default_value = "http")]
+        kind: TriggerKind,
+    },
+    /// Removes a trigger.
+    Rm { name: String },
+}
+
+/// Parses a value the way the API's JSON would spell it.
+fn serde_value<T: DeserializeOwned>(s: &str) -> Result<T, String> {
+    serde_json::from_value(serde_json::Value::String(s.to_string())).map_err(|e| e.to_string())
+}
+
+fn parse_env(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .ok_or_else(|| format!("expected KEY=VALUE, got '{}'", s))
+}
+
+fn print_json(out: &mut dyn Write, value: &impl Serialize) -> anyhow::Result<()> {
+    writeln!(out, "{}", serde_json::to_string(value)?)?;
+    Ok(())
+}
+
+pub async fn run(cli: Cli, out: &mut dyn Write) -> anyhow::Result<()> {
+    let config_path = cli.config.clone().unwrap_or_else(Config::default_path);
+    let config = Config::load(&config_path)?;
+    let url = cli
+        .url
+        .clone()
+        .or_else(|| config.url.clone())
+        .unwrap_or_else(|| config::DEFAULT_URL.to_string());
+    // Never send the saved token to another server than the one it came from
+    let token = match &config.url {
+        Some(saved) if *saved != url => None,
+        _ => config.token.clone(),
+    };
+    let client = Client::new(&url, token);
+    let json = cli.output == Output::Json;
+
+    match cli.command {
+        Command::Login { email, password } => {
+            let token = client.login(&email, &password).await?;
+            Config {
+                url: Some(url.clone()),
+                token: Some(token),
+            }
+            .save(&config_path)?;
+            if json {
+                print_json(out, &serde_json::json!({ "url": url }))?;
+            } else {
+                writeln!(out, "Logged in to {}", url)?;
+            }
+        }
+        Command::Deploy(args) => {
+            let mut function = deploy_definition(&args)?;
+            let existing = client.find_function(&function.name).await?;
+            if args.definition.is_none()
+                && let Some(existing) = &existing
+            {
+                // Redeploying a binary keeps the settings it was deployed with
+                function = Function {
+                    executable: String::new(),
+                    ..existing.clone()
+                };
+                apply_deploy_flags(&mut function, &args);
+            }
+            let deployed = client
+                .deploy(&function, &args.wasm, existing.is_some())
+                .await?;
+            if json {
+                print_json(out, &deployed)?;
+            } else {
+                let verb = if existing.is_some() {
+                    "Updated"
+                } else {
+                    "Created"
+                };
+                writeln!(out, "{} function {}", verb, deployed.name)?;
+            }
+        }
+        Command::Invoke {
+            function,
+            data,
+            background,
+        } => {
+            if background {
+                client.invoke_async(&function, data).await?;
+                if json {
+                    print_json(
+                        out,
+                        &serde_json::json!({ "function": function, "queued": true }),
+                    )?;
+                } else {
+                    writeln!(out, "Queued invocation of {}", function)?;
+                }
+            } else {
+                let response = client.invoke(&function, data).await?;
+                writeln!(out, "{}", response)?;
+            }
+        }
+        Command::Logs {
+            function,
+            follow,
+            interval,
+        } => {
+            let mut cursor = LogCursor::default();
+            loop {
+                for entry in cursor.advance(client.logs(&function).await?) {
+                    if json {
+                        print_json(out, &entry)?;
+                    } else {
+                        writeln!(out, "{} {:<5} {}", entry.timestamp, entry.level, entry.body)?;
+                    }
+                }
+                out.flush()?;
+                if !follow {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_secs(interval.max(1))).await;
+            }
+        }
+        Command::Triggers(TriggerCommand::Add {
+            name,
+            function,
+            path,
+            method,
+            kind,
+        }) => {
+            let trigger = Trigger {
+                name,
+                method: method.to_uppercase(),
+                path,
+                function_name: function,
+                kind,
+                rate_limit: None,
+                cache: None,
+                readonly: false,
+            };
+            let created = client.create_trigger(&trigger).await?;
+            if json {
+                print_json(out, &created)?;
+            } else {
+                writeln!(
+                    out,
+                    "Added trigger {}: {} {} -> {}",
+                    created.name, created.method, created.path, created.function_name
+                )?;
+            }
+        }
+        Command::Triggers(TriggerCommand::Rm { name }) => {
+            client.delete_trigger(&name).await?;
+            if json {
+                print_json(out, &serde_json::json!({ "deleted": name }))?;
+            } else {
+                writeln!(out, "Removed trigger {}", name)?;
+            }
+        }
+        Command::Rollback { function } => {
+            let restored = client.rollback(&function).await?;
+            if json {
+                print_json(out, &restored)?;
+            } else {
+                writeln!(out, "Rolled back function {}", restored.name)?;
+            }
+        }
+        Command::Apply { manifest, dry_run } => {
+            let content = std::fs::read_to_string(&manifest)
+                .with_context(|| format!("Failed to read {}", manifest.display()))?;
+            let content_type = manifest_content_type(&manifest);
+            let artifacts = manifest_artifacts(&manifest, &content, content_type)?;
+            let plan = client
+                .apply(content, content_type, artifacts, dry_run)
+                .await?;
+            if json {
+                print_json(out, &plan)?;
+            } else {
+                print_plan(out, &plan)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The function to deploy, from `--definition` and the flags.
+fn deploy_definition(args: &DeployArgs) -> anyhow::Result<Function> {
+    let mut function = match &args.definition {
+        Some(path) => {
+            let content = std::fs::read(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            serde_json::from_slice(&content)
+                .with_context(|| format!("Invalid function definition {}", path.display()))?
+        }
+        None => Function {
+            cpu: "1".to_string(),
+            memory: "128".to_string(),
+            ..Default::default()
+        },
+    };
+    if function.name.is_empty() {
+        function.name = args
+            .wasm
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .context("Cannot derive a function name from the binary; pass --name")?
+            .to_string();
+    }
+    apply_deploy_flags(&mut function, args);
+    Ok(function)
+}
+
+fn apply_deploy_flags(function: &mut Function, args: &DeployArgs) {
+    if let Some(name) = &args.name {
+        function.name = name.clone();
+    }
+    if let Some(language) = &args.language {
+        function.language = language.clone();
+    }
+    if let Some(cpu) = &args.cpu {
+        function.cpu = cpu.clone();
+    }
+    if let Some(memory) = &args.memory {
+        function.memory = memory.clone();
+    }
+    function.env.extend(args.env.iter().cloned());
+}
+
+fn manifest_content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("yaml" | "yml") => "application/yaml",
+        Some("toml") => "application/toml",
+        _ => "application/json",
+    }
+}
+
+/// Reads the binaries the manifest's functions name, relative to the manifest,
+/// to upload with it.
+fn manifest_artifacts(
+    path: &Path,
+    content: &str,
+    content_type: &str,
+) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+    let manifest = Manifest::parse(content, ManifestFormat::from_content_type(content_type))?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let names: BTreeSet<String> = manifest
+        .functions
+        .into_iter()
+        .filter_map(|f| f.artifact)
+        .collect();
+    names
+        .into_iter()
+        .map(|name| {
+            let file = dir.join(&name);
+            let bytes = std::fs::read(&file)
+                .with_context(|| format!("Failed to read artifact {}", file.display()))?;
+            Ok((name, bytes))
+        })
+        .collect()
+}
+
+fn print_plan(out: &mut dyn Write, plan: &Plan) -> anyhow::Result<()> {
+    for change in &plan.changes {
+        let sign = match change.action {
+            ChangeAction::Create => '+',
+            ChangeAction::Update => '~',
+            ChangeAction::Delete => '-',
+        };
+        let resource = match change.resource {
+            ResourceKind::Function => "function",
+            ResourceKind::Trigger => "trigger",
+        };
+        write!(out, "{} {} {}", sign, resource, change.name)?;
+        if !change.fields.is_empty() {
+            write!(out, " ({})", change.fields.join(", "))?;
+        }
+        writeln!(out)?;
+    }
+    match (plan.changes.len(), plan.applied) {
+        (0, _) => writeln!(out, "No changes")?,
+        (n, true) => writeln!(out, "Applied {} changes", n)?,
+        (n, false) => writeln!(out, "{} changes to apply", n)?,
+    }
+    Ok(())
+}
+
+/// Remembers which log lines were printed, so polling only prints new ones.
+#[derive(Default)]
+struct LogCursor {
+    latest: Option<String>,
+    /// Lines at `latest`: later polls may return more with the same timestamp.
+    seen_at_latest: HashSet<(String, String)>,
+}
+
+impl LogCursor {
+    /// Takes a poll's lines, newest first, and returns the unseen ones oldest first.
+    fn advance(&mut self, mut entries: Vec<LogEntry>) -> Vec<LogEntry> {
+        entries.reverse();
+        entries.retain(|e| match &self.latest {
+            Some(latest) => {
+                e.timestamp > *latest
+                    || (e.timestamp == *latest
+                        && !self
+                            .seen_at_latest
+                            .contains(&(e.timestamp.clone(), e.body.clone())))
+            }
+            None => true,
+        });
+        for entry in &entries {
+            if self.latest.as_ref() != Some(&entry.timestamp) {
+                self.latest = Some(entry.timestamp.clone());
+                self.seen_at_latest.clear();
+            }
+            self.seen_at_latest
+                .insert((entry.timestamp.clone(), entry.body.clone()));
+        }
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(timestamp: &str, body: &str) -> LogEntry {
+        LogEntry {
+            timestamp: timestamp.to_string(),
+            level: "INFO".to_string(),
+            body: body.to_string(),
+            trace_id: String::new(),
+            function_name: "hello".to_string(),
+            key: 0,
+        }
+    }
+
+    fn bodies(entries: Vec<LogEntry>) -> Vec<String> {
+        entries.into_iter().map(|e| e.body).collect()
+    }
+
+    #[test]
+    fn test_log_cursor_prints_new_lines_once() {
+        let mut cursor = LogCursor::default();
+        let first = cursor.advance(vec![entry("2", "b"), entry("1", "a")]);
+        assert_eq!(bodies(first), ["a", "b"]);
+
+        let second = cursor.advance(vec![
+            entry("3", "d"),
+            entry("2", "c"),
+            entry("2", "b"),
+            entry("1", "a"),
+        ]);
+        assert_eq!(bodies(second), ["c", "d"]);
+
+        assert!(cursor.advance(vec![entry("3", "d")]).is_empty());
+    }
+
+    #[test]
+    fn test_deploy_definition() {
+        let cli = Cli::try_parse_from([
+            "fluor",
+            "deploy",
+            "build/hello.wasm",
+            "--memory",
+            "256",
+            "--language",
+            "rust",
+            "-e",
+            "GREETING=hi",
+        ])
+        .unwrap();
+        let Command::Deploy(args) = cli.command else {
+            panic!("expected deploy");
+        };
+        let function = deploy_definition(&args).unwrap();
+        assert_eq!(function.name, "hello");
+        assert_eq!(function.language, Language::Rust);
+        assert_eq!(function.memory, "256");
+        assert_eq!(function.cpu, "1");
+        assert_eq!(function.env["GREETING"], "hi");
+
+        assert!(Cli::try_parse_from(["fluor", "deploy", "x.wasm", "-e", "NOEQUALS"]).is_err());
+    }
+}
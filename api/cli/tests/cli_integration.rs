@@ -0,0 +1,256 @@
+use actix_web::{App, HttpServer, web};
+use api::application::auth_service::AuthService;
+use api::application::background_service::BackgroundService;
+use api::application::function_service::FunctionService;
+use api::application::invocation_service::InvocationService;
+use api::application::manifest_service::ManifestService;
+use api::application::rate_limit_service::RateLimitService;
+use api::application::response_cache::ResponseCache;
+use api::application::trigger_service::TriggerService;
+use api::application::workflow_service::WorkflowService;
+use api::domain::wasm_runtime::{WasmRuntime, WasmSession};
+use api::infrastructure::db::sqlite::{SqliteRepository, create_pool};
+use api::infrastructure::http::handlers;
+use api::infrastructure::rate_limit::InMemoryRateLimitStore;
+use async_trait::async_trait;
+use clap::Parser;
+use fluor_cli::{self as cli, Cli};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tempfile::{TempDir, tempdir};
+
+/// Answers every invocation with the binary it was loaded from, so tests can
+/// tell which version is deployed.
+#[derive(Debug, Default)]
+struct TestRuntime {
+    functions: Mutex<HashMap<String, String>>,
+}
+
+#[async_trait]
+impl WasmRuntime for TestRuntime {
+    fn validate(&self, _bytes: &[u8]) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn set_env(&self, _function_name: &str, _env: &BTreeMap<String, String>) {}
+
+    async fn load_function(&self, name: &str, wasm_path: &str) -> anyhow::Result<()> {
+        let content = std::fs::read_to_string(wasm_path)?;
+        self.functions
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), content);
+        Ok(())
+    }
+
+    fn unload_function(&self, name: &str, _paths: &[String]) {
+        self.functions.lock().unwrap().remove(name);
+    }
+
+    fn rename_function(&self, from: &str, to: &str) {
+        let mut functions = self.functions.lock().unwrap();
+        if let Some(loaded) = functions.remove(from) {
+            functions.insert(to.to_string(), loaded);
+        }
+    }
+
+    fn move_compiled(&self, _from: &str, _to: &str) {}
+
+    fn remove_compiled(&self, _paths: &[String]) {}
+
+    async fn invoke(&self, name: &str, _params: &str) -> anyhow::Result<String> {
+        self.functions
+            .lock()
+            .unwrap()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Function {} not loaded", name))
+    }
+
+    async fn open_session(&self, name: &str) -> anyhow::Result<Box<dyn WasmSession>> {
+        Err(anyhow::anyhow!("Function {} has no sessions", name))
+    }
+}
+
+/// Serves the API on a local port and returns its address.
+async fn spawn_server(temp_dir: &Path) -> String {
+    let db_url = format!("sqlite:{}?mode=rwc", temp_dir.join("test.db").display());
+    let pool = create_pool(db_url).await;
+    api::infrastructure::db::sqlite::seed_admin(&pool, "admin@fluor.com", "admin", "secret").await;
+    let repo = Arc::new(SqliteRepository::new(pool));
+    let runtime = Arc::new(TestRuntime::default());
+
+    let auth_service = Arc::new(AuthService::new(
+        repo.clone(),
+        "secret".to_string(),
+        "jwt_secret".to_string(),
+    ));
+    let response_cache = Arc::new(ResponseCache::new(1024 * 1024));
+    let function_service = Arc::new(FunctionService::new(
+        repo.clone(),
+        runtime.clone(),
+        temp_dir.join("wasm").to_string_lossy().into_owned(),
+    ));
+    let invocation_service = Arc::new(InvocationService::new(
+        repo.clone(),
+        repo.clone(),
+        runtime.clone(),
+    ));
+    let trigger_service = Arc::new(TriggerService::new(
+        repo.clone(),
+        invocation_service.clone(),
+    ));
+    let manifest_service = Arc::new(ManifestService::new(
+        repo.clone(),
+        function_service.clone(),
+        trigger_service.clone(),
+    ));
+    let rate_limit_service = Arc::new(RateLimitService::new(
+        Arc::new(InMemoryRateLimitStore::new()),
+        auth_service.clone(),
+    ));
+    let background_service = Arc::new(BackgroundService::new(
+        invocation_service.clone(),
+        repo.clone(),
+        repo.clone(),
+        repo.clone(),
+    ));
+    let workflow_service = Arc::new(WorkflowService::new(
+        repo.clone(),
+        repo.clone(),
+        invocation_service.clone(),
+    ));
+
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(web::Data::new(auth_service.clone()))
+            .app_data(web::Data::new(function_service.clone()))
+            .app_data(web::Data::new(trigger_service.clone()))
+            .app_data(web::Data::new(invocation_service.clone()))
+            .app_data(web::Data::new(manifest_service.clone()))
+            .app_data(web::Data::new(rate_limit_service.clone()))
+            .app_data(web::Data::new(response_cache.clone()))
+            .app_data(web::Data::new(background_service.clone()))
+            .app_data(web::Data::new(workflow_service.clone()))
+            .configure(handlers::auth::config)
+            .configure(handlers::dead_letters::config)
+            .configure(handlers::functions::config)
+            .configure(handlers::manifest::config)
+            .configure(handlers::triggers::config)
+            .service(web::scope("/function").default_service(web::to(handlers::gateway::gateway)))
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+    let addr = server.addrs()[0];
+    actix_rt::spawn(server.run());
+    format!("http://{}", addr)
+}
+
+/// Runs `fluor` with the whitespace-separated `args` against the config in
+/// `home`, returning its output.
+async fn fluor(home: &TempDir, args: &str) -> anyhow::Result<String> {
+    let config = home.path().join("config.toml");
+    let cli = Cli::try_parse_from(
+        ["fluor", "--config", config.to_str().unwrap()]
+            .into_iter()
+            .chain(args.split_whitespace()),
+    )?;
+    let mut out = Vec::new();
+    cli::run(cli, &mut out).await?;
+    Ok(String::from_utf8(out)?)
+}
+
+#[actix_rt::test]
+async fn test_cli_deploy_invoke_rollback() {
+    let temp_dir = tempdir().unwrap();
+    let url = spawn_server(temp_dir.path()).await;
+    let home = tempdir().unwrap();
+
+    let login = format!("--url {} login --email admin@fluor.com --password", url);
+    assert!(fluor(&home, &format!("{} wrong", login)).await.is_err());
+    let out = fluor(&home, &format!("{} admin", login)).await.unwrap();
+    assert_eq!(out.trim(), format!("Logged in to {}", url));
+
+    // Later commands find the server in the saved config
+    let wasm = home.path().join("hello.wasm");
+    std::fs::write(&wasm, "v1").unwrap();
+    let deploy = format!("deploy {}", wasm.display());
+    let out = fluor(&home, &format!("{} --memory 256", deploy))
+        .await
+        .unwrap();
+    assert_eq!(out.trim(), "Created function hello");
+
+    let out = fluor(&home, "triggers add hello --function hello --path /hello")
+        .await
+        .unwrap();
+    assert_eq!(out.trim(), "Added trigger hello: GET /hello -> hello");
+    assert_eq!(fluor(&home, "invoke hello").await.unwrap().trim(), "v1");
+
+    std::fs::write(&wasm, "v2").unwrap();
+    let out = fluor(&home, &format!("-o json {}", deploy)).await.unwrap();
+    let deployed: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(deployed["memory"], "256", "redeploys keep earlier settings");
+    assert_eq!(fluor(&home, "invoke hello").await.unwrap().trim(), "v2");
+
+    let out = fluor(&home, "rollback hello").await.unwrap();
+    assert_eq!(out.trim(), "Rolled back function hello");
+    assert_eq!(fluor(&home, "invoke hello").await.unwrap().trim(), "v1");
+
+    let out = fluor(&home, "invoke hello --async").await.unwrap();
+    assert_eq!(out.trim(), "Queued invocation of hello");
+
+    let out = fluor(&home, "triggers rm hello").await.unwrap();
+    assert_eq!(out.trim(), "Removed trigger hello");
+    let err = fluor(&home, "invoke hello").await.unwrap_err();
+    assert!(err.to_string().contains("no HTTP trigger"));
+}
+
+#[actix_rt::test]
+async fn test_cli_apply() {
+    let temp_dir = tempdir().unwrap();
+    let url = spawn_server(temp_dir.path()).await;
+    let home = tempdir().unwrap();
+
+    let wasm = home.path().join("hello.wasm");
+    std::fs::write(&wasm, "v1").unwrap();
+    let manifest = home.path().join("fluor.yaml");
+    // Artifacts are read relative to the manifest and uploaded with it
+    std::fs::write(
+        &manifest,
+        "
+functions:
+  - name: m-hello
+    language: rust
+    artifact: hello.wasm
+    cpu: '1'
+    memory: '128'
+triggers:
+  - { name: m-hello, method: GET, path: /m-hello, function: m-hello }
+",
+    )
+    .unwrap();
+    let apply = format!("--url {} apply {}", url, manifest.display());
+
+    let out = fluor(&home, &format!("{} --dry-run", apply)).await.unwrap();
+    assert_eq!(
+        out.lines().collect::<Vec<_>>(),
+        [
+            "+ function m-hello",
+            "+ trigger m-hello",
+            "2 changes to apply"
+        ]
+    );
+
+    let out = fluor(&home, &format!("-o json {}", apply)).await.unwrap();
+    let plan: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(plan["applied"], true);
+    assert_eq!(plan["changes"].as_array().unwrap().len(), 2);
+
+    assert_eq!(fluor(&home, &apply).await.unwrap().trim(), "No changes");
+    let out = fluor(&home, &format!("--url {} invoke m-hello", url))
+        .await
+        .unwrap();
+    assert_eq!(out.trim(), "v1");
+}
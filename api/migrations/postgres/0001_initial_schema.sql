@@ -0,0 +1,82 @@
+-- Same tables as the SQLite schema at version 2. Timestamps stay RFC 3339 text
+-- and integers are BIGINT so both adapters share their row types.
+CREATE TABLE users (
+    id BIGSERIAL PRIMARY KEY,
+    name TEXT NOT NULL DEFAULT '',
+    email TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL,
+    role TEXT NOT NULL DEFAULT 'user'
+);
+
+CREATE TABLE functions (
+    name TEXT PRIMARY KEY,
+    language TEXT NOT NULL,
+    executable TEXT NOT NULL,
+    cpu TEXT NOT NULL,
+    memory TEXT NOT NULL,
+    readonly BOOLEAN NOT NULL DEFAULT FALSE,
+    snapshot BOOLEAN NOT NULL DEFAULT FALSE,
+    max_concurrency BIGINT,
+    max_queue BIGINT,
+    allowed_calls TEXT NOT NULL DEFAULT '[]',
+    retry TEXT,
+    env TEXT NOT NULL DEFAULT '{}'
+);
+
+CREATE TABLE triggers (
+    name TEXT PRIMARY KEY,
+    method TEXT NOT NULL,
+    path TEXT NOT NULL,
+    function TEXT NOT NULL,
+    kind TEXT NOT NULL DEFAULT 'http',
+    rate_limit TEXT,
+    cache TEXT,
+    readonly BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE TABLE rate_limit_buckets (
+    key TEXT PRIMARY KEY,
+    tokens DOUBLE PRECISION NOT NULL,
+    updated_at DOUBLE PRECISION NOT NULL
+);
+
+CREATE TABLE workflows (
+    name TEXT PRIMARY KEY,
+    steps TEXT NOT NULL
+);
+
+CREATE TABLE workflow_runs (
+    id TEXT PRIMARY KEY,
+    workflow TEXT NOT NULL,
+    definition TEXT NOT NULL,
+    status TEXT NOT NULL,
+    input TEXT NOT NULL,
+    output TEXT,
+    error TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+CREATE TABLE workflow_steps (
+    run_id TEXT NOT NULL REFERENCES workflow_runs(id) ON DELETE CASCADE,
+    step TEXT NOT NULL,
+    position BIGINT NOT NULL,
+    status TEXT NOT NULL,
+    input TEXT,
+    output TEXT,
+    error TEXT,
+    attempts BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (run_id, step)
+);
+
+CREATE INDEX idx_workflow_runs_status ON workflow_runs(status);
+
+CREATE TABLE dead_letters (
+    id TEXT PRIMARY KEY,
+    function TEXT NOT NULL,
+    source TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    error TEXT NOT NULL,
+    attempts BIGINT NOT NULL,
+    created_at TEXT NOT NULL
+);
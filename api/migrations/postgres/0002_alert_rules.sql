@@ -0,0 +1,11 @@
+CREATE TABLE alert_rules (
+    name TEXT PRIMARY KEY,
+    function TEXT NOT NULL,
+    condition TEXT NOT NULL,
+    window_secs BIGINT NOT NULL,
+    webhooks TEXT NOT NULL DEFAULT '[]',
+    state TEXT NOT NULL DEFAULT 'ok',
+    value DOUBLE PRECISION,
+    since TEXT,
+    evaluated_at TEXT
+);
@@ -0,0 +1,20 @@
+CREATE TABLE audit_log (
+    id BIGSERIAL PRIMARY KEY,
+    at TEXT NOT NULL,
+    actor TEXT NOT NULL,
+    source_ip TEXT,
+    action TEXT NOT NULL,
+    target TEXT NOT NULL,
+    changes TEXT NOT NULL DEFAULT '{}'
+);
+
+CREATE INDEX idx_audit_log_target ON audit_log(target);
+
+CREATE FUNCTION audit_log_append_only() RETURNS trigger LANGUAGE plpgsql AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_log is append-only';
+END;
+$$;
+
+CREATE TRIGGER audit_log_append_only BEFORE UPDATE OR DELETE ON audit_log
+FOR EACH ROW EXECUTE FUNCTION audit_log_append_only();
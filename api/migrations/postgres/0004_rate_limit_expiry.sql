@@ -0,0 +1,6 @@
+-- When each bucket is back to full capacity, after which the row can be dropped.
+ALTER TABLE rate_limit_buckets ADD COLUMN full_at DOUBLE PRECISION NOT NULL DEFAULT 0;
+
+UPDATE rate_limit_buckets SET full_at = updated_at + 86400;
+
+CREATE INDEX idx_rate_limit_buckets_full_at ON rate_limit_buckets(full_at);
@@ -0,0 +1,8 @@
+CREATE TABLE background_jobs (
+    id TEXT PRIMARY KEY,
+    function TEXT NOT NULL,
+    source TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    attempts BIGINT NOT NULL,
+    created_at TEXT NOT NULL
+);
@@ -0,0 +1,21 @@
+-- Workflow triggers move their target into `workflow`, so `function` can
+-- reference `functions` and each target kind has its own foreign key.
+ALTER TABLE triggers ALTER COLUMN function DROP NOT NULL;
+ALTER TABLE triggers ADD COLUMN workflow TEXT;
+
+UPDATE triggers SET workflow = function, function = NULL WHERE kind = 'workflow';
+
+-- Triggers whose target is gone never routed anywhere and would fail the
+-- foreign keys below.
+DELETE FROM triggers
+    WHERE (function IS NOT NULL AND function NOT IN (SELECT name FROM functions))
+       OR (workflow IS NOT NULL AND workflow NOT IN (SELECT name FROM workflows));
+
+ALTER TABLE triggers
+    ADD FOREIGN KEY (function) REFERENCES functions(name),
+    ADD FOREIGN KEY (workflow) REFERENCES workflows(name),
+    ADD CHECK ((kind = 'workflow') = (workflow IS NOT NULL AND function IS NULL)),
+    ADD CHECK (function IS NOT NULL OR workflow IS NOT NULL);
+
+CREATE INDEX triggers_function ON triggers (function);
+CREATE INDEX triggers_workflow ON triggers (workflow);
@@ -0,0 +1,7 @@
+-- The single row names the node serving from this database and until when
+-- (milliseconds since the epoch) it holds the lease unless it renews it.
+CREATE TABLE node_lease (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    node TEXT NOT NULL,
+    expires_at BIGINT NOT NULL
+);
@@ -0,0 +1,24 @@
+-- `function` holds a trigger's target; `function_name` was never written.
+-- Rebuilding the table also drops the `triggers -> functions` foreign key that
+-- databases created before workflow triggers still carry.
+CREATE TABLE triggers_new (
+    name TEXT PRIMARY KEY,
+    method TEXT NOT NULL,
+    path TEXT NOT NULL,
+    function TEXT NOT NULL,
+    kind TEXT NOT NULL DEFAULT 'http',
+    rate_limit TEXT,
+    cache TEXT,
+    readonly BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+INSERT INTO triggers_new (name, method, path, function, kind, rate_limit, cache, readonly)
+    SELECT name, method, path, function, kind, rate_limit, cache, readonly
+    FROM triggers;
+
+DROP TABLE triggers;
+
+ALTER TABLE triggers_new RENAME TO triggers;
+
+-- Steps whose run is gone would fail the foreign key check once enforced.
+DELETE FROM workflow_steps WHERE run_id NOT IN (SELECT id FROM workflow_runs);
@@ -0,0 +1,11 @@
+CREATE TABLE alert_rules (
+    name TEXT PRIMARY KEY,
+    function TEXT NOT NULL,
+    condition TEXT NOT NULL,
+    window_secs INTEGER NOT NULL,
+    webhooks TEXT NOT NULL DEFAULT '[]',
+    state TEXT NOT NULL DEFAULT 'ok',
+    value REAL,
+    since TEXT,
+    evaluated_at TEXT
+);
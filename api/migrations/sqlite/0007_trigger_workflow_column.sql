@@ -0,0 +1,33 @@
+-- Workflow triggers move their target into `workflow`, so `function` can
+-- reference `functions` again and each target kind has its own foreign key.
+CREATE TABLE triggers_new (
+    name TEXT PRIMARY KEY,
+    method TEXT NOT NULL,
+    path TEXT NOT NULL,
+    function TEXT REFERENCES functions(name),
+    workflow TEXT REFERENCES workflows(name),
+    kind TEXT NOT NULL DEFAULT 'http',
+    rate_limit TEXT,
+    cache TEXT,
+    readonly BOOLEAN NOT NULL DEFAULT FALSE,
+    CHECK ((kind = 'workflow') = (workflow IS NOT NULL AND function IS NULL)),
+    CHECK (function IS NOT NULL OR workflow IS NOT NULL)
+);
+
+-- Triggers whose target is gone never routed anywhere and would fail the
+-- foreign key check.
+INSERT INTO triggers_new (name, method, path, function, workflow, kind, rate_limit, cache, readonly)
+    SELECT name, method, path,
+           CASE WHEN kind = 'workflow' THEN NULL ELSE function END,
+           CASE WHEN kind = 'workflow' THEN function ELSE NULL END,
+           kind, rate_limit, cache, readonly
+    FROM triggers
+    WHERE (kind = 'workflow' AND function IN (SELECT name FROM workflows))
+       OR (kind <> 'workflow' AND function IN (SELECT name FROM functions));
+
+DROP TABLE triggers;
+
+ALTER TABLE triggers_new RENAME TO triggers;
+
+CREATE INDEX triggers_function ON triggers (function);
+CREATE INDEX triggers_workflow ON triggers (workflow);
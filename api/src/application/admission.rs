@@ -0,0 +1,241 @@
+use crate::domain::entities::{DomainError, Function};
+use ahash::RandomState;
+use papaya::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Concurrency slots of a single function, rebuilt when its limits change.
+struct FunctionGate {
+    max_concurrency: u32,
+    max_queue: u32,
+    semaphore: Arc<Semaphore>,
+    waiting: AtomicUsize,
+}
+
+/// Held for the duration of an invocation; dropping it frees the slots.
+pub struct AdmissionPermit {
+    _global: OwnedSemaphorePermit,
+    _function: Option<OwnedSemaphorePermit>,
+}
+
+/// Held for the lifetime of a WebSocket session, which keeps an instance the whole time.
+pub struct SessionPermit {
+    _session: OwnedSemaphorePermit,
+}
+
+/// Decides whether an invocation may run now, has to wait, or is rejected.
+///
+/// Each function can cap its simultaneous invocations and let a bounded number of
+/// callers wait for a slot. On top of that, a global limit keeps the total number of
+/// running invocations below what the runtime's instance pool can hold, so callers
+/// get a clean rejection instead of an instantiation failure. WebSocket sessions
+/// are counted apart, against the share of the pool the invocations leave free.
+pub struct AdmissionController {
+    capacity: usize,
+    global: Arc<Semaphore>,
+    sessions: Arc<Semaphore>,
+    functions: HashMap<String, Arc<FunctionGate>, RandomState>,
+    queue_timeout: Duration,
+}
+
+impl AdmissionController {
+    pub fn new(max_in_flight: usize, queue_timeout: Duration) -> Self {
+        Self {
+            capacity: max_in_flight,
+            global: Arc::new(Semaphore::new(max_in_flight)),
+            sessions: Arc::new(Semaphore::new(Semaphore::MAX_PERMITS)),
+            functions: HashMap::builder().hasher(RandomState::new()).build(),
+            queue_timeout,
+        }
+    }
+
+    /// Caps the number of WebSocket sessions open at once.
+    pub fn with_max_sessions(mut self, max_sessions: usize) -> Self {
+        self.sessions = Arc::new(Semaphore::new(max_sessions));
+        self
+    }
+
+    /// Only per-function limits apply.
+    pub fn unbounded() -> Self {
+        Self::new(Semaphore::MAX_PERMITS, Duration::from_secs(30))
+    }
+
+    /// Number of invocations currently holding a global slot.
+    pub fn in_flight(&self) -> usize {
+        self.capacity
+            .saturating_sub(self.global.available_permits())
+    }
+
+    pub async fn admit(&self, function: &Function) -> Result<AdmissionPermit, DomainError> {
+        // Queue on the function first, so waiting callers don't hold global slots
+        let function_permit = match function.max_concurrency {
+            Some(max) => Some(self.acquire_function(function, max).await?),
+            None => None,
+        };
+
+        let global = self.global.clone().try_acquire_owned().map_err(|_| {
+            DomainError::Overloaded("All function instances are in use".to_string())
+        })?;
+
+        Ok(AdmissionPermit {
+            _global: global,
+            _function: function_permit,
+        })
+    }
+
+    pub fn admit_session(&self) -> Result<SessionPermit, DomainError> {
+        let session = self.sessions.clone().try_acquire_owned().map_err(|_| {
+            DomainError::Overloaded("All WebSocket session instances are in use".to_string())
+        })?;
+        Ok(SessionPermit { _session: session })
+    }
+
+    fn gate(&self, function: &Function, max_concurrency: u32) -> Arc<FunctionGate> {
+        let max_queue = function.max_queue.unwrap_or(0);
+        let pin = self.functions.pin();
+
+        if let Some(gate) = pin.get(&function.name)
+            && gate.max_concurrency == max_concurrency
+            && gate.max_queue == max_queue
+        {
+            return gate.clone();
+        }
+
+        // Invocations admitted by a replaced gate keep their permits until they finish
+        let gate = Arc::new(FunctionGate {
+            max_concurrency,
+            max_queue,
+            semaphore: Arc::new(Semaphore::new(max_concurrency as usize)),
+            waiting: AtomicUsize::new(0),
+        });
+        pin.insert(function.name.clone(), gate.clone());
+        gate
+    }
+
+    async fn acquire_function(
+        &self,
+        function: &Function,
+        max_concurrency: u32,
+    ) -> Result<OwnedSemaphorePermit, DomainError> {
+        let gate = self.gate(function, max_concurrency);
+
+        if let Ok(permit) = gate.semaphore.clone().try_acquire_owned() {
+            return Ok(permit);
+        }
+
+        let max_queue = gate.max_queue as usize;
+        gate.waiting
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < max_queue).then_some(n + 1)
+            })
+            .map_err(|_| {
+                DomainError::LimitExceeded(format!(
+                    "Function '{}' is at its concurrency limit of {}",
+                    function.name, max_concurrency
+                ))
+            })?;
+
+        let result =
+            tokio::time::timeout(self.queue_timeout, gate.semaphore.clone().acquire_owned()).await;
+        gate.waiting.fetch_sub(1, Ordering::SeqCst);
+
+        match result {
+            Ok(Ok(permit)) => Ok(permit),
+            Ok(Err(e)) => Err(DomainError::Internal(e.to_string())),
+            Err(_) => Err(DomainError::Overloaded(format!(
+                "Timed out waiting for function '{}'",
+                function.name
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn function(max_concurrency: Option<u32>, max_queue: Option<u32>) -> Function {
+        Function {
+            name: "f".to_string(),
+            max_concurrency,
+            max_queue,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rejects_when_queue_is_full() {
+        let controller = AdmissionController::new(10, Duration::from_secs(5));
+        let f = function(Some(1), None);
+
+        let permit = controller.admit(&f).await.unwrap();
+        let result = controller.admit(&f).await;
+        assert!(matches!(result, Err(DomainError::LimitExceeded(_))));
+
+        drop(permit);
+        assert!(controller.admit(&f).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_queued_invocation_runs_when_slot_frees() {
+        let controller = Arc::new(AdmissionController::new(10, Duration::from_secs(5)));
+        let f = function(Some(1), Some(1));
+
+        let permit = controller.admit(&f).await.unwrap();
+
+        let waiter = {
+            let controller = controller.clone();
+            let f = f.clone();
+            tokio::spawn(async move { controller.admit(&f).await.map(|_| ()) })
+        };
+        tokio::task::yield_now().await;
+
+        // The single queue slot is taken by the waiter
+        assert!(matches!(
+            controller.admit(&f).await,
+            Err(DomainError::LimitExceeded(_))
+        ));
+
+        drop(permit);
+        assert!(waiter.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_queue_timeout() {
+        let controller = AdmissionController::new(10, Duration::from_millis(10));
+        let f = function(Some(1), Some(5));
+
+        let _permit = controller.admit(&f).await.unwrap();
+        let result = controller.admit(&f).await;
+        assert!(matches!(result, Err(DomainError::Overloaded(_))));
+    }
+
+    #[tokio::test]
+    async fn test_global_capacity() {
+        let controller = AdmissionController::new(1, Duration::from_secs(5));
+        let unlimited = function(None, None);
+
+        let _permit = controller.admit(&unlimited).await.unwrap();
+        let result = controller.admit(&unlimited).await;
+        assert!(matches!(result, Err(DomainError::Overloaded(_))));
+        assert_eq!(controller.in_flight(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_session_capacity() {
+        let controller = AdmissionController::new(1, Duration::from_secs(5)).with_max_sessions(1);
+
+        let session = controller.admit_session().unwrap();
+        assert!(matches!(
+            controller.admit_session(),
+            Err(DomainError::Overloaded(_))
+        ));
+        // Sessions and invocations don't take each other's slots
+        assert!(controller.admit(&function(None, None)).await.is_ok());
+
+        drop(session);
+        assert!(controller.admit_session().is_ok());
+    }
+}
@@ -0,0 +1,550 @@
+use crate::application::audit_service::AuditService;
+use crate::domain::entities::{
+    Actor, AlertCondition, AlertRule, AlertState, AlertStatus, AuditAction, DomainError,
+    Granularity, InvocationStats, TimeRange,
+};
+use crate::domain::ports::{
+    AlertNotifier, AlertRepository, FunctionRepository, TelemetryRepository,
+};
+use chrono::{DateTime, TimeDelta, Utc};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// How often rules are evaluated unless configured otherwise.
+pub const DEFAULT_EVALUATION_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Shortest and longest window a rule may look back over.
+pub const MIN_ALERT_WINDOW_SECS: u64 = 60;
+pub const MAX_ALERT_WINDOW_SECS: u64 = 7 * 24 * 3600;
+
+/// Evaluates alert rules against the telemetry store and notifies webhooks
+/// whenever a rule starts firing or resolves.
+pub struct AlertService {
+    repository: Arc<dyn AlertRepository>,
+    function_repository: Arc<dyn FunctionRepository>,
+    telemetry_repository: Arc<dyn TelemetryRepository>,
+    notifier: Arc<dyn AlertNotifier>,
+    audit: Option<Arc<AuditService>>,
+    default_webhooks: Vec<String>,
+    interval: Duration,
+}
+
+impl AlertService {
+    pub fn new(
+        repository: Arc<dyn AlertRepository>,
+        function_repository: Arc<dyn FunctionRepository>,
+        telemetry_repository: Arc<dyn TelemetryRepository>,
+        notifier: Arc<dyn AlertNotifier>,
+    ) -> Self {
+        Self {
+            repository,
+            function_repository,
+            telemetry_repository,
+            notifier,
+            audit: None,
+            default_webhooks: Vec::new(),
+            interval: DEFAULT_EVALUATION_INTERVAL,
+        }
+    }
+
+    /// Webhooks notified for rules that don't name their own.
+    pub fn with_default_webhooks(mut self, webhooks: Vec<String>) -> Self {
+        self.default_webhooks = webhooks;
+        self
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Records rule changes in the audit log.
+    pub fn with_audit_log(mut self, audit: Arc<AuditService>) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    async fn audit(
+        &self,
+        actor: &Actor,
+        action: AuditAction,
+        name: &str,
+        before: Option<&AlertRule>,
+        after: Option<&AlertRule>,
+    ) {
+        if let Some(audit) = &self.audit {
+            audit.record(actor, action, name, before, after).await;
+        }
+    }
+
+    pub async fn list_rules(&self) -> Result<Vec<AlertRule>, DomainError> {
+        self.repository.find_alert_rules().await
+    }
+
+    pub async fn get_rule(&self, name: &str) -> Result<AlertRule, DomainError> {
+        self.repository
+            .find_alert_rule(name)
+            .await?
+            .ok_or_else(|| DomainError::NotFound(format!("Alert rule '{}' not found", name)))
+    }
+
+    pub async fn create_rule(
+        &self,
+        mut rule: AlertRule,
+        actor: &Actor,
+    ) -> Result<AlertRule, DomainError> {
+        if self.repository.find_alert_rule(&rule.name).await?.is_some() {
+            return Err(DomainError::AlreadyExists(format!(
+                "Alert rule '{}' already exists",
+                rule.name
+            )));
+        }
+        self.validate(&rule).await?;
+        rule.status = AlertStatus::default();
+        let created = self.repository.save_alert_rule(&rule).await?;
+        self.audit(
+            actor,
+            AuditAction::AlertCreate,
+            &created.name,
+            None,
+            Some(&created),
+        )
+        .await;
+        Ok(created)
+    }
+
+    /// Replaces a rule's definition. It keeps its state, so a firing rule only
+    /// notifies again once the next evaluation disagrees with it.
+    pub async fn update_rule(
+        &self,
+        name: &str,
+        mut rule: AlertRule,
+        actor: &Actor,
+    ) -> Result<AlertRule, DomainError> {
+        let existing = self.get_rule(name).await?;
+        rule.name = name.to_string();
+        self.validate(&rule).await?;
+        rule.status = existing.status.clone();
+        let updated = self.repository.save_alert_rule(&rule).await?;
+        self.audit(
+            actor,
+            AuditAction::AlertUpdate,
+            name,
+            Some(&existing),
+            Some(&updated),
+        )
+        .await;
+        Ok(updated)
+    }
+
+    pub async fn delete_rule(&self, name: &str, actor: &Actor) -> Result<(), DomainError> {
+        let current = match &self.audit {
+            Some(_) => self.repository.find_alert_rule(name).await?,
+            None => None,
+        };
+        self.repository.delete_alert_rule(name).await?;
+        self.audit(
+            actor,
+            AuditAction::AlertDelete,
+            name,
+            current.as_ref(),
+            None,
+        )
+        .await;
+        Ok(())
+    }
+
+    async fn validate(&self, rule: &AlertRule) -> Result<(), DomainError> {
+        let invalid = |msg: String| Err(DomainError::ValidationError(msg));
+
+        if rule.name.trim().is_empty() {
+            return invalid("Alert rule name is required".to_string());
+        }
+        if self
+            .function_repository
+            .find_by_name(&rule.function)
+            .await?
+            .is_none()
+        {
+            return invalid(format!("Function '{}' not found", rule.function));
+        }
+        if !(MIN_ALERT_WINDOW_SECS..=MAX_ALERT_WINDOW_SECS).contains(&rule.window_secs) {
+            return invalid(format!(
+                "window_secs must be between {} and {}",
+                MIN_ALERT_WINDOW_SECS, MAX_ALERT_WINDOW_SECS
+            ));
+        }
+        match rule.condition {
+            AlertCondition::ErrorRate { threshold, .. } if !(0.0..1.0).contains(&threshold) => {
+                return invalid("Error rate threshold must be at least 0 and below 1".to_string());
+            }
+            AlertCondition::LatencyP99 { threshold_ms } if threshold_ms <= 0.0 => {
+                return invalid("Latency threshold must be positive".to_string());
+            }
+            _ => {}
+        }
+        if let Some(url) = rule
+            .webhooks
+            .iter()
+            .find(|url| !url.starts_with("http://") && !url.starts_with("https://"))
+        {
+            return invalid(format!("Webhook '{}' is not an http(s) URL", url));
+        }
+        Ok(())
+    }
+
+    /// Evaluates every rule over the window ending at `now`, persists the outcome
+    /// and notifies about rules that changed state, which are returned.
+    pub async fn evaluate(&self, now: DateTime<Utc>) -> Result<Vec<AlertRule>, DomainError> {
+        let rules = self.repository.find_alert_rules().await?;
+
+        // Rules sharing a window share the query
+        let mut stats_by_window: HashMap<u64, HashMap<String, InvocationStats>> = HashMap::new();
+        let mut changed = Vec::new();
+        for mut rule in rules {
+            if let Entry::Vacant(entry) = stats_by_window.entry(rule.window_secs) {
+                let range = TimeRange {
+                    from: now - TimeDelta::seconds(rule.window_secs as i64),
+                    to: now,
+                    step: Granularity::Minute,
+                };
+                let stats = self
+                    .telemetry_repository
+                    .get_function_stats(&range)
+                    .await?
+                    .into_iter()
+                    .map(|f| (f.function_name, f.stats))
+                    .collect();
+                entry.insert(stats);
+            }
+            let stats = stats_by_window[&rule.window_secs]
+                .get(&rule.function)
+                .cloned()
+                .unwrap_or_default();
+
+            let (value, breached) = check(&rule.condition, &stats);
+            let state = if breached {
+                AlertState::Firing
+            } else {
+                AlertState::Ok
+            };
+            let transition = state != rule.status.state;
+            rule.status = AlertStatus {
+                state,
+                value,
+                since: if transition {
+                    Some(now)
+                } else {
+                    rule.status.since
+                },
+                evaluated_at: Some(now),
+            };
+
+            self.repository
+                .save_alert_status(&rule.name, &rule.status)
+                .await?;
+            if transition {
+                // Not `function_name`, which would file this among the function's own logs
+                info!(
+                    alert = rule.name,
+                    function = rule.function,
+                    "Alert {:?}, value {:?}",
+                    state,
+                    value
+                );
+                self.notify(&rule).await;
+                changed.push(rule);
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Delivery is best effort: the state change stands even if a webhook is down.
+    async fn notify(&self, rule: &AlertRule) {
+        let webhooks = if rule.webhooks.is_empty() {
+            &self.default_webhooks
+        } else {
+            &rule.webhooks
+        };
+        for webhook in webhooks {
+            if let Err(e) = self.notifier.notify(webhook, rule).await {
+                warn!(alert = rule.name, "Failed to notify {}: {}", webhook, e);
+            }
+        }
+    }
+
+    /// Evaluates rules every interval until the process exits.
+    pub fn start(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.evaluate(Utc::now()).await {
+                    error!("Alert evaluation failed: {}", e);
+                }
+            }
+        });
+    }
+}
+
+/// The observed value of `condition` and whether it crosses its threshold.
+fn check(condition: &AlertCondition, stats: &InvocationStats) -> (Option<f64>, bool) {
+    match *condition {
+        AlertCondition::ErrorRate {
+            threshold,
+            min_invocations,
+        } => {
+            if stats.invocations == 0 {
+                return (None, false);
+            }
+            let rate = stats.errors as f64 / stats.invocations as f64;
+            (
+                Some(rate),
+                stats.invocations >= min_invocations && rate > threshold,
+            )
+        }
+        AlertCondition::LatencyP99 { threshold_ms } => {
+            if stats.invocations == 0 {
+                return (None, false);
+            }
+            (Some(stats.p99_ms), stats.p99_ms > threshold_ms)
+        }
+        AlertCondition::Absence => (Some(stats.invocations as f64), stats.invocations == 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::entities::{Function, FunctionStats};
+    use crate::domain::ports::{
+        MockAlertNotifier, MockAlertRepository, MockFunctionRepository, MockTelemetryRepository,
+    };
+    use std::sync::Mutex;
+
+    fn rule(condition: AlertCondition) -> AlertRule {
+        AlertRule {
+            name: "checkout-errors".to_string(),
+            function: "checkout".to_string(),
+            condition,
+            window_secs: 300,
+            webhooks: vec!["http://hooks.local/alerts".to_string()],
+            status: AlertStatus::default(),
+        }
+    }
+
+    fn stats(invocations: u64, errors: u64, p99_ms: f64) -> InvocationStats {
+        InvocationStats {
+            invocations,
+            errors,
+            p99_ms,
+            ..Default::default()
+        }
+    }
+
+    /// A service over `rules`, whose telemetry reports `stats` for `checkout`;
+    /// returns it with the statuses saved and the rules notified.
+    #[allow(clippy::type_complexity)]
+    fn alert_service(
+        rules: Vec<AlertRule>,
+        stats: InvocationStats,
+    ) -> (
+        AlertService,
+        Arc<Mutex<Vec<AlertStatus>>>,
+        Arc<Mutex<Vec<(String, AlertState)>>>,
+    ) {
+        let mut repository = MockAlertRepository::new();
+        repository
+            .expect_find_alert_rules()
+            .returning(move || Ok(rules.clone()));
+        let saved = Arc::new(Mutex::new(Vec::new()));
+        let saved_clone = saved.clone();
+        repository
+            .expect_save_alert_status()
+            .returning(move |_, status| {
+                saved_clone.lock().unwrap().push(status.clone());
+                Ok(())
+            });
+
+        let mut telemetry = MockTelemetryRepository::new();
+        telemetry.expect_get_function_stats().returning(move |_| {
+            Ok(vec![FunctionStats {
+                function_name: "checkout".to_string(),
+                stats: stats.clone(),
+            }])
+        });
+
+        let notified = Arc::new(Mutex::new(Vec::new()));
+        let notified_clone = notified.clone();
+        let mut notifier = MockAlertNotifier::new();
+        notifier.expect_notify().returning(move |webhook, rule| {
+            notified_clone
+                .lock()
+                .unwrap()
+                .push((webhook.to_string(), rule.status.state));
+            Ok(())
+        });
+
+        let service = AlertService::new(
+            Arc::new(repository),
+            Arc::new(MockFunctionRepository::new()),
+            Arc::new(telemetry),
+            Arc::new(notifier),
+        );
+        (service, saved, notified)
+    }
+
+    #[tokio::test]
+    async fn test_error_rate_fires_once() {
+        let condition = AlertCondition::ErrorRate {
+            threshold: 0.1,
+            min_invocations: 5,
+        };
+        let (service, saved, notified) =
+            alert_service(vec![rule(condition.clone())], stats(10, 3, 0.0));
+        let now = Utc::now();
+
+        let changed = service.evaluate(now).await.unwrap();
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].status.state, AlertState::Firing);
+        assert_eq!(changed[0].status.since, Some(now));
+        assert_eq!(saved.lock().unwrap()[0].value, Some(0.3));
+        assert_eq!(
+            *notified.lock().unwrap(),
+            vec![("http://hooks.local/alerts".to_string(), AlertState::Firing)]
+        );
+
+        // Already firing: the state is refreshed without notifying again
+        let mut firing = rule(condition.clone());
+        firing.status = changed[0].status.clone();
+        let (service, saved, notified) = alert_service(vec![firing], stats(10, 3, 0.0));
+        let later = now + TimeDelta::minutes(1);
+        assert!(service.evaluate(later).await.unwrap().is_empty());
+        let status = saved.lock().unwrap()[0].clone();
+        assert_eq!(status.since, Some(now));
+        assert_eq!(status.evaluated_at, Some(later));
+        assert!(notified.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_error_rate_needs_min_invocations_and_resolves() {
+        let condition = AlertCondition::ErrorRate {
+            threshold: 0.1,
+            min_invocations: 5,
+        };
+        let (service, _, notified) = alert_service(vec![rule(condition.clone())], stats(2, 2, 0.0));
+        assert!(service.evaluate(Utc::now()).await.unwrap().is_empty());
+        assert!(notified.lock().unwrap().is_empty());
+
+        let mut firing = rule(condition.clone());
+        firing.status.state = AlertState::Firing;
+        let (service, _, notified) = alert_service(vec![firing], stats(10, 0, 0.0));
+        let changed = service.evaluate(Utc::now()).await.unwrap();
+        assert_eq!(changed[0].status.state, AlertState::Ok);
+        assert_eq!(notified.lock().unwrap()[0].1, AlertState::Ok);
+    }
+
+    #[tokio::test]
+    async fn test_latency_and_absence() {
+        let latency = rule(AlertCondition::LatencyP99 {
+            threshold_ms: 250.0,
+        });
+        let (service, _, _) = alert_service(vec![latency.clone()], stats(10, 0, 300.0));
+        assert_eq!(service.evaluate(Utc::now()).await.unwrap().len(), 1);
+        let (service, _, _) = alert_service(vec![latency], stats(10, 0, 200.0));
+        assert!(service.evaluate(Utc::now()).await.unwrap().is_empty());
+
+        let mut absence = rule(AlertCondition::Absence);
+        absence.function = "nightly-report".to_string();
+        let (service, saved, _) = alert_service(vec![absence], stats(10, 0, 0.0));
+        let changed = service.evaluate(Utc::now()).await.unwrap();
+        assert_eq!(changed[0].status.state, AlertState::Firing);
+        assert_eq!(saved.lock().unwrap()[0].value, Some(0.0));
+    }
+
+    #[tokio::test]
+    async fn test_default_webhooks() {
+        let mut quiet = rule(AlertCondition::Absence);
+        quiet.function = "nightly-report".to_string();
+        quiet.webhooks.clear();
+        let (service, _, notified) = alert_service(vec![quiet], stats(0, 0, 0.0));
+        let service = service.with_default_webhooks(vec![
+            "http://a.local".to_string(),
+            "http://b.local".to_string(),
+        ]);
+
+        service.evaluate(Utc::now()).await.unwrap();
+        let webhooks: Vec<String> = notified
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(w, _)| w.clone())
+            .collect();
+        assert_eq!(webhooks, vec!["http://a.local", "http://b.local"]);
+    }
+
+    #[tokio::test]
+    async fn test_create_rule_validation() {
+        let mut repository = MockAlertRepository::new();
+        repository.expect_find_alert_rule().returning(|_| Ok(None));
+        repository
+            .expect_save_alert_rule()
+            .returning(|rule| Ok(rule.clone()));
+        let mut functions = MockFunctionRepository::new();
+        functions.expect_find_by_name().returning(|name| {
+            Ok((name == "checkout").then(|| Function {
+                name: name.to_string(),
+                ..Default::default()
+            }))
+        });
+        let service = AlertService::new(
+            Arc::new(repository),
+            Arc::new(functions),
+            Arc::new(MockTelemetryRepository::new()),
+            Arc::new(MockAlertNotifier::new()),
+        );
+
+        let valid = rule(AlertCondition::Absence);
+        let mut created = valid.clone();
+        created.status.state = AlertState::Firing;
+        assert_eq!(
+            service
+                .create_rule(created, &Actor::system())
+                .await
+                .unwrap()
+                .status,
+            AlertStatus::default()
+        );
+
+        let mut invalid = Vec::new();
+        let mut r = valid.clone();
+        r.function = "missing".to_string();
+        invalid.push(r);
+        let mut r = valid.clone();
+        r.window_secs = 10;
+        invalid.push(r);
+        let mut r = valid.clone();
+        r.condition = AlertCondition::ErrorRate {
+            threshold: 1.5,
+            min_invocations: 1,
+        };
+        invalid.push(r);
+        let mut r = valid.clone();
+        r.condition = AlertCondition::LatencyP99 { threshold_ms: 0.0 };
+        invalid.push(r);
+        let mut r = valid;
+        r.webhooks = vec!["ftp://hooks.local".to_string()];
+        invalid.push(r);
+
+        for rule in invalid {
+            assert!(matches!(
+                service.create_rule(rule, &Actor::system()).await,
+                Err(DomainError::ValidationError(_))
+            ));
+        }
+    }
+}
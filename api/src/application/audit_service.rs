@@ -0,0 +1,380 @@
+use crate::application::telemetry_service::parse_instant;
+use crate::domain::entities::{
+    Actor, AuditAction, AuditEntry, AuditPage, AuditQuery, DomainError, FieldChange,
+};
+use crate::domain::ports::AuditRepository;
+use chrono::{DateTime, Utc};
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::error;
+
+/// Most entries an audit search returns per page, and the page size of exports.
+pub const MAX_AUDIT_LIMIT: usize = 1000;
+
+/// Fields whose values never reach the log; only which keys changed does.
+/// Webhook URLs carry their own credentials.
+const SECRET_FIELDS: [&str; 3] = ["env", "password_hash", "webhooks"];
+const REDACTED: &str = "[redacted]";
+
+/// Audit log search as given by the caller. `from` and `to` are read like in
+/// `RangeParams`; `cursor` comes from the previous page's `next_cursor`.
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct AuditParams {
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub target: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+}
+
+impl AuditParams {
+    /// Validates the filters; `default_limit` applies when no `limit` is given.
+    pub fn resolve(
+        &self,
+        now: DateTime<Utc>,
+        default_limit: usize,
+    ) -> Result<AuditQuery, DomainError> {
+        let non_empty = |v: &Option<String>| v.clone().filter(|v| !v.is_empty());
+
+        let limit = self.limit.unwrap_or(default_limit);
+        if limit == 0 || limit > MAX_AUDIT_LIMIT {
+            return Err(DomainError::ValidationError(format!(
+                "`limit` must be between 1 and {}",
+                MAX_AUDIT_LIMIT
+            )));
+        }
+
+        let from = non_empty(&self.from)
+            .map(|from| parse_instant(&from, now))
+            .transpose()?;
+        let to = non_empty(&self.to)
+            .map(|to| parse_instant(&to, now))
+            .transpose()?;
+        if let (Some(from), Some(to)) = (from, to)
+            && from >= to
+        {
+            return Err(DomainError::ValidationError(
+                "`from` must be before `to`".to_string(),
+            ));
+        }
+        let before_id = non_empty(&self.cursor)
+            .map(|cursor| {
+                cursor
+                    .parse::<i64>()
+                    .map_err(|_| DomainError::ValidationError("Invalid cursor".to_string()))
+            })
+            .transpose()?;
+
+        Ok(AuditQuery {
+            actor: non_empty(&self.actor),
+            action: non_empty(&self.action),
+            target: non_empty(&self.target),
+            from,
+            to,
+            before_id,
+            limit,
+        })
+    }
+}
+
+/// Records who changed what through the management API, and serves the log back.
+pub struct AuditService {
+    repository: Arc<dyn AuditRepository>,
+}
+
+impl AuditService {
+    pub fn new(repository: Arc<dyn AuditRepository>) -> Self {
+        Self { repository }
+    }
+
+    /// Appends an entry with the fields that differ between `before` and `after`,
+    /// either of which is `None` when the target didn't exist on that side.
+    ///
+    /// The change has already been made by the time it is recorded, so failing to
+    /// record it is logged rather than returned.
+    pub async fn record<T: Serialize + Sync>(
+        &self,
+        actor: &Actor,
+        action: AuditAction,
+        target: &str,
+        before: Option<&T>,
+        after: Option<&T>,
+    ) {
+        let to_value = |state: Option<&T>| {
+            state
+                .and_then(|s| serde_json::to_value(s).ok())
+                .unwrap_or(Value::Null)
+        };
+        let entry = AuditEntry {
+            id: 0,
+            at: Utc::now(),
+            actor: actor.id.clone(),
+            source_ip: actor.source_ip.clone(),
+            action,
+            target: target.to_string(),
+            changes: diff(&to_value(before), &to_value(after)),
+        };
+        if let Err(e) = self.repository.append_audit_entry(&entry).await {
+            error!(
+                actor = %entry.actor,
+                target = %entry.target,
+                "Failed to record {:?} in the audit log: {}",
+                entry.action,
+                e
+            );
+        }
+    }
+
+    pub async fn search(&self, params: &AuditParams) -> Result<AuditPage, DomainError> {
+        let query = params.resolve(Utc::now(), 100)?;
+        let entries = self.repository.find_audit_entries(&query).await?;
+        let next_cursor = if entries.len() < query.limit {
+            None
+        } else {
+            entries.last().map(|entry| entry.id.to_string())
+        };
+        Ok(AuditPage {
+            entries,
+            next_cursor,
+        })
+    }
+
+    /// Every entry matching the filters, newest first, a page at a time. `limit`
+    /// is ignored.
+    pub fn export(
+        &self,
+        params: &AuditParams,
+    ) -> Result<impl Stream<Item = Result<Vec<AuditEntry>, DomainError>> + use<>, DomainError> {
+        let params = AuditParams {
+            limit: None,
+            ..params.clone()
+        };
+        let query = params.resolve(Utc::now(), MAX_AUDIT_LIMIT)?;
+
+        let repository = self.repository.clone();
+        Ok(futures_util::stream::try_unfold(
+            Some(query),
+            move |query| {
+                let repository = repository.clone();
+                async move {
+                    let Some(mut query) = query else {
+                        return Ok(None);
+                    };
+                    let entries = repository.find_audit_entries(&query).await?;
+                    if entries.is_empty() {
+                        return Ok(None);
+                    }
+                    let next = if entries.len() < query.limit {
+                        None
+                    } else {
+                        query.before_id = entries.last().map(|entry| entry.id);
+                        Some(query)
+                    };
+                    Ok(Some((entries, next)))
+                }
+            },
+        ))
+    }
+}
+
+/// Top-level fields that differ between two serialized states, with secret
+/// values redacted.
+pub fn diff(before: &Value, after: &Value) -> BTreeMap<String, FieldChange> {
+    let empty = Map::new();
+    let before_fields = before.as_object().unwrap_or(&empty);
+    let after_fields = after.as_object().unwrap_or(&empty);
+
+    let mut changes = BTreeMap::new();
+    for field in before_fields.keys().chain(after_fields.keys()) {
+        let old = before_fields.get(field).unwrap_or(&Value::Null);
+        let new = after_fields.get(field).unwrap_or(&Value::Null);
+        if old != new && !changes.contains_key(field) {
+            changes.insert(
+                field.clone(),
+                FieldChange {
+                    before: redact(field, old),
+                    after: redact(field, new),
+                },
+            );
+        }
+    }
+    changes
+}
+
+/// Keeps the keys of secret maps so added or removed variables still show.
+fn redact(field: &str, value: &Value) -> Value {
+    if !SECRET_FIELDS.contains(&field) || value.is_null() {
+        return value.clone();
+    }
+    match value {
+        Value::Object(map) => map
+            .keys()
+            .map(|key| (key.clone(), Value::from(REDACTED)))
+            .collect::<Map<_, _>>()
+            .into(),
+        _ => Value::from(REDACTED),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ports::MockAuditRepository;
+    use futures_util::TryStreamExt;
+    use serde_json::json;
+
+    fn entry(id: i64) -> AuditEntry {
+        AuditEntry {
+            id,
+            at: Utc::now(),
+            actor: "admin@fluor.com".to_string(),
+            source_ip: None,
+            action: AuditAction::FunctionUpdate,
+            target: "hello".to_string(),
+            changes: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let before = json!({
+            "name": "hello",
+            "memory": "128",
+            "env": { "TOKEN": "old", "MODE": "test" },
+        });
+        let after = json!({
+            "name": "hello",
+            "memory": "256",
+            "env": { "TOKEN": "new", "MODE": "test" },
+            "readonly": true,
+        });
+
+        let changes = diff(&before, &after);
+        assert_eq!(
+            changes.keys().collect::<Vec<_>>(),
+            ["env", "memory", "readonly"]
+        );
+        assert_eq!(changes["memory"].before, "128");
+        assert_eq!(changes["memory"].after, "256");
+        assert_eq!(changes["readonly"].before, Value::Null);
+        // The value changed, but only the keys are kept
+        let redacted = json!({ "MODE": REDACTED, "TOKEN": REDACTED });
+        assert_eq!(changes["env"].before, redacted);
+        assert_eq!(changes["env"].after, redacted);
+
+        let created = diff(&Value::Null, &before);
+        assert_eq!(created.len(), 3);
+        assert_eq!(created["name"].before, Value::Null);
+        assert_eq!(created["name"].after, "hello");
+
+        let deleted = diff(&before, &Value::Null);
+        assert_eq!(deleted["name"].after, Value::Null);
+        assert!(diff(&before, &before).is_empty());
+
+        let rule = json!({ "webhooks": ["https://hooks.slack.com/services/T0/B0/secret"] });
+        let created = diff(&Value::Null, &rule);
+        assert_eq!(created["webhooks"].after, REDACTED);
+    }
+
+    #[tokio::test]
+    async fn test_record() {
+        let mut repo = MockAuditRepository::new();
+        repo.expect_append_audit_entry()
+            .withf(|entry| {
+                entry.actor == "admin@fluor.com"
+                    && entry.source_ip.as_deref() == Some("10.0.0.1")
+                    && entry.action == AuditAction::FunctionUpdate
+                    && entry.target == "hello"
+                    && entry.changes.keys().eq(["memory"])
+            })
+            .times(1)
+            .returning(|entry| Ok(entry.clone()));
+
+        let service = AuditService::new(Arc::new(repo));
+        service
+            .record(
+                &Actor::new("admin@fluor.com", Some("10.0.0.1".to_string())),
+                AuditAction::FunctionUpdate,
+                "hello",
+                Some(&json!({ "name": "hello", "memory": "128" })),
+                Some(&json!({ "name": "hello", "memory": "256" })),
+            )
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_search_pages() {
+        let mut repo = MockAuditRepository::new();
+        repo.expect_find_audit_entries()
+            .withf(|query| {
+                query.action.as_deref() == Some("function")
+                    && query.before_id == Some(10)
+                    && query.limit == 2
+            })
+            .returning(|_| Ok(vec![entry(9), entry(8)]));
+
+        let service = AuditService::new(Arc::new(repo));
+        let params = AuditParams {
+            action: Some("function".to_string()),
+            cursor: Some("10".to_string()),
+            limit: Some(2),
+            ..Default::default()
+        };
+        let page = service.search(&params).await.unwrap();
+        assert_eq!(page.entries.len(), 2);
+        assert_eq!(page.next_cursor.as_deref(), Some("8"));
+
+        for params in [
+            AuditParams {
+                limit: Some(0),
+                ..Default::default()
+            },
+            AuditParams {
+                cursor: Some("abc".to_string()),
+                ..Default::default()
+            },
+            AuditParams {
+                from: Some("now".to_string()),
+                to: Some("now-1h".to_string()),
+                ..Default::default()
+            },
+        ] {
+            assert!(matches!(
+                service.search(&params).await,
+                Err(DomainError::ValidationError(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_export_reads_every_page() {
+        let mut repo = MockAuditRepository::new();
+        repo.expect_find_audit_entries()
+            .returning(|query| match query.before_id {
+                None => Ok((0..MAX_AUDIT_LIMIT as i64)
+                    .rev()
+                    .map(|id| entry(id + 1))
+                    .collect()),
+                Some(1) => Ok(Vec::new()),
+                Some(id) => panic!("unexpected cursor {}", id),
+            });
+
+        let service = AuditService::new(Arc::new(repo));
+        let pages: Vec<Vec<AuditEntry>> = service
+            .export(&AuditParams {
+                limit: Some(5),
+                ..Default::default()
+            })
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].len(), MAX_AUDIT_LIMIT);
+    }
+}
@@ -1,4 +1,5 @@
-use crate::domain::entities::DomainError;
+use crate::application::audit_service::AuditService;
+use crate::domain::entities::{Actor, AuditAction, DomainError, User};
 use crate::domain::ports::UserRepository;
 use argon2::{
     Algorithm, Argon2, Params, PasswordHasher, Version,
@@ -7,6 +8,7 @@ use argon2::{
 use chrono::{Duration, Utc};
 use jsonwebtoken::{EncodingKey, Header, encode};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::sync::Arc;
 
 #[derive(Serialize, Deserialize)]
@@ -20,6 +22,7 @@ pub struct AuthService {
     user_repository: Arc<dyn UserRepository>,
     pepper: String,
     jwt_secret: String,
+    audit: Option<Arc<AuditService>>,
 }
 
 impl AuthService {
@@ -32,6 +35,37 @@ impl AuthService {
             user_repository,
             pepper,
             jwt_secret,
+            audit: None,
+        }
+    }
+
+    /// Records profile and password changes in the audit log.
+    pub fn with_audit_log(mut self, audit: Arc<AuditService>) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    async fn audit(
+        &self,
+        user: &User,
+        source_ip: Option<String>,
+        action: AuditAction,
+        before: Option<&User>,
+        after: Option<&User>,
+    ) {
+        if let Some(audit) = &self.audit {
+            // The hash never reaches the log, so a password change has no diff
+            let profile = |u: &User| json!({ "name": u.name, "email": u.email, "role": u.role });
+            let actor = Actor::new(user.email.clone(), source_ip);
+            audit
+                .record(
+                    &actor,
+                    action,
+                    &user.email,
+                    before.map(profile).as_ref(),
+                    after.map(profile).as_ref(),
+                )
+                .await;
         }
     }
 
@@ -79,8 +113,9 @@ impl AuthService {
         Ok(token)
     }
 
-    pub async fn get_current_user(&self, token: &str) -> Result<crate::domain::entities::User, DomainError> {
-        use jsonwebtoken::{Validation, DecodingKey, decode};
+    /// Checks a token's signature and expiry without looking the user up.
+    pub fn verify_token(&self, token: &str) -> Result<Claims, DomainError> {
+        use jsonwebtoken::{DecodingKey, Validation, decode};
 
         let token_data = decode::<Claims>(
             token,
@@ -89,7 +124,11 @@ impl AuthService {
         )
         .map_err(|e| DomainError::ValidationError(format!("Invalid token: {}", e)))?;
 
-        let email = token_data.claims.sub;
+        Ok(token_data.claims)
+    }
+
+    pub async fn get_current_user(&self, token: &str) -> Result<User, DomainError> {
+        let email = self.verify_token(token)?.sub;
 
         self.user_repository
             .find_by_email(&email)
@@ -97,8 +136,15 @@ impl AuthService {
             .ok_or_else(|| DomainError::NotFound("User not found".to_string()))
     }
 
-    pub async fn update_user(&self, token: &str, name: Option<String>, email: Option<String>) -> Result<crate::domain::entities::User, DomainError> {
-        let mut user = self.get_current_user(token).await?;
+    pub async fn update_user(
+        &self,
+        token: &str,
+        name: Option<String>,
+        email: Option<String>,
+        source_ip: Option<String>,
+    ) -> Result<User, DomainError> {
+        let current = self.get_current_user(token).await?;
+        let mut user = current.clone();
 
         if let Some(n) = name {
             user.name = n;
@@ -107,10 +153,25 @@ impl AuthService {
             user.email = e;
         }
 
-        self.user_repository.update(&user).await
+        let updated = self.user_repository.update(&user).await?;
+        self.audit(
+            &current,
+            source_ip,
+            AuditAction::UserUpdate,
+            Some(&current),
+            Some(&updated),
+        )
+        .await;
+        Ok(updated)
     }
 
-    pub async fn change_password(&self, token: &str, current_password: &str, new_password: &str) -> Result<(), DomainError> {
+    pub async fn change_password(
+        &self,
+        token: &str,
+        current_password: &str,
+        new_password: &str,
+        source_ip: Option<String>,
+    ) -> Result<(), DomainError> {
         let mut user = self.get_current_user(token).await?;
 
         // Verify current password
@@ -124,19 +185,25 @@ impl AuthService {
             .verify_password(password_with_pepper.as_bytes(), &parsed_hash)
             .is_err()
         {
-            return Err(DomainError::ValidationError("Invalid current password".to_string()));
+            return Err(DomainError::ValidationError(
+                "Invalid current password".to_string(),
+            ));
         }
 
         // Hash new password
-        let salt = argon2::password_hash::SaltString::generate(&mut argon2::password_hash::rand_core::OsRng);
+        let salt = argon2::password_hash::SaltString::generate(
+            &mut argon2::password_hash::rand_core::OsRng,
+        );
         let new_password_with_pepper = format!("{}{}", new_password, self.pepper);
-        
+
         user.password_hash = argon2
             .hash_password(new_password_with_pepper.as_bytes(), &salt)
             .map_err(|e| DomainError::Internal(e.to_string()))?
             .to_string();
 
         self.user_repository.update(&user).await?;
+        self.audit(&user, source_ip, AuditAction::PasswordChange, None, None)
+            .await;
 
         Ok(())
     }
@@ -0,0 +1,433 @@
+use crate::application::audit_service::AuditService;
+use crate::application::invocation_service::InvocationService;
+use crate::domain::entities::{
+    Actor, AuditAction, BackgroundJob, DeadLetter, DomainError, RetryPolicy,
+};
+use crate::domain::ports::{BackgroundJobRepository, DeadLetterRepository, FunctionRepository};
+use chrono::Utc;
+use opentelemetry::{KeyValue, global};
+use rand_core::{OsRng, RngCore};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// Runs invocations nobody waits on, retrying failures per the function's
+/// `RetryPolicy` and parking the ones that never succeed as dead letters.
+///
+/// Jobs are stored until they finish and resumed by `recover` after a
+/// restart, so delivery is at-least-once: a job interrupted mid-attempt runs
+/// that attempt again.
+pub struct BackgroundService {
+    invocation_service: Arc<InvocationService>,
+    function_repository: Arc<dyn FunctionRepository>,
+    jobs: Arc<dyn BackgroundJobRepository>,
+    dead_letters: Arc<dyn DeadLetterRepository>,
+    audit: Option<Arc<AuditService>>,
+}
+
+impl BackgroundService {
+    pub fn new(
+        invocation_service: Arc<InvocationService>,
+        function_repository: Arc<dyn FunctionRepository>,
+        jobs: Arc<dyn BackgroundJobRepository>,
+        dead_letters: Arc<dyn DeadLetterRepository>,
+    ) -> Self {
+        Self {
+            invocation_service,
+            function_repository,
+            jobs,
+            dead_letters,
+            audit: None,
+        }
+    }
+
+    /// Records redriven, deleted and purged dead letters in the audit log.
+    pub fn with_audit_log(mut self, audit: Arc<AuditService>) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    /// Payloads may hold user data, so entries only name what was touched.
+    async fn audit(&self, actor: &Actor, action: AuditAction, target: &str) {
+        if let Some(audit) = &self.audit {
+            audit
+                .record::<DeadLetter>(actor, action, target, None, None)
+                .await;
+        }
+    }
+
+    /// Queues an invocation of `function`; `source` records what started it.
+    pub async fn submit(
+        self: &Arc<Self>,
+        function: &str,
+        payload: String,
+        source: &str,
+    ) -> Result<(), DomainError> {
+        let func = self
+            .function_repository
+            .find_by_name(function)
+            .await?
+            .ok_or_else(|| DomainError::NotFound(format!("Function '{}' not found", function)))?;
+
+        let job = BackgroundJob {
+            id: uuid::Uuid::new_v4().to_string(),
+            function: func.name,
+            source: source.to_string(),
+            payload,
+            attempts: 0,
+            created_at: Utc::now(),
+        };
+        // Stored before it is accepted, so a restart can't lose it
+        self.jobs.save_job(&job).await?;
+        self.spawn(job, func.retry.unwrap_or_default());
+        Ok(())
+    }
+
+    /// Resumes the jobs a previous run left unfinished; returns how many.
+    pub async fn recover(self: &Arc<Self>) -> Result<usize, DomainError> {
+        let jobs = self.jobs.find_jobs().await?;
+        let count = jobs.len();
+        for job in jobs {
+            // A deleted function fails the next attempt and dead-letters the job
+            let policy = match self.function_repository.find_by_name(&job.function).await {
+                Ok(Some(func)) => func.retry.unwrap_or_default(),
+                _ => RetryPolicy::default(),
+            };
+            self.spawn(job, policy);
+        }
+        Ok(count)
+    }
+
+    fn spawn(self: &Arc<Self>, job: BackgroundJob, policy: RetryPolicy) {
+        let service = self.clone();
+        tokio::spawn(async move {
+            service.process(job, &policy).await;
+        });
+    }
+
+    /// Runs the invocation until it succeeds or attempts run out; returns the
+    /// dead letter stored in the latter case. The job is removed either way.
+    async fn process(&self, job: BackgroundJob, policy: &RetryPolicy) -> Option<DeadLetter> {
+        let function = job.function.as_str();
+        let max_attempts = policy.max_attempts.max(1);
+        let mut attempt = job.attempts;
+        let error = loop {
+            attempt += 1;
+            match self
+                .invocation_service
+                .invoke_background(function, &job.payload, attempt)
+                .await
+            {
+                Ok(_) => {
+                    self.finish(&job).await;
+                    return None;
+                }
+                // The function is gone, retrying won't bring it back
+                Err(e @ DomainError::NotFound(_)) => break e,
+                Err(e) if attempt < max_attempts => {
+                    let delay = jittered(policy.backoff_ms(attempt));
+                    warn!(
+                        function_name = function,
+                        attempt, "Background invocation failed, retrying in {}ms: {}", delay, e
+                    );
+                    let retrying = BackgroundJob {
+                        attempts: attempt,
+                        ..job.clone()
+                    };
+                    if let Err(e) = self.jobs.save_job(&retrying).await {
+                        warn!(function_name = function, "Failed to record attempt: {}", e);
+                    }
+                    tokio::time::sleep(Duration::from_millis(delay)).await;
+                }
+                Err(e) => break e,
+            }
+        };
+
+        let letter = DeadLetter {
+            id: uuid::Uuid::new_v4().to_string(),
+            function: function.to_string(),
+            source: job.source.clone(),
+            payload: job.payload.clone(),
+            error: error.to_string(),
+            attempts: attempt,
+            created_at: Utc::now(),
+        };
+        error!(
+            function_name = function,
+            dead_letter = letter.id,
+            "Background invocation failed after {} attempts: {}",
+            attempt,
+            error
+        );
+        global::meter("fluor-api")
+            .u64_counter("dead_letters")
+            .build()
+            .add(1, &[KeyValue::new("function_name", function.to_string())]);
+
+        if let Err(e) = self.dead_letters.save_dead_letter(&letter).await {
+            error!(
+                function_name = function,
+                "Failed to store dead letter: {}", e
+            );
+        }
+        self.finish(&job).await;
+        Some(letter)
+    }
+
+    async fn finish(&self, job: &BackgroundJob) {
+        if let Err(e) = self.jobs.delete_job(&job.id).await {
+            warn!(
+                function_name = job.function,
+                "Failed to remove finished background job: {}", e
+            );
+        }
+    }
+
+    pub async fn list_dead_letters(
+        &self,
+        function: Option<&str>,
+    ) -> Result<Vec<DeadLetter>, DomainError> {
+        self.dead_letters.find_dead_letters(function).await
+    }
+
+    pub async fn get_dead_letter(&self, id: &str) -> Result<DeadLetter, DomainError> {
+        self.dead_letters
+            .find_dead_letter(id)
+            .await?
+            .ok_or_else(|| DomainError::NotFound(format!("Dead letter '{}' not found", id)))
+    }
+
+    /// Submits a dead letter's payload again, with a fresh set of attempts.
+    pub async fn redrive(
+        self: &Arc<Self>,
+        id: &str,
+        actor: &Actor,
+    ) -> Result<DeadLetter, DomainError> {
+        let letter = self.get_dead_letter(id).await?;
+        self.submit(&letter.function, letter.payload.clone(), &letter.source)
+            .await?;
+        self.dead_letters.delete_dead_letter(id).await?;
+        info!(
+            function_name = letter.function,
+            dead_letter = id,
+            "Dead letter redriven"
+        );
+        self.audit(actor, AuditAction::DeadLetterRedrive, id).await;
+        Ok(letter)
+    }
+
+    pub async fn delete_dead_letter(&self, id: &str, actor: &Actor) -> Result<(), DomainError> {
+        self.dead_letters.delete_dead_letter(id).await?;
+        self.audit(actor, AuditAction::DeadLetterDelete, id).await;
+        Ok(())
+    }
+
+    /// Drops the dead letters of `function`, or all of them; the audit entry's
+    /// target is the function, or `*`.
+    pub async fn purge_dead_letters(
+        &self,
+        function: Option<&str>,
+        actor: &Actor,
+    ) -> Result<u64, DomainError> {
+        let purged = self.dead_letters.purge_dead_letters(function).await?;
+        self.audit(actor, AuditAction::DeadLetterPurge, function.unwrap_or("*"))
+            .await;
+        Ok(purged)
+    }
+}
+
+/// "Equal jitter": half of the backoff is kept, the other half is random, so
+/// retries of jobs that failed together spread out.
+fn jittered(backoff_ms: u64) -> u64 {
+    let half = backoff_ms / 2;
+    half + OsRng.next_u64() % (backoff_ms - half + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::entities::Function;
+    use crate::domain::ports::{
+        MockBackgroundJobRepository, MockDeadLetterRepository, MockFunctionRepository,
+        MockTriggerRepository,
+    };
+    use crate::domain::wasm_runtime::MockWasmRuntime;
+    use mockall::predicate::*;
+
+    const POLICY: RetryPolicy = RetryPolicy {
+        max_attempts: 3,
+        initial_backoff_ms: 0,
+        max_backoff_ms: 0,
+    };
+
+    fn job(function: &str, payload: &str, attempts: u32) -> BackgroundJob {
+        BackgroundJob {
+            id: "j1".to_string(),
+            function: function.to_string(),
+            source: "async".to_string(),
+            payload: payload.to_string(),
+            attempts,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// A job store expecting the job to be removed once it finishes.
+    fn jobs() -> MockBackgroundJobRepository {
+        let mut jobs = MockBackgroundJobRepository::new();
+        jobs.expect_save_job().returning(|_| Ok(()));
+        jobs.expect_delete_job()
+            .with(eq("j1"))
+            .times(1)
+            .returning(|_| Ok(()));
+        jobs
+    }
+
+    fn service(
+        runtime: MockWasmRuntime,
+        jobs: MockBackgroundJobRepository,
+        dead_letters: MockDeadLetterRepository,
+    ) -> BackgroundService {
+        let functions = || {
+            let mut repo = MockFunctionRepository::new();
+            repo.expect_find_by_name().returning(|name| {
+                Ok(Some(Function {
+                    name: name.to_string(),
+                    ..Default::default()
+                }))
+            });
+            Arc::new(repo)
+        };
+        let invocation_service = Arc::new(InvocationService::new(
+            Arc::new(MockTriggerRepository::new()),
+            functions(),
+            Arc::new(runtime),
+        ));
+        BackgroundService::new(
+            invocation_service,
+            functions(),
+            Arc::new(jobs),
+            Arc::new(dead_letters),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_process_retries_until_success() {
+        let mut runtime = MockWasmRuntime::new();
+        let mut seq = mockall::Sequence::new();
+        runtime
+            .expect_invoke()
+            .times(2)
+            .in_sequence(&mut seq)
+            .returning(|_, _| Err(anyhow::anyhow!("flaky")));
+        runtime
+            .expect_invoke()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| Ok("done".to_string()));
+        let mut dead_letters = MockDeadLetterRepository::new();
+        dead_letters.expect_save_dead_letter().never();
+
+        let letter = service(runtime, jobs(), dead_letters)
+            .process(job("job", "{}", 0), &POLICY)
+            .await;
+        assert!(letter.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_process_dead_letters_after_max_attempts() {
+        let mut runtime = MockWasmRuntime::new();
+        runtime
+            .expect_invoke()
+            .with(eq("job"), eq("payload"))
+            .times(3)
+            .returning(|_, _| Err(anyhow::anyhow!("boom")));
+        let mut dead_letters = MockDeadLetterRepository::new();
+        dead_letters
+            .expect_save_dead_letter()
+            .withf(|l| l.function == "job" && l.payload == "payload" && l.attempts == 3)
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let letter = service(runtime, jobs(), dead_letters)
+            .process(job("job", "payload", 0), &POLICY)
+            .await
+            .unwrap();
+        assert_eq!(letter.source, "async");
+        assert!(letter.error.contains("boom"));
+    }
+
+    #[tokio::test]
+    async fn test_process_resumes_recorded_attempts() {
+        let mut runtime = MockWasmRuntime::new();
+        runtime
+            .expect_invoke()
+            .times(1)
+            .returning(|_, _| Err(anyhow::anyhow!("boom")));
+        let mut jobs = MockBackgroundJobRepository::new();
+        jobs.expect_save_job().never();
+        jobs.expect_delete_job()
+            .with(eq("j1"))
+            .times(1)
+            .returning(|_| Ok(()));
+        let mut dead_letters = MockDeadLetterRepository::new();
+        dead_letters
+            .expect_save_dead_letter()
+            .withf(|l| l.attempts == 3)
+            .times(1)
+            .returning(|_| Ok(()));
+
+        // Two attempts failed before the restart, so only the last one is left
+        let letter = service(runtime, jobs, dead_letters)
+            .process(job("job", "payload", 2), &POLICY)
+            .await;
+        assert!(letter.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_process_records_failed_attempts() {
+        let mut runtime = MockWasmRuntime::new();
+        let mut seq = mockall::Sequence::new();
+        runtime
+            .expect_invoke()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| Err(anyhow::anyhow!("flaky")));
+        runtime
+            .expect_invoke()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| Ok("done".to_string()));
+        let mut jobs = MockBackgroundJobRepository::new();
+        jobs.expect_save_job()
+            .withf(|j| j.id == "j1" && j.attempts == 1)
+            .times(1)
+            .returning(|_| Ok(()));
+        jobs.expect_delete_job()
+            .with(eq("j1"))
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let letter = service(runtime, jobs, MockDeadLetterRepository::new())
+            .process(job("job", "{}", 0), &POLICY)
+            .await;
+        assert!(letter.is_none());
+    }
+
+    #[test]
+    fn test_backoff_and_jitter() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            initial_backoff_ms: 100,
+            max_backoff_ms: 1000,
+        };
+        assert_eq!(policy.backoff_ms(1), 100);
+        assert_eq!(policy.backoff_ms(3), 400);
+        assert_eq!(policy.backoff_ms(8), 1000);
+
+        for _ in 0..100 {
+            let delay = jittered(400);
+            assert!((200..=400).contains(&delay));
+        }
+        assert_eq!(jittered(0), 0);
+    }
+}
@@ -1,15 +1,34 @@
-use crate::domain::entities::{DomainError, Function};
+use crate::application::audit_service::AuditService;
+use crate::application::invocation_service::InvocationService;
+use crate::application::response_cache::ResponseCache;
+use crate::domain::entities::{Actor, AuditAction, DomainError, Function};
 use crate::domain::ports::FunctionRepository;
-use crate::domain::wasm_runtime::WasmRuntime;
+use crate::domain::wasm_runtime::{Snapshotter, WasmRuntime};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tracing::{info, warn};
+use tracing::{error, info, warn};
+
+/// Largest Wasm binary accepted on upload unless configured otherwise.
+pub const DEFAULT_MAX_WASM_BYTES: u64 = 50 * 1024 * 1024;
+
+/// A deploy validated and loaded by [`FunctionService::prepare`], which serves
+/// once its row is stored and [`FunctionService::install`] runs.
+pub struct PreparedFunction {
+    /// The function as it is to be stored.
+    pub function: Function,
+    staged: Option<Function>,
+}
 
 pub struct FunctionService {
     repository: Arc<dyn FunctionRepository>,
     runtime: Arc<dyn WasmRuntime>,
+    snapshotter: Option<Arc<dyn Snapshotter>>,
+    response_cache: Option<Arc<ResponseCache>>,
+    audit: Option<Arc<AuditService>>,
+    invocation_service: Option<Arc<InvocationService>>,
     storage_path: String,
+    max_wasm_bytes: u64,
 }
 
 impl FunctionService {
@@ -24,66 +43,484 @@ impl FunctionService {
         Self {
             repository,
             runtime,
+            snapshotter: None,
+            response_cache: None,
+            audit: None,
+            invocation_service: None,
             storage_path,
+            max_wasm_bytes: DEFAULT_MAX_WASM_BYTES,
         }
     }
 
-    fn store_wasm(&self, name: &str, source_path: &str) -> Result<String, DomainError> {
-        let source = Path::new(source_path);
-        if !source.exists() {
-            return Err(DomainError::Internal(format!(
-                "Source file not found: {}",
-                source_path
-            )));
+    /// Enables deploy-time pre-initialization for functions with `snapshot` set.
+    pub fn with_snapshotter(mut self, snapshotter: Arc<dyn Snapshotter>) -> Self {
+        self.snapshotter = Some(snapshotter);
+        self
+    }
+
+    pub fn with_max_wasm_bytes(mut self, max_wasm_bytes: u64) -> Self {
+        self.max_wasm_bytes = max_wasm_bytes;
+        self
+    }
+
+    /// Largest binary accepted, so uploads can be cut off while streaming.
+    pub fn max_wasm_bytes(&self) -> u64 {
+        self.max_wasm_bytes
+    }
+
+    /// Purges cached gateway responses of functions that get redeployed or removed.
+    pub fn with_response_cache(mut self, response_cache: Arc<ResponseCache>) -> Self {
+        self.response_cache = Some(response_cache);
+        self
+    }
+
+    /// Records deploys, rollbacks and deletions in the audit log.
+    pub fn with_audit_log(mut self, audit: Arc<AuditService>) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    /// Reloads the gateway routes whenever a function changes, so triggers serve
+    /// its current limits and settings.
+    pub fn with_invocation_service(mut self, invocation_service: Arc<InvocationService>) -> Self {
+        self.invocation_service = Some(invocation_service);
+        self
+    }
+
+    async fn refresh_routes(&self) {
+        if let Some(invocation_service) = &self.invocation_service
+            && let Err(e) = invocation_service.load_routes().await
+        {
+            warn!("Failed to refresh routes: {}", e);
+        }
+    }
+
+    async fn audit(
+        &self,
+        actor: &Actor,
+        action: AuditAction,
+        name: &str,
+        before: Option<&Function>,
+        after: Option<&Function>,
+    ) {
+        if let Some(audit) = &self.audit {
+            audit.record(actor, action, name, before, after).await;
         }
+    }
 
-        let file_name = format!("{}.wasm", name);
-        let dest = Path::new(&self.storage_path).join(&file_name);
+    fn purge_cached_responses(&self, name: &str) {
+        if let Some(cache) = &self.response_cache {
+            let purged = cache.purge_function(name);
+            if purged > 0 {
+                info!("Purged {} cached responses of function {}", purged, name);
+            }
+        }
+    }
 
-        fs::copy(source, &dest)
-            .map_err(|e| DomainError::Internal(format!("Failed to copy Wasm binary: {}", e)))?;
+    /// Path of the binary the runtime should load for `function`: the
+    /// pre-initialized snapshot when one was produced, the upload otherwise.
+    pub fn artifact_path(&self, function: &Function) -> String {
+        if function.snapshot {
+            let snapshot = snapshot_path(&function.executable);
+            if Path::new(&snapshot).exists() {
+                return snapshot;
+            }
+        }
+        function.executable.clone()
+    }
 
-        info!("Stored Wasm for {} at {:?}", name, dest);
+    async fn snapshot_wasm(&self, function: &Function) -> Result<(), DomainError> {
+        if !function.snapshot {
+            return Ok(());
+        }
+
+        let snapshotter = self.snapshotter.as_ref().ok_or_else(|| {
+            DomainError::ValidationError("Snapshots are not enabled on this server".to_string())
+        })?;
+
+        let dest = snapshot_path(&function.executable);
+        snapshotter
+            .snapshot(&function.executable, &dest)
+            .await
+            .map_err(|e| {
+                DomainError::ValidationError(format!(
+                    "Failed to snapshot function '{}': {:#}",
+                    function.name, e
+                ))
+            })?;
 
-        dest.to_str()
-            .map(|s| s.to_string())
-            .ok_or_else(|| DomainError::Internal("Invalid path encoding".to_string()))
+        info!("Stored snapshot for {} at {}", function.name, dest);
+        Ok(())
     }
 
-    pub async fn create_function(&self, mut function: Function) -> Result<Function, DomainError> {
-        if !function.executable.is_empty() {
-            let new_path = self.store_wasm(&function.name, &function.executable)?;
-            function.executable = new_path;
+    /// Rejects binaries the runtime could not run, before anything is stored.
+    fn validate_wasm(&self, source_path: &str) -> Result<(), DomainError> {
+        let size = fs::metadata(source_path)
+            .map_err(|_| DomainError::Internal(format!("Source file not found: {}", source_path)))?
+            .len();
+        if size > self.max_wasm_bytes {
+            return Err(DomainError::ValidationError(format!(
+                "Wasm binary is {} bytes, above the limit of {} bytes",
+                size, self.max_wasm_bytes
+            )));
         }
 
-        let created = self.repository.save(&function).await?;
+        let bytes = fs::read(source_path)
+            .map_err(|e| DomainError::Internal(format!("Failed to read Wasm binary: {}", e)))?;
+        self.runtime
+            .validate(&bytes)
+            .map_err(|e| DomainError::ValidationError(format!("Invalid Wasm component: {:#}", e)))
+    }
 
-        if !created.executable.is_empty()
-            && let Err(e) = self
-                .runtime
-                .load_function(&created.name, &created.executable)
-            {
-                warn!("Failed to preload wasm: {}", e);
+    /// Deploys a new function. Its binary is loaded from a staging copy before the
+    /// row is saved, so a create that fails leaves nothing behind.
+    pub async fn create_function(
+        &self,
+        mut function: Function,
+        actor: &Actor,
+    ) -> Result<Function, DomainError> {
+        validate_name(&function.name)?;
+        if !function.executable.is_empty() {
+            self.validate_wasm(&function.executable)?;
+        }
+        // A taken name fails before anything is staged
+        if self
+            .repository
+            .find_by_name(&function.name)
+            .await?
+            .is_some()
+        {
+            return Err(DomainError::AlreadyExists(function.name));
+        }
+        let staged = if function.executable.is_empty() {
+            None
+        } else {
+            Some(self.stage(&function).await?)
+        };
+        if staged.is_some() {
+            function.executable = self.wasm_path(&function.name);
+        }
+
+        let created = match self.repository.save(&function).await {
+            Ok(created) => created,
+            Err(e) => {
+                if let Some(staged) = &staged {
+                    self.unload_staged(staged);
+                }
+                return Err(e);
             }
+        };
+        if let Err(e) = self.persist(staged.as_ref(), None) {
+            if let Err(e) = self.repository.delete(&created.name).await {
+                warn!("Failed to roll back function {}: {}", created.name, e);
+            }
+            if let Some(staged) = &staged {
+                self.remove_artifacts(&created.executable);
+                self.unload_staged(staged);
+            }
+            return Err(e);
+        }
+        self.runtime.set_env(&created.name, &created.env);
 
+        self.refresh_routes().await;
+        self.audit(
+            actor,
+            AuditAction::FunctionCreate,
+            &created.name,
+            None,
+            Some(&created),
+        )
+        .await;
         Ok(created)
     }
 
-    pub async fn update_function(&self, mut function: Function) -> Result<Function, DomainError> {
+    /// Where the definition and binary replaced by the last update are kept.
+    fn previous_paths(&self, name: &str) -> (PathBuf, PathBuf) {
+        let dir = Path::new(&self.storage_path);
+        (
+            dir.join(format!("{}.previous.json", name)),
+            dir.join(format!("{}.previous.wasm", name)),
+        )
+    }
+
+    fn keep_previous(&self, current: &Function) -> Result<(), DomainError> {
+        let (definition, wasm) = self.previous_paths(&current.name);
+        if !current.executable.is_empty() && Path::new(&current.executable).exists() {
+            fs::copy(&current.executable, &wasm).map_err(|e| {
+                DomainError::Internal(format!("Failed to keep previous Wasm binary: {}", e))
+            })?;
+        }
+        let json = serde_json::to_vec(current).map_err(|e| DomainError::Internal(e.to_string()))?;
+        fs::write(&definition, json).map_err(|e| {
+            DomainError::Internal(format!("Failed to keep previous definition: {}", e))
+        })
+    }
+
+    pub async fn update_function(
+        &self,
+        function: Function,
+        actor: &Actor,
+    ) -> Result<Function, DomainError> {
+        let (current, updated) = self.replace(function).await?;
+        self.audit(
+            actor,
+            AuditAction::FunctionUpdate,
+            &updated.name,
+            current.as_ref(),
+            Some(&updated),
+        )
+        .await;
+        Ok(updated)
+    }
+
+    /// Deploys `function` over the current version, which is returned along with
+    /// the update.
+    ///
+    /// A new binary is loaded from a staging copy under a staging name first and
+    /// only serves once stored, so a version that fails to load leaves the
+    /// running version, the stored binary, the previous version and the row as
+    /// they were.
+    async fn replace(
+        &self,
+        mut function: Function,
+    ) -> Result<(Option<Function>, Function), DomainError> {
+        validate_name(&function.name)?;
         if !function.executable.is_empty() {
-            let new_path = self.store_wasm(&function.name, &function.executable)?;
-            function.executable = new_path;
+            self.validate_wasm(&function.executable)?;
+        }
+        let current = self.repository.find_by_name(&function.name).await?;
+        let staged = if function.executable.is_empty() {
+            None
+        } else {
+            Some(self.stage(&function).await?)
+        };
+        if staged.is_some() {
+            function.executable = self.wasm_path(&function.name);
         }
 
-        let updated = self.repository.update(&function).await?;
-        if !updated.executable.is_empty()
-            && let Err(e) = self
-                .runtime
-                .load_function(&updated.name, &updated.executable)
+        let updated = match self.repository.update(&function).await {
+            Ok(updated) => updated,
+            Err(e) => {
+                if let Some(staged) = &staged {
+                    self.unload_staged(staged);
+                }
+                return Err(e);
+            }
+        };
+        if let Err(e) = self.persist(staged.as_ref(), current.as_ref()) {
+            // Put the row back so it matches the binary still on disk
+            if let Some(current) = &current
+                && let Err(e) = self.repository.update(current).await
             {
-                warn!("Failed to reload wasm: {}", e);
+                warn!("Failed to roll back function {}: {}", current.name, e);
             }
-        Ok(updated)
+            if let Some(staged) = &staged {
+                self.unload_staged(staged);
+            }
+            return Err(e);
+        }
+
+        self.runtime.set_env(&updated.name, &updated.env);
+        self.refresh_routes().await;
+        self.purge_cached_responses(&updated.name);
+        Ok((current, updated))
+    }
+
+    fn wasm_path(&self, name: &str) -> String {
+        Path::new(&self.storage_path)
+            .join(format!("{}.wasm", name))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn staging_path(&self, name: &str) -> String {
+        Path::new(&self.storage_path)
+            .join(format!("{}.staging.wasm", name))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Copies the binary of `function` to a staging path and loads it from there
+    /// under a staging name, so the running version serves until the staged one
+    /// is stored. Returns the function as staged.
+    async fn stage(&self, function: &Function) -> Result<Function, DomainError> {
+        let staging = self.staging_path(&function.name);
+        fs::copy(&function.executable, &staging)
+            .map_err(|e| DomainError::Internal(format!("Failed to copy Wasm binary: {}", e)))?;
+        let staged = Function {
+            executable: staging,
+            ..function.clone()
+        };
+
+        let name = staging_name(&staged.name);
+        let loaded = async {
+            self.snapshot_wasm(&staged).await?;
+            self.runtime.set_env(&name, &staged.env);
+            self.runtime
+                .load_function(&name, &self.artifact_path(&staged))
+                .await
+                .map_err(|e| load_error(&staged.name, e))
+        }
+        .await;
+        if let Err(e) = loaded {
+            self.unload_staged(&staged);
+            return Err(e);
+        }
+        Ok(staged)
+    }
+
+    /// Keeps `current` as the previous version and puts the staged binary in
+    /// place of the stored one.
+    fn persist(
+        &self,
+        staged: Option<&Function>,
+        current: Option<&Function>,
+    ) -> Result<(), DomainError> {
+        if let Some(current) = current {
+            self.keep_previous(current)?;
+        }
+        match staged {
+            Some(staged) => self.store_staged(staged),
+            None => Ok(()),
+        }
+    }
+
+    /// Moves the staged binary in place of the stored one, and has the runtime
+    /// serve it.
+    fn store_staged(&self, staged: &Function) -> Result<(), DomainError> {
+        // Compiled code follows the artifact it was loaded from, so a restart reuses it
+        let wasm = self.wasm_path(&staged.name);
+        let staged_snapshot = snapshot_path(&staged.executable);
+        if Path::new(&staged_snapshot).exists() {
+            fs::rename(&staged_snapshot, snapshot_path(&wasm))
+                .map_err(|e| DomainError::Internal(format!("Failed to store snapshot: {}", e)))?;
+            self.runtime
+                .move_compiled(&staged_snapshot, &snapshot_path(&wasm));
+        }
+        fs::rename(&staged.executable, &wasm)
+            .map_err(|e| DomainError::Internal(format!("Failed to store Wasm binary: {}", e)))?;
+        self.runtime.move_compiled(&staged.executable, &wasm);
+        self.runtime
+            .rename_function(&staging_name(&staged.name), &staged.name);
+        info!("Stored Wasm for {} at {}", staged.name, wasm);
+        Ok(())
+    }
+
+    /// Deletes a binary, its snapshot and the code compiled from them.
+    fn remove_artifacts(&self, executable: &str) {
+        let artifacts = [executable.to_string(), snapshot_path(executable)];
+        for artifact in &artifacts {
+            let _ = fs::remove_file(artifact);
+        }
+        self.runtime.remove_compiled(&artifacts);
+    }
+
+    /// Drops a staged version that won't be stored.
+    fn unload_staged(&self, staged: &Function) {
+        self.remove_artifacts(&staged.executable);
+        self.runtime
+            .unload_function(&staging_name(&staged.name), &[]);
+    }
+
+    /// Validates `function` and loads its binary under a staging name, leaving
+    /// the stored and running versions alone. Once the returned function is
+    /// stored, `install` puts it in service; `discard` drops it otherwise.
+    pub async fn prepare(&self, mut function: Function) -> Result<PreparedFunction, DomainError> {
+        validate_name(&function.name)?;
+        if !function.executable.is_empty() {
+            self.validate_wasm(&function.executable)?;
+        }
+        let staged = if function.executable.is_empty() {
+            None
+        } else {
+            Some(self.stage(&function).await?)
+        };
+        if staged.is_some() {
+            function.executable = self.wasm_path(&function.name);
+        }
+        Ok(PreparedFunction { function, staged })
+    }
+
+    /// Puts a prepared function in service in place of `current`, now that its
+    /// row is stored. Only renames within the storage directory are left that
+    /// could fail; if one does, the running version keeps serving and the error
+    /// is logged, since the stored row can't be taken back.
+    pub fn install(&self, prepared: PreparedFunction, current: Option<&Function>) {
+        let function = &prepared.function;
+        if let Some(current) = current
+            && let Err(e) = self.keep_previous(current)
+        {
+            warn!(
+                "Failed to keep the previous version of {}: {}",
+                current.name, e
+            );
+        }
+        if let Some(staged) = &prepared.staged
+            && let Err(e) = self.store_staged(staged)
+        {
+            error!("Failed to install function {}: {}", function.name, e);
+            self.unload_staged(staged);
+            return;
+        }
+        self.runtime.set_env(&function.name, &function.env);
+        self.purge_cached_responses(&function.name);
+    }
+
+    /// Drops a prepared function that won't be stored.
+    pub fn discard(&self, prepared: PreparedFunction) {
+        if let Some(staged) = &prepared.staged {
+            self.unload_staged(staged);
+        }
+    }
+
+    /// Restores the definition and binary the last update replaced. The
+    /// replaced version becomes the previous one, so rolling back twice
+    /// returns to where it started.
+    pub async fn rollback_function(
+        &self,
+        name: &str,
+        actor: &Actor,
+    ) -> Result<Function, DomainError> {
+        validate_name(name)?;
+        let (definition, wasm) = self.previous_paths(name);
+        let json = fs::read(&definition).map_err(|_| {
+            DomainError::NotFound(format!("Function '{}' has no previous version", name))
+        })?;
+        let mut previous: Function =
+            serde_json::from_slice(&json).map_err(|e| DomainError::Internal(e.to_string()))?;
+        if previous.executable.is_empty() {
+            let (current, restored) = self.replace(previous).await?;
+            self.audit(
+                actor,
+                AuditAction::FunctionRollback,
+                name,
+                current.as_ref(),
+                Some(&restored),
+            )
+            .await;
+            return Ok(restored);
+        }
+
+        // The update keeps the binary it replaces at `wasm`, so restore from a copy;
+        // `wasm` stays untouched if the rollback fails
+        let restoring = Path::new(&self.storage_path).join(format!("{}.restoring.wasm", name));
+        fs::copy(&wasm, &restoring).map_err(|e| {
+            DomainError::Internal(format!("Failed to restore previous Wasm binary: {}", e))
+        })?;
+        previous.executable = restoring.to_string_lossy().into_owned();
+        let result = self.replace(previous).await;
+        let _ = fs::remove_file(&restoring);
+        let (current, restored) = result?;
+        info!("Rolled back function {}", name);
+        self.audit(
+            actor,
+            AuditAction::FunctionRollback,
+            name,
+            current.as_ref(),
+            Some(&restored),
+        )
+        .await;
+        Ok(restored)
     }
 
     pub async fn list_functions(&self) -> Result<Vec<Function>, DomainError> {
@@ -97,16 +534,76 @@ impl FunctionService {
             .ok_or_else(|| DomainError::NotFound(name.to_string()))
     }
 
-    pub async fn delete_function(&self, name: &str) -> Result<(), DomainError> {
-        self.repository.delete(name).await
+    pub async fn delete_function(&self, name: &str, actor: &Actor) -> Result<(), DomainError> {
+        let current = match &self.audit {
+            Some(_) => self.repository.find_by_name(name).await?,
+            None => None,
+        };
+        self.repository.delete(name).await?;
+        self.uninstall(name);
+        self.refresh_routes().await;
+        self.audit(
+            actor,
+            AuditAction::FunctionDelete,
+            name,
+            current.as_ref(),
+            None,
+        )
+        .await;
+        Ok(())
     }
+
+    /// Removes what a deleted function leaves besides its row: its binaries,
+    /// previous version, compiled code and cached responses.
+    pub fn uninstall(&self, name: &str) {
+        let (definition, wasm) = self.previous_paths(name);
+        let _ = fs::remove_file(definition);
+        let _ = fs::remove_file(wasm);
+
+        let artifacts: Vec<String> = [self.wasm_path(name), self.staging_path(name)]
+            .into_iter()
+            .flat_map(|path| [snapshot_path(&path), path])
+            .collect();
+        for artifact in &artifacts {
+            let _ = fs::remove_file(artifact);
+        }
+        self.runtime.unload_function(name, &artifacts);
+        self.purge_cached_responses(name);
+    }
+}
+
+/// Name a version is loaded under while staged, which no function can have.
+fn staging_name(name: &str) -> String {
+    format!("{}.staging", name)
+}
+
+/// Names become file names in the storage directory, where a dot could make one
+/// function's binary collide with another's snapshot or previous version.
+fn validate_name(name: &str) -> Result<(), DomainError> {
+    if name.is_empty() || name.contains(['.', '/', '\\']) {
+        return Err(DomainError::ValidationError(format!(
+            "Invalid function name '{}': it must not be empty or contain '.', '/' or '\\'",
+            name
+        )));
+    }
+    Ok(())
+}
+
+fn snapshot_path(executable: &str) -> String {
+    let stem = executable.strip_suffix(".wasm").unwrap_or(executable);
+    format!("{}.snapshot.wasm", stem)
+}
+
+fn load_error(name: &str, e: anyhow::Error) -> DomainError {
+    DomainError::ValidationError(format!("Failed to load function '{}': {:#}", name, e))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::domain::ports::MockFunctionRepository;
-    use crate::domain::wasm_runtime::MockWasmRuntime;
+    use crate::domain::entities::AuditEntry;
+    use crate::domain::ports::{MockAuditRepository, MockFunctionRepository};
+    use crate::domain::wasm_runtime::{MockSnapshotter, MockWasmRuntime};
     use mockall::predicate::*;
     use tempfile::tempdir;
 
@@ -114,14 +611,29 @@ mod tests {
     async fn test_create_function() {
         let mut repo = MockFunctionRepository::new();
         let mut runtime = MockWasmRuntime::new();
+        runtime.expect_validate().returning(|_| Ok(()));
+        runtime.expect_set_env().returning(|_, _| ());
         let temp_dir = tempdir().unwrap();
         let storage_path = temp_dir.path().to_str().unwrap().to_string();
 
+        repo.expect_find_by_name().returning(|_| Ok(None));
         repo.expect_save()
             .with(always())
             .returning(|f| Ok(f.clone()));
 
-        runtime.expect_load_function().returning(|_, _| Ok(()));
+        // Loaded under a staging name, and served once stored
+        runtime
+            .expect_load_function()
+            .withf(|name, path| {
+                name == "test-func.staging" && path.ends_with("test-func.staging.wasm")
+            })
+            .returning(|_, _| Ok(()));
+        runtime.expect_move_compiled().returning(|_, _| ());
+        runtime
+            .expect_rename_function()
+            .with(eq("test-func.staging"), eq("test-func"))
+            .times(1)
+            .returning(|_, _| ());
 
         let service = FunctionService::new(Arc::new(repo), Arc::new(runtime), storage_path.clone());
 
@@ -135,13 +647,270 @@ mod tests {
             ..Default::default()
         };
 
-        let result = service.create_function(function).await;
+        let result = service.create_function(function, &Actor::system()).await;
 
         assert!(result.is_ok());
         let created = result.unwrap();
         assert!(created.executable.starts_with(&storage_path));
         assert!(Path::new(&created.executable).exists());
+        assert!(!temp_dir.path().join("test-func.staging.wasm").exists());
+    }
+
+    #[tokio::test]
+    async fn test_create_function_init_failure() {
+        // Nothing may be saved: the repository mock only answers the name check
+        let mut repo = MockFunctionRepository::new();
+        let mut runtime = MockWasmRuntime::new();
+        runtime.expect_validate().returning(|_| Ok(()));
+        runtime.expect_set_env().returning(|_, _| ());
+        let temp_dir = tempdir().unwrap();
+        let storage_path = temp_dir.path().join("store");
+
+        repo.expect_find_by_name().returning(|_| Ok(None));
+
+        runtime
+            .expect_load_function()
+            .returning(|_, _| Err(anyhow::anyhow!("init failed: missing config")));
+        runtime
+            .expect_remove_compiled()
+            .withf(|paths| paths[0].ends_with("test-func.staging.wasm"))
+            .times(1)
+            .returning(|_| ());
+        runtime
+            .expect_unload_function()
+            .withf(|name, _| name == "test-func.staging")
+            .times(1)
+            .returning(|_, _| ());
+
+        let service = FunctionService::new(
+            Arc::new(repo),
+            Arc::new(runtime),
+            storage_path.to_str().unwrap().to_string(),
+        );
+
+        let source_file = temp_dir.path().join("test.wasm");
+        fs::write(&source_file, "dummy content").unwrap();
+
+        let function = Function {
+            name: "test-func".to_string(),
+            executable: source_file.to_str().unwrap().to_string(),
+            ..Default::default()
+        };
+
+        let result = service.create_function(function, &Actor::system()).await;
+        match result {
+            Err(DomainError::ValidationError(msg)) => assert!(msg.contains("missing config")),
+            other => panic!("expected load error, got {:?}", other),
+        }
+        assert_eq!(fs::read_dir(&storage_path).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_create_function_existing_name_keeps_live_binary() {
+        let temp_dir = tempdir().unwrap();
+        let storage_path = temp_dir.path().join("store");
+        fs::create_dir_all(&storage_path).unwrap();
+        let stored_wasm = storage_path.join("test-func.wasm");
+        fs::write(&stored_wasm, "v1").unwrap();
+        let current = Function {
+            name: "test-func".to_string(),
+            executable: stored_wasm.to_str().unwrap().to_string(),
+            ..Default::default()
+        };
+
+        // Nothing is loaded or saved: neither mock expects it
+        let mut repo = MockFunctionRepository::new();
+        repo.expect_find_by_name()
+            .returning(move |_| Ok(Some(current.clone())));
+        let mut runtime = MockWasmRuntime::new();
+        runtime.expect_validate().returning(|_| Ok(()));
+        let service = FunctionService::new(
+            Arc::new(repo),
+            Arc::new(runtime),
+            storage_path.to_str().unwrap().to_string(),
+        );
+
+        let source = temp_dir.path().join("upload.wasm");
+        fs::write(&source, "v2").unwrap();
+        let function = Function {
+            name: "test-func".to_string(),
+            executable: source.to_str().unwrap().to_string(),
+            ..Default::default()
+        };
+        let result = service.create_function(function, &Actor::system()).await;
+        assert!(matches!(result, Err(DomainError::AlreadyExists(_))));
+        assert_eq!(fs::read_to_string(&stored_wasm).unwrap(), "v1");
+        assert_eq!(fs::read_dir(&storage_path).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_create_function_loads_snapshot() {
+        let mut repo = MockFunctionRepository::new();
+        let mut runtime = MockWasmRuntime::new();
+        runtime.expect_validate().returning(|_| Ok(()));
+        runtime.expect_set_env().returning(|_, _| ());
+        let mut snapshotter = MockSnapshotter::new();
+        let temp_dir = tempdir().unwrap();
+        let storage_path = temp_dir.path().to_str().unwrap().to_string();
+
+        repo.expect_find_by_name().returning(|_| Ok(None));
+        repo.expect_save().returning(|f| Ok(f.clone()));
+
+        snapshotter
+            .expect_snapshot()
+            .times(1)
+            .returning(|input, output| {
+                assert!(input.ends_with("test-func.staging.wasm"));
+                fs::write(output, "pre-initialized").unwrap();
+                Ok(())
+            });
+
+        runtime
+            .expect_load_function()
+            .withf(|name, path| {
+                name == "test-func.staging" && path.ends_with("test-func.staging.snapshot.wasm")
+            })
+            .times(1)
+            .returning(|_, _| Ok(()));
+        runtime.expect_move_compiled().times(2).returning(|_, _| ());
+        runtime
+            .expect_rename_function()
+            .times(1)
+            .returning(|_, _| ());
+
+        let service = FunctionService::new(Arc::new(repo), Arc::new(runtime), storage_path)
+            .with_snapshotter(Arc::new(snapshotter));
+
+        let source_file = temp_dir.path().join("test.wasm");
+        fs::write(&source_file, "dummy content").unwrap();
+
+        let function = Function {
+            name: "test-func".to_string(),
+            executable: source_file.to_str().unwrap().to_string(),
+            snapshot: true,
+            ..Default::default()
+        };
+
+        let created = service
+            .create_function(function, &Actor::system())
+            .await
+            .unwrap();
+        assert!(created.executable.ends_with("test-func.wasm"));
+        let snapshot = service.artifact_path(&created);
+        assert!(snapshot.ends_with("test-func.snapshot.wasm"));
+        assert_eq!(fs::read_to_string(snapshot).unwrap(), "pre-initialized");
+    }
+
+    #[tokio::test]
+    async fn test_create_function_snapshot_disabled() {
+        let mut repo = MockFunctionRepository::new();
+        repo.expect_find_by_name().returning(|_| Ok(None));
+        let mut runtime = MockWasmRuntime::new();
+        runtime.expect_validate().returning(|_| Ok(()));
+        runtime.expect_set_env().returning(|_, _| ());
+        runtime.expect_remove_compiled().returning(|_| ());
+        runtime.expect_unload_function().returning(|_, _| ());
+        let temp_dir = tempdir().unwrap();
+        let storage_path = temp_dir.path().to_str().unwrap().to_string();
+
+        let service = FunctionService::new(Arc::new(repo), Arc::new(runtime), storage_path);
+
+        let source_file = temp_dir.path().join("test.wasm");
+        fs::write(&source_file, "dummy content").unwrap();
+
+        let function = Function {
+            name: "test-func".to_string(),
+            executable: source_file.to_str().unwrap().to_string(),
+            snapshot: true,
+            ..Default::default()
+        };
+
+        let result = service.create_function(function, &Actor::system()).await;
+        assert!(matches!(result, Err(DomainError::ValidationError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_create_function_rejects_invalid_component() {
+        // Nothing may be saved: the repository mock has no expectations
+        let repo = MockFunctionRepository::new();
+        let mut runtime = MockWasmRuntime::new();
+        runtime
+            .expect_validate()
+            .returning(|_| Err(anyhow::anyhow!("binary is not a WebAssembly component")));
+        let temp_dir = tempdir().unwrap();
+        let storage_path = temp_dir.path().join("store");
+
+        let service = FunctionService::new(
+            Arc::new(repo),
+            Arc::new(runtime),
+            storage_path.to_str().unwrap().to_string(),
+        );
+
+        let source_file = temp_dir.path().join("test.wasm");
+        fs::write(&source_file, "dummy content").unwrap();
+
+        let function = Function {
+            name: "test-func".to_string(),
+            executable: source_file.to_str().unwrap().to_string(),
+            ..Default::default()
+        };
+
+        match service.create_function(function, &Actor::system()).await {
+            Err(DomainError::ValidationError(msg)) => {
+                assert!(msg.contains("not a WebAssembly component"))
+            }
+            other => panic!("expected validation error, got {:?}", other.map(|f| f.name)),
+        }
+        assert!(!storage_path.join("test-func.wasm").exists());
     }
+
+    #[tokio::test]
+    async fn test_create_function_too_large() {
+        let repo = MockFunctionRepository::new();
+        let runtime = MockWasmRuntime::new();
+        let temp_dir = tempdir().unwrap();
+        let storage_path = temp_dir.path().to_str().unwrap().to_string();
+
+        let service = FunctionService::new(Arc::new(repo), Arc::new(runtime), storage_path)
+            .with_max_wasm_bytes(4);
+
+        let source_file = temp_dir.path().join("test.wasm");
+        fs::write(&source_file, "dummy content").unwrap();
+
+        let function = Function {
+            name: "test-func".to_string(),
+            executable: source_file.to_str().unwrap().to_string(),
+            ..Default::default()
+        };
+
+        let result = service.create_function(function, &Actor::system()).await;
+        assert!(matches!(result, Err(DomainError::ValidationError(msg)) if msg.contains("limit")));
+    }
+
+    #[tokio::test]
+    async fn test_create_function_rejects_invalid_name() {
+        // Nothing may be saved: the repository mock has no expectations
+        let repo = MockFunctionRepository::new();
+        let runtime = MockWasmRuntime::new();
+        let temp_dir = tempdir().unwrap();
+        let storage_path = temp_dir.path().to_str().unwrap().to_string();
+
+        let service = FunctionService::new(Arc::new(repo), Arc::new(runtime), storage_path);
+
+        for name in ["", "foo.snapshot", "../foo", "foo\\bar"] {
+            let function = Function {
+                name: name.to_string(),
+                ..Default::default()
+            };
+            let result = service.create_function(function, &Actor::system()).await;
+            assert!(
+                matches!(result, Err(DomainError::ValidationError(_))),
+                "{}",
+                name
+            );
+        }
+    }
+
     #[tokio::test]
     async fn test_get_function_found() {
         let mut repo = MockFunctionRepository::new();
@@ -213,13 +982,29 @@ mod tests {
     async fn test_update_function() {
         let mut repo = MockFunctionRepository::new();
         let mut runtime = MockWasmRuntime::new();
+        runtime.expect_validate().returning(|_| Ok(()));
+        runtime.expect_set_env().returning(|_, _| ());
         let temp_dir = tempdir().unwrap();
         let storage_path = temp_dir.path().to_str().unwrap().to_string();
 
+        repo.expect_find_by_name().returning(|_| Ok(None));
         repo.expect_update().returning(|f| Ok(f.clone()));
 
         // Expect reload if executable present
         runtime.expect_load_function().returning(|_, _| Ok(()));
+        runtime
+            .expect_rename_function()
+            .with(eq("test-func.staging"), eq("test-func"))
+            .times(1)
+            .returning(|_, _| ());
+        // Code compiled from the staging copy is kept for the stored binary
+        runtime
+            .expect_move_compiled()
+            .withf(|from, to| {
+                from.ends_with("test-func.staging.wasm") && to.ends_with("test-func.wasm")
+            })
+            .times(1)
+            .returning(|_, _| ());
 
         // Create dummy file for update
         let source_file = temp_dir.path().join("update.wasm");
@@ -233,24 +1018,241 @@ mod tests {
             ..Default::default()
         };
 
-        let result = service.update_function(function).await;
+        let result = service.update_function(function, &Actor::system()).await;
         assert!(result.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_update_function_load_failure_keeps_current() {
+        let temp_dir = tempdir().unwrap();
+        let storage_path = temp_dir.path().join("store");
+        fs::create_dir_all(&storage_path).unwrap();
+        let stored_wasm = storage_path.join("test-func.wasm");
+        fs::write(&stored_wasm, "v1").unwrap();
+        let current = Function {
+            name: "test-func".to_string(),
+            executable: stored_wasm.to_str().unwrap().to_string(),
+            ..Default::default()
+        };
+
+        // The row must not be touched: the mock has no `update` expectation
+        let mut repo = MockFunctionRepository::new();
+        repo.expect_find_by_name()
+            .returning(move |_| Ok(Some(current.clone())));
+        let mut runtime = MockWasmRuntime::new();
+        runtime.expect_validate().returning(|_| Ok(()));
+        runtime.expect_set_env().returning(|_, _| ());
+        // The running version is never touched, so nothing is reloaded or renamed
+        runtime
+            .expect_load_function()
+            .withf(|name, path| {
+                name == "test-func.staging" && path.ends_with("test-func.staging.wasm")
+            })
+            .times(1)
+            .returning(|_, _| Err(anyhow::anyhow!("init failed: missing config")));
+        runtime
+            .expect_remove_compiled()
+            .withf(|paths| paths[0].ends_with("test-func.staging.wasm"))
+            .times(1)
+            .returning(|_| ());
+        runtime
+            .expect_unload_function()
+            .withf(|name, _| name == "test-func.staging")
+            .times(1)
+            .returning(|_, _| ());
+
+        let service = FunctionService::new(
+            Arc::new(repo),
+            Arc::new(runtime),
+            storage_path.to_str().unwrap().to_string(),
+        );
+        let source = temp_dir.path().join("upload.wasm");
+        fs::write(&source, "v2").unwrap();
+        let function = Function {
+            name: "test-func".to_string(),
+            executable: source.to_str().unwrap().to_string(),
+            ..Default::default()
+        };
+
+        let result = service.update_function(function, &Actor::system()).await;
+        assert!(
+            matches!(result, Err(DomainError::ValidationError(msg)) if msg.contains("missing config"))
+        );
+        assert_eq!(fs::read_to_string(&stored_wasm).unwrap(), "v1");
+        assert_eq!(fs::read_dir(&storage_path).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rollback_function() {
+        let stored: Arc<std::sync::Mutex<Option<Function>>> = Default::default();
+        let mut repo = MockFunctionRepository::new();
+        let current = stored.clone();
+        repo.expect_find_by_name()
+            .returning(move |_| Ok(current.lock().unwrap().clone()));
+        let current = stored.clone();
+        repo.expect_update().returning(move |f| {
+            *current.lock().unwrap() = Some(f.clone());
+            Ok(f.clone())
+        });
+        let mut runtime = MockWasmRuntime::new();
+        runtime.expect_validate().returning(|_| Ok(()));
+        runtime.expect_set_env().returning(|_, _| ());
+        runtime.expect_load_function().returning(|_, _| Ok(()));
+        runtime.expect_move_compiled().returning(|_, _| ());
+        runtime.expect_rename_function().returning(|_, _| ());
+        let recorded: Arc<std::sync::Mutex<Vec<AuditEntry>>> = Default::default();
+        let mut audit_repo = MockAuditRepository::new();
+        let entries = recorded.clone();
+        audit_repo
+            .expect_append_audit_entry()
+            .returning(move |entry| {
+                entries.lock().unwrap().push(entry.clone());
+                Ok(entry.clone())
+            });
+        let temp_dir = tempdir().unwrap();
+        let storage_path = temp_dir.path().join("store");
+        let service = FunctionService::new(
+            Arc::new(repo),
+            Arc::new(runtime),
+            storage_path.to_str().unwrap().to_string(),
+        )
+        .with_audit_log(Arc::new(AuditService::new(Arc::new(audit_repo))));
+
+        assert!(matches!(
+            service
+                .rollback_function("test-func", &Actor::system())
+                .await,
+            Err(DomainError::NotFound(_))
+        ));
+
+        let deploy = |content: &str, memory: &str| {
+            let source = temp_dir.path().join("upload.wasm");
+            fs::write(&source, content).unwrap();
+            Function {
+                name: "test-func".to_string(),
+                executable: source.to_str().unwrap().to_string(),
+                memory: memory.to_string(),
+                ..Default::default()
+            }
+        };
+        service
+            .update_function(deploy("v1", "128"), &Actor::system())
+            .await
+            .unwrap();
+        service
+            .update_function(deploy("v2", "256"), &Actor::system())
+            .await
+            .unwrap();
+
+        let stored_wasm = storage_path.join("test-func.wasm");
+        let restored = service
+            .rollback_function("test-func", &Actor::system())
+            .await
+            .unwrap();
+        assert_eq!(restored.memory, "128");
+        assert_eq!(fs::read_to_string(&stored_wasm).unwrap(), "v1");
+
+        let restored = service
+            .rollback_function("test-func", &Actor::system())
+            .await
+            .unwrap();
+        assert_eq!(restored.memory, "256");
+        assert_eq!(fs::read_to_string(&stored_wasm).unwrap(), "v2");
+
+        // Rolling back records the rollback, not the update it is made of
+        let recorded = recorded.lock().unwrap();
+        let actions: Vec<_> = recorded.iter().map(|e| e.action).collect();
+        assert_eq!(
+            actions,
+            [
+                AuditAction::FunctionUpdate,
+                AuditAction::FunctionUpdate,
+                AuditAction::FunctionRollback,
+                AuditAction::FunctionRollback,
+            ]
+        );
+        assert_eq!(recorded[2].target, "test-func");
+        assert_eq!(recorded[2].actor, "system");
+        assert_eq!(recorded[2].changes["memory"].before, "256");
+        assert_eq!(recorded[2].changes["memory"].after, "128");
+    }
+
+    #[tokio::test]
+    async fn test_rollback_failure_keeps_previous_version() {
+        let temp_dir = tempdir().unwrap();
+        let storage_path = temp_dir.path().join("store");
+        fs::create_dir_all(&storage_path).unwrap();
+        let current = Function {
+            name: "test-func".to_string(),
+            executable: storage_path
+                .join("test-func.wasm")
+                .to_str()
+                .unwrap()
+                .to_string(),
+            ..Default::default()
+        };
+        fs::write(&current.executable, "v2").unwrap();
+        let previous_wasm = storage_path.join("test-func.previous.wasm");
+        fs::write(&previous_wasm, "v1").unwrap();
+        fs::write(
+            storage_path.join("test-func.previous.json"),
+            serde_json::to_vec(&current).unwrap(),
+        )
+        .unwrap();
+
+        let mut repo = MockFunctionRepository::new();
+        repo.expect_find_by_name()
+            .returning(move |_| Ok(Some(current.clone())));
+        let mut runtime = MockWasmRuntime::new();
+        runtime.expect_validate().returning(|_| Ok(()));
+        runtime.expect_set_env().returning(|_, _| ());
+        runtime
+            .expect_load_function()
+            .returning(|_, _| Err(anyhow::anyhow!("init failed")));
+        runtime.expect_remove_compiled().returning(|_| ());
+        runtime.expect_unload_function().returning(|_, _| ());
+        let service = FunctionService::new(
+            Arc::new(repo),
+            Arc::new(runtime),
+            storage_path.to_str().unwrap().to_string(),
+        );
+
+        for _ in 0..2 {
+            let result = service
+                .rollback_function("test-func", &Actor::system())
+                .await;
+            assert!(matches!(result, Err(DomainError::ValidationError(_))));
+            assert_eq!(fs::read_to_string(&previous_wasm).unwrap(), "v1");
+        }
+        assert!(!storage_path.join("test-func.restoring.wasm").exists());
+    }
+
     #[tokio::test]
     async fn test_delete_function() {
         let mut repo = MockFunctionRepository::new();
-        let runtime = MockWasmRuntime::new();
+        let mut runtime = MockWasmRuntime::new();
         let temp_dir = tempdir().unwrap();
         let storage_path = temp_dir.path().to_str().unwrap().to_string();
+        let stored_wasm = temp_dir.path().join("test-func.wasm");
+        fs::write(&stored_wasm, "v1").unwrap();
 
         repo.expect_delete()
             .with(eq("test-func"))
             .returning(|_| Ok(()));
+        runtime
+            .expect_unload_function()
+            .withf(|name, paths| {
+                name == "test-func"
+                    && paths.iter().any(|p| p.ends_with("test-func.wasm"))
+                    && paths.iter().any(|p| p.ends_with("test-func.snapshot.wasm"))
+            })
+            .times(1)
+            .returning(|_, _| ());
 
         let service = FunctionService::new(Arc::new(repo), Arc::new(runtime), storage_path);
-        let result = service.delete_function("test-func").await;
+        let result = service.delete_function("test-func", &Actor::system()).await;
 
         assert!(result.is_ok());
+        assert!(!stored_wasm.exists());
     }
 }
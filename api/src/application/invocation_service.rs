@@ -1,13 +1,25 @@
-use crate::domain::entities::{DomainError, Function};
-use crate::domain::ports::{FunctionRepository, TriggerRepository};
-use crate::domain::wasm_runtime::WasmRuntime;
+use crate::application::admission::AdmissionController;
+use crate::domain::entities::{CachePolicy, DomainError, Function, RateLimit, TriggerKind};
+use crate::domain::ports::{FunctionRepository, TelemetryRecorder, TriggerRepository};
+use crate::domain::wasm_runtime::{FunctionInvoker, WasmRuntime};
 use ahash::RandomState;
+use async_trait::async_trait;
+use opentelemetry::metrics::UpDownCounter;
 use opentelemetry::{KeyValue, global};
-use papaya::HashMap;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
 use std::time::Instant;
+use tokio::sync::Mutex;
 use tracing::{error, info, instrument, warn};
 
+/// Nested function calls allowed below a top-level invocation unless configured otherwise.
+pub const DEFAULT_MAX_CALL_DEPTH: u32 = 8;
+
+tokio::task_local! {
+    /// How many function-to-function calls deep the current invocation is.
+    static CALL_DEPTH: u32;
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 struct RouteKey {
     method: HttpMethod,
@@ -37,14 +49,66 @@ impl From<&str> for HttpMethod {
     }
 }
 
-type RoutesMap = HashMap<RouteKey, Function, RandomState>;
+/// Counts an invocation in `function_active_invocations` until dropped, so calls
+/// abandoned by their caller don't stay counted.
+struct ActiveInvocation {
+    counter: UpDownCounter<i64>,
+    attrs: [KeyValue; 1],
+}
+
+impl ActiveInvocation {
+    fn start(function_name: &str) -> Self {
+        let counter = global::meter("fluor-api")
+            .i64_up_down_counter("function_active_invocations")
+            .build();
+        let attrs = [KeyValue::new("function_name", function_name.to_string())];
+        counter.add(1, &attrs);
+        Self { counter, attrs }
+    }
+}
+
+impl Drop for ActiveInvocation {
+    fn drop(&mut self) {
+        self.counter.add(-1, &self.attrs);
+    }
+}
+
+/// Gateway settings of the trigger serving an HTTP route.
+#[derive(Debug, Clone)]
+pub struct RoutePolicy {
+    pub trigger: String,
+    pub function: String,
+    pub rate_limit: Option<RateLimit>,
+    pub cache: Option<CachePolicy>,
+}
+
+#[derive(Debug, Clone)]
+struct Route {
+    policy: RoutePolicy,
+    function: Function,
+}
+
+/// Routes of every trigger, rebuilt as a whole by `load_routes` so removed
+/// triggers and changed functions never linger.
+#[derive(Default)]
+struct RouteTable {
+    routes: HashMap<RouteKey, Route, RandomState>,
+    socket_routes: HashMap<String, Function, RandomState>,
+    /// Routes of workflow triggers; the policy's `function` names the workflow.
+    workflow_routes: HashMap<RouteKey, RoutePolicy, RandomState>,
+}
 
 #[derive(Clone)]
 pub struct InvocationService {
     trigger_repository: Arc<dyn TriggerRepository>,
     function_repository: Arc<dyn FunctionRepository>,
     runtime: Arc<dyn WasmRuntime>,
-    routes: Arc<RoutesMap>,
+    routes: Arc<RwLock<Arc<RouteTable>>>,
+    /// Keeps concurrent reloads from swapping in an older table last.
+    reload: Arc<Mutex<()>>,
+    admission: Arc<AdmissionController>,
+    max_call_depth: u32,
+    recorder: Option<Arc<dyn TelemetryRecorder>>,
 }
 
 impl InvocationService {
@@ -57,18 +121,64 @@ impl InvocationService {
             trigger_repository,
             function_repository,
             runtime,
-            routes: Arc::new(HashMap::builder().hasher(RandomState::new()).build()),
+            routes: Arc::default(),
+            reload: Arc::default(),
+            admission: Arc::new(AdmissionController::unbounded()),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            recorder: None,
         }
     }
 
+    /// Also records invocations into an in-process telemetry backend.
+    pub fn with_recorder(mut self, recorder: Arc<dyn TelemetryRecorder>) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    pub fn with_max_call_depth(mut self, max_call_depth: u32) -> Self {
+        self.max_call_depth = max_call_depth;
+        self
+    }
+
+    pub fn with_admission(mut self, admission: AdmissionController) -> Self {
+        self.admission = Arc::new(admission);
+        self
+    }
+
+    pub fn admission(&self) -> &AdmissionController {
+        &self.admission
+    }
+
+    fn route_table(&self) -> Arc<RouteTable> {
+        self.routes.read().unwrap().clone()
+    }
+
+    /// Reads every trigger and the function it points to, then replaces the
+    /// routes in one go. Call it whenever a trigger or function changes.
     pub async fn load_routes(&self) -> Result<(), DomainError> {
+        let _reload = self.reload.lock().await;
         let triggers = self.trigger_repository.find_all().await?;
-        let routes = &self.routes;
-
-        let mut count = 0;
-        let pin = routes.pin();
+        let mut table = RouteTable::default();
 
         for t in triggers {
+            if t.kind == TriggerKind::Workflow {
+                let key = RouteKey {
+                    method: HttpMethod::from(t.method.as_str()),
+                    path: t.path,
+                };
+                table.workflow_routes.insert(
+                    key,
+                    RoutePolicy {
+                        trigger: t.name,
+                        function: t.function_name,
+                        rate_limit: t.rate_limit,
+                        // Starting a run is never a cacheable read
+                        cache: None,
+                    },
+                );
+                continue;
+            }
+
             if let Some(mut func) = self
                 .function_repository
                 .find_by_name(&t.function_name)
@@ -76,13 +186,28 @@ impl InvocationService {
             {
                 func.runtime = Some(self.runtime.clone());
 
+                if t.kind == TriggerKind::WebSocket {
+                    table.socket_routes.insert(t.path, func);
+                    continue;
+                }
+
                 let key = RouteKey {
                     method: HttpMethod::from(t.method.as_str()),
                     path: t.path,
                 };
 
-                pin.insert(key, func);
-                count += 1;
+                table.routes.insert(
+                    key,
+                    Route {
+                        policy: RoutePolicy {
+                            trigger: t.name,
+                            function: t.function_name,
+                            rate_limit: t.rate_limit,
+                            cache: t.cache,
+                        },
+                        function: func,
+                    },
+                );
             } else {
                 warn!(
                     "Trigger {} points to missing function {}",
@@ -90,10 +215,43 @@ impl InvocationService {
                 );
             }
         }
-        info!("Loaded {} HTTP routes into memory", count);
+
+        let count = table.routes.len() + table.socket_routes.len() + table.workflow_routes.len();
+        *self.routes.write().unwrap() = Arc::new(table);
+        info!("Loaded {} trigger routes into memory", count);
         Ok(())
     }
 
+    /// Looks up the function bound to a WebSocket trigger on `path`.
+    pub fn resolve_websocket(&self, path: &str) -> Option<Function> {
+        self.route_table().socket_routes.get(path).cloned()
+    }
+
+    pub fn route_policy(&self, method: &str, path: &str) -> Option<RoutePolicy> {
+        let key = RouteKey {
+            method: HttpMethod::from(method),
+            path: path.to_string(),
+        };
+        let table = self.route_table();
+        table
+            .routes
+            .get(&key)
+            .map(|r| r.policy.clone())
+            .or_else(|| table.workflow_routes.get(&key).cloned())
+    }
+
+    /// Looks up the workflow started by requests to `method` `path`.
+    pub fn resolve_workflow(&self, method: &str, path: &str) -> Option<String> {
+        let key = RouteKey {
+            method: HttpMethod::from(method),
+            path: path.to_string(),
+        };
+        self.route_table()
+            .workflow_routes
+            .get(&key)
+            .map(|p| p.function.clone())
+    }
+
     #[instrument(skip(self, body), fields(function_name, function_status))]
     pub async fn invoke_http(
         &self,
@@ -106,58 +264,183 @@ impl InvocationService {
             path: path.to_string(),
         };
 
-        let function = { self.routes.pin().get(&key).cloned() };
-
-        if let Some(func) = function {
-            tracing::Span::current().record("function_name", &func.name);
-
-            if let Some(rt) = &func.runtime {
-                info!(function_name = func.name, "Function {} started", func.name);
-                let start = Instant::now();
-                let result = rt.invoke(&func.name, body).await;
-                let duration_ms = start.elapsed().as_millis() as u64;
-
-                let meter = global::meter("fluor-api");
-                let counter = meter.u64_counter("function_invocations").build();
-                let histogram = meter.u64_histogram("function_duration_ms").build();
-
-                let status = match &result {
-                    Ok(_) => {
-                        info!(
-                            function_name = func.name,
-                            "Function {} exited with status ok", func.name
-                        );
-                        "ok"
-                    }
-                    Err(e) => {
-                        info!(
-                            function_name = func.name,
-                            "Function {} exited with error: {}", func.name, e
-                        );
-                        "error"
-                    }
-                };
-                tracing::Span::current().record("function.status", status);
+        let function = self
+            .route_table()
+            .routes
+            .get(&key)
+            .map(|r| r.function.clone());
+
+        match function {
+            Some(func) => self.invoke_function(&func, body, None).await,
+            None => Err(DomainError::NotFound("Route not found".into())),
+        }
+    }
 
-                let attrs = [
-                    KeyValue::new("function_name", func.name.clone()),
-                    KeyValue::new("status", status.to_string()),
-                ];
+    /// Runs a function outside of any request, e.g. a queued job. `attempt` counts
+    /// from 1 and is recorded on the invocation metrics.
+    #[instrument(skip(self, body), fields(function_name, function_status))]
+    pub async fn invoke_background(
+        &self,
+        name: &str,
+        body: &str,
+        attempt: u32,
+    ) -> Result<String, DomainError> {
+        let mut func = self
+            .function_repository
+            .find_by_name(name)
+            .await?
+            .ok_or_else(|| DomainError::NotFound(format!("Function '{}' not found", name)))?;
+        func.runtime = Some(self.runtime.clone());
+        self.invoke_function(&func, body, Some(attempt)).await
+    }
 
-                counter.add(1, &attrs);
-                histogram.record(duration_ms, &attrs);
+    /// Runs `func` under its concurrency limits, recording metrics for the call.
+    async fn invoke_function(
+        &self,
+        func: &Function,
+        body: &str,
+        attempt: Option<u32>,
+    ) -> Result<String, DomainError> {
+        tracing::Span::current().record("function_name", &func.name);
+        let attempt_attr = attempt.map(|a| KeyValue::new("attempt", a as i64));
 
-                result.map_err(|e| {
-                    error!(error = %e, "Function invocation failed");
-                    DomainError::Internal(e.to_string())
-                })
-            } else {
-                error!("Runtime detached for function {}", func.name);
-                Err(DomainError::Internal("Runtime detached".into()))
+        let Some(rt) = &func.runtime else {
+            error!("Runtime detached for function {}", func.name);
+            return Err(DomainError::Internal("Runtime detached".into()));
+        };
+
+        let _permit = match self.admission.admit(func).await {
+            Ok(permit) => permit,
+            Err(e) => {
+                warn!(function_name = func.name, "Invocation rejected: {}", e);
+                tracing::Span::current().record("function_status", "rejected");
+                global::meter("fluor-api")
+                    .u64_counter("function_invocations")
+                    .build()
+                    .add(
+                        1,
+                        &[
+                            KeyValue::new("function_name", func.name.clone()),
+                            KeyValue::new("status", "rejected"),
+                        ]
+                        .into_iter()
+                        .chain(attempt_attr)
+                        .collect::<Vec<_>>(),
+                    );
+                if let Some(recorder) = &self.recorder {
+                    recorder.record_invocation(&func.name, "rejected", 0);
+                }
+                return Err(e);
             }
-        } else {
-            Err(DomainError::NotFound("Route not found".into()))
+        };
+
+        info!(function_name = func.name, "Function {} started", func.name);
+        let active = ActiveInvocation::start(&func.name);
+        let start = Instant::now();
+        let result = rt.invoke(&func.name, body).await;
+        let duration_ms = start.elapsed().as_millis() as u64;
+        drop(active);
+
+        let meter = global::meter("fluor-api");
+        let counter = meter.u64_counter("function_invocations").build();
+        let histogram = meter.u64_histogram("function_duration_ms").build();
+
+        let status = match &result {
+            Ok(_) => {
+                info!(
+                    function_name = func.name,
+                    "Function {} exited with status ok", func.name
+                );
+                "ok"
+            }
+            Err(e) => {
+                info!(
+                    function_name = func.name,
+                    "Function {} exited with error: {}", func.name, e
+                );
+                "error"
+            }
+        };
+        tracing::Span::current().record("function_status", status);
+
+        let attrs: Vec<KeyValue> = [
+            KeyValue::new("function_name", func.name.clone()),
+            KeyValue::new("status", status.to_string()),
+        ]
+        .into_iter()
+        .chain(attempt_attr)
+        .collect();
+
+        counter.add(1, &attrs);
+        histogram.record(duration_ms, &attrs);
+        if let Some(recorder) = &self.recorder {
+            recorder.record_invocation(&func.name, status, duration_ms);
         }
+
+        result.map_err(|e| {
+            error!(error = %e, "Function invocation failed");
+            DomainError::Internal(e.to_string())
+        })
+    }
+
+    /// Finds a call target by function name, or by the name of an HTTP trigger.
+    async fn resolve_target(&self, target: &str) -> Result<Option<Function>, DomainError> {
+        if let Some(mut func) = self.function_repository.find_by_name(target).await? {
+            func.runtime = Some(self.runtime.clone());
+            return Ok(Some(func));
+        }
+
+        Ok(self
+            .route_table()
+            .routes
+            .values()
+            .find(|r| r.policy.trigger == target)
+            .map(|r| r.function.clone()))
+    }
+}
+
+#[async_trait]
+impl FunctionInvoker for InvocationService {
+    #[instrument(skip(self, input), fields(function_name, function_status))]
+    async fn invoke_from(
+        &self,
+        caller: &str,
+        target: &str,
+        input: &str,
+    ) -> Result<String, DomainError> {
+        let depth = CALL_DEPTH.try_with(|d| *d).unwrap_or(0) + 1;
+        if depth > self.max_call_depth {
+            warn!(caller, target, "Call depth limit reached");
+            return Err(DomainError::LimitExceeded(format!(
+                "Call depth limit of {} reached",
+                self.max_call_depth
+            )));
+        }
+
+        let caller_fn = self
+            .function_repository
+            .find_by_name(caller)
+            .await?
+            .ok_or_else(|| DomainError::NotFound(caller.to_string()))?;
+        if !caller_fn.allowed_calls.iter().any(|t| t == target) {
+            return Err(DomainError::Forbidden(format!(
+                "Function '{}' is not allowed to call '{}'",
+                caller, target
+            )));
+        }
+
+        let func = self
+            .resolve_target(target)
+            .await?
+            .ok_or_else(|| DomainError::NotFound(target.to_string()))?;
+
+        info!(
+            function_name = func.name,
+            "Function {} called by {}", func.name, caller
+        );
+        CALL_DEPTH
+            .scope(depth, self.invoke_function(&func, input, None))
+            .await
     }
 }
 
@@ -181,6 +464,9 @@ mod tests {
             function_name: "test-func".to_string(),
             method: "POST".to_string(),
             path: "/test".to_string(),
+            kind: TriggerKind::Http,
+            rate_limit: None,
+            cache: None,
             readonly: false,
         };
 
@@ -221,6 +507,87 @@ mod tests {
         assert_eq!(result.unwrap(), "response");
     }
 
+    #[tokio::test]
+    async fn test_invoke_rejected_at_concurrency_limit() {
+        let mut trigger_repo = MockTriggerRepository::new();
+        let mut function_repo = MockFunctionRepository::new();
+        let mut runtime = MockWasmRuntime::new();
+
+        trigger_repo.expect_find_all().returning(|| {
+            Ok(vec![Trigger {
+                name: "t".to_string(),
+                function_name: "limited".to_string(),
+                method: "GET".to_string(),
+                path: "/limited".to_string(),
+                kind: TriggerKind::Http,
+                rate_limit: None,
+                cache: None,
+                readonly: false,
+            }])
+        });
+        function_repo.expect_find_by_name().returning(|_| {
+            Ok(Some(Function {
+                name: "limited".to_string(),
+                max_concurrency: Some(0),
+                ..Default::default()
+            }))
+        });
+        runtime.expect_invoke().never();
+
+        let service = InvocationService::new(
+            Arc::new(trigger_repo),
+            Arc::new(function_repo),
+            Arc::new(runtime),
+        );
+        service.load_routes().await.unwrap();
+
+        let result = service.invoke_http("GET", "/limited", "").await;
+        assert!(matches!(result, Err(DomainError::LimitExceeded(_))));
+    }
+
+    #[tokio::test]
+    async fn test_load_routes_drops_removed_triggers() {
+        let triggers: Arc<std::sync::Mutex<Vec<Trigger>>> = Default::default();
+        let mut trigger_repo = MockTriggerRepository::new();
+        let current = triggers.clone();
+        trigger_repo
+            .expect_find_all()
+            .returning(move || Ok(current.lock().unwrap().clone()));
+        let mut function_repo = MockFunctionRepository::new();
+        function_repo.expect_find_by_name().returning(|name| {
+            Ok(Some(Function {
+                name: name.to_string(),
+                ..Default::default()
+            }))
+        });
+
+        let service = InvocationService::new(
+            Arc::new(trigger_repo),
+            Arc::new(function_repo),
+            Arc::new(MockWasmRuntime::new()),
+        );
+        triggers.lock().unwrap().push(Trigger {
+            name: "t".to_string(),
+            function_name: "f".to_string(),
+            method: "GET".to_string(),
+            path: "/t".to_string(),
+            kind: TriggerKind::Http,
+            rate_limit: None,
+            cache: None,
+            readonly: false,
+        });
+        service.load_routes().await.unwrap();
+        assert!(service.route_policy("GET", "/t").is_some());
+
+        triggers.lock().unwrap().clear();
+        service.load_routes().await.unwrap();
+        assert!(service.route_policy("GET", "/t").is_none());
+        assert!(matches!(
+            service.invoke_http("GET", "/t", "").await,
+            Err(DomainError::NotFound(_))
+        ));
+    }
+
     #[tokio::test]
     async fn test_invoke_not_found() {
         let trigger_repo = MockTriggerRepository::new();
@@ -236,4 +603,85 @@ mod tests {
         let result = service.invoke_http("GET", "/unknown", "").await;
         assert!(matches!(result, Err(DomainError::NotFound(_))));
     }
+
+    fn calling_service(runtime: MockWasmRuntime) -> InvocationService {
+        let mut trigger_repo = MockTriggerRepository::new();
+        let mut function_repo = MockFunctionRepository::new();
+
+        trigger_repo.expect_find_all().returning(|| {
+            Ok(vec![Trigger {
+                name: "greet".to_string(),
+                function_name: "greeter".to_string(),
+                method: "POST".to_string(),
+                path: "/greet".to_string(),
+                kind: TriggerKind::Http,
+                rate_limit: None,
+                cache: None,
+                readonly: false,
+            }])
+        });
+        function_repo.expect_find_by_name().returning(|name| {
+            let allowed_calls = match name {
+                "caller" => vec!["greet".to_string()],
+                _ => vec![],
+            };
+            Ok(matches!(name, "caller" | "greeter").then(|| Function {
+                name: name.to_string(),
+                allowed_calls,
+                ..Default::default()
+            }))
+        });
+
+        InvocationService::new(
+            Arc::new(trigger_repo),
+            Arc::new(function_repo),
+            Arc::new(runtime),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_invoke_from_allowed_target() {
+        let mut runtime = MockWasmRuntime::new();
+        runtime
+            .expect_invoke()
+            .with(eq("greeter"), eq("bob"))
+            .times(1)
+            .returning(|_, _| Ok("hello bob".to_string()));
+
+        let service = calling_service(runtime);
+        service.load_routes().await.unwrap();
+
+        // "greet" is the trigger standing for the "greeter" function
+        let result = service.invoke_from("caller", "greet", "bob").await;
+        assert_eq!(result.unwrap(), "hello bob");
+    }
+
+    #[tokio::test]
+    async fn test_invoke_from_forbidden_target() {
+        let mut runtime = MockWasmRuntime::new();
+        runtime.expect_invoke().never();
+
+        let service = calling_service(runtime);
+        service.load_routes().await.unwrap();
+
+        let result = service.invoke_from("caller", "greeter", "bob").await;
+        assert!(matches!(result, Err(DomainError::Forbidden(_))));
+
+        let result = service.invoke_from("greeter", "caller", "bob").await;
+        assert!(matches!(result, Err(DomainError::Forbidden(_))));
+    }
+
+    #[tokio::test]
+    async fn test_invoke_from_depth_limit() {
+        let mut runtime = MockWasmRuntime::new();
+        runtime.expect_invoke().never();
+
+        let service = calling_service(runtime).with_max_call_depth(2);
+        service.load_routes().await.unwrap();
+
+        let result = CALL_DEPTH
+            .scope(2, service.invoke_from("caller", "greet", "bob"))
+            .await;
+        assert!(matches!(result, Err(DomainError::LimitExceeded(_))));
+    }
 }
@@ -0,0 +1,949 @@
+use crate::application::audit_service::AuditService;
+use crate::application::function_service::{FunctionService, PreparedFunction};
+use crate::application::trigger_service::TriggerService;
+use crate::domain::entities::{
+    Actor, AuditAction, DomainError, Function, Language, ManifestWrite, RetryPolicy, Trigger,
+};
+use crate::domain::ports::ManifestRepository;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+/// Desired state of a deployment, usually kept in git as `fluor.yaml` or `fluor.toml`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Manifest {
+    #[serde(default)]
+    pub functions: Vec<FunctionSpec>,
+    #[serde(default)]
+    pub triggers: Vec<Trigger>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FunctionSpec {
+    pub name: String,
+    #[serde(default)]
+    pub language: Language,
+    /// Name of a Wasm binary uploaded with the manifest; the CLI uploads the
+    /// file at this path, relative to the manifest.
+    #[serde(default)]
+    pub artifact: Option<String>,
+    /// SHA-256 of the binary. Checked against `artifact` when both are set; on its
+    /// own it pins the binary already deployed.
+    #[serde(default)]
+    pub sha256: Option<String>,
+    pub cpu: String,
+    pub memory: String,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub snapshot: bool,
+    #[serde(default)]
+    pub max_concurrency: Option<u32>,
+    #[serde(default)]
+    pub max_queue: Option<u32>,
+    #[serde(default)]
+    pub allowed_calls: Vec<String>,
+    #[serde(default)]
+    pub retry: Option<RetryPolicy>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl ManifestFormat {
+    /// Picks the format from a request's `Content-Type`, defaulting to JSON.
+    pub fn from_content_type(content_type: &str) -> Self {
+        if content_type.contains("yaml") {
+            ManifestFormat::Yaml
+        } else if content_type.contains("toml") {
+            ManifestFormat::Toml
+        } else {
+            ManifestFormat::Json
+        }
+    }
+}
+
+impl Manifest {
+    pub fn parse(source: &str, format: ManifestFormat) -> Result<Self, DomainError> {
+        let invalid = |e: String| DomainError::ValidationError(format!("Invalid manifest: {}", e));
+        match format {
+            ManifestFormat::Json => {
+                serde_json::from_str(source).map_err(|e| invalid(e.to_string()))
+            }
+            ManifestFormat::Yaml => {
+                serde_yaml::from_str(source).map_err(|e| invalid(e.to_string()))
+            }
+            ManifestFormat::Toml => toml::from_str(source).map_err(|e| invalid(e.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ResourceKind {
+    Function,
+    Trigger,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeAction {
+    Create,
+    Update,
+    Delete,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Change {
+    pub resource: ResourceKind,
+    pub name: String,
+    pub action: ChangeAction,
+    /// Fields that differ, for updates.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Plan {
+    pub changes: Vec<Change>,
+    pub applied: bool,
+}
+
+enum Op {
+    CreateFunction(Function),
+    UpdateFunction {
+        desired: Function,
+        current: Function,
+        fields: Vec<String>,
+    },
+    DeleteFunction(Function),
+    CreateTrigger(Trigger),
+    UpdateTrigger {
+        desired: Trigger,
+        current: Trigger,
+        fields: Vec<String>,
+    },
+    DeleteTrigger(Trigger),
+}
+
+impl Op {
+    fn change(&self) -> Change {
+        let (resource, name, action, fields) = match self {
+            Op::CreateFunction(f) => (ResourceKind::Function, &f.name, ChangeAction::Create, None),
+            Op::UpdateFunction {
+                desired, fields, ..
+            } => (
+                ResourceKind::Function,
+                &desired.name,
+                ChangeAction::Update,
+                Some(fields),
+            ),
+            Op::DeleteFunction(f) => (ResourceKind::Function, &f.name, ChangeAction::Delete, None),
+            Op::CreateTrigger(t) => (ResourceKind::Trigger, &t.name, ChangeAction::Create, None),
+            Op::UpdateTrigger {
+                desired, fields, ..
+            } => (
+                ResourceKind::Trigger,
+                &desired.name,
+                ChangeAction::Update,
+                Some(fields),
+            ),
+            Op::DeleteTrigger(t) => (ResourceKind::Trigger, &t.name, ChangeAction::Delete, None),
+        };
+        Change {
+            resource,
+            name: name.clone(),
+            action,
+            fields: fields.cloned().unwrap_or_default(),
+        }
+    }
+
+    /// What storing the change writes, with the binaries `prepare` staged.
+    fn writes(&self, prepared: &HashMap<String, PreparedFunction>) -> Vec<ManifestWrite> {
+        let stored = |f: &Function| {
+            prepared
+                .get(&f.name)
+                .map_or_else(|| f.clone(), |p| p.function.clone())
+        };
+        match self {
+            Op::CreateFunction(f) => vec![ManifestWrite::CreateFunction(stored(f))],
+            Op::UpdateFunction { desired, .. } => {
+                vec![ManifestWrite::UpdateFunction(stored(desired))]
+            }
+            Op::DeleteFunction(f) => vec![ManifestWrite::DeleteFunction(f.name.clone())],
+            Op::CreateTrigger(t) => vec![ManifestWrite::CreateTrigger(t.clone())],
+            Op::UpdateTrigger {
+                desired, current, ..
+            } => vec![
+                ManifestWrite::DeleteTrigger(current.name.clone()),
+                ManifestWrite::CreateTrigger(desired.clone()),
+            ],
+            Op::DeleteTrigger(t) => vec![ManifestWrite::DeleteTrigger(t.name.clone())],
+        }
+    }
+
+    /// Order in which changes are applied: functions exist before triggers point at them,
+    /// and triggers are gone before their functions are deleted.
+    fn rank(&self) -> u8 {
+        match self {
+            Op::CreateFunction(_) | Op::UpdateFunction { .. } => 0,
+            Op::DeleteTrigger(_) => 1,
+            Op::UpdateTrigger { .. } => 2,
+            Op::CreateTrigger(_) => 3,
+            Op::DeleteFunction(_) => 4,
+        }
+    }
+}
+
+/// Binaries uploaded along with a manifest, by the `artifact` name functions refer
+/// to them with. Removed when dropped, once the request is done.
+pub struct Artifacts {
+    dir: PathBuf,
+    files: HashMap<String, PathBuf>,
+}
+
+impl Default for Artifacts {
+    fn default() -> Self {
+        Self {
+            dir: std::env::temp_dir().join(format!("fluor-artifacts-{}", uuid::Uuid::new_v4())),
+            files: HashMap::new(),
+        }
+    }
+}
+
+impl Artifacts {
+    /// Returns the path to write the upload named `name` to.
+    pub fn create(&mut self, name: &str) -> Result<PathBuf, DomainError> {
+        fs::create_dir_all(&self.dir).map_err(|e| DomainError::Internal(e.to_string()))?;
+        let path = self.dir.join(format!("{}.wasm", self.files.len()));
+        self.files.insert(name.to_string(), path.clone());
+        Ok(path)
+    }
+
+    fn get(&self, name: &str) -> Option<&Path> {
+        self.files.get(name).map(PathBuf::as_path)
+    }
+}
+
+impl Drop for Artifacts {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Reconciles the stored functions and triggers with a manifest.
+pub struct ManifestService {
+    repository: Arc<dyn ManifestRepository>,
+    function_service: Arc<FunctionService>,
+    trigger_service: Arc<TriggerService>,
+    audit: Option<Arc<AuditService>>,
+    /// One apply at a time, so plans are computed against settled state.
+    apply_lock: Mutex<()>,
+}
+
+impl ManifestService {
+    pub fn new(
+        repository: Arc<dyn ManifestRepository>,
+        function_service: Arc<FunctionService>,
+        trigger_service: Arc<TriggerService>,
+    ) -> Self {
+        Self {
+            repository,
+            function_service,
+            trigger_service,
+            audit: None,
+            apply_lock: Mutex::new(()),
+        }
+    }
+
+    /// Records each successful apply, with its list of changes, in the audit
+    /// log next to an entry for each change.
+    pub fn with_audit_log(mut self, audit: Arc<AuditService>) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    /// Lists the changes `apply` would make, without making them.
+    pub async fn plan(
+        &self,
+        manifest: &Manifest,
+        artifacts: &Artifacts,
+    ) -> Result<Plan, DomainError> {
+        let ops = self.diff(manifest, artifacts).await?;
+        Ok(Plan {
+            changes: ops.iter().map(Op::change).collect(),
+            applied: false,
+        })
+    }
+
+    /// Applies the manifest atomically. New binaries are validated and loaded
+    /// first without serving; then every function and trigger change is stored in
+    /// one transaction, and only once that commits do the new versions serve. A
+    /// change that fails leaves everything as it was.
+    pub async fn apply(
+        &self,
+        manifest: &Manifest,
+        artifacts: &Artifacts,
+        actor: &Actor,
+    ) -> Result<Plan, DomainError> {
+        let _guard = self.apply_lock.lock().await;
+        let ops = self.diff(manifest, artifacts).await?;
+        let changes: Vec<Change> = ops.iter().map(Op::change).collect();
+
+        let mut prepared = HashMap::new();
+        if let Err(e) = self.prepare(&ops, &mut prepared).await {
+            self.discard(prepared);
+            return Err(e);
+        }
+        let writes: Vec<ManifestWrite> = ops.iter().flat_map(|op| op.writes(&prepared)).collect();
+        if let Err(e) = self.repository.apply_manifest(&writes).await {
+            error!("Manifest apply failed, nothing was changed: {}", e);
+            self.discard(prepared);
+            return Err(e);
+        }
+
+        for op in &ops {
+            self.install(op, &mut prepared, actor).await;
+        }
+        self.trigger_service.refresh_routes().await;
+
+        info!("Applied manifest with {} changes", changes.len());
+        let plan = Plan {
+            changes,
+            applied: true,
+        };
+        if let Some(audit) = &self.audit {
+            audit
+                .record(
+                    actor,
+                    AuditAction::ManifestApply,
+                    "manifest",
+                    None,
+                    Some(&plan),
+                )
+                .await;
+        }
+        Ok(plan)
+    }
+
+    /// Loads the binaries of created and updated functions under staging names
+    /// and checks the triggers, without storing or serving anything.
+    async fn prepare(
+        &self,
+        ops: &[Op],
+        prepared: &mut HashMap<String, PreparedFunction>,
+    ) -> Result<(), DomainError> {
+        for op in ops {
+            match op {
+                Op::CreateFunction(f) | Op::UpdateFunction { desired: f, .. } => {
+                    let function = self.function_service.prepare(f.clone()).await?;
+                    prepared.insert(f.name.clone(), function);
+                }
+                Op::CreateTrigger(t) | Op::UpdateTrigger { desired: t, .. } => {
+                    self.trigger_service.validate(t)?;
+                }
+                Op::DeleteFunction(_) | Op::DeleteTrigger(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn discard(&self, prepared: HashMap<String, PreparedFunction>) {
+        for function in prepared.into_values() {
+            self.function_service.discard(function);
+        }
+    }
+
+    /// Puts a stored change in service and records it in the audit log.
+    async fn install(
+        &self,
+        op: &Op,
+        prepared: &mut HashMap<String, PreparedFunction>,
+        actor: &Actor,
+    ) {
+        match op {
+            Op::CreateFunction(f) => {
+                if let Some(function) = prepared.remove(&f.name) {
+                    let stored = function.function.clone();
+                    self.function_service.install(function, None);
+                    self.record(
+                        actor,
+                        AuditAction::FunctionCreate,
+                        &f.name,
+                        None,
+                        Some(&stored),
+                    )
+                    .await;
+                }
+            }
+            Op::UpdateFunction { current, .. } => {
+                if let Some(function) = prepared.remove(&current.name) {
+                    let stored = function.function.clone();
+                    self.function_service.install(function, Some(current));
+                    self.record(
+                        actor,
+                        AuditAction::FunctionUpdate,
+                        &current.name,
+                        Some(current),
+                        Some(&stored),
+                    )
+                    .await;
+                }
+            }
+            Op::DeleteFunction(f) => {
+                self.function_service.uninstall(&f.name);
+                self.record(actor, AuditAction::FunctionDelete, &f.name, Some(f), None)
+                    .await;
+            }
+            Op::CreateTrigger(t) => {
+                self.record(actor, AuditAction::TriggerCreate, &t.name, None, Some(t))
+                    .await;
+            }
+            Op::UpdateTrigger {
+                desired, current, ..
+            } => {
+                self.trigger_service.purge_cached_responses(&current.name);
+                self.record(
+                    actor,
+                    AuditAction::TriggerDelete,
+                    &current.name,
+                    Some(current),
+                    None,
+                )
+                .await;
+                self.record(
+                    actor,
+                    AuditAction::TriggerCreate,
+                    &desired.name,
+                    None,
+                    Some(desired),
+                )
+                .await;
+            }
+            Op::DeleteTrigger(t) => {
+                self.trigger_service.purge_cached_responses(&t.name);
+                self.record(actor, AuditAction::TriggerDelete, &t.name, Some(t), None)
+                    .await;
+            }
+        }
+    }
+
+    async fn record<T: Serialize + Sync>(
+        &self,
+        actor: &Actor,
+        action: AuditAction,
+        target: &str,
+        before: Option<&T>,
+        after: Option<&T>,
+    ) {
+        if let Some(audit) = &self.audit {
+            audit.record(actor, action, target, before, after).await;
+        }
+    }
+
+    /// Largest artifact accepted, the same as for a single deploy.
+    pub fn max_artifact_bytes(&self) -> u64 {
+        self.function_service.max_wasm_bytes()
+    }
+
+    async fn diff(
+        &self,
+        manifest: &Manifest,
+        artifacts: &Artifacts,
+    ) -> Result<Vec<Op>, DomainError> {
+        let invalid = |msg: String| Err(DomainError::ValidationError(msg));
+
+        let mut names = HashSet::new();
+        for f in &manifest.functions {
+            if !names.insert(f.name.as_str()) {
+                return invalid(format!("Function '{}' is listed twice", f.name));
+            }
+        }
+        let mut trigger_names = HashSet::new();
+        for t in &manifest.triggers {
+            if !trigger_names.insert(t.name.as_str()) {
+                return invalid(format!("Trigger '{}' is listed twice", t.name));
+            }
+        }
+
+        let current_functions: HashMap<String, Function> = self
+            .function_service
+            .list_functions()
+            .await?
+            .into_iter()
+            .map(|f| (f.name.clone(), f))
+            .collect();
+        let current_triggers: HashMap<String, Trigger> = self
+            .trigger_service
+            .list_triggers()
+            .await?
+            .into_iter()
+            .map(|t| (t.name.clone(), t))
+            .collect();
+
+        let mut ops = Vec::new();
+
+        for spec in &manifest.functions {
+            let current = current_functions.get(&spec.name);
+            let desired = desired_function(spec, current, artifacts)?;
+            match current {
+                None => ops.push(Op::CreateFunction(desired)),
+                Some(current) => {
+                    let fields = function_changes(current, &desired)?;
+                    if fields.is_empty() {
+                        continue;
+                    }
+                    if current.readonly {
+                        return invalid(format!("Function '{}' is readonly", spec.name));
+                    }
+                    ops.push(Op::UpdateFunction {
+                        desired,
+                        current: current.clone(),
+                        fields,
+                    });
+                }
+            }
+        }
+        // Readonly entries are left alone when the manifest doesn't mention them
+        for current in current_functions.values() {
+            if !names.contains(current.name.as_str()) && !current.readonly {
+                ops.push(Op::DeleteFunction(current.clone()));
+            }
+        }
+
+        for t in &manifest.triggers {
+            let targets_function = t.kind != crate::domain::entities::TriggerKind::Workflow;
+            let kept = current_functions
+                .get(&t.function_name)
+                .is_some_and(|f| f.readonly);
+            if targets_function && !names.contains(t.function_name.as_str()) && !kept {
+                return invalid(format!(
+                    "Trigger '{}' points to function '{}', which is not in the manifest",
+                    t.name, t.function_name
+                ));
+            }
+
+            let desired = Trigger {
+                readonly: false,
+                ..t.clone()
+            };
+            match current_triggers.get(&t.name) {
+                None => ops.push(Op::CreateTrigger(desired)),
+                Some(current) => {
+                    let fields = trigger_changes(current, &desired);
+                    if fields.is_empty() {
+                        continue;
+                    }
+                    if current.readonly {
+                        return invalid(format!("Trigger '{}' is readonly", t.name));
+                    }
+                    ops.push(Op::UpdateTrigger {
+                        desired,
+                        current: current.clone(),
+                        fields,
+                    });
+                }
+            }
+        }
+        for current in current_triggers.values() {
+            if !trigger_names.contains(current.name.as_str()) && !current.readonly {
+                ops.push(Op::DeleteTrigger(current.clone()));
+            }
+        }
+
+        ops.sort_by(|a, b| {
+            a.rank()
+                .cmp(&b.rank())
+                .then_with(|| a.change().name.cmp(&b.change().name))
+        });
+        Ok(ops)
+    }
+}
+
+/// Builds the function a spec describes. Its `executable` is the uploaded binary,
+/// or the one already stored when the spec doesn't change it. Paths in the
+/// manifest are never opened on the API host.
+fn desired_function(
+    spec: &FunctionSpec,
+    current: Option<&Function>,
+    artifacts: &Artifacts,
+) -> Result<Function, DomainError> {
+    let invalid = |msg: String| Err(DomainError::ValidationError(msg));
+
+    let executable = match (&spec.artifact, &spec.sha256, current) {
+        (Some(artifact), expected, _) => {
+            let Some(upload) = artifacts.get(artifact) else {
+                return invalid(format!(
+                    "Artifact '{}' of '{}' was not uploaded with the manifest",
+                    artifact, spec.name
+                ));
+            };
+            let upload = upload.to_string_lossy().into_owned();
+            if let Some(expected) = expected {
+                let actual = file_sha256(&upload)?;
+                if !actual.eq_ignore_ascii_case(expected) {
+                    return invalid(format!(
+                        "Artifact of '{}' has SHA-256 {}, expected {}",
+                        spec.name, actual, expected
+                    ));
+                }
+            }
+            upload
+        }
+        (None, Some(expected), Some(current)) if !current.executable.is_empty() => {
+            let actual = file_sha256(&current.executable)?;
+            if !actual.eq_ignore_ascii_case(expected) {
+                return invalid(format!(
+                    "Function '{}' is deployed with SHA-256 {}; provide `artifact` to deploy {}",
+                    spec.name, actual, expected
+                ));
+            }
+            current.executable.clone()
+        }
+        (None, Some(_), _) => {
+            return invalid(format!(
+                "Function '{}' has no deployed binary to match `sha256`; provide `artifact`",
+                spec.name
+            ));
+        }
+        (None, None, current) => current.map(|f| f.executable.clone()).unwrap_or_default(),
+    };
+
+    Ok(Function {
+        name: spec.name.clone(),
+        language: spec.language.clone(),
+        executable,
+        cpu: spec.cpu.clone(),
+        memory: spec.memory.clone(),
+        runtime: None,
+        readonly: false,
+        snapshot: spec.snapshot,
+        max_concurrency: spec.max_concurrency,
+        max_queue: spec.max_queue,
+        allowed_calls: spec.allowed_calls.clone(),
+        retry: spec.retry.clone(),
+        env: spec.env.clone(),
+    })
+}
+
+fn function_changes(current: &Function, desired: &Function) -> Result<Vec<String>, DomainError> {
+    let mut fields = Vec::new();
+    let mut check = |name: &str, changed: bool| {
+        if changed {
+            fields.push(name.to_string());
+        }
+    };
+    check("language", current.language != desired.language);
+    check("cpu", current.cpu != desired.cpu);
+    check("memory", current.memory != desired.memory);
+    check("env", current.env != desired.env);
+    check("snapshot", current.snapshot != desired.snapshot);
+    check(
+        "max_concurrency",
+        current.max_concurrency != desired.max_concurrency,
+    );
+    check("max_queue", current.max_queue != desired.max_queue);
+    check(
+        "allowed_calls",
+        current.allowed_calls != desired.allowed_calls,
+    );
+    check("retry", current.retry != desired.retry);
+
+    let artifact_changed = if desired.executable == current.executable {
+        false
+    } else if current.executable.is_empty() || desired.executable.is_empty() {
+        true
+    } else {
+        file_sha256(&current.executable).ok() != Some(file_sha256(&desired.executable)?)
+    };
+    check("artifact", artifact_changed);
+    Ok(fields)
+}
+
+fn trigger_changes(current: &Trigger, desired: &Trigger) -> Vec<String> {
+    [
+        ("method", current.method != desired.method),
+        ("path", current.path != desired.path),
+        ("function", current.function_name != desired.function_name),
+        ("kind", current.kind != desired.kind),
+        ("rate_limit", current.rate_limit != desired.rate_limit),
+        ("cache", current.cache != desired.cache),
+    ]
+    .into_iter()
+    .filter(|(_, changed)| *changed)
+    .map(|(name, _)| name.to_string())
+    .collect()
+}
+
+fn file_sha256(path: &str) -> Result<String, DomainError> {
+    let bytes = fs::read(path).map_err(|e| {
+        DomainError::ValidationError(format!("Failed to read artifact {}: {}", path, e))
+    })?;
+    Ok(Sha256::digest(&bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::invocation_service::InvocationService;
+    use crate::domain::entities::TriggerKind;
+    use crate::domain::ports::{
+        MockFunctionRepository, MockManifestRepository, MockTriggerRepository,
+    };
+    use crate::domain::wasm_runtime::MockWasmRuntime;
+    use tempfile::tempdir;
+
+    fn trigger(name: &str, function: &str) -> Trigger {
+        Trigger {
+            name: name.to_string(),
+            method: "GET".to_string(),
+            path: format!("/{}", name),
+            function_name: function.to_string(),
+            kind: TriggerKind::Http,
+            rate_limit: None,
+            cache: None,
+            readonly: false,
+        }
+    }
+
+    fn service(functions: Vec<Function>, triggers: Vec<Trigger>) -> ManifestService {
+        let mut function_repo = MockFunctionRepository::new();
+        function_repo
+            .expect_find_all()
+            .returning(move || Ok(functions.clone()));
+        let mut trigger_repo = MockTriggerRepository::new();
+        trigger_repo
+            .expect_find_all()
+            .returning(move || Ok(triggers.clone()));
+
+        let function_repo = Arc::new(function_repo);
+        let runtime = Arc::new(MockWasmRuntime::new());
+        let storage = std::env::temp_dir().to_string_lossy().into_owned();
+        let function_service = Arc::new(FunctionService::new(
+            function_repo.clone(),
+            runtime.clone(),
+            storage,
+        ));
+        let invocation_service = Arc::new(InvocationService::new(
+            Arc::new(MockTriggerRepository::new()),
+            function_repo,
+            runtime,
+        ));
+        let trigger_service = Arc::new(TriggerService::new(
+            Arc::new(trigger_repo),
+            invocation_service,
+        ));
+        ManifestService::new(
+            Arc::new(MockManifestRepository::new()),
+            function_service,
+            trigger_service,
+        )
+    }
+
+    #[test]
+    fn test_parse_formats() {
+        let yaml = "
+functions:
+  - name: hello
+    language: rust
+    artifact: hello.wasm
+    cpu: '0.5'
+    memory: '128'
+    env: { GREETING: hi }
+triggers:
+  - { name: hello, method: GET, path: /hello, function: hello }
+";
+        let toml = r#"
+[[functions]]
+name = "hello"
+language = "rust"
+artifact = "hello.wasm"
+cpu = "0.5"
+memory = "128"
+env = { GREETING = "hi" }
+
+[[triggers]]
+name = "hello"
+method = "GET"
+path = "/hello"
+function = "hello"
+"#;
+        let from_yaml = Manifest::parse(yaml, ManifestFormat::Yaml).unwrap();
+        let from_toml = Manifest::parse(toml, ManifestFormat::Toml).unwrap();
+        assert_eq!(from_yaml, from_toml);
+        assert_eq!(from_yaml.functions[0].env["GREETING"], "hi");
+        assert_eq!(from_yaml.triggers[0].function_name, "hello");
+
+        assert!(matches!(
+            Manifest::parse("functions: 3", ManifestFormat::Yaml),
+            Err(DomainError::ValidationError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_plan_diff() {
+        let dir = tempdir().unwrap();
+        let old = dir.path().join("old.wasm");
+        fs::write(&old, "old").unwrap();
+        let path = |p: &std::path::Path| p.to_string_lossy().into_owned();
+
+        let stored = |name: &str| Function {
+            name: name.to_string(),
+            executable: path(&old),
+            cpu: "1".to_string(),
+            memory: "128".to_string(),
+            ..Default::default()
+        };
+        let service = service(
+            vec![
+                stored("same"),
+                stored("resized"),
+                stored("rebuilt"),
+                stored("gone"),
+                Function {
+                    readonly: true,
+                    ..stored("healthz")
+                },
+            ],
+            vec![
+                trigger("same", "same"),
+                trigger("moved", "same"),
+                Trigger {
+                    readonly: true,
+                    ..trigger("health-check", "healthz")
+                },
+            ],
+        );
+
+        // Uploads are matched by name; the server never reads the manifest's paths
+        let mut artifacts = Artifacts::default();
+        fs::write(artifacts.create("build/old.wasm").unwrap(), "old").unwrap();
+        fs::write(artifacts.create("build/new.wasm").unwrap(), "new").unwrap();
+
+        let spec = |name: &str, artifact: &str, memory: &str| FunctionSpec {
+            name: name.to_string(),
+            language: Language::Python,
+            artifact: Some(artifact.to_string()),
+            sha256: None,
+            cpu: "1".to_string(),
+            memory: memory.to_string(),
+            env: BTreeMap::new(),
+            snapshot: false,
+            max_concurrency: None,
+            max_queue: None,
+            allowed_calls: vec![],
+            retry: None,
+        };
+        let manifest = Manifest {
+            functions: vec![
+                spec("same", "build/old.wasm", "128"),
+                spec("resized", "build/old.wasm", "256"),
+                spec("rebuilt", "build/new.wasm", "128"),
+                spec("added", "build/new.wasm", "128"),
+            ],
+            triggers: vec![
+                trigger("same", "same"),
+                Trigger {
+                    path: "/elsewhere".to_string(),
+                    ..trigger("moved", "same")
+                },
+            ],
+        };
+
+        let plan = service.plan(&manifest, &artifacts).await.unwrap();
+        let summary: Vec<(ChangeAction, &str, Vec<String>)> = plan
+            .changes
+            .iter()
+            .map(|c| (c.action, c.name.as_str(), c.fields.clone()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (ChangeAction::Create, "added", vec![]),
+                (
+                    ChangeAction::Update,
+                    "rebuilt",
+                    vec!["artifact".to_string()]
+                ),
+                (ChangeAction::Update, "resized", vec!["memory".to_string()]),
+                (ChangeAction::Update, "moved", vec!["path".to_string()]),
+                (ChangeAction::Delete, "gone", vec![]),
+            ]
+        );
+        assert!(!plan.applied);
+    }
+
+    #[tokio::test]
+    async fn test_plan_rejects_invalid_manifests() {
+        let service = service(
+            vec![Function {
+                name: "healthz".to_string(),
+                readonly: true,
+                ..Default::default()
+            }],
+            vec![],
+        );
+        let spec = FunctionSpec {
+            name: "healthz".to_string(),
+            language: Language::Python,
+            artifact: None,
+            sha256: None,
+            cpu: "2".to_string(),
+            memory: "128".to_string(),
+            env: BTreeMap::new(),
+            snapshot: false,
+            max_concurrency: None,
+            max_queue: None,
+            allowed_calls: vec![],
+            retry: None,
+        };
+
+        for manifest in [
+            // Changing a readonly function
+            Manifest {
+                functions: vec![spec.clone()],
+                triggers: vec![],
+            },
+            // Pinning a hash nothing was deployed with
+            Manifest {
+                functions: vec![FunctionSpec {
+                    name: "new".to_string(),
+                    sha256: Some("abc".to_string()),
+                    ..spec.clone()
+                }],
+                triggers: vec![],
+            },
+            // Artifact that wasn't uploaded, even if the path exists on the host
+            Manifest {
+                functions: vec![FunctionSpec {
+                    name: "new".to_string(),
+                    artifact: Some("/etc/hostname".to_string()),
+                    ..spec.clone()
+                }],
+                triggers: vec![],
+            },
+            // Trigger to a function the manifest removes
+            Manifest {
+                functions: vec![],
+                triggers: vec![trigger("t", "missing")],
+            },
+        ] {
+            assert!(matches!(
+                service.plan(&manifest, &Artifacts::default()).await,
+                Err(DomainError::ValidationError(_))
+            ));
+        }
+    }
+}
@@ -1,5 +1,15 @@
+pub mod admission;
+pub mod alert_service;
+pub mod audit_service;
 pub mod auth_service;
+pub mod background_service;
 pub mod function_service;
 pub mod invocation_service;
+pub mod manifest_service;
+pub mod node_lease;
+pub mod rate_limit_service;
+pub mod response_cache;
 pub mod telemetry_service;
 pub mod trigger_service;
+pub mod websocket_service;
+pub mod workflow_service;
@@ -0,0 +1,146 @@
+use crate::domain::entities::DomainError;
+use crate::domain::ports::NodeLeaseRepository;
+use chrono::Utc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+const DEFAULT_TTL: Duration = Duration::from_secs(15);
+
+/// Makes this node the only one serving from the database.
+///
+/// Routes, loaded functions, cached responses and stored binaries live in each
+/// process, and background invocations and workflow runs are resumed by whoever
+/// starts, so two nodes serving at once would disagree and run work twice. The
+/// node holding the lease serves; others started against the same database wait
+/// as standbys and take over once it is released or left to expire.
+pub struct NodeLease {
+    repository: Arc<dyn NodeLeaseRepository>,
+    node: String,
+    ttl: Duration,
+}
+
+impl NodeLease {
+    pub fn new(repository: Arc<dyn NodeLeaseRepository>, node: impl Into<String>) -> Self {
+        Self {
+            repository,
+            node: node.into(),
+            ttl: DEFAULT_TTL,
+        }
+    }
+
+    /// How long the lease outlives the last renewal, which happens every third of it.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn node(&self) -> &str {
+        &self.node
+    }
+
+    /// Takes or renews the lease; returns the node holding it afterwards.
+    async fn try_acquire(&self) -> Result<String, DomainError> {
+        let now = Utc::now().timestamp_millis();
+        let expires_at = now.saturating_add(self.ttl.as_millis() as i64);
+        self.repository
+            .acquire_lease(&self.node, now, expires_at)
+            .await
+    }
+
+    /// Waits until this node holds the lease.
+    pub async fn acquire(&self) {
+        let mut standing_by_for = None;
+        loop {
+            match self.try_acquire().await {
+                Ok(holder) if holder == self.node => return,
+                Ok(holder) => {
+                    if standing_by_for.as_ref() != Some(&holder) {
+                        info!("Node {} is serving, standing by until it stops", holder);
+                        standing_by_for = Some(holder);
+                    }
+                }
+                Err(e) => warn!("Failed to acquire the node lease: {}", e),
+            }
+            tokio::time::sleep(self.ttl / 3).await;
+        }
+    }
+
+    /// Renews the lease until it is lost, either to another node or because it
+    /// could not be renewed before expiring; the node must stop serving then.
+    pub async fn hold(&self) {
+        let mut renewed = Instant::now();
+        loop {
+            tokio::time::sleep(self.ttl / 3).await;
+            match self.try_acquire().await {
+                Ok(holder) if holder == self.node => renewed = Instant::now(),
+                Ok(holder) => {
+                    error!("Lost the node lease to {}", holder);
+                    return;
+                }
+                Err(e) if renewed.elapsed() >= self.ttl => {
+                    error!("Node lease expired, last renewal failed: {}", e);
+                    return;
+                }
+                Err(e) => warn!("Failed to renew the node lease: {}", e),
+            }
+        }
+    }
+
+    /// Hands the lease over to a standby right away.
+    pub async fn release(&self) {
+        if let Err(e) = self.repository.release_lease(&self.node).await {
+            warn!("Failed to release the node lease: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ports::MockNodeLeaseRepository;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    const TTL: Duration = Duration::from_millis(30);
+
+    /// Lease store that answers with the holders in `holders`, one per call.
+    fn repository(holders: &[&str]) -> MockNodeLeaseRepository {
+        let calls = holders.len();
+        let holders: Mutex<VecDeque<String>> =
+            Mutex::new(holders.iter().map(|h| h.to_string()).collect());
+        let mut repo = MockNodeLeaseRepository::new();
+        repo.expect_acquire_lease()
+            .times(calls)
+            .returning(move |node, now, expires_at| {
+                assert_eq!(node, "a");
+                assert_eq!(expires_at - now, TTL.as_millis() as i64);
+                Ok(holders.lock().unwrap().pop_front().unwrap())
+            });
+        repo
+    }
+
+    #[tokio::test]
+    async fn test_acquire_waits_for_the_holder() {
+        let lease = NodeLease::new(Arc::new(repository(&["b", "b", "a"])), "a").with_ttl(TTL);
+        lease.acquire().await;
+    }
+
+    #[tokio::test]
+    async fn test_hold_returns_once_lost() {
+        let lease = NodeLease::new(Arc::new(repository(&["a", "a", "b"])), "a").with_ttl(TTL);
+        lease.hold().await;
+    }
+
+    #[tokio::test]
+    async fn test_hold_returns_once_expired() {
+        let mut repo = MockNodeLeaseRepository::new();
+        repo.expect_acquire_lease()
+            .returning(|_, _, _| Err(DomainError::Internal("database is down".to_string())));
+        let lease = NodeLease::new(Arc::new(repo), "a").with_ttl(TTL);
+
+        let start = Instant::now();
+        lease.hold().await;
+        assert!(start.elapsed() >= TTL);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::application::auth_service::AuthService;
+use crate::domain::entities::{DomainError, RateLimit, RateLimitScope};
+use crate::domain::ports::RateLimitStore;
+use opentelemetry::{KeyValue, global};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error};
+
+const DEFAULT_PRUNE_INTERVAL: Duration = Duration::from_secs(300);
+
+/// What the gateway knows about who is calling.
+#[derive(Debug, Default, Clone)]
+pub struct Caller {
+    pub ip: String,
+    pub bearer_token: Option<String>,
+}
+
+/// Result of a rate-limit check, carrying what goes into the `RateLimit-*` headers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the bucket is full again.
+    pub reset_secs: u64,
+    /// Seconds until the next request can succeed.
+    pub retry_after_secs: u64,
+}
+
+pub struct RateLimitService {
+    store: Arc<dyn RateLimitStore>,
+    auth_service: Arc<AuthService>,
+    prune_interval: Duration,
+}
+
+impl RateLimitService {
+    pub fn new(store: Arc<dyn RateLimitStore>, auth_service: Arc<AuthService>) -> Self {
+        Self {
+            store,
+            auth_service,
+            prune_interval: DEFAULT_PRUNE_INTERVAL,
+        }
+    }
+
+    pub fn with_prune_interval(mut self, interval: Duration) -> Self {
+        self.prune_interval = interval;
+        self
+    }
+
+    /// Drops idle buckets every interval until the process exits, so the
+    /// store doesn't grow with every client ever seen.
+    pub fn start(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.prune_interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                match self.store.prune().await {
+                    Ok(pruned) => debug!("Pruned {} idle rate limit buckets", pruned),
+                    Err(e) => error!("Rate limit bucket pruning failed: {}", e),
+                }
+            }
+        });
+    }
+
+    pub async fn check(
+        &self,
+        trigger: &str,
+        limit: &RateLimit,
+        caller: &Caller,
+    ) -> Result<RateLimitDecision, DomainError> {
+        let key = format!("{}:{}", trigger, self.caller_key(limit.scope, caller));
+        let capacity = limit.capacity();
+        let refill = limit.refill_per_sec();
+
+        let state = self.store.take(&key, capacity, refill).await?;
+
+        if !state.allowed {
+            global::meter("fluor-api")
+                .u64_counter("rate_limited_requests")
+                .build()
+                .add(
+                    1,
+                    &[
+                        KeyValue::new("trigger", trigger.to_string()),
+                        KeyValue::new("scope", format!("{:?}", limit.scope).to_lowercase()),
+                    ],
+                );
+        }
+
+        Ok(RateLimitDecision {
+            allowed: state.allowed,
+            limit: limit.requests,
+            remaining: state.tokens.max(0.0).floor() as u32,
+            reset_secs: ((capacity - state.tokens).max(0.0) / refill).ceil() as u64,
+            retry_after_secs: ((1.0 - state.tokens).max(0.0) / refill).ceil() as u64,
+        })
+    }
+
+    fn caller_key(&self, scope: RateLimitScope, caller: &Caller) -> String {
+        match scope {
+            RateLimitScope::Global => "global".to_string(),
+            RateLimitScope::Ip => format!("ip:{}", caller.ip),
+            RateLimitScope::Subject => {
+                // Only trust subjects from tokens we signed; otherwise anyone could pick a bucket
+                // and spread their requests over as many as they like
+                if let Some(claims) = caller
+                    .bearer_token
+                    .as_deref()
+                    .and_then(|t| self.auth_service.verify_token(t).ok())
+                {
+                    return format!("sub:{}", claims.sub);
+                }
+                format!("ip:{}", caller.ip)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::entities::BucketState;
+    use crate::domain::ports::{MockRateLimitStore, MockUserRepository};
+    use mockall::predicate::*;
+
+    fn auth_service() -> Arc<AuthService> {
+        Arc::new(AuthService::new(
+            Arc::new(MockUserRepository::new()),
+            "pepper".to_string(),
+            "secret".to_string(),
+        ))
+    }
+
+    fn limit(scope: RateLimitScope) -> RateLimit {
+        RateLimit {
+            requests: 10,
+            period_secs: 10,
+            burst: None,
+            scope,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_allowed() {
+        let mut store = MockRateLimitStore::new();
+        store
+            .expect_take()
+            .with(eq("hello:global"), eq(10.0), eq(1.0))
+            .returning(|_, _, _| {
+                Ok(BucketState {
+                    allowed: true,
+                    tokens: 7.5,
+                })
+            });
+
+        let service = RateLimitService::new(Arc::new(store), auth_service());
+        let decision = service
+            .check("hello", &limit(RateLimitScope::Global), &Caller::default())
+            .await
+            .unwrap();
+
+        assert!(decision.allowed);
+        assert_eq!(decision.limit, 10);
+        assert_eq!(decision.remaining, 7);
+        assert_eq!(decision.reset_secs, 3);
+        assert_eq!(decision.retry_after_secs, 0);
+    }
+
+    #[tokio::test]
+    async fn test_check_rejected() {
+        let mut store = MockRateLimitStore::new();
+        store
+            .expect_take()
+            .with(eq("hello:ip:10.0.0.1"), always(), always())
+            .returning(|_, _, _| {
+                Ok(BucketState {
+                    allowed: false,
+                    tokens: 0.25,
+                })
+            });
+
+        let service = RateLimitService::new(Arc::new(store), auth_service());
+        let caller = Caller {
+            ip: "10.0.0.1".to_string(),
+            ..Default::default()
+        };
+        let decision = service
+            .check("hello", &limit(RateLimitScope::Ip), &caller)
+            .await
+            .unwrap();
+
+        assert!(!decision.allowed);
+        assert_eq!(decision.remaining, 0);
+        assert_eq!(decision.retry_after_secs, 1);
+    }
+
+    #[test]
+    fn test_subject_key_ignores_unverified_credentials() {
+        let service = RateLimitService::new(Arc::new(MockRateLimitStore::new()), auth_service());
+        let caller = Caller {
+            ip: "10.0.0.1".to_string(),
+            bearer_token: Some("not-a-jwt".to_string()),
+        };
+        assert_eq!(
+            service.caller_key(RateLimitScope::Subject, &caller),
+            "ip:10.0.0.1"
+        );
+    }
+}
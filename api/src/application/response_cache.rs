@@ -0,0 +1,276 @@
+use opentelemetry::{KeyValue, global};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A cached function response, as served to a caller.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedResponse {
+    pub body: String,
+    pub etag: String,
+    /// Seconds since the response was produced.
+    pub age: u64,
+    /// Seconds the response stays fresh.
+    pub max_age: u64,
+}
+
+struct Entry {
+    body: String,
+    etag: String,
+    trigger: String,
+    function: String,
+    stored_at: Instant,
+    ttl: Duration,
+    seq: u64,
+}
+
+impl Entry {
+    fn size(key: &str, body: &str) -> usize {
+        key.len() + body.len()
+    }
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<String, Entry>,
+    /// Least recently used first.
+    recency: BTreeMap<u64, String>,
+    next_seq: u64,
+    bytes: usize,
+}
+
+impl CacheState {
+    fn remove(&mut self, key: &str) -> Option<Entry> {
+        let entry = self.entries.remove(key)?;
+        self.recency.remove(&entry.seq);
+        self.bytes -= Entry::size(key, &entry.body);
+        Some(entry)
+    }
+
+    fn touch(&mut self, key: &str) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.recency.remove(&entry.seq);
+            entry.seq = seq;
+            self.recency.insert(seq, key.to_string());
+        }
+    }
+}
+
+/// In-process store for gateway responses, bounded by the total size of the
+/// cached bodies and evicting the least recently used entries first.
+pub struct ResponseCache {
+    max_bytes: usize,
+    state: Mutex<CacheState>,
+}
+
+impl ResponseCache {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    /// Builds the cache key of a request; `headers` are the trigger's vary headers.
+    pub fn key(
+        trigger: &str,
+        method: &str,
+        path: &str,
+        query: &str,
+        headers: &[(&str, &str)],
+    ) -> String {
+        let mut key = format!("{}\n{} {}?{}", trigger, method, path, query);
+        for (name, value) in headers {
+            key.push('\n');
+            key.push_str(&name.to_ascii_lowercase());
+            key.push(':');
+            key.push_str(value);
+        }
+        key
+    }
+
+    pub fn etag(body: &str) -> String {
+        let digest = Sha256::digest(body.as_bytes());
+        let hash: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+        format!("\"{}\"", hash)
+    }
+
+    pub fn get(&self, key: &str) -> Option<CachedResponse> {
+        let response = self.lookup(key);
+        global::meter("fluor-api")
+            .u64_counter("response_cache_lookups")
+            .build()
+            .add(
+                1,
+                &[KeyValue::new(
+                    "result",
+                    if response.is_some() { "hit" } else { "miss" },
+                )],
+            );
+        response
+    }
+
+    fn lookup(&self, key: &str) -> Option<CachedResponse> {
+        let mut state = self.state.lock().ok()?;
+        let entry = state.entries.get(key)?;
+
+        let age = entry.stored_at.elapsed();
+        if age >= entry.ttl {
+            state.remove(key);
+            return None;
+        }
+
+        let response = CachedResponse {
+            body: entry.body.clone(),
+            etag: entry.etag.clone(),
+            age: age.as_secs(),
+            max_age: (entry.ttl - age).as_secs(),
+        };
+        state.touch(key);
+        Some(response)
+    }
+
+    pub fn insert(
+        &self,
+        key: String,
+        trigger: &str,
+        function: &str,
+        body: String,
+        ttl: Duration,
+    ) -> CachedResponse {
+        let response = CachedResponse {
+            etag: Self::etag(&body),
+            body,
+            age: 0,
+            max_age: ttl.as_secs(),
+        };
+
+        let size = Entry::size(&key, &response.body);
+        let Ok(mut state) = self.state.lock() else {
+            return response;
+        };
+        state.remove(&key);
+        if size > self.max_bytes {
+            return response;
+        }
+
+        while state.bytes + size > self.max_bytes {
+            let Some((_, oldest)) = state.recency.pop_first() else {
+                break;
+            };
+            state.remove(&oldest);
+        }
+
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        state.bytes += size;
+        state.recency.insert(seq, key.clone());
+        state.entries.insert(
+            key,
+            Entry {
+                body: response.body.clone(),
+                etag: response.etag.clone(),
+                trigger: trigger.to_string(),
+                function: function.to_string(),
+                stored_at: Instant::now(),
+                ttl,
+                seq,
+            },
+        );
+        response
+    }
+
+    /// Drops every entry served through `trigger`; returns how many were removed.
+    pub fn purge_trigger(&self, trigger: &str) -> usize {
+        self.purge(|e| e.trigger == trigger)
+    }
+
+    /// Drops every entry produced by `function`; returns how many were removed.
+    pub fn purge_function(&self, function: &str) -> usize {
+        self.purge(|e| e.function == function)
+    }
+
+    fn purge(&self, matches: impl Fn(&Entry) -> bool) -> usize {
+        let Ok(mut state) = self.state.lock() else {
+            return 0;
+        };
+        let keys: Vec<String> = state
+            .entries
+            .iter()
+            .filter(|(_, e)| matches(e))
+            .map(|(k, _)| k.clone())
+            .collect();
+        for key in &keys {
+            state.remove(key);
+        }
+        keys.len()
+    }
+
+    /// Total size of the cached keys and bodies.
+    pub fn size_bytes(&self) -> usize {
+        self.state.lock().map(|s| s.bytes).unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn test_get_and_expire() {
+        let cache = ResponseCache::new(1024);
+        let stored = cache.insert("k".into(), "t", "f", "hello".into(), TTL);
+        assert_eq!(stored.etag, ResponseCache::etag("hello"));
+
+        let hit = cache.get("k").unwrap();
+        assert_eq!(hit.body, "hello");
+        assert_eq!(hit.etag, stored.etag);
+        assert!(hit.max_age <= 60);
+
+        cache.insert("short".into(), "t", "f", "x".into(), Duration::ZERO);
+        assert!(cache.get("short").is_none());
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let cache = ResponseCache::new(20);
+        cache.insert("a".into(), "t", "f", "123456789".into(), TTL);
+        cache.insert("b".into(), "t", "f", "123456789".into(), TTL);
+        // Reading `a` makes `b` the eviction candidate
+        assert!(cache.get("a").is_some());
+        cache.insert("c".into(), "t", "f", "123456789".into(), TTL);
+
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_some());
+        assert!(cache.size_bytes() <= 20);
+
+        // Entries larger than the whole cache are never stored
+        cache.insert("big".into(), "t", "f", "x".repeat(64), TTL);
+        assert!(cache.get("big").is_none());
+    }
+
+    #[test]
+    fn test_purge() {
+        let cache = ResponseCache::new(1024);
+        cache.insert("a".into(), "t1", "f1", "a".into(), TTL);
+        cache.insert("b".into(), "t2", "f1", "b".into(), TTL);
+        cache.insert("c".into(), "t3", "f2", "c".into(), TTL);
+
+        assert_eq!(cache.purge_trigger("t3"), 1);
+        assert_eq!(cache.purge_function("f1"), 2);
+        assert_eq!(cache.size_bytes(), 0);
+    }
+
+    #[test]
+    fn test_key_includes_vary_headers() {
+        let en = ResponseCache::key("t", "GET", "/p", "q=1", &[("Accept-Language", "en")]);
+        let fr = ResponseCache::key("t", "GET", "/p", "q=1", &[("Accept-Language", "fr")]);
+        assert_ne!(en, fr);
+    }
+}
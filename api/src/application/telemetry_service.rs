@@ -1,47 +1,1046 @@
-use crate::infrastructure::db::clickhouse::{ClickHouseRepository, ExecutionMetric, LogEntry};
+use crate::domain::entities::{
+    BucketStats, DomainError, ExecutionMetric, Granularity, InvocationStats, LogEntry, LogPage,
+    LogPosition, LogQuery, SpanNode, TimeRange, Trace, TraceFilter, TraceSpan, TraceSummary,
+};
+use crate::domain::ports::TelemetryRepository;
+use chrono::{DateTime, TimeDelta, Utc};
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+/// Most buckets a metrics query may return.
+pub const MAX_BUCKETS: i64 = 1440;
+/// Widest window a metrics query may cover.
+pub const MAX_RANGE_DAYS: i64 = 400;
+
+/// Most functions a top-N summary may list.
+pub const MAX_TOP_FUNCTIONS: usize = 100;
+
+/// Most traces a trace listing may return.
+pub const MAX_TRACES: usize = 100;
+
+/// Most log lines a search returns per page.
+pub const MAX_LOG_LIMIT: usize = 1000;
+/// How often a live tail looks for new log lines unless configured otherwise.
+pub const DEFAULT_TAIL_INTERVAL: Duration = Duration::from_secs(1);
+/// How late a log line may reach the backend and still be tailed, unless
+/// configured otherwise; the collector exports in batches.
+pub const DEFAULT_TAIL_LAG: Duration = Duration::from_secs(10);
+
+const LOG_LEVELS: [&str; 5] = ["TRACE", "DEBUG", "INFO", "WARN", "ERROR"];
+
+/// Window of a metrics query as given by the caller. `from` and `to` are RFC 3339
+/// timestamps, `now`, or relative to now (`now-6h`, or just `6h`); `step` is
+/// `minute`, `hour`, `day` or `auto`.
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct RangeParams {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub step: Option<String>,
+}
+
+impl RangeParams {
+    /// Validates the window against `now`. It defaults to the last hour, and `auto`
+    /// picks the finest step that stays within `MAX_BUCKETS`.
+    pub fn resolve(&self, now: DateTime<Utc>) -> Result<TimeRange, DomainError> {
+        let from = match &self.from {
+            Some(from) => parse_instant(from, now)?,
+            None => now - TimeDelta::hours(1),
+        };
+        let to = match &self.to {
+            Some(to) => parse_instant(to, now)?,
+            None => now,
+        };
+        if from >= to {
+            return Err(DomainError::ValidationError(
+                "`from` must be before `to`".to_string(),
+            ));
+        }
+        if to - from > TimeDelta::days(MAX_RANGE_DAYS) {
+            return Err(DomainError::ValidationError(format!(
+                "Time range is limited to {} days",
+                MAX_RANGE_DAYS
+            )));
+        }
+
+        let step = match self.step.as_deref() {
+            None | Some("auto") => [Granularity::Minute, Granularity::Hour, Granularity::Day]
+                .into_iter()
+                .find(|g| bucket_count(from, to, *g) <= MAX_BUCKETS)
+                .unwrap_or(Granularity::Day),
+            Some("minute" | "1m") => Granularity::Minute,
+            Some("hour" | "1h") => Granularity::Hour,
+            Some("day" | "1d") => Granularity::Day,
+            Some(other) => {
+                return Err(DomainError::ValidationError(format!(
+                    "Unknown step '{}'; use minute, hour, day or auto",
+                    other
+                )));
+            }
+        };
+        if bucket_count(from, to, step) > MAX_BUCKETS {
+            return Err(DomainError::ValidationError(format!(
+                "Step is too fine for the range; at most {} buckets are returned",
+                MAX_BUCKETS
+            )));
+        }
+
+        Ok(TimeRange { from, to, step })
+    }
+}
+
+/// Log search as given by the caller. `from` and `to` are read like in
+/// `RangeParams`; `cursor` comes from the previous page's `next_cursor`.
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct LogParams {
+    pub function: Option<String>,
+    pub level: Option<String>,
+    pub contains: Option<String>,
+    pub trace_id: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+}
+
+impl LogParams {
+    /// Validates the filters; `default_limit` applies when no `limit` is given.
+    pub fn resolve(
+        &self,
+        now: DateTime<Utc>,
+        default_limit: usize,
+    ) -> Result<LogQuery, DomainError> {
+        let non_empty = |v: &Option<String>| v.clone().filter(|v| !v.is_empty());
+
+        let limit = self.limit.unwrap_or(default_limit);
+        if limit == 0 || limit > MAX_LOG_LIMIT {
+            return Err(DomainError::ValidationError(format!(
+                "`limit` must be between 1 and {}",
+                MAX_LOG_LIMIT
+            )));
+        }
+
+        let level = non_empty(&self.level).map(|l| l.to_uppercase());
+        if let Some(level) = &level
+            && !LOG_LEVELS.contains(&level.as_str())
+        {
+            return Err(DomainError::ValidationError(format!(
+                "Unknown level '{}'; use one of {}",
+                level,
+                LOG_LEVELS.join(", ")
+            )));
+        }
+
+        // `from` is inclusive while the query bound is not
+        let after = match non_empty(&self.from) {
+            Some(from) => Some(parse_instant(&from, now)? - TimeDelta::nanoseconds(1)),
+            None => None,
+        };
+        let to = match non_empty(&self.to) {
+            Some(to) => Some(parse_instant(&to, now)?),
+            None => None,
+        };
+        let cursor = match non_empty(&self.cursor) {
+            Some(cursor) => Some(
+                parse_cursor(&cursor)
+                    .ok_or_else(|| DomainError::ValidationError("Invalid cursor".to_string()))?,
+            ),
+            None => None,
+        };
+        let before = to.into_iter().chain(cursor.map(|c| c.at)).min();
+        if let (Some(after), Some(before)) = (after, before)
+            && after >= before
+        {
+            return Err(DomainError::ValidationError(
+                "`from` must be before `to`".to_string(),
+            ));
+        }
+
+        Ok(LogQuery {
+            function_name: non_empty(&self.function),
+            level,
+            contains: non_empty(&self.contains),
+            trace_id: non_empty(&self.trace_id),
+            after,
+            before: to,
+            cursor,
+            oldest_first: false,
+            limit,
+        })
+    }
+}
+
+/// What a top-N summary ranks functions by.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RankBy {
+    #[default]
+    Invocations,
+    Errors,
+    /// p99 latency.
+    Latency,
+}
+
+/// Top-N query; `from` and `to` are read like in `RangeParams`.
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct TopParams {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    #[serde(default)]
+    pub by: RankBy,
+    pub limit: Option<usize>,
+}
+
+/// Trace listing; `from` and `to` are read like in `RangeParams`.
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct TraceParams {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    #[serde(default)]
+    pub filter: TraceFilter,
+    pub limit: Option<usize>,
+}
+
+/// Nests spans under their parents; spans whose parent is missing become roots.
+fn span_tree(trace_id: &str, spans: Vec<TraceSpan>) -> Trace {
+    let span_count = spans.len();
+    let intervals: Vec<_> = spans.iter().filter_map(TraceSpan::interval).collect();
+    let duration_ms = match (
+        intervals.iter().map(|(start, _)| *start).min(),
+        intervals.iter().map(|(_, end)| *end).max(),
+    ) {
+        (Some(first), Some(last)) => (last - first).num_microseconds().unwrap_or(0) as f64 / 1000.0,
+        _ => 0.0,
+    };
+
+    let ids: HashSet<String> = spans.iter().map(|s| s.span_id.clone()).collect();
+    let mut children: HashMap<String, Vec<TraceSpan>> = HashMap::new();
+    let mut roots = Vec::new();
+    for span in spans {
+        if ids.contains(&span.parent_span_id) && span.parent_span_id != span.span_id {
+            children
+                .entry(span.parent_span_id.clone())
+                .or_default()
+                .push(span);
+        } else {
+            roots.push(span);
+        }
+    }
+
+    fn build(span: TraceSpan, children: &mut HashMap<String, Vec<TraceSpan>>) -> SpanNode {
+        let mut kids = children.remove(&span.span_id).unwrap_or_default();
+        kids.sort_by(|a, b| a.start_time.cmp(&b.start_time));
+        SpanNode {
+            children: kids.into_iter().map(|k| build(k, children)).collect(),
+            span,
+        }
+    }
+    roots.sort_by(|a, b| a.start_time.cmp(&b.start_time));
+
+    Trace {
+        trace_id: trace_id.to_string(),
+        span_count,
+        duration_ms,
+        roots: roots.into_iter().map(|r| build(r, &mut children)).collect(),
+    }
+}
+
+/// Invocation stats of one bucket with the rates derived from them.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct PerformanceMetric {
+    pub time_bucket: String,
+    #[serde(flatten)]
+    pub stats: InvocationStats,
+    /// Share of invocations that failed or were rejected, from 0 to 1.
+    pub error_rate: f64,
+    /// Invocations per second.
+    pub throughput: f64,
+}
+
+/// Invocation stats of one function over the queried range.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct FunctionSummary {
+    pub function_name: String,
+    #[serde(flatten)]
+    pub stats: InvocationStats,
+    pub error_rate: f64,
+    pub throughput: f64,
+}
+
+fn error_rate(stats: &InvocationStats) -> f64 {
+    if stats.invocations == 0 {
+        0.0
+    } else {
+        stats.errors as f64 / stats.invocations as f64
+    }
+}
+
+fn throughput(stats: &InvocationStats, over: TimeDelta) -> f64 {
+    stats.invocations as f64 / over.num_milliseconds().max(1) as f64 * 1000.0
+}
+
+/// Lays the stats over every bucket of `range`, empty ones included.
+fn performance_series(range: &TimeRange, buckets: Vec<BucketStats>) -> Vec<PerformanceMetric> {
+    let mut by_label: HashMap<String, InvocationStats> = buckets
+        .into_iter()
+        .map(|b| (b.time_bucket, b.stats))
+        .collect();
+    range
+        .buckets()
+        .map(|bucket| {
+            let time_bucket = TimeRange::label(bucket);
+            let stats = by_label.remove(&time_bucket).unwrap_or_default();
+            PerformanceMetric {
+                error_rate: error_rate(&stats),
+                throughput: throughput(&stats, range.step.duration()),
+                time_bucket,
+                stats,
+            }
+        })
+        .collect()
+}
+
+fn bucket_count(from: DateTime<Utc>, to: DateTime<Utc>, step: Granularity) -> i64 {
+    let width = step.duration().num_seconds();
+    (to - from).num_seconds().div_euclid(width) + 1
+}
+
+/// A cursor is `<nanoseconds since the epoch>.<key>` of the last line of a page.
+fn format_cursor(position: LogPosition) -> Option<String> {
+    let nanos = position.at.timestamp_nanos_opt()?;
+    Some(format!("{}.{}", nanos, position.key))
+}
+
+fn parse_cursor(value: &str) -> Option<LogPosition> {
+    let (nanos, key) = value.split_once('.')?;
+    Some(LogPosition {
+        at: DateTime::from_timestamp_nanos(nanos.parse().ok()?),
+        key: key.parse().ok()?,
+    })
+}
+
+pub(crate) fn parse_instant(value: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, DomainError> {
+    if value == "now" {
+        return Ok(now);
+    }
+    if let Ok(at) = DateTime::parse_from_rfc3339(value) {
+        return Ok(at.with_timezone(&Utc));
+    }
+    let ago = value.strip_prefix("now-").unwrap_or(value);
+    // Durations reaching before the earliest representable time are invalid too
+    parse_duration(ago)
+        .and_then(|d| now.checked_sub_signed(d))
+        .ok_or_else(|| {
+            DomainError::ValidationError(format!(
+                "Invalid time '{}'; use RFC 3339, `now` or a relative time like `now-6h`",
+                value
+            ))
+        })
+}
+
+/// Parses `<n><unit>` with unit `s`, `m`, `h`, `d` or `w`.
+fn parse_duration(value: &str) -> Option<TimeDelta> {
+    let split = value.len().checked_sub(1)?;
+    let (amount, unit) = value.split_at_checked(split)?;
+    let amount: i64 = amount.parse().ok().filter(|n| *n >= 0)?;
+    match unit {
+        "s" => TimeDelta::try_seconds(amount),
+        "m" => TimeDelta::try_minutes(amount),
+        "h" => TimeDelta::try_hours(amount),
+        "d" => TimeDelta::try_days(amount),
+        "w" => TimeDelta::try_weeks(amount),
+        _ => None,
+    }
+}
+
+/// Lays the counts over every bucket of `range` so charts have no gaps.
+fn zero_fill(range: &TimeRange, metrics: Vec<ExecutionMetric>) -> Vec<ExecutionMetric> {
+    let counts: HashMap<String, u64> = metrics
+        .into_iter()
+        .map(|m| (m.time_bucket, m.count))
+        .collect();
+    range
+        .buckets()
+        .map(|bucket| {
+            let time_bucket = TimeRange::label(bucket);
+            ExecutionMetric {
+                count: counts.get(&time_bucket).copied().unwrap_or(0),
+                time_bucket,
+            }
+        })
+        .collect()
+}
+
+/// Where a live tail stands between polls.
+struct Tail {
+    query: LogQuery,
+    /// Lines logged before the tail started are left out.
+    start: DateTime<Utc>,
+    /// Lines already sent that the lag window may read again.
+    sent: HashSet<LogPosition>,
+}
 
 #[derive(Clone)]
 pub struct TelemetryService {
-    clickhouse_repository: Arc<ClickHouseRepository>,
+    telemetry_repository: Arc<dyn TelemetryRepository>,
+    tail_interval: Duration,
+    tail_lag: Duration,
 }
 
 impl TelemetryService {
-    pub fn new(clickhouse_repository: Arc<ClickHouseRepository>) -> Self {
+    pub fn new(telemetry_repository: Arc<dyn TelemetryRepository>) -> Self {
         Self {
-            clickhouse_repository,
+            telemetry_repository,
+            tail_interval: DEFAULT_TAIL_INTERVAL,
+            tail_lag: DEFAULT_TAIL_LAG,
         }
     }
 
+    pub fn with_tail_interval(mut self, tail_interval: Duration) -> Self {
+        self.tail_interval = tail_interval;
+        self
+    }
+
+    pub fn with_tail_lag(mut self, tail_lag: Duration) -> Self {
+        self.tail_lag = tail_lag;
+        self
+    }
+
     pub async fn get_function_metrics(
         &self,
         function_name: &str,
-    ) -> Result<Vec<ExecutionMetric>, String> {
-        self.clickhouse_repository
-            .get_function_executions(function_name, "1h")
+        params: &RangeParams,
+    ) -> Result<Vec<ExecutionMetric>, DomainError> {
+        let range = params.resolve(Utc::now())?;
+        let metrics = self
+            .telemetry_repository
+            .get_function_executions(function_name, &range)
+            .await?;
+        Ok(zero_fill(&range, metrics))
+    }
+
+    pub async fn get_overall_metrics(
+        &self,
+        params: &RangeParams,
+    ) -> Result<Vec<ExecutionMetric>, DomainError> {
+        let range = params.resolve(Utc::now())?;
+        let metrics = self
+            .telemetry_repository
+            .get_overall_executions(&range)
+            .await?;
+        Ok(zero_fill(&range, metrics))
+    }
+
+    /// Latency percentiles, error rate and throughput of a function per bucket.
+    pub async fn get_function_performance(
+        &self,
+        function_name: &str,
+        params: &RangeParams,
+    ) -> Result<Vec<PerformanceMetric>, DomainError> {
+        let range = params.resolve(Utc::now())?;
+        let buckets = self
+            .telemetry_repository
+            .get_function_performance(function_name, &range)
+            .await?;
+        Ok(performance_series(&range, buckets))
+    }
+
+    pub async fn get_overall_performance(
+        &self,
+        params: &RangeParams,
+    ) -> Result<Vec<PerformanceMetric>, DomainError> {
+        let range = params.resolve(Utc::now())?;
+        let buckets = self
+            .telemetry_repository
+            .get_overall_performance(&range)
+            .await?;
+        Ok(performance_series(&range, buckets))
+    }
+
+    /// The `limit` functions with the most invocations, errors or highest p99
+    /// latency over the range.
+    pub async fn get_top_functions(
+        &self,
+        params: &TopParams,
+    ) -> Result<Vec<FunctionSummary>, DomainError> {
+        let limit = params.limit.unwrap_or(10);
+        if limit == 0 || limit > MAX_TOP_FUNCTIONS {
+            return Err(DomainError::ValidationError(format!(
+                "`limit` must be between 1 and {}",
+                MAX_TOP_FUNCTIONS
+            )));
+        }
+        let range = RangeParams {
+            from: params.from.clone(),
+            to: params.to.clone(),
+            step: None,
+        }
+        .resolve(Utc::now())?;
+        let mut functions = self.telemetry_repository.get_function_stats(&range).await?;
+
+        functions.sort_by(|a, b| {
+            let (a, b) = (&a.stats, &b.stats);
+            match params.by {
+                RankBy::Invocations => b.invocations.cmp(&a.invocations),
+                RankBy::Errors => b.errors.cmp(&a.errors),
+                RankBy::Latency => b.p99_ms.total_cmp(&a.p99_ms),
+            }
+        });
+
+        Ok(functions
+            .into_iter()
+            .take(limit)
+            .map(|f| FunctionSummary {
+                error_rate: error_rate(&f.stats),
+                throughput: throughput(&f.stats, range.to - range.from),
+                function_name: f.function_name,
+                stats: f.stats,
+            })
+            .collect())
+    }
+
+    /// All spans of a trace, nested by parent.
+    pub async fn get_trace(&self, trace_id: &str) -> Result<Trace, DomainError> {
+        if trace_id.len() != 32 || !trace_id.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(DomainError::ValidationError(
+                "Trace ids are 32 hexadecimal digits".to_string(),
+            ));
+        }
+        let trace_id = trace_id.to_lowercase();
+        let spans = self.telemetry_repository.get_trace_spans(&trace_id).await?;
+        if spans.is_empty() {
+            return Err(DomainError::NotFound(format!("Trace '{}'", trace_id)));
+        }
+        Ok(span_tree(&trace_id, spans))
+    }
+
+    /// The function's slowest traces, or its erroring ones, over the range.
+    pub async fn list_traces(
+        &self,
+        function_name: &str,
+        params: &TraceParams,
+    ) -> Result<Vec<TraceSummary>, DomainError> {
+        let limit = params.limit.unwrap_or(20);
+        if limit == 0 || limit > MAX_TRACES {
+            return Err(DomainError::ValidationError(format!(
+                "`limit` must be between 1 and {}",
+                MAX_TRACES
+            )));
+        }
+        let range = RangeParams {
+            from: params.from.clone(),
+            to: params.to.clone(),
+            step: None,
+        }
+        .resolve(Utc::now())?;
+        self.telemetry_repository
+            .list_traces(function_name, &range, params.filter, limit)
             .await
-            .map_err(|e| e.to_string())
-            .map_err(|e| e.to_string())
     }
 
-    pub async fn get_overall_metrics(&self) -> Result<Vec<ExecutionMetric>, String> {
-        self.clickhouse_repository
-            .get_overall_executions("1h")
+    /// One page of log lines matching `params`, newest first.
+    pub async fn search_logs(&self, params: &LogParams) -> Result<LogPage, DomainError> {
+        let query = params.resolve(Utc::now(), 100)?;
+        let entries = self.telemetry_repository.search_logs(&query).await?;
+        let next_cursor = if entries.len() < query.limit {
+            None
+        } else {
+            entries
+                .last()
+                .and_then(LogEntry::position)
+                .and_then(format_cursor)
+        };
+        Ok(LogPage {
+            entries,
+            next_cursor,
+        })
+    }
+
+    pub async fn get_function_logs(
+        &self,
+        function_name: &str,
+        params: &LogParams,
+    ) -> Result<Vec<LogEntry>, DomainError> {
+        let params = LogParams {
+            function: Some(function_name.to_string()),
+            ..params.clone()
+        };
+        let query = params.resolve(Utc::now(), 100)?;
+        self.telemetry_repository.search_logs(&query).await
+    }
+
+    pub async fn get_recent_logs(&self, params: &LogParams) -> Result<Vec<LogEntry>, DomainError> {
+        let query = params.resolve(Utc::now(), 50)?;
+        self.telemetry_repository.search_logs(&query).await
+    }
+
+    /// Follows a function's log from now on, yielding each page of new lines oldest
+    /// first (possibly none). Lines that reach the backend up to the tail lag late
+    /// are still sent, once. `from`, `to` and `cursor` are ignored.
+    pub fn tail_logs(
+        &self,
+        params: &LogParams,
+    ) -> Result<impl Stream<Item = Vec<LogEntry>> + use<>, DomainError> {
+        if params.function.as_deref().is_none_or(str::is_empty) {
+            return Err(DomainError::ValidationError(
+                "`function` is required to tail logs".to_string(),
+            ));
+        }
+        let now = Utc::now();
+        let params = LogParams {
+            from: None,
+            to: None,
+            cursor: None,
+            ..params.clone()
+        };
+        let tail = Tail {
+            query: LogQuery {
+                after: Some(now),
+                oldest_first: true,
+                ..params.resolve(now, MAX_LOG_LIMIT)?
+            },
+            start: now,
+            sent: HashSet::new(),
+        };
+
+        let repository = self.telemetry_repository.clone();
+        let interval = self.tail_interval;
+        let lag = TimeDelta::from_std(self.tail_lag).unwrap_or(TimeDelta::MAX);
+        Ok(futures_util::stream::unfold(tail, move |mut tail| {
+            let repository = repository.clone();
+            async move {
+                // A poll starts over after its last page; lines can reach the
+                // backend late, so it reads the lag window again
+                if tail.query.cursor.is_none() {
+                    tokio::time::sleep(interval).await;
+                    let window = Utc::now().checked_sub_signed(lag);
+                    tail.query.after = Some(window.map_or(tail.start, |w| w.max(tail.start)));
+                }
+                let page = match repository.search_logs(&tail.query).await {
+                    Ok(page) => page,
+                    Err(e) => {
+                        warn!("Log tail query failed: {}", e);
+                        Vec::new()
+                    }
+                };
+                tail.query.cursor = if page.len() < tail.query.limit {
+                    None
+                } else {
+                    page.last().and_then(LogEntry::position)
+                };
+
+                let entries = page
+                    .into_iter()
+                    .filter(|entry| entry.position().is_none_or(|p| tail.sent.insert(p)))
+                    .collect();
+                // Later polls only read past `after`, so those lines can't repeat
+                if tail.query.cursor.is_none()
+                    && let Some(after) = tail.query.after
+                {
+                    tail.sent.retain(|p| p.at > after);
+                }
+                Some((entries, tail))
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::entities::FunctionStats;
+    use crate::domain::ports::MockTelemetryRepository;
+    use mockall::predicate::*;
+
+    fn params(from: Option<&str>, to: Option<&str>, step: Option<&str>) -> RangeParams {
+        RangeParams {
+            from: from.map(str::to_string),
+            to: to.map(str::to_string),
+            step: step.map(str::to_string),
+        }
+    }
+
+    fn at(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value).unwrap().to_utc()
+    }
+
+    #[test]
+    fn test_range_defaults_to_last_hour_by_minute() {
+        let now = at("2026-01-01T12:00:00Z");
+        let range = RangeParams::default().resolve(now).unwrap();
+        assert_eq!(range.from, at("2026-01-01T11:00:00Z"));
+        assert_eq!(range.to, now);
+        assert_eq!(range.step, Granularity::Minute);
+    }
+
+    #[test]
+    fn test_range_accepts_relative_and_absolute_times() {
+        let now = at("2026-01-10T00:00:00Z");
+        let range = params(Some("now-2d"), Some("2026-01-09T00:00:00Z"), None)
+            .resolve(now)
+            .unwrap();
+        assert_eq!(range.from, at("2026-01-08T00:00:00Z"));
+        assert_eq!(range.to, at("2026-01-09T00:00:00Z"));
+
+        let range = params(Some("30m"), Some("now"), None).resolve(now).unwrap();
+        assert_eq!(range.from, at("2026-01-09T23:30:00Z"));
+    }
+
+    #[test]
+    fn test_auto_step_grows_with_the_range() {
+        let now = Utc::now();
+        let step = |from| params(Some(from), None, None).resolve(now).unwrap().step;
+        assert_eq!(step("12h"), Granularity::Minute);
+        assert_eq!(step("7d"), Granularity::Hour);
+        assert_eq!(step("90d"), Granularity::Day);
+    }
+
+    #[test]
+    fn test_range_rejects_invalid_input() {
+        let now = Utc::now();
+        for p in [
+            params(Some("yesterday"), None, None),
+            params(Some("now"), Some("1h"), None),
+            params(Some("1000d"), None, None),
+            params(Some("7d"), None, Some("minute")),
+            params(None, None, Some("week")),
+        ] {
+            assert!(matches!(
+                p.resolve(now),
+                Err(DomainError::ValidationError(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_parse_instant_rejects_out_of_range_times() {
+        let now = at("2026-01-01T12:00:00Z");
+        assert_eq!(
+            parse_instant("now-6h", now).unwrap(),
+            at("2026-01-01T06:00:00Z")
+        );
+        for value in ["now-99999999d", "1000000000w", "now-1000000000000000s"] {
+            assert!(matches!(
+                parse_instant(value, now),
+                Err(DomainError::ValidationError(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_function_metrics_are_zero_filled() {
+        let mut repo = MockTelemetryRepository::new();
+        repo.expect_get_function_executions()
+            .with(eq("hello"), always())
+            .returning(|_, range| {
+                Ok(vec![ExecutionMetric {
+                    time_bucket: TimeRange::label(range.bucket_of(range.from)),
+                    count: 3,
+                }])
+            });
+
+        let service = TelemetryService::new(Arc::new(repo));
+        let metrics = service
+            .get_function_metrics("hello", &params(Some("now-3h"), None, Some("hour")))
             .await
-            .map_err(|e| e.to_string())
+            .unwrap();
+        let counts: Vec<_> = metrics.iter().map(|m| m.count).collect();
+        assert_eq!(counts.len(), 4);
+        assert_eq!(counts[0], 3);
+        assert!(counts[1..].iter().all(|c| *c == 0));
     }
 
-    pub async fn get_function_logs(&self, function_name: &str) -> Result<Vec<LogEntry>, String> {
-        self.clickhouse_repository
-            .get_function_logs(function_name)
+    #[tokio::test]
+    async fn test_performance_derives_rates_per_bucket() {
+        let mut repo = MockTelemetryRepository::new();
+        repo.expect_get_overall_performance().returning(|range| {
+            Ok(vec![BucketStats {
+                time_bucket: TimeRange::label(range.bucket_of(range.from)),
+                stats: InvocationStats {
+                    invocations: 120,
+                    errors: 30,
+                    p50_ms: 5.0,
+                    p90_ms: 9.0,
+                    p99_ms: 20.0,
+                },
+            }])
+        });
+
+        let service = TelemetryService::new(Arc::new(repo));
+        let series = service
+            .get_overall_performance(&params(Some("now-10m"), None, None))
             .await
-            .map_err(|e| e.to_string())
+            .unwrap();
+        assert_eq!(series[0].error_rate, 0.25);
+        assert_eq!(series[0].throughput, 2.0);
+        assert_eq!(series[0].stats.p99_ms, 20.0);
+        assert!(series[1..].iter().all(|m| m.stats.invocations == 0));
+        assert!(series[1..].iter().all(|m| m.error_rate == 0.0));
+    }
+
+    #[tokio::test]
+    async fn test_top_functions_ranked_by_choice() {
+        let mut repo = MockTelemetryRepository::new();
+        repo.expect_get_function_stats().returning(|_| {
+            let function = |name: &str, invocations, errors, p99_ms| FunctionStats {
+                function_name: name.to_string(),
+                stats: InvocationStats {
+                    invocations,
+                    errors,
+                    p99_ms,
+                    ..Default::default()
+                },
+            };
+            Ok(vec![
+                function("busy", 100, 1, 10.0),
+                function("flaky", 10, 5, 20.0),
+                function("slow", 20, 0, 900.0),
+            ])
+        });
+        let service = TelemetryService::new(Arc::new(repo));
+
+        let top = |by, limit| {
+            let service = service.clone();
+            async move {
+                let params = TopParams {
+                    by,
+                    limit: Some(limit),
+                    ..Default::default()
+                };
+                service
+                    .get_top_functions(&params)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|f| f.function_name)
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(top(RankBy::Invocations, 2).await, ["busy", "slow"]);
+        assert_eq!(top(RankBy::Errors, 1).await, ["flaky"]);
+        assert_eq!(top(RankBy::Latency, 3).await, ["slow", "flaky", "busy"]);
+
+        let invalid = TopParams {
+            limit: Some(0),
+            ..Default::default()
+        };
+        assert!(matches!(
+            service.get_top_functions(&invalid).await,
+            Err(DomainError::ValidationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_log_params_validated() {
+        let now = Utc::now();
+        let query = LogParams {
+            function: Some("a".to_string()),
+            level: Some("error".to_string()),
+            contains: Some(String::new()),
+            ..Default::default()
+        }
+        .resolve(now, 100)
+        .unwrap();
+        assert_eq!(query.level.as_deref(), Some("ERROR"));
+        assert_eq!(query.contains, None);
+        assert_eq!(query.limit, 100);
+
+        for params in [
+            LogParams {
+                level: Some("loud".to_string()),
+                ..Default::default()
+            },
+            LogParams {
+                limit: Some(MAX_LOG_LIMIT + 1),
+                ..Default::default()
+            },
+            LogParams {
+                cursor: Some("yesterday".to_string()),
+                ..Default::default()
+            },
+            LogParams {
+                from: Some("now".to_string()),
+                to: Some("1h".to_string()),
+                ..Default::default()
+            },
+        ] {
+            assert!(matches!(
+                params.resolve(now, 100),
+                Err(DomainError::ValidationError(_))
+            ));
+        }
+    }
+
+    fn log(timestamp: String, key: u64) -> LogEntry {
+        LogEntry {
+            timestamp,
+            level: "INFO".to_string(),
+            body: key.to_string(),
+            trace_id: String::new(),
+            function_name: "a".to_string(),
+            key,
+        }
+    }
+
+    /// Answers searches from `logs` like a backend would.
+    fn search(logs: &[LogEntry], query: &LogQuery) -> Vec<LogEntry> {
+        let mut found: Vec<LogEntry> = logs
+            .iter()
+            .filter(|e| {
+                let position = e.position().unwrap();
+                query.after.is_none_or(|a| position.at > a)
+                    && query.before.is_none_or(|b| position.at < b)
+                    && query.cursor.is_none_or(|c| {
+                        if query.oldest_first {
+                            position > c
+                        } else {
+                            position < c
+                        }
+                    })
+            })
+            .cloned()
+            .collect();
+        found.sort_by_key(|e| e.position());
+        if !query.oldest_first {
+            found.reverse();
+        }
+        found.truncate(query.limit);
+        found
     }
 
-    pub async fn get_recent_logs(&self) -> Result<Vec<LogEntry>, String> {
-        self.clickhouse_repository
-            .get_recent_logs()
+    #[tokio::test]
+    async fn test_search_logs_pages_with_cursor() {
+        // Lines logged at the same instant are split across pages without loss
+        let logs: Vec<LogEntry> = (1..=3)
+            .map(|key| log("2026-01-01 00:00:01.5".to_string(), key))
+            .collect();
+        let mut repo = MockTelemetryRepository::new();
+        repo.expect_search_logs()
+            .returning(move |query| Ok(search(&logs, query)));
+        let service = TelemetryService::new(Arc::new(repo));
+
+        let mut params = LogParams {
+            limit: Some(2),
+            ..Default::default()
+        };
+        let first = service.search_logs(&params).await.unwrap();
+        let bodies: Vec<_> = first.entries.iter().map(|e| e.body.as_str()).collect();
+        assert_eq!(bodies, ["3", "2"]);
+        params.cursor = first.next_cursor;
+        let second = service.search_logs(&params).await.unwrap();
+        let bodies: Vec<_> = second.entries.iter().map(|e| e.body.as_str()).collect();
+        assert_eq!(bodies, ["1"]);
+        assert_eq!(second.next_cursor, None);
+    }
+
+    #[tokio::test]
+    async fn test_tail_pages_through_new_lines_and_late_ones_once() {
+        use futures_util::StreamExt;
+        use std::sync::Mutex;
+
+        let soon = |millis| {
+            (Utc::now() + TimeDelta::milliseconds(millis))
+                .format(crate::domain::entities::LOG_TIMESTAMP_FORMAT)
+                .to_string()
+        };
+        let logs = Arc::new(Mutex::new(vec![
+            log(soon(1000), 1),
+            log(soon(1000), 2),
+            log(soon(1000), 3),
+        ]));
+        let stored = logs.clone();
+        let mut repo = MockTelemetryRepository::new();
+        repo.expect_search_logs()
+            .returning(move |query| Ok(search(&stored.lock().unwrap(), query)));
+        let service = TelemetryService::new(Arc::new(repo))
+            .with_tail_interval(Duration::from_millis(1))
+            .with_tail_lag(Duration::from_secs(60));
+
+        let params = LogParams {
+            function: Some("a".to_string()),
+            limit: Some(2),
+            ..Default::default()
+        };
+        let mut tail = Box::pin(service.tail_logs(&params).unwrap());
+        let bodies =
+            |entries: Vec<LogEntry>| entries.into_iter().map(|e| e.body).collect::<Vec<_>>();
+        // More new lines than fit in a page come in consecutive pages
+        assert_eq!(bodies(tail.next().await.unwrap()), ["1", "2"]);
+        assert_eq!(bodies(tail.next().await.unwrap()), ["3"]);
+
+        // A line exported after later ones is still sent, and nothing repeats
+        logs.lock().unwrap().push(log(soon(500), 4));
+        let mut late = Vec::new();
+        for _ in 0..3 {
+            late.extend(bodies(tail.next().await.unwrap()));
+        }
+        assert_eq!(late, ["4"]);
+    }
+
+    fn span(id: &str, parent: &str, start: &str, duration_ms: f64) -> TraceSpan {
+        TraceSpan {
+            trace_id: "0af7651916cd43dd8448eb211c80319c".to_string(),
+            span_id: id.to_string(),
+            parent_span_id: parent.to_string(),
+            name: id.to_string(),
+            service_name: "fluor-api".to_string(),
+            start_time: format!("2026-01-01 00:00:{}", start),
+            duration_ms,
+            status_code: "Unset".to_string(),
+            status_message: String::new(),
+            attributes: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_trace_spans_nested_by_parent() {
+        let mut repo = MockTelemetryRepository::new();
+        repo.expect_get_trace_spans()
+            .with(eq("0af7651916cd43dd8448eb211c80319c"))
+            .returning(|_| {
+                Ok(vec![
+                    span("call", "root", "00.300", 100.0),
+                    span("root", "", "00.000", 500.0),
+                    span("orphan", "gone", "01.000", 50.0),
+                    span("invoke", "root", "00.100", 150.0),
+                ])
+            });
+        let service = TelemetryService::new(Arc::new(repo));
+
+        let trace = service
+            .get_trace("0AF7651916CD43DD8448EB211C80319C")
             .await
-            .map_err(|e| e.to_string())
+            .unwrap();
+        assert_eq!(trace.span_count, 4);
+        assert_eq!(trace.duration_ms, 1050.0);
+        assert_eq!(trace.roots.len(), 2);
+        assert_eq!(trace.roots[0].span.span_id, "root");
+        let children: Vec<_> = trace.roots[0]
+            .children
+            .iter()
+            .map(|c| c.span.span_id.as_str())
+            .collect();
+        assert_eq!(children, ["invoke", "call"]);
+        assert_eq!(trace.roots[1].span.span_id, "orphan");
+
+        assert!(matches!(
+            service.get_trace("not-a-trace").await,
+            Err(DomainError::ValidationError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_trace_not_found() {
+        let mut repo = MockTelemetryRepository::new();
+        repo.expect_get_trace_spans().returning(|_| Ok(Vec::new()));
+        let service = TelemetryService::new(Arc::new(repo));
+
+        assert!(matches!(
+            service.get_trace("0af7651916cd43dd8448eb211c80319c").await,
+            Err(DomainError::NotFound(_))
+        ));
     }
 }
@@ -1,12 +1,16 @@
+use crate::application::audit_service::AuditService;
 use crate::application::invocation_service::InvocationService;
-use crate::domain::entities::{DomainError, Trigger};
+use crate::application::response_cache::ResponseCache;
+use crate::domain::entities::{Actor, AuditAction, DomainError, Trigger};
 use crate::domain::ports::TriggerRepository;
 use std::sync::Arc;
-use tracing::warn;
+use tracing::{info, warn};
 
 pub struct TriggerService {
     repository: Arc<dyn TriggerRepository>,
     invocation_service: Arc<InvocationService>,
+    audit: Option<Arc<AuditService>>,
+    response_cache: Option<Arc<ResponseCache>>,
 }
 
 impl TriggerService {
@@ -17,14 +21,71 @@ impl TriggerService {
         Self {
             repository,
             invocation_service,
+            audit: None,
+            response_cache: None,
         }
     }
 
-    pub async fn create_trigger(&self, trigger: Trigger) -> Result<Trigger, DomainError> {
-        let created = self.repository.save(&trigger).await?;
+    /// Records created and deleted triggers in the audit log.
+    pub fn with_audit_log(mut self, audit: Arc<AuditService>) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    /// Drops a deleted trigger's cached responses, which a trigger later
+    /// created under the same name would otherwise serve.
+    pub fn with_response_cache(mut self, response_cache: Arc<ResponseCache>) -> Self {
+        self.response_cache = Some(response_cache);
+        self
+    }
+
+    /// Rejects settings the gateway can't serve, before anything is stored.
+    pub fn validate(&self, trigger: &Trigger) -> Result<(), DomainError> {
+        if let Some(limit) = &trigger.rate_limit
+            && (limit.requests == 0 || limit.period_secs == 0 || limit.burst == Some(0))
+        {
+            return Err(DomainError::ValidationError(
+                "Rate limit requests, period_secs and burst must be positive".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Reloads the gateway routes from the stored triggers and functions.
+    pub async fn refresh_routes(&self) {
         if let Err(e) = self.invocation_service.load_routes().await {
             warn!("Failed to refresh routes: {}", e);
         }
+    }
+
+    pub fn purge_cached_responses(&self, name: &str) {
+        if let Some(cache) = &self.response_cache {
+            let purged = cache.purge_trigger(name);
+            if purged > 0 {
+                info!("Purged {} cached responses of trigger {}", purged, name);
+            }
+        }
+    }
+
+    pub async fn create_trigger(
+        &self,
+        trigger: Trigger,
+        actor: &Actor,
+    ) -> Result<Trigger, DomainError> {
+        self.validate(&trigger)?;
+        let created = self.repository.save(&trigger).await?;
+        self.refresh_routes().await;
+        if let Some(audit) = &self.audit {
+            audit
+                .record(
+                    actor,
+                    AuditAction::TriggerCreate,
+                    &created.name,
+                    None,
+                    Some(&created),
+                )
+                .await;
+        }
         Ok(created)
     }
 
@@ -32,10 +93,29 @@ impl TriggerService {
         self.repository.find_all().await
     }
 
-    pub async fn delete_trigger(&self, name: &str) -> Result<(), DomainError> {
+    pub async fn delete_trigger(&self, name: &str, actor: &Actor) -> Result<(), DomainError> {
+        let current = match &self.audit {
+            Some(_) => self
+                .repository
+                .find_all()
+                .await?
+                .into_iter()
+                .find(|t| t.name == name),
+            None => None,
+        };
         self.repository.delete(name).await?;
-        if let Err(e) = self.invocation_service.load_routes().await {
-            warn!("Failed to refresh routes: {}", e);
+        self.refresh_routes().await;
+        self.purge_cached_responses(name);
+        if let Some(audit) = &self.audit {
+            audit
+                .record(
+                    actor,
+                    AuditAction::TriggerDelete,
+                    name,
+                    current.as_ref(),
+                    None,
+                )
+                .await;
         }
         Ok(())
     }
@@ -44,6 +124,7 @@ impl TriggerService {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::domain::entities::TriggerKind;
     use crate::domain::ports::{MockFunctionRepository, MockTriggerRepository};
     use crate::domain::wasm_runtime::MockWasmRuntime;
     use mockall::predicate::*;
@@ -59,6 +140,9 @@ mod tests {
             function_name: "test-func".to_string(),
             method: "GET".to_string(),
             path: "/test".to_string(),
+            kind: TriggerKind::Http,
+            rate_limit: None,
+            cache: None,
             readonly: false,
         };
 
@@ -80,7 +164,7 @@ mod tests {
 
         let service = TriggerService::new(trigger_repo_arc, invocation_service);
 
-        let result = service.create_trigger(trigger).await;
+        let result = service.create_trigger(trigger, &Actor::system()).await;
 
         assert!(result.is_ok());
         let created = result.unwrap();
@@ -98,6 +182,9 @@ mod tests {
                 function_name: "f1".to_string(),
                 method: "GET".to_string(),
                 path: "/1".to_string(),
+                kind: TriggerKind::Http,
+                rate_limit: None,
+                cache: None,
                 readonly: false,
             }])
         });
@@ -143,7 +230,40 @@ mod tests {
             )),
         );
 
-        let result = service.delete_trigger("t1").await;
+        let result = service.delete_trigger("t1", &Actor::system()).await;
         assert!(result.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_delete_trigger_purges_cached_responses() {
+        let mut trigger_repo = MockTriggerRepository::new();
+        trigger_repo
+            .expect_delete()
+            .with(eq("t1"))
+            .returning(|_| Ok(()));
+        trigger_repo.expect_find_all().returning(|| Ok(vec![]));
+        let trigger_repo_arc = Arc::new(trigger_repo);
+
+        let cache = Arc::new(ResponseCache::new(1024));
+        let ttl = std::time::Duration::from_secs(60);
+        cache.insert("a".into(), "t1", "f1", "a".into(), ttl);
+        cache.insert("b".into(), "t2", "f1", "b".into(), ttl);
+
+        let service = TriggerService::new(
+            trigger_repo_arc.clone(),
+            Arc::new(InvocationService::new(
+                trigger_repo_arc,
+                Arc::new(MockFunctionRepository::new()),
+                Arc::new(MockWasmRuntime::new()),
+            )),
+        )
+        .with_response_cache(cache.clone());
+
+        service
+            .delete_trigger("t1", &Actor::system())
+            .await
+            .unwrap();
+        assert!(cache.get("a").is_none());
+        assert!(cache.get("b").is_some());
+    }
 }
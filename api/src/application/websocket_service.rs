@@ -0,0 +1,546 @@
+use crate::application::admission::SessionPermit;
+use crate::application::invocation_service::InvocationService;
+use crate::domain::entities::DomainError;
+use crate::domain::wasm_runtime::WasmSession;
+use ahash::RandomState;
+use opentelemetry::{KeyValue, global};
+use papaya::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap as StdHashMap, HashSet};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tracing::{error, info, warn};
+
+#[derive(Debug, Clone)]
+pub struct WebSocketLimits {
+    pub max_connections_per_function: usize,
+    pub max_message_bytes: usize,
+    pub max_queued_messages: usize,
+    pub idle_timeout: Duration,
+}
+
+impl Default for WebSocketLimits {
+    fn default() -> Self {
+        Self {
+            max_connections_per_function: 1000,
+            max_message_bytes: 64 * 1024,
+            max_queued_messages: 256,
+            idle_timeout: Duration::from_secs(300),
+        }
+    }
+}
+
+impl WebSocketLimits {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            max_connections_per_function: env_or(
+                "WS_MAX_CONNECTIONS_PER_FUNCTION",
+                defaults.max_connections_per_function,
+            ),
+            max_message_bytes: env_or("WS_MAX_MESSAGE_BYTES", defaults.max_message_bytes),
+            max_queued_messages: env_or("WS_MAX_QUEUED_MESSAGES", defaults.max_queued_messages)
+                .max(1),
+            idle_timeout: Duration::from_secs(env_or(
+                "WS_IDLE_TIMEOUT_SECS",
+                defaults.idle_timeout.as_secs(),
+            )),
+        }
+    }
+}
+
+fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    std::env::var(key)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Event delivered to the guest's `handle` export for each connection callback.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+enum SocketEvent<'a> {
+    Connect {
+        connection_id: &'a str,
+        path: &'a str,
+    },
+    Message {
+        connection_id: &'a str,
+        data: &'a str,
+    },
+    Disconnect {
+        connection_id: &'a str,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct Broadcast {
+    channel: String,
+    data: String,
+}
+
+/// What the guest asks the host to do after handling an event. Any output that
+/// isn't exactly this shape is sent back to the connection verbatim.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SocketActions {
+    #[serde(default)]
+    send: Vec<String>,
+    #[serde(default)]
+    broadcast: Vec<Broadcast>,
+    #[serde(default)]
+    subscribe: Vec<String>,
+    #[serde(default)]
+    unsubscribe: Vec<String>,
+    #[serde(default)]
+    close: bool,
+}
+
+impl SocketActions {
+    fn parse(output: &str) -> Self {
+        match serde_json::from_str(output) {
+            Ok(actions) => actions,
+            Err(_) if output.is_empty() => Self::default(),
+            Err(_) => Self {
+                send: vec![output.to_string()],
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Queue of the text messages to push to one client. A client that lets it fill
+/// up is flagged as lagging and disconnected, and messages meanwhile are dropped.
+#[derive(Clone)]
+struct Outbound {
+    sender: mpsc::Sender<String>,
+    lagging: Arc<AtomicBool>,
+}
+
+impl Outbound {
+    fn send(&self, message: String) {
+        if let Err(TrySendError::Full(_)) = self.sender.try_send(message) {
+            self.lagging.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Channel name -> connection id -> outbound queue of that connection.
+#[derive(Default)]
+struct Channels {
+    members: Mutex<StdHashMap<String, StdHashMap<String, Outbound>>>,
+}
+
+impl Channels {
+    fn subscribe(&self, channel: &str, connection_id: &str, outbound: &Outbound) {
+        let mut members = self.members.lock().unwrap();
+        members
+            .entry(channel.to_string())
+            .or_default()
+            .insert(connection_id.to_string(), outbound.clone());
+    }
+
+    fn unsubscribe(&self, channel: &str, connection_id: &str) {
+        let mut members = self.members.lock().unwrap();
+        if let Some(subscribers) = members.get_mut(channel) {
+            subscribers.remove(connection_id);
+            if subscribers.is_empty() {
+                members.remove(channel);
+            }
+        }
+    }
+
+    fn broadcast(&self, channel: &str, data: &str) {
+        let members = self.members.lock().unwrap();
+        if let Some(subscribers) = members.get(channel) {
+            for outbound in subscribers.values() {
+                outbound.send(data.to_string());
+            }
+        }
+    }
+}
+
+/// Releases a connection slot of a function when the connection is dropped.
+struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub struct WebSocketService {
+    invocation_service: Arc<InvocationService>,
+    channels: Arc<Channels>,
+    connections: Arc<HashMap<String, Arc<AtomicUsize>, RandomState>>,
+    limits: WebSocketLimits,
+}
+
+impl WebSocketService {
+    pub fn new(invocation_service: Arc<InvocationService>, limits: WebSocketLimits) -> Self {
+        Self {
+            invocation_service,
+            channels: Arc::new(Channels::default()),
+            connections: Arc::new(HashMap::builder().hasher(RandomState::new()).build()),
+            limits,
+        }
+    }
+
+    pub fn limits(&self) -> &WebSocketLimits {
+        &self.limits
+    }
+
+    fn acquire_slot(&self, function_name: &str) -> Result<ConnectionSlot, DomainError> {
+        let counter = self
+            .connections
+            .pin()
+            .get_or_insert_with(function_name.to_string(), || Arc::new(AtomicUsize::new(0)))
+            .clone();
+
+        let max = self.limits.max_connections_per_function;
+        counter
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < max).then_some(n + 1)
+            })
+            .map_err(|_| {
+                DomainError::LimitExceeded(format!(
+                    "Function '{}' reached {} WebSocket connections",
+                    function_name, max
+                ))
+            })?;
+
+        Ok(ConnectionSlot(counter))
+    }
+
+    /// Opens a connection on the WebSocket trigger bound to `path`, instantiating the
+    /// function for the lifetime of the connection and delivering the `connect` event.
+    /// The returned receiver yields the text messages to push to the client.
+    pub async fn connect(
+        &self,
+        path: &str,
+    ) -> Result<(WebSocketConnection, mpsc::Receiver<String>), DomainError> {
+        let func = self
+            .invocation_service
+            .resolve_websocket(path)
+            .ok_or_else(|| DomainError::NotFound("Route not found".into()))?;
+
+        let runtime = func.runtime.clone().ok_or_else(|| {
+            error!("Runtime detached for function {}", func.name);
+            DomainError::Internal("Runtime detached".into())
+        })?;
+
+        let slot = self.acquire_slot(&func.name)?;
+        let instance = self.invocation_service.admission().admit_session()?;
+
+        let session = runtime
+            .open_session(&func.name)
+            .await
+            .map_err(|e| DomainError::Internal(e.to_string()))?;
+
+        let (sender, receiver) = mpsc::channel(self.limits.max_queued_messages);
+        let outbound = Outbound {
+            sender,
+            lagging: Arc::new(AtomicBool::new(false)),
+        };
+        let mut connection = WebSocketConnection {
+            id: uuid::Uuid::new_v4().to_string(),
+            function_name: func.name,
+            session,
+            outbound,
+            subscriptions: HashSet::new(),
+            channels: self.channels.clone(),
+            closed: false,
+            _slot: slot,
+            _instance: instance,
+        };
+
+        info!(
+            function_name = connection.function_name,
+            connection_id = connection.id,
+            "WebSocket connected"
+        );
+
+        let id = connection.id.clone();
+        let event = SocketEvent::Connect {
+            connection_id: &id,
+            path,
+        };
+        connection.dispatch(&event).await?;
+
+        Ok((connection, receiver))
+    }
+}
+
+pub struct WebSocketConnection {
+    pub id: String,
+    function_name: String,
+    session: Box<dyn WasmSession>,
+    outbound: Outbound,
+    subscriptions: HashSet<String>,
+    channels: Arc<Channels>,
+    closed: bool,
+    _slot: ConnectionSlot,
+    _instance: SessionPermit,
+}
+
+impl WebSocketConnection {
+    /// Whether the function asked for the connection to be closed.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Whether the client fell `max_queued_messages` behind what was sent to it.
+    pub fn is_lagging(&self) -> bool {
+        self.outbound.lagging.load(Ordering::SeqCst)
+    }
+
+    pub async fn on_message(&mut self, data: &str) -> Result<(), DomainError> {
+        let id = self.id.clone();
+        let event = SocketEvent::Message {
+            connection_id: &id,
+            data,
+        };
+        self.dispatch(&event).await
+    }
+
+    pub async fn disconnect(mut self) {
+        let id = self.id.clone();
+        let event = SocketEvent::Disconnect { connection_id: &id };
+        if let Err(e) = self.dispatch(&event).await {
+            warn!(connection_id = id, "Disconnect callback failed: {}", e);
+        }
+
+        for channel in &self.subscriptions {
+            self.channels.unsubscribe(channel, &self.id);
+        }
+
+        info!(
+            function_name = self.function_name,
+            connection_id = self.id,
+            "WebSocket disconnected"
+        );
+    }
+
+    async fn dispatch(&mut self, event: &SocketEvent<'_>) -> Result<(), DomainError> {
+        let input =
+            serde_json::to_string(event).map_err(|e| DomainError::Internal(e.to_string()))?;
+
+        let start = Instant::now();
+        let result = self.session.invoke(&input).await;
+        let duration_ms = start.elapsed().as_millis() as u64;
+
+        let status = if result.is_ok() { "ok" } else { "error" };
+        let attrs = [
+            KeyValue::new("function_name", self.function_name.clone()),
+            KeyValue::new("status", status.to_string()),
+            KeyValue::new("trigger", "websocket"),
+        ];
+        let meter = global::meter("fluor-api");
+        meter
+            .u64_counter("function_invocations")
+            .build()
+            .add(1, &attrs);
+        meter
+            .u64_histogram("function_duration_ms")
+            .build()
+            .record(duration_ms, &attrs);
+
+        let output = result.map_err(|e| {
+            error!(error = %e, "WebSocket callback failed");
+            DomainError::Internal(e.to_string())
+        })?;
+
+        self.apply(SocketActions::parse(&output));
+        Ok(())
+    }
+
+    fn apply(&mut self, actions: SocketActions) {
+        for channel in actions.subscribe {
+            self.channels.subscribe(&channel, &self.id, &self.outbound);
+            self.subscriptions.insert(channel);
+        }
+        for channel in actions.unsubscribe {
+            self.channels.unsubscribe(&channel, &self.id);
+            self.subscriptions.remove(&channel);
+        }
+        for message in actions.send {
+            self.outbound.send(message);
+        }
+        for broadcast in actions.broadcast {
+            self.channels.broadcast(&broadcast.channel, &broadcast.data);
+        }
+        self.closed |= actions.close;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::admission::AdmissionController;
+    use crate::domain::entities::{Function, Trigger, TriggerKind};
+    use crate::domain::ports::{MockFunctionRepository, MockTriggerRepository};
+    use crate::domain::wasm_runtime::{MockWasmRuntime, MockWasmSession};
+    use mockall::predicate::*;
+
+    /// Guest that joins the `room` channel on connect and broadcasts every message.
+    fn chat_session() -> MockWasmSession {
+        let mut session = MockWasmSession::new();
+        session.expect_invoke().returning(|input| {
+            let event: serde_json::Value = serde_json::from_str(input).unwrap();
+            let output = match event["event"].as_str().unwrap() {
+                "connect" => r#"{"subscribe": ["room"], "send": ["welcome"]}"#.to_string(),
+                "message" => serde_json::json!({
+                    "broadcast": [{ "channel": "room", "data": event["data"] }],
+                    "close": event["data"] == "bye",
+                })
+                .to_string(),
+                _ => String::new(),
+            };
+            Ok(output)
+        });
+        session
+    }
+
+    async fn service(limits: WebSocketLimits, admission: AdmissionController) -> WebSocketService {
+        let mut trigger_repo = MockTriggerRepository::new();
+        let mut function_repo = MockFunctionRepository::new();
+        let mut runtime = MockWasmRuntime::new();
+
+        trigger_repo.expect_find_all().returning(|| {
+            Ok(vec![Trigger {
+                name: "chat".to_string(),
+                function_name: "chat-func".to_string(),
+                method: "GET".to_string(),
+                path: "/chat".to_string(),
+                kind: TriggerKind::WebSocket,
+                rate_limit: None,
+                cache: None,
+                readonly: false,
+            }])
+        });
+
+        function_repo
+            .expect_find_by_name()
+            .with(eq("chat-func"))
+            .returning(|_| {
+                Ok(Some(Function {
+                    name: "chat-func".to_string(),
+                    ..Default::default()
+                }))
+            });
+
+        runtime
+            .expect_open_session()
+            .with(eq("chat-func"))
+            .returning(|_| Ok(Box::new(chat_session())));
+
+        let invocation_service = Arc::new(
+            InvocationService::new(
+                Arc::new(trigger_repo),
+                Arc::new(function_repo),
+                Arc::new(runtime),
+            )
+            .with_admission(admission),
+        );
+        invocation_service.load_routes().await.unwrap();
+
+        WebSocketService::new(invocation_service, limits)
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_between_connections() {
+        let service = service(WebSocketLimits::default(), AdmissionController::unbounded()).await;
+
+        let (mut alice, mut alice_rx) = service.connect("/chat").await.unwrap();
+        let (_bob, mut bob_rx) = service.connect("/chat").await.unwrap();
+        assert_eq!(alice_rx.recv().await.unwrap(), "welcome");
+        assert_eq!(bob_rx.recv().await.unwrap(), "welcome");
+
+        alice.on_message("hello").await.unwrap();
+        assert_eq!(alice_rx.recv().await.unwrap(), "hello");
+        assert_eq!(bob_rx.recv().await.unwrap(), "hello");
+        assert!(!alice.is_closed());
+
+        alice.on_message("bye").await.unwrap();
+        assert!(alice.is_closed());
+        alice.disconnect().await;
+
+        assert_eq!(bob_rx.recv().await.unwrap(), "bye");
+        assert!(bob_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_slow_client_is_flagged_instead_of_buffered() {
+        let service = service(
+            WebSocketLimits {
+                max_queued_messages: 2,
+                ..Default::default()
+            },
+            AdmissionController::unbounded(),
+        )
+        .await;
+
+        let (mut alice, mut alice_rx) = service.connect("/chat").await.unwrap();
+        let (bob, mut bob_rx) = service.connect("/chat").await.unwrap();
+        for message in ["one", "two", "three"] {
+            alice.on_message(message).await.unwrap();
+            while alice_rx.try_recv().is_ok() {}
+        }
+        assert!(!alice.is_lagging());
+        assert!(bob.is_lagging());
+
+        assert_eq!(bob_rx.recv().await.unwrap(), "welcome");
+        assert_eq!(bob_rx.recv().await.unwrap(), "one");
+        assert!(bob_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_connection_limit() {
+        let service = service(
+            WebSocketLimits {
+                max_connections_per_function: 1,
+                ..Default::default()
+            },
+            AdmissionController::unbounded(),
+        )
+        .await;
+
+        let (first, _rx) = service.connect("/chat").await.unwrap();
+        let result = service.connect("/chat").await;
+        assert!(matches!(result, Err(DomainError::LimitExceeded(_))));
+
+        first.disconnect().await;
+        assert!(service.connect("/chat").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_session_limit() {
+        let admission = AdmissionController::new(10, Duration::from_secs(5)).with_max_sessions(1);
+        let service = service(WebSocketLimits::default(), admission).await;
+
+        let (first, _rx) = service.connect("/chat").await.unwrap();
+        let result = service.connect("/chat").await;
+        assert!(matches!(result, Err(DomainError::Overloaded(_))));
+
+        first.disconnect().await;
+        assert!(service.connect("/chat").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_connect_unknown_route() {
+        let service = service(WebSocketLimits::default(), AdmissionController::unbounded()).await;
+        let result = service.connect("/missing").await;
+        assert!(matches!(result, Err(DomainError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_plain_output_is_sent_verbatim() {
+        let actions = SocketActions::parse(r#"{"status": "ok"}"#);
+        assert_eq!(actions.send, vec![r#"{"status": "ok"}"#.to_string()]);
+        assert!(SocketActions::parse("").send.is_empty());
+    }
+}
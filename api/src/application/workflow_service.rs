@@ -0,0 +1,735 @@
+use crate::application::audit_service::AuditService;
+use crate::application::invocation_service::InvocationService;
+use crate::domain::entities::{
+    Actor, AuditAction, DomainError, RunStatus, StepCondition, StepRun, StepStatus, Workflow,
+    WorkflowRun, WorkflowStep,
+};
+use crate::domain::ports::{FunctionRepository, WorkflowRepository};
+use chrono::Utc;
+use futures_util::future::join_all;
+use opentelemetry::{KeyValue, global};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// Runs workflows step by step, persisting every transition so runs survive a restart.
+pub struct WorkflowService {
+    repository: Arc<dyn WorkflowRepository>,
+    function_repository: Arc<dyn FunctionRepository>,
+    invocation_service: Arc<InvocationService>,
+    audit: Option<Arc<AuditService>>,
+}
+
+enum Next {
+    Run(String),
+    Skip,
+}
+
+impl WorkflowService {
+    pub fn new(
+        repository: Arc<dyn WorkflowRepository>,
+        function_repository: Arc<dyn FunctionRepository>,
+        invocation_service: Arc<InvocationService>,
+    ) -> Self {
+        Self {
+            repository,
+            function_repository,
+            invocation_service,
+            audit: None,
+        }
+    }
+
+    /// Records workflow definition changes in the audit log.
+    pub fn with_audit_log(mut self, audit: Arc<AuditService>) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    async fn audit(
+        &self,
+        actor: &Actor,
+        action: AuditAction,
+        name: &str,
+        before: Option<&Workflow>,
+        after: Option<&Workflow>,
+    ) {
+        if let Some(audit) = &self.audit {
+            audit.record(actor, action, name, before, after).await;
+        }
+    }
+
+    pub async fn list_workflows(&self) -> Result<Vec<Workflow>, DomainError> {
+        self.repository.find_workflows().await
+    }
+
+    pub async fn get_workflow(&self, name: &str) -> Result<Workflow, DomainError> {
+        self.repository
+            .find_workflow(name)
+            .await?
+            .ok_or_else(|| DomainError::NotFound(format!("Workflow '{}' not found", name)))
+    }
+
+    pub async fn create_workflow(
+        &self,
+        workflow: Workflow,
+        actor: &Actor,
+    ) -> Result<Workflow, DomainError> {
+        if self
+            .repository
+            .find_workflow(&workflow.name)
+            .await?
+            .is_some()
+        {
+            return Err(DomainError::AlreadyExists(format!(
+                "Workflow '{}' already exists",
+                workflow.name
+            )));
+        }
+        self.validate(&workflow).await?;
+        let created = self.repository.save_workflow(&workflow).await?;
+        self.audit(
+            actor,
+            AuditAction::WorkflowCreate,
+            &created.name,
+            None,
+            Some(&created),
+        )
+        .await;
+        Ok(created)
+    }
+
+    /// Replaces a workflow's steps; runs already started keep their original definition.
+    pub async fn update_workflow(
+        &self,
+        name: &str,
+        mut workflow: Workflow,
+        actor: &Actor,
+    ) -> Result<Workflow, DomainError> {
+        let existing = self.get_workflow(name).await?;
+        workflow.name = name.to_string();
+        self.validate(&workflow).await?;
+        let updated = self.repository.save_workflow(&workflow).await?;
+        self.audit(
+            actor,
+            AuditAction::WorkflowUpdate,
+            name,
+            Some(&existing),
+            Some(&updated),
+        )
+        .await;
+        Ok(updated)
+    }
+
+    pub async fn delete_workflow(&self, name: &str, actor: &Actor) -> Result<(), DomainError> {
+        let current = match &self.audit {
+            Some(_) => self.repository.find_workflow(name).await?,
+            None => None,
+        };
+        self.repository.delete_workflow(name).await?;
+        self.audit(
+            actor,
+            AuditAction::WorkflowDelete,
+            name,
+            current.as_ref(),
+            None,
+        )
+        .await;
+        Ok(())
+    }
+
+    pub async fn list_runs(&self, workflow: &str) -> Result<Vec<WorkflowRun>, DomainError> {
+        self.repository.find_runs(workflow).await
+    }
+
+    pub async fn get_run(&self, workflow: &str, id: &str) -> Result<WorkflowRun, DomainError> {
+        self.repository
+            .find_run(id)
+            .await?
+            .filter(|run| run.workflow == workflow)
+            .ok_or_else(|| DomainError::NotFound(format!("Run '{}' not found", id)))
+    }
+
+    /// Checks that steps form a DAG of existing functions with well-formed conditions.
+    async fn validate(&self, workflow: &Workflow) -> Result<(), DomainError> {
+        let invalid = |msg: String| Err(DomainError::ValidationError(msg));
+
+        if workflow.name.trim().is_empty() {
+            return invalid("Workflow name is required".to_string());
+        }
+        if workflow.steps.is_empty() {
+            return invalid("Workflow needs at least one step".to_string());
+        }
+
+        let mut names = HashSet::new();
+        for step in &workflow.steps {
+            if !names.insert(step.name.as_str()) {
+                return invalid(format!("Duplicate step '{}'", step.name));
+            }
+        }
+
+        for step in &workflow.steps {
+            for dep in &step.after {
+                if dep == &step.name || !names.contains(dep.as_str()) {
+                    return invalid(format!(
+                        "Step '{}' depends on unknown step '{}'",
+                        step.name, dep
+                    ));
+                }
+            }
+            if let Some(when) = &step.when
+                && !step.after.contains(&when.step)
+            {
+                return invalid(format!(
+                    "Condition of step '{}' must refer to one of its dependencies",
+                    step.name
+                ));
+            }
+            if self
+                .function_repository
+                .find_by_name(&step.function)
+                .await?
+                .is_none()
+            {
+                return invalid(format!(
+                    "Step '{}' calls unknown function '{}'",
+                    step.name, step.function
+                ));
+            }
+        }
+
+        // Kahn's algorithm: every step must become ready once its dependencies are done
+        let mut remaining: HashMap<&str, usize> = workflow
+            .steps
+            .iter()
+            .map(|s| (s.name.as_str(), s.after.len()))
+            .collect();
+        let mut ready: Vec<&str> = remaining
+            .iter()
+            .filter(|(_, n)| **n == 0)
+            .map(|(name, _)| *name)
+            .collect();
+        let mut visited = 0;
+        while let Some(done) = ready.pop() {
+            visited += 1;
+            for step in &workflow.steps {
+                if step.after.iter().any(|d| d == done)
+                    && let Some(n) = remaining.get_mut(step.name.as_str())
+                {
+                    *n -= 1;
+                    if *n == 0 {
+                        ready.push(step.name.as_str());
+                    }
+                }
+            }
+        }
+        if visited != workflow.steps.len() {
+            return invalid("Workflow steps contain a cycle".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Persists a new run of `workflow` and executes it in the background.
+    pub async fn start(
+        self: &Arc<Self>,
+        workflow: &str,
+        input: &str,
+    ) -> Result<WorkflowRun, DomainError> {
+        let definition = self.get_workflow(workflow).await?;
+        let now = Utc::now();
+        let run = WorkflowRun {
+            id: uuid::Uuid::new_v4().to_string(),
+            workflow: definition.name.clone(),
+            status: RunStatus::Running,
+            input: input.to_string(),
+            output: None,
+            error: None,
+            created_at: now,
+            updated_at: now,
+            steps: definition
+                .steps
+                .iter()
+                .map(|s| StepRun {
+                    step: s.name.clone(),
+                    status: StepStatus::Pending,
+                    input: None,
+                    output: None,
+                    error: None,
+                    attempts: 0,
+                })
+                .collect(),
+            definition,
+        };
+        self.repository.save_run(&run).await?;
+        info!(workflow, run_id = run.id, "Workflow run started");
+
+        tokio::spawn(self.clone().execute(run.clone()));
+        Ok(run)
+    }
+
+    /// Picks up runs left unfinished by a previous process. Steps that were running
+    /// when it stopped are executed again.
+    pub async fn resume(self: &Arc<Self>) -> Result<usize, DomainError> {
+        let runs = self.repository.find_unfinished_runs().await?;
+        let count = runs.len();
+        for mut run in runs {
+            for step in &mut run.steps {
+                if step.status == StepStatus::Running {
+                    step.status = StepStatus::Pending;
+                }
+            }
+            info!(
+                workflow = run.workflow,
+                run_id = run.id,
+                "Resuming workflow run"
+            );
+            tokio::spawn(self.clone().execute(run));
+        }
+        Ok(count)
+    }
+
+    async fn execute(self: Arc<Self>, mut run: WorkflowRun) {
+        if let Err(e) = self.drive(&mut run).await {
+            error!(run_id = run.id, "Workflow run aborted: {}", e);
+            run.status = RunStatus::Failed;
+            run.error = Some(e.to_string());
+            run.updated_at = Utc::now();
+            if let Err(e) = self.repository.save_run(&run).await {
+                error!(run_id = run.id, "Failed to persist workflow run: {}", e);
+            }
+        }
+    }
+
+    /// Runs every step whose dependencies are done, in parallel, until none are left.
+    async fn drive(&self, run: &mut WorkflowRun) -> Result<(), DomainError> {
+        loop {
+            let next: Vec<(usize, Next)> = (0..run.steps.len())
+                .filter(|&i| run.steps[i].status == StepStatus::Pending)
+                .filter_map(|i| next_action(run, &run.definition.steps[i]).map(|n| (i, n)))
+                .collect();
+            if next.is_empty() {
+                break;
+            }
+
+            let mut ready = Vec::new();
+            for (i, action) in next {
+                let state = &mut run.steps[i];
+                match action {
+                    Next::Skip => state.status = StepStatus::Skipped,
+                    Next::Run(input) => {
+                        state.status = StepStatus::Running;
+                        state.input = Some(input);
+                        ready.push(i);
+                    }
+                }
+                self.repository.save_step(&run.id, state).await?;
+            }
+
+            let results = join_all(
+                ready
+                    .iter()
+                    .map(|&i| self.run_step(&run.id, &run.definition.steps[i], &run.steps[i])),
+            )
+            .await;
+            for (i, result) in ready.into_iter().zip(results) {
+                run.steps[i] = result?;
+            }
+
+            if let Some(failed) = run.steps.iter().find(|s| s.status == StepStatus::Failed) {
+                run.status = RunStatus::Failed;
+                run.error = Some(format!(
+                    "Step '{}' failed: {}",
+                    failed.step,
+                    failed.error.as_deref().unwrap_or("unknown error")
+                ));
+                for step in &mut run.steps {
+                    if step.status == StepStatus::Pending {
+                        step.status = StepStatus::Skipped;
+                    }
+                }
+                return self.finish(run).await;
+            }
+        }
+
+        let sinks: Vec<&StepRun> = run
+            .definition
+            .steps
+            .iter()
+            .zip(&run.steps)
+            .filter(|(def, _)| {
+                !run.definition
+                    .steps
+                    .iter()
+                    .any(|s| s.after.contains(&def.name))
+            })
+            .map(|(_, state)| state)
+            .filter(|s| s.status == StepStatus::Succeeded)
+            .collect();
+        run.output = (!sinks.is_empty()).then(|| combine_outputs(&sinks));
+        run.status = RunStatus::Succeeded;
+        self.finish(run).await
+    }
+
+    async fn finish(&self, run: &mut WorkflowRun) -> Result<(), DomainError> {
+        run.updated_at = Utc::now();
+        self.repository.save_run(run).await?;
+
+        let status = if run.status == RunStatus::Succeeded {
+            "succeeded"
+        } else {
+            "failed"
+        };
+        info!(
+            workflow = run.workflow,
+            run_id = run.id,
+            "Workflow run {}",
+            status
+        );
+        global::meter("fluor-api")
+            .u64_counter("workflow_runs")
+            .build()
+            .add(
+                1,
+                &[
+                    KeyValue::new("workflow", run.workflow.clone()),
+                    KeyValue::new("status", status),
+                ],
+            );
+        Ok(())
+    }
+
+    /// Invokes a step's function through the invocation limits, retrying with
+    /// exponential backoff.
+    async fn run_step(
+        &self,
+        run_id: &str,
+        step: &WorkflowStep,
+        state: &StepRun,
+    ) -> Result<StepRun, DomainError> {
+        let mut state = state.clone();
+        let input = state.input.clone().unwrap_or_default();
+
+        loop {
+            state.attempts += 1;
+            self.repository.save_step(run_id, &state).await?;
+
+            match self
+                .invocation_service
+                .invoke_background(&step.function, &input, state.attempts)
+                .await
+            {
+                Ok(output) => {
+                    state.status = StepStatus::Succeeded;
+                    state.output = Some(output);
+                    state.error = None;
+                    break;
+                }
+                Err(e) if state.attempts <= step.retries => {
+                    let delay = step
+                        .retry_backoff_ms
+                        .saturating_mul(1 << (state.attempts - 1).min(16));
+                    warn!(
+                        run_id,
+                        step = step.name,
+                        attempt = state.attempts,
+                        "Step failed, retrying in {}ms: {:#}",
+                        delay,
+                        e
+                    );
+                    state.error = Some(format!("{:#}", e));
+                    tokio::time::sleep(Duration::from_millis(delay)).await;
+                }
+                Err(e) => {
+                    state.status = StepStatus::Failed;
+                    state.error = Some(format!("{:#}", e));
+                    break;
+                }
+            }
+        }
+
+        self.repository.save_step(run_id, &state).await?;
+        Ok(state)
+    }
+}
+
+/// What to do with a pending step, or `None` while its dependencies are still running.
+fn next_action(run: &WorkflowRun, step: &WorkflowStep) -> Option<Next> {
+    let deps: Vec<&StepRun> = step
+        .after
+        .iter()
+        .filter_map(|name| run.steps.iter().find(|s| &s.step == name))
+        .collect();
+    if !deps.iter().all(|d| d.status.is_finished()) {
+        return None;
+    }
+
+    let succeeded: Vec<&StepRun> = deps
+        .iter()
+        .copied()
+        .filter(|d| d.status == StepStatus::Succeeded)
+        .collect();
+    // Skipping a branch skips everything that only depends on it
+    if !deps.is_empty() && succeeded.is_empty() {
+        return Some(Next::Skip);
+    }
+    if let Some(when) = &step.when
+        && !condition_met(when, &succeeded)
+    {
+        return Some(Next::Skip);
+    }
+
+    Some(Next::Run(match step.after.len() {
+        0 => run.input.clone(),
+        1 => succeeded[0].output.clone().unwrap_or_default(),
+        _ => combine_outputs(&succeeded),
+    }))
+}
+
+fn condition_met(when: &StepCondition, deps: &[&StepRun]) -> bool {
+    let Some(output) = deps
+        .iter()
+        .find(|d| d.step == when.step)
+        .and_then(|d| d.output.as_deref())
+    else {
+        return false;
+    };
+
+    let value = output_value(output);
+    match &when.path {
+        Some(path) => value.pointer(path) == Some(&when.equals),
+        None => value == when.equals,
+    }
+}
+
+/// Parses an output as JSON, falling back to a plain string.
+fn output_value(output: &str) -> serde_json::Value {
+    serde_json::from_str(output).unwrap_or_else(|_| serde_json::Value::String(output.to_string()))
+}
+
+/// Joins the outputs of several steps into one JSON object keyed by step name;
+/// a single output is passed through as is.
+fn combine_outputs(steps: &[&StepRun]) -> String {
+    if let [single] = steps {
+        return single.output.clone().unwrap_or_default();
+    }
+    let object: serde_json::Map<String, serde_json::Value> = steps
+        .iter()
+        .map(|s| {
+            (
+                s.step.clone(),
+                output_value(s.output.as_deref().unwrap_or_default()),
+            )
+        })
+        .collect();
+    serde_json::Value::Object(object).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::entities::Function;
+    use crate::domain::ports::{
+        MockFunctionRepository, MockTriggerRepository, MockWorkflowRepository,
+    };
+    use crate::domain::wasm_runtime::MockWasmRuntime;
+    use std::sync::Mutex;
+
+    fn step(name: &str, after: &[&str]) -> WorkflowStep {
+        WorkflowStep {
+            name: name.to_string(),
+            function: name.to_string(),
+            after: after.iter().map(|s| s.to_string()).collect(),
+            when: None,
+            retries: 0,
+            retry_backoff_ms: 0,
+        }
+    }
+
+    fn run_of(steps: Vec<WorkflowStep>, input: &str) -> WorkflowRun {
+        WorkflowRun {
+            id: "run-1".to_string(),
+            workflow: "wf".to_string(),
+            status: RunStatus::Running,
+            input: input.to_string(),
+            output: None,
+            error: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            steps: steps
+                .iter()
+                .map(|s| StepRun {
+                    step: s.name.clone(),
+                    status: StepStatus::Pending,
+                    input: None,
+                    output: None,
+                    error: None,
+                    attempts: 0,
+                })
+                .collect(),
+            definition: Workflow {
+                name: "wf".to_string(),
+                steps,
+            },
+        }
+    }
+
+    fn repository() -> MockWorkflowRepository {
+        let mut repo = MockWorkflowRepository::new();
+        repo.expect_save_step().returning(|_, _| Ok(()));
+        repo.expect_save_run().returning(|_| Ok(()));
+        repo
+    }
+
+    fn service(runtime: MockWasmRuntime) -> WorkflowService {
+        let functions = || {
+            let mut repo = MockFunctionRepository::new();
+            repo.expect_find_by_name().returning(|name| {
+                Ok((name != "missing").then(|| Function {
+                    name: name.to_string(),
+                    ..Default::default()
+                }))
+            });
+            Arc::new(repo)
+        };
+        let invocation_service = Arc::new(InvocationService::new(
+            Arc::new(MockTriggerRepository::new()),
+            functions(),
+            Arc::new(runtime),
+        ));
+        WorkflowService::new(Arc::new(repository()), functions(), invocation_service)
+    }
+
+    #[tokio::test]
+    async fn test_validate() {
+        let service = service(MockWasmRuntime::new());
+        let workflow = |steps| Workflow {
+            name: "wf".to_string(),
+            steps,
+        };
+
+        assert!(
+            service
+                .validate(&workflow(vec![step("a", &[]), step("b", &["a"])]))
+                .await
+                .is_ok()
+        );
+        for steps in [
+            vec![],
+            vec![step("a", &[]), step("a", &[])],
+            vec![step("a", &["nope"])],
+            vec![step("a", &["b"]), step("b", &["a"])],
+            vec![step("missing", &[])],
+        ] {
+            assert!(matches!(
+                service.validate(&workflow(steps)).await,
+                Err(DomainError::ValidationError(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_drive_parallel_branches() {
+        let mut runtime = MockWasmRuntime::new();
+        runtime
+            .expect_invoke()
+            .returning(|name, input| Ok(format!("{}({})", name, input)));
+
+        let mut run = run_of(
+            vec![
+                step("a", &[]),
+                step("b", &["a"]),
+                step("c", &["a"]),
+                step("d", &["b", "c"]),
+            ],
+            "x",
+        );
+        service(runtime).drive(&mut run).await.unwrap();
+
+        assert_eq!(run.status, RunStatus::Succeeded);
+        assert_eq!(run.steps[1].input.as_deref(), Some("a(x)"));
+        let joined: serde_json::Value =
+            serde_json::from_str(run.steps[3].input.as_deref().unwrap()).unwrap();
+        assert_eq!(joined["b"], "b(a(x))");
+        assert_eq!(joined["c"], "c(a(x))");
+        assert!(run.output.unwrap().starts_with("d("));
+    }
+
+    #[tokio::test]
+    async fn test_drive_condition_skips_branch() {
+        let mut runtime = MockWasmRuntime::new();
+        runtime.expect_invoke().returning(|name, _| {
+            Ok(match name {
+                "check" => r#"{"ok":false}"#.to_string(),
+                other => other.to_string(),
+            })
+        });
+
+        let when = |equals: bool| StepCondition {
+            step: "check".to_string(),
+            path: Some("/ok".to_string()),
+            equals: serde_json::Value::Bool(equals),
+        };
+        let mut run = run_of(
+            vec![
+                step("check", &[]),
+                WorkflowStep {
+                    when: Some(when(true)),
+                    ..step("accept", &["check"])
+                },
+                WorkflowStep {
+                    when: Some(when(false)),
+                    ..step("reject", &["check"])
+                },
+                step("notify", &["accept"]),
+            ],
+            "",
+        );
+        service(runtime).drive(&mut run).await.unwrap();
+
+        let statuses: Vec<StepStatus> = run.steps.iter().map(|s| s.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                StepStatus::Succeeded,
+                StepStatus::Skipped,
+                StepStatus::Succeeded,
+                StepStatus::Skipped,
+            ]
+        );
+        assert_eq!(run.output.as_deref(), Some("reject"));
+    }
+
+    #[tokio::test]
+    async fn test_drive_retries_then_fails() {
+        let calls = Arc::new(Mutex::new(0));
+        let counter = calls.clone();
+        let mut runtime = MockWasmRuntime::new();
+        runtime.expect_invoke().returning(move |name, _| {
+            if name == "flaky" {
+                *counter.lock().unwrap() += 1;
+                anyhow::bail!("boom")
+            }
+            Ok(String::new())
+        });
+
+        let mut run = run_of(
+            vec![
+                WorkflowStep {
+                    retries: 2,
+                    ..step("flaky", &[])
+                },
+                step("after", &["flaky"]),
+            ],
+            "",
+        );
+        service(runtime).drive(&mut run).await.unwrap();
+
+        assert_eq!(*calls.lock().unwrap(), 3);
+        assert_eq!(run.status, RunStatus::Failed);
+        assert_eq!(run.steps[0].attempts, 3);
+        assert_eq!(run.steps[0].status, StepStatus::Failed);
+        assert_eq!(run.steps[1].status, StepStatus::Skipped);
+        assert!(run.error.unwrap().contains("boom"));
+    }
+}
@@ -1,5 +1,7 @@
 use crate::domain::wasm_runtime::WasmRuntime;
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -31,9 +33,229 @@ pub struct Function {
     pub runtime: Option<Arc<dyn WasmRuntime>>,
     #[serde(default)]
     pub readonly: bool,
+    /// Pre-initialize the component at deploy time and load the snapshot instead.
+    #[serde(default)]
+    pub snapshot: bool,
+    /// Maximum simultaneous invocations; unlimited when unset.
+    #[serde(default)]
+    pub max_concurrency: Option<u32>,
+    /// Invocations allowed to wait for a free slot once `max_concurrency` is reached.
+    #[serde(default)]
+    pub max_queue: Option<u32>,
+    /// Functions (or trigger names standing for them) this function may call
+    /// through the `fluor:fun/host` import.
+    #[serde(default)]
+    pub allowed_calls: Vec<String>,
+    /// How background invocations of this function are retried; defaults apply when unset.
+    #[serde(default)]
+    pub retry: Option<RetryPolicy>,
+    /// Environment variables exposed to the function through WASI.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Total attempts, including the first one.
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    #[serde(default = "default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    #[serde(default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+}
+
+fn default_max_attempts() -> u32 {
+    3
+}
+
+fn default_initial_backoff_ms() -> u64 {
+    1000
+}
+
+fn default_max_backoff_ms() -> u64 {
+    60_000
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_max_attempts(),
+            initial_backoff_ms: default_initial_backoff_ms(),
+            max_backoff_ms: default_max_backoff_ms(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Upper bound of the delay after failed attempt `attempt` (1-based), doubling
+    /// from `initial_backoff_ms` up to `max_backoff_ms`.
+    pub fn backoff_ms(&self, attempt: u32) -> u64 {
+        self.initial_backoff_ms
+            .saturating_mul(1 << attempt.saturating_sub(1).min(32))
+            .min(self.max_backoff_ms)
+    }
+}
+
+/// A background invocation that hasn't succeeded or been dead-lettered yet,
+/// stored so it survives a restart.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BackgroundJob {
+    pub id: String,
+    pub function: String,
+    /// What started the invocation, e.g. `async`.
+    pub source: String,
+    pub payload: String,
+    /// Attempts that have failed so far.
+    pub attempts: u32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A background invocation that failed on every attempt, kept for inspection or redrive.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DeadLetter {
+    pub id: String,
+    pub function: String,
+    /// What started the invocation, e.g. `async`.
+    pub source: String,
+    pub payload: String,
+    pub error: String,
+    pub attempts: u32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// What an alert rule checks over its window.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "condition", rename_all = "snake_case")]
+pub enum AlertCondition {
+    /// Share of failed invocations, from 0 to 1, above `threshold`. Windows with
+    /// fewer than `min_invocations` calls never fire.
+    ErrorRate {
+        threshold: f64,
+        #[serde(default = "default_min_invocations")]
+        min_invocations: u64,
+    },
+    /// 99th percentile duration above `threshold_ms`.
+    LatencyP99 { threshold_ms: f64 },
+    /// No invocation at all, e.g. a scheduled function that stopped being called.
+    Absence,
+}
+
+fn default_min_invocations() -> u64 {
+    1
+}
+
+fn default_alert_window_secs() -> u64 {
+    300
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertState {
+    #[default]
+    Ok,
+    Firing,
+}
+
+/// Where an alert rule stands, as of its last evaluation.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct AlertStatus {
+    pub state: AlertState,
+    /// Error rate, p99 in milliseconds or invocation count, depending on the condition;
+    /// unset when the window had no invocations to measure.
+    pub value: Option<f64>,
+    /// When the rule entered its current state.
+    pub since: Option<DateTime<Utc>>,
+    pub evaluated_at: Option<DateTime<Utc>>,
+}
+
+/// A condition on one function's telemetry, evaluated periodically.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AlertRule {
+    pub name: String,
+    pub function: String,
+    #[serde(flatten)]
+    pub condition: AlertCondition,
+    /// How far back each evaluation looks.
+    #[serde(default = "default_alert_window_secs")]
+    pub window_secs: u64,
+    /// URLs notified when the rule fires or resolves; the configured defaults
+    /// apply when empty.
+    #[serde(default)]
+    pub webhooks: Vec<String>,
+    /// Maintained by the evaluator; ignored on writes.
+    #[serde(default)]
+    pub status: AlertStatus,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TriggerKind {
+    #[default]
+    Http,
+    WebSocket,
+    /// Starts the workflow named by the trigger's `function` field.
+    Workflow,
+}
+
+/// Who shares a rate-limit bucket.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitScope {
+    /// One bucket for every caller of the trigger.
+    #[default]
+    Global,
+    /// One bucket per client IP.
+    Ip,
+    /// One bucket per verified JWT subject, falling back to the client IP.
+    Subject,
+}
+
+/// Token bucket: `requests` tokens refill every `period_secs`, holding at most `burst`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RateLimit {
+    pub requests: u32,
+    #[serde(default = "default_rate_limit_period")]
+    pub period_secs: u32,
+    /// Bucket size; defaults to `requests`.
+    #[serde(default)]
+    pub burst: Option<u32>,
+    #[serde(default)]
+    pub scope: RateLimitScope,
+}
+
+fn default_rate_limit_period() -> u32 {
+    60
+}
+
+impl RateLimit {
+    pub fn capacity(&self) -> f64 {
+        self.burst.unwrap_or(self.requests) as f64
+    }
+
+    pub fn refill_per_sec(&self) -> f64 {
+        self.requests as f64 / self.period_secs as f64
+    }
+}
+
+/// Opt-in gateway caching of successful GET responses.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CachePolicy {
+    pub ttl_secs: u32,
+    /// Request headers whose values become part of the cache key.
+    #[serde(default)]
+    pub vary_headers: Vec<String>,
+}
+
+/// Outcome of taking a token from a rate-limit bucket.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BucketState {
+    pub allowed: bool,
+    /// Tokens left after the request.
+    pub tokens: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Trigger {
     pub name: String,
     pub method: String,
@@ -41,9 +263,444 @@ pub struct Trigger {
     #[serde(rename = "function")]
     pub function_name: String,
     #[serde(default)]
+    pub kind: TriggerKind,
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
+    #[serde(default)]
+    pub cache: Option<CachePolicy>,
+    #[serde(default)]
     pub readonly: bool,
 }
 
+/// One write of a manifest apply, made together with the others.
+#[derive(Debug, Clone)]
+pub enum ManifestWrite {
+    CreateFunction(Function),
+    UpdateFunction(Function),
+    DeleteFunction(String),
+    CreateTrigger(Trigger),
+    DeleteTrigger(String),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct Workflow {
+    pub name: String,
+    pub steps: Vec<WorkflowStep>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct WorkflowStep {
+    pub name: String,
+    pub function: String,
+    /// Steps that must finish first; steps whose dependencies are met run in parallel.
+    #[serde(default)]
+    pub after: Vec<String>,
+    /// Runs the step only when a dependency's output matches; it is skipped otherwise.
+    #[serde(default)]
+    pub when: Option<StepCondition>,
+    #[serde(default)]
+    pub retries: u32,
+    /// Delay before the first retry, doubled on each further attempt.
+    #[serde(default)]
+    pub retry_backoff_ms: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct StepCondition {
+    /// A step listed in `after`.
+    pub step: String,
+    /// JSON pointer into the step's output (e.g. `/status`); the whole output when unset.
+    #[serde(default)]
+    pub path: Option<String>,
+    pub equals: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StepStatus {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+    Skipped,
+}
+
+impl StepStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(
+            self,
+            StepStatus::Succeeded | StepStatus::Failed | StepStatus::Skipped
+        )
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct WorkflowRun {
+    pub id: String,
+    pub workflow: String,
+    pub status: RunStatus,
+    pub input: String,
+    pub output: Option<String>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub steps: Vec<StepRun>,
+    /// Definition the run started with, so edits don't affect runs in flight.
+    #[serde(skip)]
+    pub definition: Workflow,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct StepRun {
+    pub step: String,
+    pub status: StepStatus,
+    pub input: Option<String>,
+    pub output: Option<String>,
+    pub error: Option<String>,
+    pub attempts: u32,
+}
+
+/// Width of the buckets a metrics query is grouped into.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    Minute,
+    Hour,
+    Day,
+}
+
+impl Granularity {
+    pub fn duration(self) -> TimeDelta {
+        match self {
+            Granularity::Minute => TimeDelta::minutes(1),
+            Granularity::Hour => TimeDelta::hours(1),
+            Granularity::Day => TimeDelta::days(1),
+        }
+    }
+}
+
+/// Half-open window `[from, to)` of a metrics query and its bucket width.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeRange {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub step: Granularity,
+}
+
+impl TimeRange {
+    /// Start of the bucket `at` falls in.
+    pub fn bucket_of(&self, at: DateTime<Utc>) -> DateTime<Utc> {
+        at.duration_trunc(self.step.duration()).unwrap_or(at)
+    }
+
+    /// Starts of every bucket overlapping the window, oldest first.
+    pub fn buckets(&self) -> impl Iterator<Item = DateTime<Utc>> + use<> {
+        let step = self.step.duration();
+        let to = self.to;
+        std::iter::successors(Some(self.bucket_of(self.from)), move |b| Some(*b + step))
+            .take_while(move |b| *b < to)
+    }
+
+    /// How buckets are labelled in `ExecutionMetric::time_bucket`.
+    pub fn label(bucket: DateTime<Utc>) -> String {
+        bucket.format("%Y-%m-%d %H:%M:%S").to_string()
+    }
+}
+
+/// Invocations started in one time bucket.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ExecutionMetric {
+    pub time_bucket: String,
+    pub count: u64,
+}
+
+/// Outcomes and latency percentiles of a set of invocations. Rejected invocations
+/// count as errors.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct InvocationStats {
+    pub invocations: u64,
+    pub errors: u64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+}
+
+/// Invocation stats of one time bucket.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BucketStats {
+    pub time_bucket: String,
+    #[serde(flatten)]
+    pub stats: InvocationStats,
+}
+
+/// Invocation stats of one function over a whole time range.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FunctionStats {
+    pub function_name: String,
+    #[serde(flatten)]
+    pub stats: InvocationStats,
+}
+
+/// One span of a trace.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TraceSpan {
+    pub trace_id: String,
+    pub span_id: String,
+    /// Empty for the root span.
+    pub parent_span_id: String,
+    pub name: String,
+    pub service_name: String,
+    /// Written in `LOG_TIMESTAMP_FORMAT`.
+    pub start_time: String,
+    pub duration_ms: f64,
+    /// `Unset`, `Ok` or `Error`.
+    pub status_code: String,
+    pub status_message: String,
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl TraceSpan {
+    /// Start and end of the span, read back from `start_time` and `duration_ms`.
+    pub fn interval(&self) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        let start = parse_timestamp(&self.start_time)?;
+        let duration = TimeDelta::nanoseconds((self.duration_ms * 1e6) as i64);
+        Some((start, start + duration))
+    }
+}
+
+/// A span and the spans it started, ordered by start time.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SpanNode {
+    #[serde(flatten)]
+    pub span: TraceSpan,
+    pub children: Vec<SpanNode>,
+}
+
+/// All spans of a trace as a tree. Spans whose parent was not exported show up as
+/// extra roots.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Trace {
+    pub trace_id: String,
+    pub span_count: usize,
+    /// From the first span's start to the last span's end.
+    pub duration_ms: f64,
+    pub roots: Vec<SpanNode>,
+}
+
+/// Which of a function's traces to list.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TraceFilter {
+    /// All traces, slowest first.
+    #[default]
+    Slowest,
+    /// Traces with a failed or rejected invocation, newest first.
+    Errors,
+}
+
+/// A trace in which a function was invoked, summarized by that invocation.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TraceSummary {
+    pub trace_id: String,
+    pub function_name: String,
+    pub start_time: String,
+    pub duration_ms: f64,
+    pub error: bool,
+}
+
+/// How `LogEntry::timestamp` is written, with up to nanosecond precision.
+pub const LOG_TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
+
+/// A log line emitted on behalf of a function.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LogEntry {
+    pub timestamp: String,
+    pub level: String,
+    pub body: String,
+    pub trace_id: String,
+    pub function_name: String,
+    /// Orders lines logged at the same instant. Only meaningful to the backend
+    /// that returned the line, so it is kept out of responses.
+    #[serde(default, skip_serializing)]
+    pub key: u64,
+}
+
+impl LogEntry {
+    /// When the line was logged, read back from `timestamp` (UTC).
+    pub fn logged_at(&self) -> Option<DateTime<Utc>> {
+        parse_timestamp(&self.timestamp)
+    }
+
+    /// Where the line sits in the order of a log search.
+    pub fn position(&self) -> Option<LogPosition> {
+        self.logged_at().map(|at| LogPosition { at, key: self.key })
+    }
+}
+
+/// A line's place in log order: by time, then by `LogEntry::key`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LogPosition {
+    pub at: DateTime<Utc>,
+    pub key: u64,
+}
+
+/// Reads a timestamp written in `LOG_TIMESTAMP_FORMAT`, taken as UTC.
+fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    chrono::NaiveDateTime::parse_from_str(value, LOG_TIMESTAMP_FORMAT)
+        .ok()
+        .map(|t| t.and_utc())
+}
+
+/// Filters of a log search; unset filters match every line.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LogQuery {
+    pub function_name: Option<String>,
+    /// Severity in upper case, e.g. `ERROR`.
+    pub level: Option<String>,
+    /// Case-insensitive substring of the body.
+    pub contains: Option<String>,
+    pub trace_id: Option<String>,
+    /// Only lines logged strictly after this instant.
+    pub after: Option<DateTime<Utc>>,
+    /// Only lines logged strictly before this instant.
+    pub before: Option<DateTime<Utc>>,
+    /// Only lines past this position in the order of the results, so a page
+    /// can end between lines logged at the same instant.
+    pub cursor: Option<LogPosition>,
+    /// Oldest lines first instead of newest first.
+    pub oldest_first: bool,
+    pub limit: usize,
+}
+
+/// One page of a log search, newest first.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LogPage {
+    pub entries: Vec<LogEntry>,
+    /// Pass as `cursor` to get the next (older) page; unset on the last page.
+    pub next_cursor: Option<String>,
+}
+
+/// Who is making a management change, and from where.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Actor {
+    /// Email of the signed-in user, or `anonymous`.
+    pub id: String,
+    pub source_ip: Option<String>,
+}
+
+impl Actor {
+    pub fn new(id: impl Into<String>, source_ip: Option<String>) -> Self {
+        Self {
+            id: id.into(),
+            source_ip,
+        }
+    }
+
+    /// Changes the server makes on its own.
+    pub fn system() -> Self {
+        Self::new("system", None)
+    }
+}
+
+/// Management change recorded in the audit log, named `<resource>.<verb>`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    #[serde(rename = "function.create")]
+    FunctionCreate,
+    #[serde(rename = "function.update")]
+    FunctionUpdate,
+    #[serde(rename = "function.rollback")]
+    FunctionRollback,
+    #[serde(rename = "function.delete")]
+    FunctionDelete,
+    #[serde(rename = "trigger.create")]
+    TriggerCreate,
+    #[serde(rename = "trigger.delete")]
+    TriggerDelete,
+    #[serde(rename = "user.update")]
+    UserUpdate,
+    #[serde(rename = "user.password_change")]
+    PasswordChange,
+    #[serde(rename = "alert.create")]
+    AlertCreate,
+    #[serde(rename = "alert.update")]
+    AlertUpdate,
+    #[serde(rename = "alert.delete")]
+    AlertDelete,
+    #[serde(rename = "workflow.create")]
+    WorkflowCreate,
+    #[serde(rename = "workflow.update")]
+    WorkflowUpdate,
+    #[serde(rename = "workflow.delete")]
+    WorkflowDelete,
+    #[serde(rename = "dead_letter.redrive")]
+    DeadLetterRedrive,
+    #[serde(rename = "dead_letter.delete")]
+    DeadLetterDelete,
+    #[serde(rename = "dead_letter.purge")]
+    DeadLetterPurge,
+    #[serde(rename = "cache.purge")]
+    CachePurge,
+    #[serde(rename = "manifest.apply")]
+    ManifestApply,
+}
+
+/// Value of a field before and after a change; `null` on the side where the
+/// target didn't exist.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FieldChange {
+    pub before: serde_json::Value,
+    pub after: serde_json::Value,
+}
+
+/// One entry of the append-only audit log.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AuditEntry {
+    /// Assigned on append, increasing.
+    pub id: i64,
+    pub at: DateTime<Utc>,
+    pub actor: String,
+    pub source_ip: Option<String>,
+    pub action: AuditAction,
+    /// Name of the function, trigger, alert rule or workflow, email of the user,
+    /// id of the dead letter, or what a purge covered.
+    pub target: String,
+    /// Top-level fields that changed, with secrets redacted.
+    pub changes: BTreeMap<String, FieldChange>,
+}
+
+/// Filters of an audit log search; unset filters match every entry.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AuditQuery {
+    pub actor: Option<String>,
+    /// An action like `function.update`, or a resource like `function` for all of its actions.
+    pub action: Option<String>,
+    pub target: Option<String>,
+    /// Only entries recorded at or after this instant.
+    pub from: Option<DateTime<Utc>>,
+    /// Only entries recorded strictly before this instant.
+    pub to: Option<DateTime<Utc>>,
+    /// Only entries with a lower id.
+    pub before_id: Option<i64>,
+    pub limit: usize,
+}
+
+/// One page of an audit log search, newest first.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AuditPage {
+    pub entries: Vec<AuditEntry>,
+    /// Pass as `cursor` to get the next (older) page; unset on the last page.
+    pub next_cursor: Option<String>,
+}
+
 // Domain Error
 #[derive(Debug, thiserror::Error)]
 pub enum DomainError {
@@ -55,4 +712,10 @@ pub enum DomainError {
     Internal(String),
     #[error("Already exists: {0}")]
     AlreadyExists(String),
+    #[error("Limit exceeded: {0}")]
+    LimitExceeded(String),
+    #[error("Overloaded: {0}")]
+    Overloaded(String),
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
 }
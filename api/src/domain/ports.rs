@@ -1,4 +1,9 @@
-use crate::domain::entities::{DomainError, Function, Trigger, User};
+use crate::domain::entities::{
+    AlertRule, AlertStatus, AuditEntry, AuditQuery, BackgroundJob, BucketState, BucketStats,
+    DeadLetter, DomainError, ExecutionMetric, Function, FunctionStats, LogEntry, LogQuery,
+    ManifestWrite, StepRun, TimeRange, TraceFilter, TraceSpan, TraceSummary, Trigger, User,
+    Workflow, WorkflowRun,
+};
 use async_trait::async_trait;
 
 #[cfg_attr(test, mockall::automock)]
@@ -26,3 +31,177 @@ pub trait TriggerRepository: Send + Sync {
     async fn save(&self, trigger: &Trigger) -> Result<Trigger, DomainError>;
     async fn delete(&self, name: &str) -> Result<(), DomainError>;
 }
+
+/// Stores the function and trigger changes of a manifest apply.
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait ManifestRepository: Send + Sync {
+    /// Makes `writes` in order within one transaction, with the checks of the
+    /// single-resource methods: other requests see all of them or none, and the
+    /// first that fails undoes the ones before it.
+    async fn apply_manifest(&self, writes: &[ManifestWrite]) -> Result<(), DomainError>;
+}
+
+/// The lease that makes a node the only one serving from the database.
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait NodeLeaseRepository: Send + Sync {
+    /// Gives `node` the lease until `expires_at` if it is free, expired at `now`
+    /// or already held by `node`, and returns the node holding it afterwards.
+    /// Times are milliseconds since the epoch.
+    async fn acquire_lease(
+        &self,
+        node: &str,
+        now: i64,
+        expires_at: i64,
+    ) -> Result<String, DomainError>;
+
+    /// Frees the lease if `node` holds it.
+    async fn release_lease(&self, node: &str) -> Result<(), DomainError>;
+}
+
+/// Token-bucket state, kept in process or in the database.
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Refills bucket `key` up to `capacity` and takes one token if available.
+    async fn take(
+        &self,
+        key: &str,
+        capacity: f64,
+        refill_per_sec: f64,
+    ) -> Result<BucketState, DomainError>;
+
+    /// Drops buckets that have refilled to capacity and returns how many went.
+    /// A missing bucket starts full, so pruning never changes a decision.
+    async fn prune(&self) -> Result<u64, DomainError>;
+}
+
+/// Workflow definitions and the state of their runs, which must survive restarts.
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait WorkflowRepository: Send + Sync {
+    async fn find_workflows(&self) -> Result<Vec<Workflow>, DomainError>;
+    async fn find_workflow(&self, name: &str) -> Result<Option<Workflow>, DomainError>;
+    async fn save_workflow(&self, workflow: &Workflow) -> Result<Workflow, DomainError>;
+    async fn delete_workflow(&self, name: &str) -> Result<(), DomainError>;
+    /// Inserts or updates a run together with all of its steps.
+    async fn save_run(&self, run: &WorkflowRun) -> Result<(), DomainError>;
+    async fn save_step(&self, run_id: &str, step: &StepRun) -> Result<(), DomainError>;
+    async fn find_run(&self, id: &str) -> Result<Option<WorkflowRun>, DomainError>;
+    async fn find_runs(&self, workflow: &str) -> Result<Vec<WorkflowRun>, DomainError>;
+    async fn find_unfinished_runs(&self) -> Result<Vec<WorkflowRun>, DomainError>;
+}
+
+/// Background invocations still running or waiting to retry.
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait BackgroundJobRepository: Send + Sync {
+    /// Inserts the job, or records its attempts if it is already stored.
+    async fn save_job(&self, job: &BackgroundJob) -> Result<(), DomainError>;
+    /// Oldest first.
+    async fn find_jobs(&self) -> Result<Vec<BackgroundJob>, DomainError>;
+    async fn delete_job(&self, id: &str) -> Result<(), DomainError>;
+}
+
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait DeadLetterRepository: Send + Sync {
+    async fn save_dead_letter(&self, letter: &DeadLetter) -> Result<(), DomainError>;
+    /// Most recent first, optionally limited to one function.
+    async fn find_dead_letters<'a>(
+        &self,
+        function: Option<&'a str>,
+    ) -> Result<Vec<DeadLetter>, DomainError>;
+    async fn find_dead_letter(&self, id: &str) -> Result<Option<DeadLetter>, DomainError>;
+    async fn delete_dead_letter(&self, id: &str) -> Result<(), DomainError>;
+    /// Deletes all dead letters, or those of one function; returns how many were removed.
+    async fn purge_dead_letters<'a>(&self, function: Option<&'a str>) -> Result<u64, DomainError>;
+}
+
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait AlertRepository: Send + Sync {
+    async fn find_alert_rules(&self) -> Result<Vec<AlertRule>, DomainError>;
+    async fn find_alert_rule(&self, name: &str) -> Result<Option<AlertRule>, DomainError>;
+    /// Inserts or replaces the rule, status included.
+    async fn save_alert_rule(&self, rule: &AlertRule) -> Result<AlertRule, DomainError>;
+    /// Records an evaluation without touching the rule's definition.
+    async fn save_alert_status(&self, name: &str, status: &AlertStatus) -> Result<(), DomainError>;
+    async fn delete_alert_rule(&self, name: &str) -> Result<(), DomainError>;
+}
+
+/// Append-only record of management changes: entries are never updated or deleted.
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait AuditRepository: Send + Sync {
+    /// Stores the entry and returns it with its assigned id.
+    async fn append_audit_entry(&self, entry: &AuditEntry) -> Result<AuditEntry, DomainError>;
+    /// Entries matching `query`, newest first.
+    async fn find_audit_entries(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>, DomainError>;
+}
+
+/// Delivers alert state changes to a webhook.
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait AlertNotifier: Send + Sync {
+    async fn notify(&self, webhook: &str, rule: &AlertRule) -> Result<(), DomainError>;
+}
+
+/// Read side of invocation telemetry: invocation metrics and function logs.
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait TelemetryRepository: Send + Sync {
+    /// Invocations of the function per bucket of `range`, oldest first. Empty
+    /// buckets may be left out.
+    async fn get_function_executions(
+        &self,
+        function_name: &str,
+        range: &TimeRange,
+    ) -> Result<Vec<ExecutionMetric>, DomainError>;
+    /// Invocations of all functions but `healthz` per bucket of `range`, oldest first.
+    async fn get_overall_executions(
+        &self,
+        range: &TimeRange,
+    ) -> Result<Vec<ExecutionMetric>, DomainError>;
+    /// Outcomes and latency of the function's invocations per bucket of `range`,
+    /// oldest first. Empty buckets may be left out.
+    async fn get_function_performance(
+        &self,
+        function_name: &str,
+        range: &TimeRange,
+    ) -> Result<Vec<BucketStats>, DomainError>;
+    /// Outcomes and latency of all functions but `healthz` per bucket of `range`,
+    /// oldest first. Empty buckets may be left out.
+    async fn get_overall_performance(
+        &self,
+        range: &TimeRange,
+    ) -> Result<Vec<BucketStats>, DomainError>;
+    /// Outcomes and latency of each function but `healthz` invoked during `range`.
+    async fn get_function_stats(
+        &self,
+        range: &TimeRange,
+    ) -> Result<Vec<FunctionStats>, DomainError>;
+    /// Every span of the trace, in no particular order; empty when it is unknown.
+    async fn get_trace_spans(&self, trace_id: &str) -> Result<Vec<TraceSpan>, DomainError>;
+    /// Up to `limit` traces in which the function was invoked during `range`.
+    async fn list_traces(
+        &self,
+        function_name: &str,
+        range: &TimeRange,
+        filter: TraceFilter,
+        limit: usize,
+    ) -> Result<Vec<TraceSummary>, DomainError>;
+    /// Up to `query.limit` log lines matching `query`, newest first. Without a
+    /// function filter, routine `INFO` lines of `healthz` are left out.
+    async fn search_logs(&self, query: &LogQuery) -> Result<Vec<LogEntry>, DomainError>;
+}
+
+/// Write side for telemetry backends that collect in-process instead of through
+/// the OpenTelemetry collector.
+pub trait TelemetryRecorder: Send + Sync {
+    /// Records a finished (or rejected) invocation.
+    fn record_invocation(&self, function_name: &str, status: &str, duration_ms: u64);
+    /// Records a log line emitted on behalf of a function.
+    fn record_log(&self, function_name: &str, level: &str, body: &str, trace_id: &str);
+}
@@ -1,8 +1,56 @@
+use crate::domain::entities::DomainError;
 use async_trait::async_trait;
+use std::collections::BTreeMap;
 
 #[cfg_attr(test, mockall::automock)]
 #[async_trait]
 pub trait WasmRuntime: Send + Sync + std::fmt::Debug {
-    fn load_function(&self, name: &str, path: &str) -> anyhow::Result<()>;
+    /// Checks that `bytes` is a component this runtime can run: well formed,
+    /// exporting the `fluor:fun` world and importing only what the host provides.
+    fn validate(&self, bytes: &[u8]) -> anyhow::Result<()>;
+    /// Sets the environment variables a function sees from its next instantiation on.
+    fn set_env(&self, function_name: &str, env: &BTreeMap<String, String>);
+    /// Compiles and caches a function, running its optional `init` export once.
+    async fn load_function(&self, name: &str, path: &str) -> anyhow::Result<()>;
+    /// Forgets a deleted function, along with the compiled code cached for the
+    /// artifacts at `paths`.
+    fn unload_function(&self, name: &str, paths: &[String]);
+    /// Serves the function loaded as `from`, along with its environment, as `to`
+    /// from now on, e.g. once a version loaded under a staging name is stored.
+    fn rename_function(&self, from: &str, to: &str);
+    /// Carries the compiled code cached for the artifact at `from` over to `to`,
+    /// once the artifact itself was moved there, so loading `to` reuses it.
+    fn move_compiled(&self, from: &str, to: &str);
+    /// Drops the compiled code cached for the artifacts at `paths`.
+    fn remove_compiled(&self, paths: &[String]);
     async fn invoke(&self, function_name: &str, input: &str) -> anyhow::Result<String>;
+    /// Instantiates a function once and keeps the instance alive across calls,
+    /// e.g. for the lifetime of a WebSocket connection.
+    async fn open_session(&self, function_name: &str) -> anyhow::Result<Box<dyn WasmSession>>;
+}
+
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait WasmSession: Send {
+    async fn invoke(&mut self, input: &str) -> anyhow::Result<String>;
+}
+
+/// Produces a pre-initialized copy of a component (Wizer-style), so the
+/// runtime doesn't pay for interpreter startup on every instantiation.
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait Snapshotter: Send + Sync {
+    async fn snapshot(&self, input_path: &str, output_path: &str) -> anyhow::Result<()>;
+}
+
+/// Lets a running function call another one by name, on behalf of `caller`.
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait FunctionInvoker: Send + Sync {
+    async fn invoke_from(
+        &self,
+        caller: &str,
+        target: &str,
+        input: &str,
+    ) -> Result<String, DomainError>;
 }
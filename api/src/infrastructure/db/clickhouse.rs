@@ -1,21 +1,179 @@
+use crate::domain::entities::{
+    BucketStats, DomainError, ExecutionMetric, FunctionStats, Granularity, InvocationStats,
+    LogEntry, LogQuery, TimeRange, TraceFilter, TraceSpan, TraceSummary,
+};
+use crate::domain::ports::TelemetryRepository;
+use async_trait::async_trait;
 use clickhouse::{Client, Row};
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 
-#[derive(Debug, Deserialize, Serialize, Row)]
-pub struct ExecutionMetric {
-    pub time_bucket: String,
-    pub count: u64,
+#[derive(Deserialize, Row)]
+struct ExecutionRow {
+    time_bucket: String,
+    count: u64,
 }
 
-#[derive(Debug, Deserialize, Serialize, Row)]
-pub struct LogEntry {
-    pub timestamp: String,
-    pub level: String,
-    pub body: String,
-    pub trace_id: String,
-    pub function_name: String,
+impl From<ExecutionRow> for ExecutionMetric {
+    fn from(row: ExecutionRow) -> Self {
+        Self {
+            time_bucket: row.time_bucket,
+            count: row.count,
+        }
+    }
+}
+
+/// Aggregates of invocation spans; `Duration` is in nanoseconds and the status is
+/// the `function_status` field recorded by `InvocationService`.
+const STATS_COLUMNS: &str = "
+                count() as invocations,
+                countIf(SpanAttributes['function_status'] IN ('error', 'rejected')) as errors,
+                quantile(0.5)(Duration) / 1e6 as p50_ms,
+                quantile(0.9)(Duration) / 1e6 as p90_ms,
+                quantile(0.99)(Duration) / 1e6 as p99_ms";
+
+#[derive(Deserialize, Row)]
+struct StatsRow {
+    key: String,
+    invocations: u64,
+    errors: u64,
+    p50_ms: f64,
+    p90_ms: f64,
+    p99_ms: f64,
+}
+
+impl StatsRow {
+    fn split(self) -> (String, InvocationStats) {
+        (
+            self.key,
+            InvocationStats {
+                invocations: self.invocations,
+                errors: self.errors,
+                p50_ms: self.p50_ms,
+                p90_ms: self.p90_ms,
+                p99_ms: self.p99_ms,
+            },
+        )
+    }
+}
+
+impl From<StatsRow> for BucketStats {
+    fn from(row: StatsRow) -> Self {
+        let (time_bucket, stats) = row.split();
+        Self { time_bucket, stats }
+    }
+}
+
+impl From<StatsRow> for FunctionStats {
+    fn from(row: StatsRow) -> Self {
+        let (function_name, stats) = row.split();
+        Self {
+            function_name,
+            stats,
+        }
+    }
+}
+
+#[derive(Deserialize, Row)]
+struct SpanRow {
+    trace_id: String,
+    span_id: String,
+    parent_span_id: String,
+    name: String,
+    service_name: String,
+    start_time: String,
+    duration_ms: f64,
+    status_code: String,
+    status_message: String,
+    attribute_keys: Vec<String>,
+    attribute_values: Vec<String>,
+}
+
+impl From<SpanRow> for TraceSpan {
+    fn from(row: SpanRow) -> Self {
+        Self {
+            trace_id: row.trace_id,
+            span_id: row.span_id,
+            parent_span_id: row.parent_span_id,
+            name: row.name,
+            service_name: row.service_name,
+            start_time: row.start_time,
+            duration_ms: row.duration_ms,
+            status_code: row.status_code,
+            status_message: row.status_message,
+            attributes: row
+                .attribute_keys
+                .into_iter()
+                .zip(row.attribute_values)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Deserialize, Row)]
+struct TraceSummaryRow {
+    trace_id: String,
+    function_name: String,
+    start_time: String,
+    duration_ms: f64,
+    error: bool,
+}
+
+impl From<TraceSummaryRow> for TraceSummary {
+    fn from(row: TraceSummaryRow) -> Self {
+        Self {
+            trace_id: row.trace_id,
+            function_name: row.function_name,
+            start_time: row.start_time,
+            duration_ms: row.duration_ms,
+            error: row.error,
+        }
+    }
 }
 
+#[derive(Deserialize, Row)]
+struct LogRow {
+    timestamp: String,
+    level: String,
+    body: String,
+    trace_id: String,
+    function_name: String,
+    key: u64,
+}
+
+impl From<LogRow> for LogEntry {
+    fn from(row: LogRow) -> Self {
+        Self {
+            timestamp: row.timestamp,
+            level: row.level,
+            body: row.body,
+            trace_id: row.trace_id,
+            function_name: row.function_name,
+            key: row.key,
+        }
+    }
+}
+
+/// Orders log lines exported with the same `Timestamp`, which has no unique id.
+const LOG_KEY: &str =
+    "cityHash64(TraceId, SpanId, SeverityText, Body, LogAttributes['function_name'])";
+
+/// `Timestamp` rounded down to the start of its bucket and printed as
+/// `TimeRange::label` does. Both name UTC: they otherwise follow the server's
+/// timezone, and the labels would not match the zero-filled buckets.
+fn bucket_of(step: Granularity) -> String {
+    let start_of = match step {
+        Granularity::Minute => "toStartOfMinute",
+        Granularity::Hour => "toStartOfHour",
+        Granularity::Day => "toStartOfDay",
+    };
+    format!("toString({}(Timestamp, 'UTC'), 'UTC')", start_of)
+}
+
+fn query_error(e: clickhouse::error::Error) -> DomainError {
+    DomainError::Internal(format!("ClickHouse query failed: {}", e))
+}
+
+/// Reads what the OpenTelemetry collector exported into ClickHouse.
 #[derive(Clone)]
 pub struct ClickHouseRepository {
     client: Client,
@@ -30,99 +188,329 @@ impl ClickHouseRepository {
             .with_database(db);
         Self { client }
     }
+}
 
-    pub async fn get_function_executions(
+#[async_trait]
+impl TelemetryRepository for ClickHouseRepository {
+    async fn get_function_executions(
         &self,
         function_name: &str,
-        _time_range: &str, // Unused for now
-    ) -> anyhow::Result<Vec<ExecutionMetric>> {
-        // Query to get execution counts per minute
+        range: &TimeRange,
+    ) -> Result<Vec<ExecutionMetric>, DomainError> {
         // We filter by the 'function_name' attribute we set in invocation_service.rs
-        let query = "
+        let query = format!(
+            "
             SELECT
-                toString(toStartOfMinute(Timestamp)) as time_bucket,
+                {} as time_bucket,
                 count() as count
             FROM otel_traces
             WHERE SpanAttributes['function_name'] = ?
-            AND Timestamp > now() - toIntervalHour(1)
+            AND Timestamp >= fromUnixTimestamp64Milli(?)
+            AND Timestamp < fromUnixTimestamp64Milli(?)
             GROUP BY time_bucket
             ORDER BY time_bucket
-        ";
+        ",
+            bucket_of(range.step)
+        );
 
         let rows = self
             .client
-            .query(query)
+            .query(&query)
             .bind(function_name)
-            .fetch_all::<ExecutionMetric>()
-            .await?;
+            .bind(range.from.timestamp_millis())
+            .bind(range.to.timestamp_millis())
+            .fetch_all::<ExecutionRow>()
+            .await
+            .map_err(query_error)?;
 
-        Ok(rows)
+        Ok(rows.into_iter().map(Into::into).collect())
     }
 
-    pub async fn get_overall_executions(
+    async fn get_overall_executions(
         &self,
-        _time_range: &str, // Unused for now
-    ) -> anyhow::Result<Vec<ExecutionMetric>> {
-        let query = "
+        range: &TimeRange,
+    ) -> Result<Vec<ExecutionMetric>, DomainError> {
+        let query = format!(
+            "
             SELECT
-                toString(toStartOfMinute(Timestamp)) as time_bucket,
+                {} as time_bucket,
                 count() as count
             FROM otel_traces
             WHERE SpanAttributes['function_name'] != 'healthz'
-            AND Timestamp > now() - toIntervalHour(1)
+            AND Timestamp >= fromUnixTimestamp64Milli(?)
+            AND Timestamp < fromUnixTimestamp64Milli(?)
             GROUP BY time_bucket
             ORDER BY time_bucket
-        ";
+        ",
+            bucket_of(range.step)
+        );
 
         let rows = self
             .client
-            .query(query)
-            .fetch_all::<ExecutionMetric>()
-            .await?;
+            .query(&query)
+            .bind(range.from.timestamp_millis())
+            .bind(range.to.timestamp_millis())
+            .fetch_all::<ExecutionRow>()
+            .await
+            .map_err(query_error)?;
 
-        Ok(rows)
+        Ok(rows.into_iter().map(Into::into).collect())
     }
 
-    pub async fn get_function_logs(&self, function_name: &str) -> anyhow::Result<Vec<LogEntry>> {
+    async fn get_function_performance(
+        &self,
+        function_name: &str,
+        range: &TimeRange,
+    ) -> Result<Vec<BucketStats>, DomainError> {
+        let query = format!(
+            "
+            SELECT
+                {} as key,{}
+            FROM otel_traces
+            WHERE SpanAttributes['function_name'] = ?
+            AND Timestamp >= fromUnixTimestamp64Milli(?)
+            AND Timestamp < fromUnixTimestamp64Milli(?)
+            GROUP BY key
+            ORDER BY key
+        ",
+            bucket_of(range.step),
+            STATS_COLUMNS
+        );
+
+        let rows = self
+            .client
+            .query(&query)
+            .bind(function_name)
+            .bind(range.from.timestamp_millis())
+            .bind(range.to.timestamp_millis())
+            .fetch_all::<StatsRow>()
+            .await
+            .map_err(query_error)?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    async fn get_overall_performance(
+        &self,
+        range: &TimeRange,
+    ) -> Result<Vec<BucketStats>, DomainError> {
+        let query = format!(
+            "
+            SELECT
+                {} as key,{}
+            FROM otel_traces
+            WHERE SpanAttributes['function_name'] NOT IN ('', 'healthz')
+            AND Timestamp >= fromUnixTimestamp64Milli(?)
+            AND Timestamp < fromUnixTimestamp64Milli(?)
+            GROUP BY key
+            ORDER BY key
+        ",
+            bucket_of(range.step),
+            STATS_COLUMNS
+        );
+
+        let rows = self
+            .client
+            .query(&query)
+            .bind(range.from.timestamp_millis())
+            .bind(range.to.timestamp_millis())
+            .fetch_all::<StatsRow>()
+            .await
+            .map_err(query_error)?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    async fn get_function_stats(
+        &self,
+        range: &TimeRange,
+    ) -> Result<Vec<FunctionStats>, DomainError> {
+        let query = format!(
+            "
+            SELECT
+                SpanAttributes['function_name'] as key,{}
+            FROM otel_traces
+            WHERE SpanAttributes['function_name'] NOT IN ('', 'healthz')
+            AND Timestamp >= fromUnixTimestamp64Milli(?)
+            AND Timestamp < fromUnixTimestamp64Milli(?)
+            GROUP BY key
+        ",
+            STATS_COLUMNS
+        );
+
+        let rows = self
+            .client
+            .query(&query)
+            .bind(range.from.timestamp_millis())
+            .bind(range.to.timestamp_millis())
+            .fetch_all::<StatsRow>()
+            .await
+            .map_err(query_error)?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    async fn get_trace_spans(&self, trace_id: &str) -> Result<Vec<TraceSpan>, DomainError> {
         let query = "
             SELECT
-                toString(Timestamp) as timestamp,
-                SeverityText as level,
-                Body as body,
                 TraceId as trace_id,
-                LogAttributes['function_name'] as function_name
-            FROM otel_logs
-            WHERE LogAttributes['function_name'] = ?
-            ORDER BY Timestamp DESC
-            LIMIT 100
+                SpanId as span_id,
+                ParentSpanId as parent_span_id,
+                SpanName as name,
+                ServiceName as service_name,
+                toString(Timestamp, 'UTC') as start_time,
+                toFloat64(Duration) / 1e6 as duration_ms,
+                toString(StatusCode) as status_code,
+                StatusMessage as status_message,
+                mapKeys(SpanAttributes) as attribute_keys,
+                mapValues(SpanAttributes) as attribute_values
+            FROM otel_traces
+            WHERE TraceId = ?
+            ORDER BY Timestamp
         ";
 
         let rows = self
             .client
             .query(query)
+            .bind(trace_id)
+            .fetch_all::<SpanRow>()
+            .await
+            .map_err(query_error)?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    async fn list_traces(
+        &self,
+        function_name: &str,
+        range: &TimeRange,
+        filter: TraceFilter,
+        limit: usize,
+    ) -> Result<Vec<TraceSummary>, DomainError> {
+        // The invocation spans are the ones carrying the function attributes
+        let (having, order) = match filter {
+            TraceFilter::Slowest => ("", "duration_ms DESC"),
+            TraceFilter::Errors => ("HAVING error", "start_time DESC"),
+        };
+        let query = format!(
+            "
+            SELECT
+                TraceId as trace_id,
+                any(SpanAttributes['function_name']) as function_name,
+                toString(min(Timestamp), 'UTC') as start_time,
+                toFloat64(max(Duration)) / 1e6 as duration_ms,
+                toBool(max(SpanAttributes['function_status'] IN ('error', 'rejected')
+                    OR toString(StatusCode) IN ('Error', 'STATUS_CODE_ERROR'))) as error
+            FROM otel_traces
+            WHERE SpanAttributes['function_name'] = ?
+            AND Timestamp >= fromUnixTimestamp64Milli(?)
+            AND Timestamp < fromUnixTimestamp64Milli(?)
+            GROUP BY trace_id
+            {}
+            ORDER BY {}
+            LIMIT ?
+        ",
+            having, order
+        );
+
+        let rows = self
+            .client
+            .query(&query)
             .bind(function_name)
-            .fetch_all::<LogEntry>()
-            .await?;
+            .bind(range.from.timestamp_millis())
+            .bind(range.to.timestamp_millis())
+            .bind(limit as u64)
+            .fetch_all::<TraceSummaryRow>()
+            .await
+            .map_err(query_error)?;
 
-        Ok(rows)
+        Ok(rows.into_iter().map(Into::into).collect())
     }
 
-    pub async fn get_recent_logs(&self) -> anyhow::Result<Vec<LogEntry>> {
-        let query = "
+    async fn search_logs(&self, query: &LogQuery) -> Result<Vec<LogEntry>, DomainError> {
+        let (past, order) = if query.oldest_first {
+            (">", "ASC")
+        } else {
+            ("<", "DESC")
+        };
+        let cursor_condition = format!(
+            "(Timestamp, {}) {} (fromUnixTimestamp64Nano(?), ?)",
+            LOG_KEY, past
+        );
+
+        let mut conditions = Vec::new();
+        if query.function_name.is_some() {
+            conditions.push("LogAttributes['function_name'] = ?");
+        } else {
+            conditions
+                .push("NOT (LogAttributes['function_name'] = 'healthz' AND SeverityText = 'INFO')");
+        }
+        if query.level.is_some() {
+            conditions.push("SeverityText = ?");
+        }
+        if query.contains.is_some() {
+            conditions.push("positionCaseInsensitive(Body, ?) > 0");
+        }
+        if query.trace_id.is_some() {
+            conditions.push("TraceId = ?");
+        }
+        if query.after.is_some() {
+            conditions.push("Timestamp > fromUnixTimestamp64Nano(?)");
+        }
+        if query.before.is_some() {
+            conditions.push("Timestamp < fromUnixTimestamp64Nano(?)");
+        }
+        if query.cursor.is_some() {
+            conditions.push(&cursor_condition);
+        }
+
+        let sql = format!(
+            "
             SELECT
-                toString(Timestamp) as timestamp,
+                toString(Timestamp, 'UTC') as timestamp,
                 SeverityText as level,
                 Body as body,
                 TraceId as trace_id,
-                LogAttributes['function_name'] as function_name
+                LogAttributes['function_name'] as function_name,
+                {} as key
             FROM otel_logs
-            WHERE NOT (LogAttributes['function_name'] = 'healthz' AND SeverityText = 'INFO')
-            ORDER BY Timestamp DESC
-            LIMIT 50
-        ";
+            WHERE {}
+            ORDER BY Timestamp {}, key {}
+            LIMIT ?
+        ",
+            LOG_KEY,
+            conditions.join(" AND "),
+            order,
+            order
+        );
+
+        let mut request = self.client.query(&sql);
+        for value in [
+            &query.function_name,
+            &query.level,
+            &query.contains,
+            &query.trace_id,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            request = request.bind(value.as_str());
+        }
+        for instant in [query.after, query.before].into_iter().flatten() {
+            request = request.bind(instant.timestamp_nanos_opt().unwrap_or(i64::MAX));
+        }
+        if let Some(cursor) = query.cursor {
+            request = request
+                .bind(cursor.at.timestamp_nanos_opt().unwrap_or(i64::MAX))
+                .bind(cursor.key);
+        }
 
-        let rows = self.client.query(query).fetch_all::<LogEntry>().await?;
+        let rows = request
+            .bind(query.limit as u64)
+            .fetch_all::<LogRow>()
+            .await
+            .map_err(query_error)?;
 
-        Ok(rows)
+        Ok(rows.into_iter().map(Into::into).collect())
     }
 }
@@ -0,0 +1,468 @@
+use crate::domain::entities::{
+    BucketStats, DomainError, ExecutionMetric, FunctionStats, InvocationStats,
+    LOG_TIMESTAMP_FORMAT, LogEntry, LogPosition, LogQuery, TimeRange, TraceFilter, TraceSpan,
+    TraceSummary,
+};
+use crate::domain::ports::{TelemetryRecorder, TelemetryRepository};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Oldest invocations are dropped past this many.
+const MAX_INVOCATIONS: usize = 100_000;
+/// Oldest log lines are dropped past this many.
+const MAX_LOGS: usize = 10_000;
+
+/// Functions whose routine activity is left out of the overall views.
+const HEALTHCHECK_FUNCTION: &str = "healthz";
+
+struct Invocation {
+    at: DateTime<Utc>,
+    function_name: String,
+    failed: bool,
+    duration_ms: u64,
+}
+
+struct Log {
+    at: DateTime<Utc>,
+    entry: LogEntry,
+}
+
+impl Log {
+    fn position(&self) -> LogPosition {
+        LogPosition {
+            at: self.at,
+            key: self.entry.key,
+        }
+    }
+
+    fn matches(&self, query: &LogQuery) -> bool {
+        let entry = &self.entry;
+        let function_matches = match &query.function_name {
+            Some(function_name) => entry.function_name == *function_name,
+            None => !(entry.function_name == HEALTHCHECK_FUNCTION && entry.level == "INFO"),
+        };
+        function_matches
+            && query.level.as_ref().is_none_or(|l| entry.level == *l)
+            && query
+                .contains
+                .as_ref()
+                .is_none_or(|text| entry.body.to_lowercase().contains(&text.to_lowercase()))
+            && query.trace_id.as_ref().is_none_or(|t| entry.trace_id == *t)
+            && query.after.is_none_or(|after| self.at > after)
+            && query.before.is_none_or(|before| self.at < before)
+            && query.cursor.is_none_or(|cursor| {
+                if query.oldest_first {
+                    self.position() > cursor
+                } else {
+                    self.position() < cursor
+                }
+            })
+    }
+}
+
+/// Keeps recent invocations and logs in the API process, for running without
+/// ClickHouse and the OpenTelemetry collector. Each node only sees its own traffic
+/// and nothing survives a restart. Spans are not kept, so no traces are found.
+#[derive(Default)]
+pub struct EmbeddedTelemetryRepository {
+    invocations: Mutex<VecDeque<Invocation>>,
+    logs: Mutex<VecDeque<Log>>,
+    /// Key of the next log line, telling apart lines logged at the same instant.
+    next_log_key: AtomicU64,
+}
+
+impl EmbeddedTelemetryRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Per-bucket counts of the invocations in `range` matching `include`.
+    fn executions(
+        &self,
+        range: &TimeRange,
+        include: impl Fn(&Invocation) -> bool,
+    ) -> Result<Vec<ExecutionMetric>, DomainError> {
+        let invocations = self
+            .invocations
+            .lock()
+            .map_err(|_| DomainError::Internal("Telemetry store poisoned".to_string()))?;
+
+        let mut buckets: BTreeMap<DateTime<Utc>, u64> = BTreeMap::new();
+        for invocation in invocations
+            .iter()
+            .filter(|i| i.at >= range.from && i.at < range.to && include(i))
+        {
+            *buckets.entry(range.bucket_of(invocation.at)).or_default() += 1;
+        }
+
+        Ok(buckets
+            .into_iter()
+            .map(|(bucket, count)| ExecutionMetric {
+                time_bucket: TimeRange::label(bucket),
+                count,
+            })
+            .collect())
+    }
+
+    /// Invocations in `range` matching `include`, grouped by `key`.
+    fn stats_by<K: Ord>(
+        &self,
+        range: &TimeRange,
+        include: impl Fn(&Invocation) -> bool,
+        key: impl Fn(&Invocation) -> K,
+    ) -> Result<BTreeMap<K, InvocationStats>, DomainError> {
+        let invocations = self
+            .invocations
+            .lock()
+            .map_err(|_| DomainError::Internal("Telemetry store poisoned".to_string()))?;
+
+        let mut groups: BTreeMap<K, (u64, Vec<u64>)> = BTreeMap::new();
+        for invocation in invocations
+            .iter()
+            .filter(|i| i.at >= range.from && i.at < range.to && include(i))
+        {
+            let (errors, durations) = groups.entry(key(invocation)).or_default();
+            *errors += invocation.failed as u64;
+            durations.push(invocation.duration_ms);
+        }
+
+        Ok(groups
+            .into_iter()
+            .map(|(k, (errors, mut durations))| {
+                durations.sort_unstable();
+                let stats = InvocationStats {
+                    invocations: durations.len() as u64,
+                    errors,
+                    p50_ms: percentile(&durations, 0.5),
+                    p90_ms: percentile(&durations, 0.9),
+                    p99_ms: percentile(&durations, 0.99),
+                };
+                (k, stats)
+            })
+            .collect())
+    }
+
+    fn performance(
+        &self,
+        range: &TimeRange,
+        include: impl Fn(&Invocation) -> bool,
+    ) -> Result<Vec<BucketStats>, DomainError> {
+        Ok(self
+            .stats_by(range, include, |i| range.bucket_of(i.at))?
+            .into_iter()
+            .map(|(bucket, stats)| BucketStats {
+                time_bucket: TimeRange::label(bucket),
+                stats,
+            })
+            .collect())
+    }
+}
+
+/// Nearest-rank percentile of ascending `sorted`.
+fn percentile(sorted: &[u64], q: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (q * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1] as f64
+}
+
+fn push_bounded<T>(queue: &Mutex<VecDeque<T>>, item: T, max: usize) {
+    // Telemetry is best effort; a poisoned store just stops recording
+    if let Ok(mut queue) = queue.lock() {
+        if queue.len() >= max {
+            queue.pop_front();
+        }
+        queue.push_back(item);
+    }
+}
+
+impl TelemetryRecorder for EmbeddedTelemetryRepository {
+    fn record_invocation(&self, function_name: &str, status: &str, duration_ms: u64) {
+        push_bounded(
+            &self.invocations,
+            Invocation {
+                at: Utc::now(),
+                function_name: function_name.to_string(),
+                failed: status != "ok",
+                duration_ms,
+            },
+            MAX_INVOCATIONS,
+        );
+    }
+
+    fn record_log(&self, function_name: &str, level: &str, body: &str, trace_id: &str) {
+        let at = Utc::now();
+        push_bounded(
+            &self.logs,
+            Log {
+                at,
+                entry: LogEntry {
+                    timestamp: at.format(LOG_TIMESTAMP_FORMAT).to_string(),
+                    level: level.to_string(),
+                    body: body.to_string(),
+                    trace_id: trace_id.to_string(),
+                    function_name: function_name.to_string(),
+                    key: self.next_log_key.fetch_add(1, Ordering::Relaxed),
+                },
+            },
+            MAX_LOGS,
+        );
+    }
+}
+
+#[async_trait]
+impl TelemetryRepository for EmbeddedTelemetryRepository {
+    async fn get_function_executions(
+        &self,
+        function_name: &str,
+        range: &TimeRange,
+    ) -> Result<Vec<ExecutionMetric>, DomainError> {
+        self.executions(range, |i| i.function_name == function_name)
+    }
+
+    async fn get_overall_executions(
+        &self,
+        range: &TimeRange,
+    ) -> Result<Vec<ExecutionMetric>, DomainError> {
+        self.executions(range, |i| i.function_name != HEALTHCHECK_FUNCTION)
+    }
+
+    async fn get_function_performance(
+        &self,
+        function_name: &str,
+        range: &TimeRange,
+    ) -> Result<Vec<BucketStats>, DomainError> {
+        self.performance(range, |i| i.function_name == function_name)
+    }
+
+    async fn get_overall_performance(
+        &self,
+        range: &TimeRange,
+    ) -> Result<Vec<BucketStats>, DomainError> {
+        self.performance(range, |i| i.function_name != HEALTHCHECK_FUNCTION)
+    }
+
+    async fn get_function_stats(
+        &self,
+        range: &TimeRange,
+    ) -> Result<Vec<FunctionStats>, DomainError> {
+        Ok(self
+            .stats_by(
+                range,
+                |i| i.function_name != HEALTHCHECK_FUNCTION,
+                |i| i.function_name.clone(),
+            )?
+            .into_iter()
+            .map(|(function_name, stats)| FunctionStats {
+                function_name,
+                stats,
+            })
+            .collect())
+    }
+
+    async fn get_trace_spans(&self, _trace_id: &str) -> Result<Vec<TraceSpan>, DomainError> {
+        Ok(Vec::new())
+    }
+
+    async fn list_traces(
+        &self,
+        _function_name: &str,
+        _range: &TimeRange,
+        _filter: TraceFilter,
+        _limit: usize,
+    ) -> Result<Vec<TraceSummary>, DomainError> {
+        Ok(Vec::new())
+    }
+
+    async fn search_logs(&self, query: &LogQuery) -> Result<Vec<LogEntry>, DomainError> {
+        let logs = self
+            .logs
+            .lock()
+            .map_err(|_| DomainError::Internal("Telemetry store poisoned".to_string()))?;
+
+        // Lines recorded concurrently may be stored slightly out of order
+        let mut matching: Vec<&Log> = logs.iter().filter(|l| l.matches(query)).collect();
+        matching.sort_by_key(|l| l.position());
+        if !query.oldest_first {
+            matching.reverse();
+        }
+        Ok(matching
+            .into_iter()
+            .take(query.limit)
+            .map(|l| l.entry.clone())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::entities::Granularity;
+    use chrono::TimeDelta;
+
+    fn last_hour() -> TimeRange {
+        let now = Utc::now();
+        TimeRange {
+            from: now - TimeDelta::hours(1),
+            to: now + TimeDelta::seconds(1),
+            step: Granularity::Minute,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_counts_invocations_per_function() {
+        let store = EmbeddedTelemetryRepository::new();
+        store.record_invocation("a", "ok", 3);
+        store.record_invocation("a", "error", 5);
+        store.record_invocation("b", "ok", 1);
+        store.record_invocation("healthz", "ok", 1);
+
+        let a = store
+            .get_function_executions("a", &last_hour())
+            .await
+            .unwrap();
+        assert_eq!(a.iter().map(|m| m.count).sum::<u64>(), 2);
+
+        let overall = store.get_overall_executions(&last_hour()).await.unwrap();
+        assert_eq!(overall.iter().map(|m| m.count).sum::<u64>(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_stats_per_function() {
+        let store = EmbeddedTelemetryRepository::new();
+        for ms in 1..=100 {
+            store.record_invocation("a", "ok", ms);
+        }
+        store.record_invocation("a", "error", 500);
+        store.record_invocation("a", "rejected", 0);
+        store.record_invocation("healthz", "ok", 1);
+
+        let stats = store.get_function_stats(&last_hour()).await.unwrap();
+        assert_eq!(stats.len(), 1);
+        let a = &stats[0].stats;
+        assert_eq!(a.invocations, 102);
+        assert_eq!(a.errors, 2);
+        assert_eq!(a.p50_ms, 50.0);
+        assert_eq!(a.p90_ms, 91.0);
+        assert_eq!(a.p99_ms, 100.0);
+
+        let buckets = store
+            .get_function_performance("a", &last_hour())
+            .await
+            .unwrap();
+        let total: u64 = buckets.iter().map(|b| b.stats.invocations).sum();
+        assert_eq!(total, 102);
+    }
+
+    fn query(function_name: Option<&str>) -> LogQuery {
+        LogQuery {
+            function_name: function_name.map(str::to_string),
+            limit: 100,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_logs_newest_first_without_healthz_noise() {
+        let store = EmbeddedTelemetryRepository::new();
+        store.record_log("a", "INFO", "first", "");
+        store.record_log("healthz", "INFO", "ping", "");
+        store.record_log("healthz", "ERROR", "down", "");
+        store.record_log("a", "ERROR", "second", "abc");
+
+        let logs = store.search_logs(&query(Some("a"))).await.unwrap();
+        let bodies: Vec<_> = logs.iter().map(|l| l.body.as_str()).collect();
+        assert_eq!(bodies, ["second", "first"]);
+        assert_eq!(logs[0].trace_id, "abc");
+
+        let recent = store.search_logs(&query(None)).await.unwrap();
+        let bodies: Vec<_> = recent.iter().map(|l| l.body.as_str()).collect();
+        assert_eq!(bodies, ["second", "down", "first"]);
+    }
+
+    #[tokio::test]
+    async fn test_logs_filtered_by_level_text_trace_and_time() {
+        let store = EmbeddedTelemetryRepository::new();
+        store.record_log("a", "INFO", "Connected to DB", "t1");
+        store.record_log("a", "ERROR", "db timeout", "t2");
+        store.record_log("a", "ERROR", "bad input", "t2");
+
+        let search = |q: LogQuery| {
+            let store = &store;
+            async move {
+                store
+                    .search_logs(&q)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|l| l.body)
+                    .collect::<Vec<_>>()
+            }
+        };
+        let errors = LogQuery {
+            level: Some("ERROR".to_string()),
+            ..query(Some("a"))
+        };
+        assert_eq!(search(errors).await, ["bad input", "db timeout"]);
+        let db = LogQuery {
+            contains: Some("DB".to_string()),
+            ..query(Some("a"))
+        };
+        assert_eq!(search(db).await, ["db timeout", "Connected to DB"]);
+        let trace = LogQuery {
+            trace_id: Some("t1".to_string()),
+            ..query(None)
+        };
+        assert_eq!(search(trace).await, ["Connected to DB"]);
+
+        let newest = store.search_logs(&query(Some("a"))).await.unwrap();
+        let before = LogQuery {
+            before: newest[0].logged_at(),
+            ..query(Some("a"))
+        };
+        assert_eq!(search(before).await, ["db timeout", "Connected to DB"]);
+        let after = LogQuery {
+            after: newest[1].logged_at(),
+            ..query(Some("a"))
+        };
+        assert_eq!(search(after).await, ["bad input"]);
+    }
+
+    #[tokio::test]
+    async fn test_logs_paged_past_cursor_in_either_order() {
+        let store = EmbeddedTelemetryRepository::new();
+        for body in ["1", "2", "3"] {
+            store.record_log("a", "INFO", body, "");
+        }
+
+        for (oldest_first, expected) in [(false, ["3", "2", "1"]), (true, ["1", "2", "3"])] {
+            let mut query = LogQuery {
+                oldest_first,
+                limit: 1,
+                ..query(Some("a"))
+            };
+            let mut bodies = Vec::new();
+            while let [entry] = store.search_logs(&query).await.unwrap().as_slice() {
+                bodies.push(entry.body.clone());
+                query.cursor = entry.position();
+            }
+            assert_eq!(bodies, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_drops_oldest_logs_past_capacity() {
+        let store = EmbeddedTelemetryRepository::new();
+        for i in 0..MAX_LOGS + 5 {
+            store.record_log("a", "INFO", &i.to_string(), "");
+        }
+
+        assert_eq!(store.logs.lock().unwrap().len(), MAX_LOGS);
+        let newest = store.search_logs(&query(Some("a"))).await.unwrap();
+        assert_eq!(newest[0].body, (MAX_LOGS + 4).to_string());
+    }
+}
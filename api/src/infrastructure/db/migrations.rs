@@ -0,0 +1,531 @@
+//! Versioned, forward-only schema migrations for the control-plane database.
+
+use anyhow::{Context, bail};
+use chrono::Utc;
+use sqlx::Connection;
+use sqlx::sqlite::SqlitePool;
+use tracing::info;
+
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    pub sql: &'static str,
+}
+
+/// Applied in order. Never edit a migration that has shipped; add a new one.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial schema",
+        sql: include_str!("../../../migrations/sqlite/0001_initial_schema.sql"),
+    },
+    Migration {
+        version: 2,
+        description: "drop triggers.function_name",
+        sql: include_str!("../../../migrations/sqlite/0002_drop_trigger_function_name.sql"),
+    },
+    Migration {
+        version: 3,
+        description: "alert rules",
+        sql: include_str!("../../../migrations/sqlite/0003_alert_rules.sql"),
+    },
+    Migration {
+        version: 4,
+        description: "audit log",
+        sql: include_str!("../../../migrations/sqlite/0004_audit_log.sql"),
+    },
+    Migration {
+        version: 5,
+        description: "rate limit bucket expiry",
+        sql: include_str!("../../../migrations/sqlite/0005_rate_limit_expiry.sql"),
+    },
+    Migration {
+        version: 6,
+        description: "background jobs",
+        sql: include_str!("../../../migrations/sqlite/0006_background_jobs.sql"),
+    },
+    Migration {
+        version: 7,
+        description: "trigger workflow column",
+        sql: include_str!("../../../migrations/sqlite/0007_trigger_workflow_column.sql"),
+    },
+    Migration {
+        version: 8,
+        description: "node lease",
+        sql: include_str!("../../../migrations/sqlite/0008_node_lease.sql"),
+    },
+];
+
+/// Columns the unversioned bootstrap added with `ALTER TABLE` after creating
+/// its tables, which older databases may lack.
+const LEGACY_COLUMNS: &[(&str, &str, &str)] = &[
+    ("functions", "readonly", "BOOLEAN NOT NULL DEFAULT FALSE"),
+    ("functions", "snapshot", "BOOLEAN NOT NULL DEFAULT FALSE"),
+    ("functions", "max_concurrency", "INTEGER"),
+    ("functions", "max_queue", "INTEGER"),
+    ("functions", "allowed_calls", "TEXT NOT NULL DEFAULT '[]'"),
+    ("functions", "retry", "TEXT"),
+    ("functions", "env", "TEXT NOT NULL DEFAULT '{}'"),
+    ("triggers", "function_name", "TEXT"),
+    ("triggers", "readonly", "BOOLEAN NOT NULL DEFAULT FALSE"),
+    ("triggers", "kind", "TEXT NOT NULL DEFAULT 'http'"),
+    ("triggers", "rate_limit", "TEXT"),
+    ("triggers", "cache", "TEXT"),
+];
+
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+pub async fn current_version(pool: &SqlitePool) -> anyhow::Result<i64> {
+    if !table_exists(pool, "schema_version").await? {
+        return Ok(0);
+    }
+    Ok(
+        sqlx::query_scalar("SELECT COALESCE(MAX(version), 0) FROM schema_version")
+            .fetch_one(pool)
+            .await?,
+    )
+}
+
+/// Brings the database to the latest schema version.
+pub async fn migrate(pool: &SqlitePool) -> anyhow::Result<()> {
+    migrate_to(pool, latest_version()).await
+}
+
+/// Applies pending migrations up to and including `target`.
+pub async fn migrate_to(pool: &SqlitePool, target: i64) -> anyhow::Result<()> {
+    let current = current_version(pool).await?;
+    if current > latest_version() {
+        bail!(
+            "Database schema version {} is newer than this build supports ({})",
+            current,
+            latest_version()
+        );
+    }
+    if current == 0 && table_exists(pool, "functions").await? {
+        info!("Adopting unversioned database schema");
+        add_legacy_columns(pool).await?;
+    }
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            description TEXT NOT NULL,
+            applied_at TEXT NOT NULL
+        )",
+    )
+    .execute(pool)
+    .await?;
+
+    for migration in MIGRATIONS
+        .iter()
+        .filter(|m| m.version > current && m.version <= target)
+    {
+        apply(pool, migration).await.with_context(|| {
+            format!(
+                "Migration {} ({}) failed",
+                migration.version, migration.description
+            )
+        })?;
+    }
+    Ok(())
+}
+
+async fn apply(pool: &SqlitePool, migration: &Migration) -> anyhow::Result<()> {
+    let mut conn = pool.acquire().await?;
+    // Table rebuilds would trip foreign keys halfway through, so they are
+    // switched off (which only works outside a transaction) and checked
+    // before committing instead
+    sqlx::query("PRAGMA foreign_keys = OFF")
+        .execute(&mut *conn)
+        .await?;
+
+    let result: anyhow::Result<bool> = async {
+        // IMMEDIATE takes the write lock up front, so nodes starting together
+        // apply each migration once
+        let mut tx = conn.begin_with("BEGIN IMMEDIATE").await?;
+        let applied: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM schema_version WHERE version = ?")
+                .bind(migration.version)
+                .fetch_one(&mut *tx)
+                .await?;
+        if applied > 0 {
+            return Ok(false);
+        }
+
+        sqlx::raw_sql(migration.sql).execute(&mut *tx).await?;
+        let violations: Vec<(String, Option<i64>, String, i64)> =
+            sqlx::query_as("PRAGMA foreign_key_check")
+                .fetch_all(&mut *tx)
+                .await?;
+        if let Some((table, _, parent, _)) = violations.first() {
+            bail!(
+                "{} rows violate foreign keys, first in {} referencing {}",
+                violations.len(),
+                table,
+                parent
+            );
+        }
+
+        sqlx::query(
+            "INSERT INTO schema_version (version, description, applied_at) VALUES (?, ?, ?)",
+        )
+        .bind(migration.version)
+        .bind(migration.description)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+    .await;
+
+    sqlx::query("PRAGMA foreign_keys = ON")
+        .execute(&mut *conn)
+        .await?;
+    if result? {
+        info!(
+            "Applied migration {}: {}",
+            migration.version, migration.description
+        );
+    }
+    Ok(())
+}
+
+async fn add_legacy_columns(pool: &SqlitePool) -> anyhow::Result<()> {
+    for (table, column, definition) in LEGACY_COLUMNS {
+        if !table_exists(pool, table).await? {
+            continue;
+        }
+        let exists: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM pragma_table_info(?) WHERE name = ?")
+                .bind(table)
+                .bind(column)
+                .fetch_one(pool)
+                .await?;
+        if exists == 0 {
+            sqlx::query(&format!(
+                "ALTER TABLE {} ADD COLUMN {} {}",
+                table, column, definition
+            ))
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+async fn table_exists(pool: &SqlitePool, name: &str) -> anyhow::Result<bool> {
+    let count: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?")
+            .bind(name)
+            .fetch_one(pool)
+            .await?;
+    Ok(count > 0)
+}
+
+/// The PostgreSQL schema starts from the current SQLite shape, so it is
+/// numbered separately.
+#[cfg(feature = "postgres")]
+pub const POSTGRES_MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial schema",
+        sql: include_str!("../../../migrations/postgres/0001_initial_schema.sql"),
+    },
+    Migration {
+        version: 2,
+        description: "alert rules",
+        sql: include_str!("../../../migrations/postgres/0002_alert_rules.sql"),
+    },
+    Migration {
+        version: 3,
+        description: "audit log",
+        sql: include_str!("../../../migrations/postgres/0003_audit_log.sql"),
+    },
+    Migration {
+        version: 4,
+        description: "rate limit bucket expiry",
+        sql: include_str!("../../../migrations/postgres/0004_rate_limit_expiry.sql"),
+    },
+    Migration {
+        version: 5,
+        description: "background jobs",
+        sql: include_str!("../../../migrations/postgres/0005_background_jobs.sql"),
+    },
+    Migration {
+        version: 6,
+        description: "trigger workflow column",
+        sql: include_str!("../../../migrations/postgres/0006_trigger_workflow_column.sql"),
+    },
+    Migration {
+        version: 7,
+        description: "node lease",
+        sql: include_str!("../../../migrations/postgres/0007_node_lease.sql"),
+    },
+];
+
+/// Advisory lock key held while migrating, so nodes starting together apply
+/// each migration once.
+#[cfg(feature = "postgres")]
+const POSTGRES_LOCK_KEY: i64 = 0x0066_6c75_6f72;
+
+/// Brings a PostgreSQL database to the latest schema version. DDL is
+/// transactional there, so all pending migrations commit or none do.
+#[cfg(feature = "postgres")]
+pub async fn migrate_postgres(pool: &sqlx::PgPool) -> anyhow::Result<()> {
+    let latest = POSTGRES_MIGRATIONS.last().map_or(0, |m| m.version);
+    let mut tx = pool.begin().await?;
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(POSTGRES_LOCK_KEY)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version BIGINT PRIMARY KEY,
+            description TEXT NOT NULL,
+            applied_at TEXT NOT NULL
+        )",
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let current: i64 = sqlx::query_scalar("SELECT COALESCE(MAX(version), 0) FROM schema_version")
+        .fetch_one(&mut *tx)
+        .await?;
+    if current > latest {
+        bail!(
+            "Database schema version {} is newer than this build supports ({})",
+            current,
+            latest
+        );
+    }
+
+    let pending: Vec<&Migration> = POSTGRES_MIGRATIONS
+        .iter()
+        .filter(|m| m.version > current)
+        .collect();
+    for migration in &pending {
+        sqlx::raw_sql(migration.sql)
+            .execute(&mut *tx)
+            .await
+            .with_context(|| {
+                format!(
+                    "Migration {} ({}) failed",
+                    migration.version, migration.description
+                )
+            })?;
+        sqlx::query(
+            "INSERT INTO schema_version (version, description, applied_at) VALUES ($1, $2, $3)",
+        )
+        .bind(migration.version)
+        .bind(migration.description)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    for migration in pending {
+        info!(
+            "Applied migration {}: {}",
+            migration.version, migration.description
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqliteConnectOptions;
+    use tempfile::{TempDir, tempdir};
+
+    async fn pool() -> (SqlitePool, TempDir) {
+        let dir = tempdir().unwrap();
+        let options = SqliteConnectOptions::new()
+            .filename(dir.path().join("test.db"))
+            .foreign_keys(true)
+            .create_if_missing(true);
+        (SqlitePool::connect_with(options).await.unwrap(), dir)
+    }
+
+    async fn columns(pool: &SqlitePool, table: &str) -> Vec<String> {
+        sqlx::query_scalar("SELECT name FROM pragma_table_info(?)")
+            .bind(table)
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    /// Rows every schema version can hold, so upgrades can be checked to keep them.
+    async fn insert_rows(pool: &SqlitePool) {
+        for statement in [
+            "INSERT INTO functions (name, language, executable, cpu, memory) VALUES ('hello', 'rust', 'hello.wasm', '1', '128')",
+            "INSERT INTO triggers (name, method, path, function) VALUES ('hello', 'GET', '/hello', 'hello')",
+        ] {
+            sqlx::query(statement).execute(pool).await.unwrap();
+        }
+    }
+
+    async fn assert_rows_kept(pool: &SqlitePool) {
+        let function: String =
+            sqlx::query_scalar("SELECT function FROM triggers WHERE name = 'hello'")
+                .fetch_one(pool)
+                .await
+                .unwrap();
+        assert_eq!(function, "hello");
+        let memory: String =
+            sqlx::query_scalar("SELECT memory FROM functions WHERE name = 'hello'")
+                .fetch_one(pool)
+                .await
+                .unwrap();
+        assert_eq!(memory, "128");
+    }
+
+    #[tokio::test]
+    async fn test_migrate_fresh_database() {
+        let (pool, _dir) = pool().await;
+        migrate(&pool).await.unwrap();
+        assert_eq!(current_version(&pool).await.unwrap(), latest_version());
+
+        // Running again is a no-op
+        migrate(&pool).await.unwrap();
+        let applied: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM schema_version")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(applied, MIGRATIONS.len() as i64);
+
+        assert!(
+            !columns(&pool, "triggers")
+                .await
+                .contains(&"function_name".to_string())
+        );
+        let enforced: i64 = sqlx::query_scalar("PRAGMA foreign_keys")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(enforced, 1);
+        let orphan = sqlx::query(
+            "INSERT INTO workflow_steps (run_id, step, position, status) VALUES ('missing', 'a', 0, 'pending')",
+        )
+        .execute(&pool)
+        .await;
+        assert!(orphan.is_err());
+
+        // Triggers keep their function or workflow from being deleted
+        insert_rows(&pool).await;
+        for statement in [
+            "INSERT INTO workflows (name, steps) VALUES ('pipeline', '[]')",
+            "INSERT INTO triggers (name, method, path, workflow, kind) VALUES ('flow', 'POST', '/flow', 'pipeline', 'workflow')",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+        for statement in [
+            "DELETE FROM functions WHERE name = 'hello'",
+            "DELETE FROM workflows WHERE name = 'pipeline'",
+            "INSERT INTO triggers (name, method, path, function) VALUES ('ghost', 'GET', '/ghost', 'missing')",
+            "INSERT INTO triggers (name, method, path, function, kind) VALUES ('stray', 'POST', '/stray', 'hello', 'workflow')",
+        ] {
+            assert!(
+                sqlx::query(statement).execute(&pool).await.is_err(),
+                "{statement}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upgrade_moves_workflow_targets() {
+        let (pool, _dir) = pool().await;
+        migrate_to(&pool, 6).await.unwrap();
+        insert_rows(&pool).await;
+        for statement in [
+            "INSERT INTO workflows (name, steps) VALUES ('pipeline', '[]')",
+            "INSERT INTO triggers (name, method, path, function, kind) VALUES ('flow', 'POST', '/flow', 'pipeline', 'workflow')",
+            "INSERT INTO triggers (name, method, path, function) VALUES ('ghost', 'GET', '/ghost', 'missing')",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+
+        migrate(&pool).await.unwrap();
+        assert_rows_kept(&pool).await;
+        let (function, workflow): (Option<String>, Option<String>) =
+            sqlx::query_as("SELECT function, workflow FROM triggers WHERE name = 'flow'")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(function, None);
+        assert_eq!(workflow.as_deref(), Some("pipeline"));
+        let ghost: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM triggers WHERE name = 'ghost'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(ghost, 0);
+    }
+
+    #[tokio::test]
+    async fn test_upgrade_from_each_version() {
+        for version in 1..latest_version() {
+            let (pool, _dir) = pool().await;
+            migrate_to(&pool, version).await.unwrap();
+            assert_eq!(current_version(&pool).await.unwrap(), version);
+            insert_rows(&pool).await;
+
+            migrate(&pool).await.unwrap();
+            assert_eq!(current_version(&pool).await.unwrap(), latest_version());
+            assert_rows_kept(&pool).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_adopt_unversioned_schema() {
+        let (pool, _dir) = pool().await;
+        // The shape the unversioned bootstrap first created
+        for statement in [
+            "CREATE TABLE users (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL DEFAULT '', email TEXT NOT NULL UNIQUE, password_hash TEXT NOT NULL, role TEXT NOT NULL DEFAULT 'user')",
+            "CREATE TABLE functions (name TEXT PRIMARY KEY, language TEXT NOT NULL, executable TEXT NOT NULL, cpu TEXT NOT NULL, memory TEXT NOT NULL)",
+            "CREATE TABLE triggers (name TEXT PRIMARY KEY, method TEXT NOT NULL, path TEXT NOT NULL, function TEXT NOT NULL REFERENCES functions(name), function_name TEXT)",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+        insert_rows(&pool).await;
+
+        migrate(&pool).await.unwrap();
+        assert_eq!(current_version(&pool).await.unwrap(), latest_version());
+        assert_rows_kept(&pool).await;
+        assert!(
+            columns(&pool, "functions")
+                .await
+                .contains(&"env".to_string())
+        );
+        assert!(
+            columns(&pool, "triggers")
+                .await
+                .contains(&"rate_limit".to_string())
+        );
+
+        // Workflow triggers name their target in their own column
+        for statement in [
+            "INSERT INTO workflows (name, steps) VALUES ('pipeline', '[]')",
+            "INSERT INTO triggers (name, method, path, workflow, kind) VALUES ('flow', 'POST', '/flow', 'pipeline', 'workflow')",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rejects_newer_schema() {
+        let (pool, _dir) = pool().await;
+        migrate(&pool).await.unwrap();
+        sqlx::query("INSERT INTO schema_version (version, description, applied_at) VALUES (?, 'future', '')")
+            .bind(latest_version() + 1)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let err = migrate(&pool).await.unwrap_err();
+        assert!(err.to_string().contains("newer than this build"));
+    }
+}
@@ -1,2 +1,117 @@
 pub mod clickhouse;
+pub mod embedded_telemetry;
+pub mod migrations;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+mod rows;
 pub mod sqlite;
+
+use crate::domain::ports::{
+    AlertRepository, AuditRepository, BackgroundJobRepository, DeadLetterRepository,
+    FunctionRepository, ManifestRepository, NodeLeaseRepository, RateLimitStore, TelemetryRecorder,
+    TelemetryRepository, TriggerRepository, UserRepository, WorkflowRepository,
+};
+use argon2::{
+    Algorithm, Argon2, Params, Version,
+    password_hash::{PasswordHasher, SaltString},
+};
+use rand_core::OsRng;
+use std::env;
+use std::sync::Arc;
+
+/// Every port the control-plane database backs.
+pub trait Repository:
+    UserRepository
+    + FunctionRepository
+    + TriggerRepository
+    + ManifestRepository
+    + RateLimitStore
+    + WorkflowRepository
+    + BackgroundJobRepository
+    + DeadLetterRepository
+    + AlertRepository
+    + AuditRepository
+    + NodeLeaseRepository
+{
+}
+
+impl<T> Repository for T where
+    T: UserRepository
+        + FunctionRepository
+        + TriggerRepository
+        + ManifestRepository
+        + RateLimitStore
+        + WorkflowRepository
+        + BackgroundJobRepository
+        + DeadLetterRepository
+        + AlertRepository
+        + AuditRepository
+        + NodeLeaseRepository
+{
+}
+
+/// Whether `database_url` names a PostgreSQL database rather than SQLite.
+pub fn is_postgres_url(database_url: &str) -> bool {
+    database_url.starts_with("postgres://") || database_url.starts_with("postgresql://")
+}
+
+/// Connects to `DATABASE_URL`, picking the adapter by its scheme, and seeds it.
+pub async fn init_repository() -> Arc<dyn Repository> {
+    let database_url =
+        env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite:fluor.db?mode=rwc".to_string());
+    if is_postgres_url(&database_url) {
+        #[cfg(feature = "postgres")]
+        return Arc::new(postgres::PostgresRepository::new(
+            postgres::init_db(database_url).await,
+        ));
+        #[cfg(not(feature = "postgres"))]
+        panic!(
+            "DATABASE_URL points at PostgreSQL but the API was built without the `postgres` feature"
+        );
+    }
+    Arc::new(sqlite::SqliteRepository::new(
+        sqlite::init_db(database_url).await,
+    ))
+}
+
+/// Where telemetry is read from, and what records it when the backend lives in-process.
+pub struct TelemetryBackend {
+    pub repository: Arc<dyn TelemetryRepository>,
+    /// Set for backends fed by the API itself rather than by the collector.
+    pub recorder: Option<Arc<dyn TelemetryRecorder>>,
+}
+
+/// Picks the telemetry backend from `TELEMETRY_BACKEND`: `clickhouse` (default)
+/// reads what the collector exported, `embedded` keeps recent data in memory.
+pub fn init_telemetry_backend() -> TelemetryBackend {
+    match env::var("TELEMETRY_BACKEND").as_deref() {
+        Ok("embedded") => {
+            let store = Arc::new(embedded_telemetry::EmbeddedTelemetryRepository::new());
+            TelemetryBackend {
+                repository: store.clone(),
+                recorder: Some(store),
+            }
+        }
+        _ => {
+            let url = env::var("CLICKHOUSE_URL").unwrap_or("http://localhost:8123".to_string());
+            let user = env::var("CLICKHOUSE_USER").unwrap_or("default".to_string());
+            let password = env::var("CLICKHOUSE_PASSWORD").unwrap_or("password".to_string());
+            let db = env::var("CLICKHOUSE_DB").unwrap_or("default".to_string());
+            TelemetryBackend {
+                repository: Arc::new(clickhouse::ClickHouseRepository::new(
+                    &url, &user, &password, &db,
+                )),
+                recorder: None,
+            }
+        }
+    }
+}
+
+fn hash_password(password: &str, pepper: &str) -> String {
+    let salt = SaltString::generate(&mut OsRng);
+    let password_with_pepper = format!("{}{}", password, pepper);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, Params::default())
+        .hash_password(password_with_pepper.as_bytes(), &salt)
+        .unwrap()
+        .to_string()
+}
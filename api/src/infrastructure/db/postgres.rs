@@ -0,0 +1,968 @@
+use async_trait::async_trait;
+use sqlx::pool::PoolConnection;
+use sqlx::postgres::{PgConnection, PgPool, PgPoolOptions, Postgres};
+
+use super::rows::{
+    AlertRuleRow, AuditEntryRow, BackgroundJobRow, DeadLetterRow, FunctionRow, StepRunRow,
+    TriggerRow, UserRow, WorkflowRow, WorkflowRunRow, is_foreign_key_violation, status_str,
+    trigger_kind_str, trigger_target, unknown_target,
+};
+use crate::domain::entities::{
+    AlertRule, AlertStatus, AuditEntry, AuditQuery, BackgroundJob, BucketState, DeadLetter,
+    DomainError, Function, ManifestWrite, RunStatus, StepRun, Trigger, User, Workflow, WorkflowRun,
+};
+use crate::domain::ports::{
+    AlertRepository, AuditRepository, BackgroundJobRepository, DeadLetterRepository,
+    FunctionRepository, ManifestRepository, NodeLeaseRepository, RateLimitStore, TriggerRepository,
+    UserRepository, WorkflowRepository,
+};
+use std::env;
+use tracing::info;
+
+#[derive(Clone)]
+pub struct PostgresRepository {
+    pool: PgPool,
+}
+
+impl PostgresRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    async fn connection(&self) -> Result<PoolConnection<Postgres>, DomainError> {
+        self.pool.acquire().await.map_err(internal)
+    }
+}
+
+pub async fn create_pool(database_url: &str) -> PgPool {
+    let pool = PgPoolOptions::new()
+        .max_connections(
+            env::var("DATABASE_MAX_CONNECTIONS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(10),
+        )
+        .connect(database_url)
+        .await
+        .expect("Failed to connect to DB");
+
+    super::migrations::migrate_postgres(&pool)
+        .await
+        .expect("Failed to migrate database");
+
+    pool
+}
+
+pub async fn init_db(database_url: String) -> PgPool {
+    let pool = create_pool(&database_url).await;
+
+    seed_data(&pool).await;
+
+    let admin_email = env::var("ADMIN_EMAIL").expect("ADMIN_EMAIL must be set");
+    let admin_password = env::var("ADMIN_PASSWORD").expect("ADMIN_PASSWORD must be set");
+    let pepper = env::var("PASSWORD_PEPPER").expect("PASSWORD_PEPPER must be set");
+    seed_admin(&pool, &admin_email, &admin_password, &pepper).await;
+
+    pool
+}
+
+pub async fn seed_data(pool: &PgPool) {
+    // Ensure healthz always exists; nodes starting together may race, so conflicts are ignored
+    let seeded = sqlx::query(
+        "INSERT INTO functions (name, language, executable, cpu, memory, readonly) VALUES
+         ('healthz', 'rust', 'modules/healthz/target/wasm32-wasip1/release/healthz.wasm', '0.1', '128', TRUE)
+         ON CONFLICT (name) DO NOTHING",
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to seed healthz function");
+
+    if seeded.rows_affected() > 0 {
+        sqlx::query(
+            "INSERT INTO triggers (name, method, path, function, readonly) VALUES
+             ('health-check', 'GET', '/healthz', 'healthz', TRUE)
+             ON CONFLICT (name) DO NOTHING",
+        )
+        .execute(pool)
+        .await
+        .expect("Failed to seed healthz trigger");
+
+        info!("Seeded healthz function");
+    }
+}
+
+pub async fn seed_admin(pool: &PgPool, admin_email: &str, admin_password: &str, pepper: &str) {
+    let seeded = sqlx::query(
+        "INSERT INTO users (email, password_hash, role) VALUES ($1, $2, 'admin')
+         ON CONFLICT (email) DO NOTHING",
+    )
+    .bind(admin_email)
+    .bind(super::hash_password(admin_password, pepper))
+    .execute(pool)
+    .await
+    .expect("Failed to seed admin user");
+
+    if seeded.rows_affected() > 0 {
+        info!("Seeded admin user");
+    } else {
+        // Accounts seeded before roles were checked have the default role
+        sqlx::query("UPDATE users SET role = 'admin' WHERE email = $1")
+            .bind(admin_email)
+            .execute(pool)
+            .await
+            .expect("Failed to grant the admin role");
+    }
+}
+
+fn internal(e: sqlx::Error) -> DomainError {
+    DomainError::Internal(e.to_string())
+}
+
+/// Maps unique-key violations to `AlreadyExists(name)`.
+fn conflict(e: sqlx::Error, name: &str) -> DomainError {
+    if e.as_database_error()
+        .is_some_and(|db| db.is_unique_violation())
+    {
+        DomainError::AlreadyExists(name.to_string())
+    } else {
+        internal(e)
+    }
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> Result<String, DomainError> {
+    serde_json::to_string(value).map_err(|e| DomainError::Internal(e.to_string()))
+}
+
+fn to_json_opt<T: serde::Serialize>(value: &Option<T>) -> Result<Option<String>, DomainError> {
+    value.as_ref().map(to_json).transpose()
+}
+
+#[async_trait]
+impl UserRepository for PostgresRepository {
+    async fn find_by_email(&self, email: &str) -> Result<Option<User>, DomainError> {
+        let row = sqlx::query_as::<_, UserRow>("SELECT * FROM users WHERE email = $1")
+            .bind(email)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(internal)?;
+
+        Ok(row.map(Into::into))
+    }
+
+    async fn save(&self, user: &User) -> Result<User, DomainError> {
+        // Users are only created by seeding, as with the SQLite adapter
+        Ok(user.clone())
+    }
+
+    async fn update(&self, user: &User) -> Result<User, DomainError> {
+        let result =
+            sqlx::query("UPDATE users SET name = $1, email = $2, password_hash = $3 WHERE id = $4")
+                .bind(&user.name)
+                .bind(&user.email)
+                .bind(&user.password_hash)
+                .bind(user.id)
+                .execute(&self.pool)
+                .await
+                .map_err(|e| conflict(e, &user.email))?;
+
+        if result.rows_affected() == 0 {
+            return Err(DomainError::NotFound(format!("User with id {}", user.id)));
+        }
+
+        Ok(user.clone())
+    }
+}
+
+#[async_trait]
+impl FunctionRepository for PostgresRepository {
+    async fn find_all(&self) -> Result<Vec<Function>, DomainError> {
+        let rows = sqlx::query_as::<_, FunctionRow>("SELECT * FROM functions")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(internal)?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    async fn find_by_name(&self, name: &str) -> Result<Option<Function>, DomainError> {
+        find_function(&mut *self.connection().await?, name).await
+    }
+
+    async fn save(&self, f: &Function) -> Result<Function, DomainError> {
+        insert_function(&mut *self.connection().await?, f).await?;
+        Ok(f.clone())
+    }
+
+    async fn update(&self, f: &Function) -> Result<Function, DomainError> {
+        update_function(&mut *self.connection().await?, f).await?;
+        Ok(f.clone())
+    }
+
+    async fn delete(&self, name: &str) -> Result<(), DomainError> {
+        delete_function(&mut *self.connection().await?, name).await
+    }
+}
+
+#[async_trait]
+impl TriggerRepository for PostgresRepository {
+    async fn find_all(&self) -> Result<Vec<Trigger>, DomainError> {
+        let rows = sqlx::query_as::<_, TriggerRow>("SELECT * FROM triggers")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(internal)?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    async fn save(&self, t: &Trigger) -> Result<Trigger, DomainError> {
+        insert_trigger(&mut *self.connection().await?, t).await?;
+        Ok(t.clone())
+    }
+
+    async fn delete(&self, name: &str) -> Result<(), DomainError> {
+        delete_trigger(&mut *self.connection().await?, name).await
+    }
+}
+
+#[async_trait]
+impl ManifestRepository for PostgresRepository {
+    async fn apply_manifest(&self, writes: &[ManifestWrite]) -> Result<(), DomainError> {
+        let mut tx = self.pool.begin().await.map_err(internal)?;
+        for write in writes {
+            match write {
+                ManifestWrite::CreateFunction(f) => insert_function(&mut tx, f).await?,
+                ManifestWrite::UpdateFunction(f) => update_function(&mut tx, f).await?,
+                ManifestWrite::DeleteFunction(name) => delete_function(&mut tx, name).await?,
+                ManifestWrite::CreateTrigger(t) => insert_trigger(&mut tx, t).await?,
+                ManifestWrite::DeleteTrigger(name) => delete_trigger(&mut tx, name).await?,
+            }
+        }
+        tx.commit().await.map_err(internal)
+    }
+}
+
+// Function and trigger writes take a connection, so a manifest apply can make
+// them in one transaction.
+
+async fn find_function(
+    conn: &mut PgConnection,
+    name: &str,
+) -> Result<Option<Function>, DomainError> {
+    let row = sqlx::query_as::<_, FunctionRow>("SELECT * FROM functions WHERE name = $1")
+        .bind(name)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(internal)?;
+
+    Ok(row.map(Into::into))
+}
+
+async fn insert_function(conn: &mut PgConnection, f: &Function) -> Result<(), DomainError> {
+    sqlx::query(
+        "INSERT INTO functions (name, language, executable, cpu, memory, readonly, snapshot, max_concurrency, max_queue, allowed_calls, retry, env)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)",
+    )
+    .bind(&f.name)
+    .bind(format!("{:?}", f.language))
+    .bind(&f.executable)
+    .bind(&f.cpu)
+    .bind(&f.memory)
+    .bind(f.readonly)
+    .bind(f.snapshot)
+    .bind(f.max_concurrency.map(i64::from))
+    .bind(f.max_queue.map(i64::from))
+    .bind(to_json(&f.allowed_calls)?)
+    .bind(to_json_opt(&f.retry)?)
+    .bind(to_json(&f.env)?)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| conflict(e, &f.name))?;
+    Ok(())
+}
+
+async fn update_function(conn: &mut PgConnection, f: &Function) -> Result<(), DomainError> {
+    match find_function(conn, &f.name).await? {
+        Some(current) if current.readonly => {
+            return Err(DomainError::ValidationError(format!(
+                "Function '{}' is readonly",
+                f.name
+            )));
+        }
+        Some(_) => {}
+        None => return Err(DomainError::NotFound(f.name.clone())),
+    }
+
+    let result = sqlx::query(
+        "UPDATE functions SET language = $1, executable = $2, cpu = $3, memory = $4, snapshot = $5,
+             max_concurrency = $6, max_queue = $7, allowed_calls = $8, retry = $9, env = $10
+         WHERE name = $11",
+    )
+    .bind(format!("{:?}", f.language))
+    .bind(&f.executable)
+    .bind(&f.cpu)
+    .bind(&f.memory)
+    .bind(f.snapshot)
+    .bind(f.max_concurrency.map(i64::from))
+    .bind(f.max_queue.map(i64::from))
+    .bind(to_json(&f.allowed_calls)?)
+    .bind(to_json_opt(&f.retry)?)
+    .bind(to_json(&f.env)?)
+    .bind(&f.name)
+    .execute(&mut *conn)
+    .await
+    .map_err(internal)?;
+
+    if result.rows_affected() == 0 {
+        return Err(DomainError::NotFound(f.name.clone()));
+    }
+    Ok(())
+}
+
+async fn delete_function(conn: &mut PgConnection, name: &str) -> Result<(), DomainError> {
+    match find_function(conn, name).await? {
+        Some(current) if current.readonly => {
+            return Err(DomainError::ValidationError(format!(
+                "Function '{}' is readonly",
+                name
+            )));
+        }
+        Some(_) => {}
+        None => return Err(DomainError::NotFound(name.to_string())),
+    }
+
+    // A failed statement aborts the transaction it runs in, so the triggers
+    // are looked up first; the foreign key still catches one added meanwhile
+    let triggers: Vec<String> =
+        sqlx::query_scalar("SELECT name FROM triggers WHERE function = $1 ORDER BY name")
+            .bind(name)
+            .fetch_all(&mut *conn)
+            .await
+            .map_err(internal)?;
+    let still_used = || {
+        DomainError::ValidationError(format!(
+            "Function '{}' is still used by triggers: {}",
+            name,
+            triggers.join(", ")
+        ))
+    };
+    if !triggers.is_empty() {
+        return Err(still_used());
+    }
+
+    let result = sqlx::query("DELETE FROM functions WHERE name = $1")
+        .bind(name)
+        .execute(&mut *conn)
+        .await;
+    match result {
+        Ok(result) if result.rows_affected() == 0 => Err(DomainError::NotFound(name.to_string())),
+        Ok(_) => Ok(()),
+        Err(e) if is_foreign_key_violation(&e) => Err(still_used()),
+        Err(e) => Err(internal(e)),
+    }
+}
+
+async fn insert_trigger(conn: &mut PgConnection, t: &Trigger) -> Result<(), DomainError> {
+    let (function, workflow) = trigger_target(t);
+    sqlx::query(
+        "INSERT INTO triggers (name, method, path, function, workflow, kind, rate_limit, cache, readonly)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+    )
+    .bind(&t.name)
+    .bind(&t.method)
+    .bind(&t.path)
+    .bind(function)
+    .bind(workflow)
+    .bind(trigger_kind_str(t.kind))
+    .bind(to_json_opt(&t.rate_limit)?)
+    .bind(to_json_opt(&t.cache)?)
+    .bind(t.readonly)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| {
+        if is_foreign_key_violation(&e) {
+            unknown_target(t)
+        } else {
+            conflict(e, &t.name)
+        }
+    })?;
+    Ok(())
+}
+
+async fn delete_trigger(conn: &mut PgConnection, name: &str) -> Result<(), DomainError> {
+    let readonly: Option<bool> =
+        sqlx::query_scalar("SELECT readonly FROM triggers WHERE name = $1")
+            .bind(name)
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(internal)?;
+
+    match readonly {
+        Some(true) => {
+            return Err(DomainError::ValidationError(format!(
+                "Trigger '{}' is readonly",
+                name
+            )));
+        }
+        Some(false) => {}
+        None => return Err(DomainError::NotFound(name.to_string())),
+    }
+
+    let result = sqlx::query("DELETE FROM triggers WHERE name = $1")
+        .bind(name)
+        .execute(&mut *conn)
+        .await
+        .map_err(internal)?;
+
+    if result.rows_affected() == 0 {
+        return Err(DomainError::NotFound(name.to_string()));
+    }
+    Ok(())
+}
+
+/// Buckets live in the database, so every API node sharing it enforces the same limits.
+#[async_trait]
+impl RateLimitStore for PostgresRepository {
+    async fn take(
+        &self,
+        key: &str,
+        capacity: f64,
+        refill_per_sec: f64,
+    ) -> Result<BucketState, DomainError> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_err(|e| DomainError::Internal(e.to_string()))?
+            .as_secs_f64();
+
+        // The upsert locks the row, so concurrent nodes can't both spend a token
+        let taken: Option<f64> = sqlx::query_scalar(
+            "INSERT INTO rate_limit_buckets AS b (key, tokens, updated_at, full_at)
+             VALUES ($1, $2 - 1, $3, $3 + 1 / $4)
+             ON CONFLICT (key) DO UPDATE SET
+                 tokens = LEAST($2, b.tokens + GREATEST(0, $3 - b.updated_at) * $4) - 1,
+                 updated_at = $3,
+                 full_at = $3 + ($2 + 1 - LEAST($2, b.tokens + GREATEST(0, $3 - b.updated_at) * $4)) / $4
+             WHERE LEAST($2, b.tokens + GREATEST(0, $3 - b.updated_at) * $4) >= 1
+             RETURNING tokens",
+        )
+        .bind(key)
+        .bind(capacity)
+        .bind(now)
+        .bind(refill_per_sec)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(internal)?;
+
+        if let Some(tokens) = taken {
+            return Ok(BucketState {
+                allowed: true,
+                tokens,
+            });
+        }
+
+        let tokens: f64 = sqlx::query_scalar(
+            "SELECT LEAST($2, tokens + GREATEST(0, $3 - updated_at) * $4)
+             FROM rate_limit_buckets WHERE key = $1",
+        )
+        .bind(key)
+        .bind(capacity)
+        .bind(now)
+        .bind(refill_per_sec)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(internal)?
+        .unwrap_or(0.0);
+
+        Ok(BucketState {
+            allowed: false,
+            tokens,
+        })
+    }
+
+    async fn prune(&self) -> Result<u64, DomainError> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_err(|e| DomainError::Internal(e.to_string()))?
+            .as_secs_f64();
+
+        let result = sqlx::query("DELETE FROM rate_limit_buckets WHERE full_at <= $1")
+            .bind(now)
+            .execute(&self.pool)
+            .await
+            .map_err(internal)?;
+        Ok(result.rows_affected())
+    }
+}
+
+impl PostgresRepository {
+    async fn load_run(&self, row: WorkflowRunRow) -> Result<WorkflowRun, DomainError> {
+        let steps = sqlx::query_as::<_, StepRunRow>(
+            "SELECT step, status, input, output, error, attempts FROM workflow_steps
+             WHERE run_id = $1 ORDER BY position",
+        )
+        .bind(&row.id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(internal)?;
+
+        row.into_run(steps)
+    }
+
+    async fn load_runs(&self, rows: Vec<WorkflowRunRow>) -> Result<Vec<WorkflowRun>, DomainError> {
+        let mut runs = Vec::with_capacity(rows.len());
+        for row in rows {
+            runs.push(self.load_run(row).await?);
+        }
+        Ok(runs)
+    }
+}
+
+#[async_trait]
+impl WorkflowRepository for PostgresRepository {
+    async fn find_workflows(&self) -> Result<Vec<Workflow>, DomainError> {
+        sqlx::query_as::<_, WorkflowRow>("SELECT name, steps FROM workflows ORDER BY name")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(internal)?
+            .into_iter()
+            .map(Workflow::try_from)
+            .collect()
+    }
+
+    async fn find_workflow(&self, name: &str) -> Result<Option<Workflow>, DomainError> {
+        sqlx::query_as::<_, WorkflowRow>("SELECT name, steps FROM workflows WHERE name = $1")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(internal)?
+            .map(Workflow::try_from)
+            .transpose()
+    }
+
+    async fn save_workflow(&self, workflow: &Workflow) -> Result<Workflow, DomainError> {
+        sqlx::query(
+            "INSERT INTO workflows (name, steps) VALUES ($1, $2)
+             ON CONFLICT (name) DO UPDATE SET steps = excluded.steps",
+        )
+        .bind(&workflow.name)
+        .bind(to_json(&workflow.steps)?)
+        .execute(&self.pool)
+        .await
+        .map_err(internal)?;
+        Ok(workflow.clone())
+    }
+
+    async fn delete_workflow(&self, name: &str) -> Result<(), DomainError> {
+        let result = sqlx::query("DELETE FROM workflows WHERE name = $1")
+            .bind(name)
+            .execute(&self.pool)
+            .await;
+        match result {
+            Ok(result) if result.rows_affected() == 0 => {
+                Err(DomainError::NotFound(name.to_string()))
+            }
+            Ok(_) => Ok(()),
+            Err(e) if is_foreign_key_violation(&e) => {
+                let triggers: Vec<String> = sqlx::query_scalar(
+                    "SELECT name FROM triggers WHERE workflow = $1 ORDER BY name",
+                )
+                .bind(name)
+                .fetch_all(&self.pool)
+                .await
+                .map_err(internal)?;
+                Err(DomainError::ValidationError(format!(
+                    "Workflow '{}' is still used by triggers: {}",
+                    name,
+                    triggers.join(", ")
+                )))
+            }
+            Err(e) => Err(internal(e)),
+        }
+    }
+
+    async fn save_run(&self, run: &WorkflowRun) -> Result<(), DomainError> {
+        let definition = to_json(&run.definition)?;
+        let mut tx = self.pool.begin().await.map_err(internal)?;
+
+        sqlx::query(
+            "INSERT INTO workflow_runs (id, workflow, definition, status, input, output, error, created_at, updated_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+             ON CONFLICT (id) DO UPDATE SET
+                 status = excluded.status,
+                 output = excluded.output,
+                 error = excluded.error,
+                 updated_at = excluded.updated_at",
+        )
+        .bind(&run.id)
+        .bind(&run.workflow)
+        .bind(definition)
+        .bind(status_str(run.status))
+        .bind(&run.input)
+        .bind(&run.output)
+        .bind(&run.error)
+        .bind(run.created_at.to_rfc3339())
+        .bind(run.updated_at.to_rfc3339())
+        .execute(&mut *tx)
+        .await
+        .map_err(internal)?;
+
+        for (position, step) in run.steps.iter().enumerate() {
+            sqlx::query(
+                "INSERT INTO workflow_steps (run_id, step, position, status, input, output, error, attempts)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                 ON CONFLICT (run_id, step) DO UPDATE SET
+                     status = excluded.status,
+                     input = excluded.input,
+                     output = excluded.output,
+                     error = excluded.error,
+                     attempts = excluded.attempts",
+            )
+            .bind(&run.id)
+            .bind(&step.step)
+            .bind(position as i64)
+            .bind(status_str(step.status))
+            .bind(&step.input)
+            .bind(&step.output)
+            .bind(&step.error)
+            .bind(i64::from(step.attempts))
+            .execute(&mut *tx)
+            .await
+            .map_err(internal)?;
+        }
+
+        tx.commit().await.map_err(internal)
+    }
+
+    async fn save_step(&self, run_id: &str, step: &StepRun) -> Result<(), DomainError> {
+        let result = sqlx::query(
+            "UPDATE workflow_steps SET status = $1, input = $2, output = $3, error = $4, attempts = $5
+             WHERE run_id = $6 AND step = $7",
+        )
+        .bind(status_str(step.status))
+        .bind(&step.input)
+        .bind(&step.output)
+        .bind(&step.error)
+        .bind(i64::from(step.attempts))
+        .bind(run_id)
+        .bind(&step.step)
+        .execute(&self.pool)
+        .await
+        .map_err(internal)?;
+
+        if result.rows_affected() == 0 {
+            return Err(DomainError::NotFound(format!("{}/{}", run_id, step.step)));
+        }
+        Ok(())
+    }
+
+    async fn find_run(&self, id: &str) -> Result<Option<WorkflowRun>, DomainError> {
+        let row = sqlx::query_as::<_, WorkflowRunRow>("SELECT * FROM workflow_runs WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(internal)?;
+
+        match row {
+            Some(row) => Ok(Some(self.load_run(row).await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn find_runs(&self, workflow: &str) -> Result<Vec<WorkflowRun>, DomainError> {
+        let rows = sqlx::query_as::<_, WorkflowRunRow>(
+            "SELECT * FROM workflow_runs WHERE workflow = $1 ORDER BY created_at DESC LIMIT 100",
+        )
+        .bind(workflow)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(internal)?;
+        self.load_runs(rows).await
+    }
+
+    async fn find_unfinished_runs(&self) -> Result<Vec<WorkflowRun>, DomainError> {
+        let rows = sqlx::query_as::<_, WorkflowRunRow>(
+            "SELECT * FROM workflow_runs WHERE status = $1 ORDER BY created_at",
+        )
+        .bind(status_str(RunStatus::Running))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(internal)?;
+        self.load_runs(rows).await
+    }
+}
+
+#[async_trait]
+impl BackgroundJobRepository for PostgresRepository {
+    async fn save_job(&self, job: &BackgroundJob) -> Result<(), DomainError> {
+        sqlx::query(
+            "INSERT INTO background_jobs (id, function, source, payload, attempts, created_at)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             ON CONFLICT (id) DO UPDATE SET attempts = excluded.attempts",
+        )
+        .bind(&job.id)
+        .bind(&job.function)
+        .bind(&job.source)
+        .bind(&job.payload)
+        .bind(i64::from(job.attempts))
+        .bind(job.created_at.to_rfc3339())
+        .execute(&self.pool)
+        .await
+        .map_err(internal)?;
+        Ok(())
+    }
+
+    async fn find_jobs(&self) -> Result<Vec<BackgroundJob>, DomainError> {
+        sqlx::query_as::<_, BackgroundJobRow>("SELECT * FROM background_jobs ORDER BY created_at")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(internal)?
+            .into_iter()
+            .map(BackgroundJob::try_from)
+            .collect()
+    }
+
+    async fn delete_job(&self, id: &str) -> Result<(), DomainError> {
+        sqlx::query("DELETE FROM background_jobs WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(internal)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl NodeLeaseRepository for PostgresRepository {
+    async fn acquire_lease(
+        &self,
+        node: &str,
+        now: i64,
+        expires_at: i64,
+    ) -> Result<String, DomainError> {
+        sqlx::query(
+            "INSERT INTO node_lease (id, node, expires_at) VALUES (1, $1, $2)
+             ON CONFLICT (id) DO UPDATE SET node = excluded.node, expires_at = excluded.expires_at
+             WHERE node_lease.node = excluded.node OR node_lease.expires_at <= $3",
+        )
+        .bind(node)
+        .bind(expires_at)
+        .bind(now)
+        .execute(&self.pool)
+        .await
+        .map_err(internal)?;
+        sqlx::query_scalar("SELECT node FROM node_lease WHERE id = 1")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(internal)
+    }
+
+    async fn release_lease(&self, node: &str) -> Result<(), DomainError> {
+        sqlx::query("DELETE FROM node_lease WHERE node = $1")
+            .bind(node)
+            .execute(&self.pool)
+            .await
+            .map_err(internal)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DeadLetterRepository for PostgresRepository {
+    async fn save_dead_letter(&self, letter: &DeadLetter) -> Result<(), DomainError> {
+        sqlx::query(
+            "INSERT INTO dead_letters (id, function, source, payload, error, attempts, created_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(&letter.id)
+        .bind(&letter.function)
+        .bind(&letter.source)
+        .bind(&letter.payload)
+        .bind(&letter.error)
+        .bind(i64::from(letter.attempts))
+        .bind(letter.created_at.to_rfc3339())
+        .execute(&self.pool)
+        .await
+        .map_err(internal)?;
+        Ok(())
+    }
+
+    async fn find_dead_letters<'a>(
+        &self,
+        function: Option<&'a str>,
+    ) -> Result<Vec<DeadLetter>, DomainError> {
+        sqlx::query_as::<_, DeadLetterRow>(
+            "SELECT * FROM dead_letters WHERE $1::TEXT IS NULL OR function = $1
+             ORDER BY created_at DESC LIMIT 1000",
+        )
+        .bind(function)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(internal)?
+        .into_iter()
+        .map(DeadLetter::try_from)
+        .collect()
+    }
+
+    async fn find_dead_letter(&self, id: &str) -> Result<Option<DeadLetter>, DomainError> {
+        sqlx::query_as::<_, DeadLetterRow>("SELECT * FROM dead_letters WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(internal)?
+            .map(DeadLetter::try_from)
+            .transpose()
+    }
+
+    async fn delete_dead_letter(&self, id: &str) -> Result<(), DomainError> {
+        let result = sqlx::query("DELETE FROM dead_letters WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(internal)?;
+
+        if result.rows_affected() == 0 {
+            return Err(DomainError::NotFound(id.to_string()));
+        }
+        Ok(())
+    }
+
+    async fn purge_dead_letters<'a>(&self, function: Option<&'a str>) -> Result<u64, DomainError> {
+        let result =
+            sqlx::query("DELETE FROM dead_letters WHERE $1::TEXT IS NULL OR function = $1")
+                .bind(function)
+                .execute(&self.pool)
+                .await
+                .map_err(internal)?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[async_trait]
+impl AlertRepository for PostgresRepository {
+    async fn find_alert_rules(&self) -> Result<Vec<AlertRule>, DomainError> {
+        sqlx::query_as::<_, AlertRuleRow>("SELECT * FROM alert_rules ORDER BY name")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(internal)?
+            .into_iter()
+            .map(AlertRule::try_from)
+            .collect()
+    }
+
+    async fn find_alert_rule(&self, name: &str) -> Result<Option<AlertRule>, DomainError> {
+        sqlx::query_as::<_, AlertRuleRow>("SELECT * FROM alert_rules WHERE name = $1")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(internal)?
+            .map(AlertRule::try_from)
+            .transpose()
+    }
+
+    async fn save_alert_rule(&self, rule: &AlertRule) -> Result<AlertRule, DomainError> {
+        sqlx::query(
+            "INSERT INTO alert_rules
+                 (name, function, condition, window_secs, webhooks, state, value, since, evaluated_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+             ON CONFLICT(name) DO UPDATE SET
+                 function = excluded.function,
+                 condition = excluded.condition,
+                 window_secs = excluded.window_secs,
+                 webhooks = excluded.webhooks,
+                 state = excluded.state,
+                 value = excluded.value,
+                 since = excluded.since,
+                 evaluated_at = excluded.evaluated_at",
+        )
+        .bind(&rule.name)
+        .bind(&rule.function)
+        .bind(to_json(&rule.condition)?)
+        .bind(rule.window_secs as i64)
+        .bind(to_json(&rule.webhooks)?)
+        .bind(status_str(rule.status.state))
+        .bind(rule.status.value)
+        .bind(rule.status.since.map(|t| t.to_rfc3339()))
+        .bind(rule.status.evaluated_at.map(|t| t.to_rfc3339()))
+        .execute(&self.pool)
+        .await
+        .map_err(internal)?;
+        Ok(rule.clone())
+    }
+
+    async fn save_alert_status(&self, name: &str, status: &AlertStatus) -> Result<(), DomainError> {
+        sqlx::query(
+            "UPDATE alert_rules SET state = ?, value = ?, since = ?, evaluated_at = ?
+             WHERE name = $1",
+        )
+        .bind(status_str(status.state))
+        .bind(status.value)
+        .bind(status.since.map(|t| t.to_rfc3339()))
+        .bind(status.evaluated_at.map(|t| t.to_rfc3339()))
+        .bind(name)
+        .execute(&self.pool)
+        .await
+        .map_err(internal)?;
+        Ok(())
+    }
+
+    async fn delete_alert_rule(&self, name: &str) -> Result<(), DomainError> {
+        let result = sqlx::query("DELETE FROM alert_rules WHERE name = $1")
+            .bind(name)
+            .execute(&self.pool)
+            .await
+            .map_err(internal)?;
+
+        if result.rows_affected() == 0 {
+            return Err(DomainError::NotFound(name.to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AuditRepository for PostgresRepository {
+    async fn append_audit_entry(&self, entry: &AuditEntry) -> Result<AuditEntry, DomainError> {
+        let id: i64 = sqlx::query_scalar(
+            "INSERT INTO audit_log (at, actor, source_ip, action, target, changes)
+             VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
+        )
+        .bind(entry.at.to_rfc3339())
+        .bind(&entry.actor)
+        .bind(&entry.source_ip)
+        .bind(status_str(entry.action))
+        .bind(&entry.target)
+        .bind(to_json(&entry.changes)?)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(internal)?;
+        Ok(AuditEntry {
+            id,
+            ..entry.clone()
+        })
+    }
+
+    async fn find_audit_entries(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>, DomainError> {
+        sqlx::query_as::<_, AuditEntryRow>(
+            "SELECT * FROM audit_log
+             WHERE ($1::TEXT IS NULL OR actor = $1)
+               AND ($2::TEXT IS NULL OR action = $2 OR substr(action, 1, length($2) + 1) = $2 || '.')
+               AND ($3::TEXT IS NULL OR target = $3)
+               AND ($4::TEXT IS NULL OR at >= $4)
+               AND ($5::TEXT IS NULL OR at < $5)
+               AND ($6::BIGINT IS NULL OR id < $6)
+             ORDER BY id DESC LIMIT $7",
+        )
+        .bind(&query.actor)
+        .bind(&query.action)
+        .bind(&query.target)
+        .bind(query.from.map(|t| t.to_rfc3339()))
+        .bind(query.to.map(|t| t.to_rfc3339()))
+        .bind(query.before_id)
+        .bind(query.limit as i64)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(internal)?
+        .into_iter()
+        .map(AuditEntry::try_from)
+        .collect()
+    }
+}
@@ -0,0 +1,349 @@
+//! Row types and conversions shared by the SQL repository adapters.
+//!
+//! Integer columns decode as `i64`, the one integer type every backend supports.
+
+use crate::domain::entities::{
+    AlertRule, AlertStatus, AuditEntry, BackgroundJob, DeadLetter, DomainError, Function, Language,
+    StepRun, Trigger, TriggerKind, User, Workflow, WorkflowRun,
+};
+use chrono::{DateTime, Utc};
+
+#[derive(sqlx::FromRow)]
+pub(super) struct UserRow {
+    pub(super) id: i64,
+    pub(super) name: String,
+    pub(super) email: String,
+    pub(super) password_hash: String,
+    pub(super) role: String,
+}
+
+impl From<UserRow> for User {
+    fn from(row: UserRow) -> Self {
+        User {
+            id: row.id,
+            name: row.name,
+            email: row.email,
+            password_hash: row.password_hash,
+            role: row.role,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct FunctionRow {
+    pub(super) name: String,
+    pub(super) language: String,
+    pub(super) executable: String,
+    pub(super) cpu: String,
+    pub(super) memory: String,
+    pub(super) readonly: bool,
+    pub(super) snapshot: bool,
+    pub(super) max_concurrency: Option<i64>,
+    pub(super) max_queue: Option<i64>,
+    pub(super) allowed_calls: String,
+    pub(super) retry: Option<String>,
+    pub(super) env: String,
+}
+
+impl From<FunctionRow> for Function {
+    fn from(row: FunctionRow) -> Self {
+        let lang = match row.language.as_str() {
+            "python" | "Python" => Language::Python,
+            "rust" | "Rust" => Language::Rust,
+            "go" | "Go" => Language::Go,
+            _ => Language::Python, // Default or error
+        };
+        Function {
+            name: row.name,
+            language: lang,
+            executable: row.executable,
+            cpu: row.cpu,
+            memory: row.memory,
+            runtime: None,
+            readonly: row.readonly,
+            snapshot: row.snapshot,
+            max_concurrency: row.max_concurrency.and_then(|n| u32::try_from(n).ok()),
+            max_queue: row.max_queue.and_then(|n| u32::try_from(n).ok()),
+            allowed_calls: serde_json::from_str(&row.allowed_calls).unwrap_or_default(),
+            retry: row.retry.and_then(|json| serde_json::from_str(&json).ok()),
+            env: serde_json::from_str(&row.env).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct TriggerRow {
+    pub(super) name: String,
+    pub(super) method: String,
+    pub(super) path: String,
+    pub(super) function: Option<String>,
+    pub(super) workflow: Option<String>,
+    pub(super) kind: String,
+    pub(super) rate_limit: Option<String>,
+    pub(super) cache: Option<String>,
+    pub(super) readonly: bool,
+}
+
+impl From<TriggerRow> for Trigger {
+    fn from(row: TriggerRow) -> Self {
+        let kind = match row.kind.as_str() {
+            "websocket" => TriggerKind::WebSocket,
+            "workflow" => TriggerKind::Workflow,
+            _ => TriggerKind::Http,
+        };
+        Trigger {
+            name: row.name,
+            method: row.method,
+            path: row.path,
+            function_name: row.function.or(row.workflow).unwrap_or_default(),
+            kind,
+            rate_limit: row
+                .rate_limit
+                .and_then(|json| serde_json::from_str(&json).ok()),
+            cache: row.cache.and_then(|json| serde_json::from_str(&json).ok()),
+            readonly: row.readonly,
+        }
+    }
+}
+
+pub(super) fn trigger_kind_str(kind: TriggerKind) -> &'static str {
+    match kind {
+        TriggerKind::Http => "http",
+        TriggerKind::WebSocket => "websocket",
+        TriggerKind::Workflow => "workflow",
+    }
+}
+
+/// Splits a trigger's target into its `function` and `workflow` columns.
+pub(super) fn trigger_target(t: &Trigger) -> (Option<&str>, Option<&str>) {
+    match t.kind {
+        TriggerKind::Workflow => (None, Some(&t.function_name)),
+        _ => (Some(&t.function_name), None),
+    }
+}
+
+pub(super) fn unknown_target(t: &Trigger) -> DomainError {
+    let kind = match t.kind {
+        TriggerKind::Workflow => "workflow",
+        _ => "function",
+    };
+    DomainError::ValidationError(format!(
+        "Trigger '{}' targets unknown {} '{}'",
+        t.name, kind, t.function_name
+    ))
+}
+
+pub(super) fn is_foreign_key_violation(e: &sqlx::Error) -> bool {
+    e.as_database_error()
+        .is_some_and(|db| db.is_foreign_key_violation())
+}
+
+pub(super) fn status_str<T: serde::Serialize>(status: T) -> String {
+    serde_json::to_value(status)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+pub(super) fn parse_status<T: serde::de::DeserializeOwned>(status: &str) -> Result<T, DomainError> {
+    serde_json::from_value(serde_json::Value::String(status.to_string()))
+        .map_err(|e| DomainError::Internal(format!("Invalid status '{}': {}", status, e)))
+}
+
+pub(super) fn parse_time(value: &str) -> Result<DateTime<Utc>, DomainError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|e| DomainError::Internal(e.to_string()))
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct WorkflowRow {
+    pub(super) name: String,
+    pub(super) steps: String,
+}
+
+impl TryFrom<WorkflowRow> for Workflow {
+    type Error = DomainError;
+
+    fn try_from(row: WorkflowRow) -> Result<Self, Self::Error> {
+        Ok(Workflow {
+            name: row.name,
+            steps: serde_json::from_str(&row.steps)
+                .map_err(|e| DomainError::Internal(e.to_string()))?,
+        })
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct WorkflowRunRow {
+    pub(super) id: String,
+    pub(super) workflow: String,
+    pub(super) definition: String,
+    pub(super) status: String,
+    pub(super) input: String,
+    pub(super) output: Option<String>,
+    pub(super) error: Option<String>,
+    pub(super) created_at: String,
+    pub(super) updated_at: String,
+}
+
+impl WorkflowRunRow {
+    /// Assembles the run from its row and its step rows, in position order.
+    pub(super) fn into_run(self, steps: Vec<StepRunRow>) -> Result<WorkflowRun, DomainError> {
+        Ok(WorkflowRun {
+            status: parse_status(&self.status)?,
+            created_at: parse_time(&self.created_at)?,
+            updated_at: parse_time(&self.updated_at)?,
+            definition: serde_json::from_str(&self.definition)
+                .map_err(|e| DomainError::Internal(e.to_string()))?,
+            steps: steps
+                .into_iter()
+                .map(StepRun::try_from)
+                .collect::<Result<_, _>>()?,
+            id: self.id,
+            workflow: self.workflow,
+            input: self.input,
+            output: self.output,
+            error: self.error,
+        })
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct StepRunRow {
+    pub(super) step: String,
+    pub(super) status: String,
+    pub(super) input: Option<String>,
+    pub(super) output: Option<String>,
+    pub(super) error: Option<String>,
+    pub(super) attempts: i64,
+}
+
+impl TryFrom<StepRunRow> for StepRun {
+    type Error = DomainError;
+
+    fn try_from(row: StepRunRow) -> Result<Self, Self::Error> {
+        Ok(StepRun {
+            step: row.step,
+            status: parse_status(&row.status)?,
+            input: row.input,
+            output: row.output,
+            error: row.error,
+            attempts: u32::try_from(row.attempts).unwrap_or_default(),
+        })
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct BackgroundJobRow {
+    pub(super) id: String,
+    pub(super) function: String,
+    pub(super) source: String,
+    pub(super) payload: String,
+    pub(super) attempts: i64,
+    pub(super) created_at: String,
+}
+
+impl TryFrom<BackgroundJobRow> for BackgroundJob {
+    type Error = DomainError;
+
+    fn try_from(row: BackgroundJobRow) -> Result<Self, Self::Error> {
+        Ok(BackgroundJob {
+            created_at: parse_time(&row.created_at)?,
+            id: row.id,
+            function: row.function,
+            source: row.source,
+            payload: row.payload,
+            attempts: u32::try_from(row.attempts).unwrap_or_default(),
+        })
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct DeadLetterRow {
+    pub(super) id: String,
+    pub(super) function: String,
+    pub(super) source: String,
+    pub(super) payload: String,
+    pub(super) error: String,
+    pub(super) attempts: i64,
+    pub(super) created_at: String,
+}
+
+impl TryFrom<DeadLetterRow> for DeadLetter {
+    type Error = DomainError;
+
+    fn try_from(row: DeadLetterRow) -> Result<Self, Self::Error> {
+        Ok(DeadLetter {
+            created_at: parse_time(&row.created_at)?,
+            id: row.id,
+            function: row.function,
+            source: row.source,
+            payload: row.payload,
+            error: row.error,
+            attempts: u32::try_from(row.attempts).unwrap_or_default(),
+        })
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct AlertRuleRow {
+    pub(super) name: String,
+    pub(super) function: String,
+    pub(super) condition: String,
+    pub(super) window_secs: i64,
+    pub(super) webhooks: String,
+    pub(super) state: String,
+    pub(super) value: Option<f64>,
+    pub(super) since: Option<String>,
+    pub(super) evaluated_at: Option<String>,
+}
+
+impl TryFrom<AlertRuleRow> for AlertRule {
+    type Error = DomainError;
+
+    fn try_from(row: AlertRuleRow) -> Result<Self, Self::Error> {
+        Ok(AlertRule {
+            condition: serde_json::from_str(&row.condition)
+                .map_err(|e| DomainError::Internal(e.to_string()))?,
+            window_secs: u64::try_from(row.window_secs).unwrap_or_default(),
+            webhooks: serde_json::from_str(&row.webhooks).unwrap_or_default(),
+            status: AlertStatus {
+                state: parse_status(&row.state)?,
+                value: row.value,
+                since: row.since.as_deref().map(parse_time).transpose()?,
+                evaluated_at: row.evaluated_at.as_deref().map(parse_time).transpose()?,
+            },
+            name: row.name,
+            function: row.function,
+        })
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct AuditEntryRow {
+    pub(super) id: i64,
+    pub(super) at: String,
+    pub(super) actor: String,
+    pub(super) source_ip: Option<String>,
+    pub(super) action: String,
+    pub(super) target: String,
+    pub(super) changes: String,
+}
+
+impl TryFrom<AuditEntryRow> for AuditEntry {
+    type Error = DomainError;
+
+    fn try_from(row: AuditEntryRow) -> Result<Self, Self::Error> {
+        Ok(AuditEntry {
+            at: parse_time(&row.at)?,
+            action: parse_status(&row.action)?,
+            changes: serde_json::from_str(&row.changes)
+                .map_err(|e| DomainError::Internal(e.to_string()))?,
+            id: row.id,
+            actor: row.actor,
+            source_ip: row.source_ip,
+            target: row.target,
+        })
+    }
+}
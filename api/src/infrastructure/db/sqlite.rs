@@ -1,13 +1,21 @@
 use async_trait::async_trait;
-use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
+use sqlx::pool::PoolConnection;
+use sqlx::sqlite::{Sqlite, SqliteConnectOptions, SqliteConnection, SqlitePool};
 
-use crate::domain::entities::{DomainError, Function, Language, Trigger, User};
-use crate::domain::ports::{FunctionRepository, TriggerRepository, UserRepository};
-use argon2::{
-    Algorithm, Argon2, Params, Version,
-    password_hash::{PasswordHasher, SaltString},
+use super::rows::{
+    AlertRuleRow, AuditEntryRow, BackgroundJobRow, DeadLetterRow, FunctionRow, StepRunRow,
+    TriggerRow, UserRow, WorkflowRow, WorkflowRunRow, is_foreign_key_violation, status_str,
+    trigger_kind_str, trigger_target, unknown_target,
+};
+use crate::domain::entities::{
+    AlertRule, AlertStatus, AuditEntry, AuditQuery, BackgroundJob, BucketState, DeadLetter,
+    DomainError, Function, ManifestWrite, RunStatus, StepRun, Trigger, User, Workflow, WorkflowRun,
+};
+use crate::domain::ports::{
+    AlertRepository, AuditRepository, BackgroundJobRepository, DeadLetterRepository,
+    FunctionRepository, ManifestRepository, NodeLeaseRepository, RateLimitStore, TriggerRepository,
+    UserRepository, WorkflowRepository,
 };
-use rand_core::OsRng;
 use std::env;
 use std::str::FromStr;
 use std::time::Duration;
@@ -22,6 +30,13 @@ impl SqliteRepository {
     pub fn new(pool: SqlitePool) -> Self {
         Self { pool }
     }
+
+    async fn connection(&self) -> Result<PoolConnection<Sqlite>, DomainError> {
+        self.pool
+            .acquire()
+            .await
+            .map_err(|e| DomainError::Internal(e.to_string()))
+    }
 }
 
 pub async fn create_pool(mut database_url: String) -> SqlitePool {
@@ -38,77 +53,21 @@ pub async fn create_pool(mut database_url: String) -> SqlitePool {
         .expect("Failed to parse database URL")
         .journal_mode(sqlx::sqlite::SqliteJournalMode::Wal)
         .busy_timeout(Duration::from_millis(5000))
+        .foreign_keys(true)
         .create_if_missing(true);
 
     let pool = SqlitePool::connect_with(connection_options)
         .await
         .expect("Failed to connect to DB");
 
-    // Create Schema
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS users (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            name TEXT NOT NULL DEFAULT '',
-            email TEXT NOT NULL UNIQUE,
-            password_hash TEXT NOT NULL,
-            role TEXT NOT NULL DEFAULT 'user'
-        )",
-    )
-    .execute(&pool)
-    .await
-    .expect("Failed to create users table");
-
-    info!("Users table initialized");
-
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS functions (
-            name TEXT PRIMARY KEY,
-            language TEXT NOT NULL,
-            executable TEXT NOT NULL,
-            cpu TEXT NOT NULL,
-
-            memory TEXT NOT NULL,
-            readonly BOOLEAN NOT NULL DEFAULT FALSE
-        )",
-    )
-    .execute(&pool)
-    .await
-    .expect("Failed to create functions table");
-
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS triggers (
-            name TEXT PRIMARY KEY,
-            method TEXT NOT NULL,
-            path TEXT NOT NULL,
-            function TEXT NOT NULL,
-            function_name TEXT, 
-            readonly BOOLEAN NOT NULL DEFAULT FALSE,
-            FOREIGN KEY (function) REFERENCES functions(name)
-        )", // Note: I noticed `function` column in triggers table in previous view, but Trigger struct has function_name.
-            // In seed data: `function TEXT NOT NULL`.
-            // I will keep it as is, but ensuring schema matches what was there.
-            // Wait, checking original file line 83: `function TEXT NOT NULL`.
-            // Line 246 in original struct From impl maps `row.function` to `Trigger.function_name`.
-            // So database column is `function`.
-    )
-    .execute(&pool)
-    .await
-    .expect("Failed to create triggers table");
-
-    // Attempt to add 'readonly' column if it doesn't exist (for existing DBs)
-    let _ = sqlx::query("ALTER TABLE functions ADD COLUMN readonly BOOLEAN NOT NULL DEFAULT FALSE")
-        .execute(&pool)
-        .await;
-    let _ = sqlx::query("ALTER TABLE triggers ADD COLUMN readonly BOOLEAN NOT NULL DEFAULT FALSE")
-        .execute(&pool)
-        .await;
+    super::migrations::migrate(&pool)
+        .await
+        .expect("Failed to migrate database");
 
     pool
 }
 
-pub async fn init_db() -> SqlitePool {
-    let database_url =
-        env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite:fluor.db?mode=rwc".to_string());
+pub async fn init_db(database_url: String) -> SqlitePool {
     let pool = create_pool(database_url).await;
 
     seed_data(&pool).await;
@@ -122,7 +81,6 @@ pub async fn init_db() -> SqlitePool {
 }
 
 pub async fn seed_data(pool: &SqlitePool) {
-
     // Ensure healthz always exists
     let healthz_exists: (i64,) =
         sqlx::query_as("SELECT COUNT(*) FROM functions WHERE name = 'healthz'")
@@ -161,97 +119,21 @@ pub async fn seed_admin(pool: &SqlitePool, admin_email: &str, admin_password: &s
         .expect("Failed to check for existing admin user");
 
     if exists.0 == 0 {
-        let salt = SaltString::generate(&mut OsRng);
-        let password_with_pepper = format!("{}{}", admin_password, pepper);
-
-        let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, Params::default());
-
-        let password_hash = argon2
-            .hash_password(password_with_pepper.as_bytes(), &salt)
-            .unwrap()
-            .to_string();
-
-        sqlx::query("INSERT INTO users (email, password_hash) VALUES (?, ?)")
+        sqlx::query("INSERT INTO users (email, password_hash, role) VALUES (?, ?, 'admin')")
             .bind(admin_email)
-            .bind(password_hash)
+            .bind(super::hash_password(admin_password, pepper))
             .execute(pool)
             .await
             .expect("Failed to seed admin user");
 
         info!("Seeded admin user");
-    }
-}
-
-// DTOs
-#[derive(sqlx::FromRow)]
-struct UserRow {
-    id: i64,
-    name: String,
-    email: String,
-    password_hash: String,
-    role: String,
-}
-
-impl From<UserRow> for User {
-    fn from(row: UserRow) -> Self {
-        User {
-            id: row.id,
-            name: row.name,
-            email: row.email,
-            password_hash: row.password_hash,
-            role: row.role,
-        }
-    }
-}
-
-#[derive(sqlx::FromRow)]
-struct FunctionRow {
-    name: String,
-    language: String,
-    executable: String,
-    cpu: String,
-    memory: String,
-    readonly: bool,
-}
-
-impl From<FunctionRow> for Function {
-    fn from(row: FunctionRow) -> Self {
-        let lang = match row.language.as_str() {
-            "python" | "Python" => Language::Python,
-            "rust" | "Rust" => Language::Rust,
-            "go" | "Go" => Language::Go,
-            _ => Language::Python, // Default or error
-        };
-        Function {
-            name: row.name,
-            language: lang,
-            executable: row.executable,
-            cpu: row.cpu,
-            memory: row.memory,
-            runtime: None,
-            readonly: row.readonly,
-        }
-    }
-}
-
-#[derive(sqlx::FromRow)]
-struct TriggerRow {
-    name: String,
-    method: String,
-    path: String,
-    function: String,
-    readonly: bool,
-}
-
-impl From<TriggerRow> for Trigger {
-    fn from(row: TriggerRow) -> Self {
-        Trigger {
-            name: row.name,
-            method: row.method,
-            path: row.path,
-            function_name: row.function,
-            readonly: row.readonly,
-        }
+    } else {
+        // Accounts seeded before roles were checked have the default role
+        sqlx::query("UPDATE users SET role = 'admin' WHERE email = ?")
+            .bind(admin_email)
+            .execute(pool)
+            .await
+            .expect("Failed to grant the admin role");
     }
 }
 
@@ -274,20 +156,21 @@ impl UserRepository for SqliteRepository {
     }
 
     async fn update(&self, user: &User) -> Result<User, DomainError> {
-        let result = sqlx::query("UPDATE users SET name = ?, email = ?, password_hash = ? WHERE id = ?")
-            .bind(&user.name)
-            .bind(&user.email)
-            .bind(&user.password_hash)
-            .bind(user.id)
-            .execute(&self.pool)
-            .await
-            .map_err(|e| {
-                if e.to_string().contains("UNIQUE constraint failed") {
-                    DomainError::AlreadyExists(user.email.clone())
-                } else {
-                    DomainError::Internal(e.to_string())
-                }
-            })?;
+        let result =
+            sqlx::query("UPDATE users SET name = ?, email = ?, password_hash = ? WHERE id = ?")
+                .bind(&user.name)
+                .bind(&user.email)
+                .bind(&user.password_hash)
+                .bind(user.id)
+                .execute(&self.pool)
+                .await
+                .map_err(|e| {
+                    if e.to_string().contains("UNIQUE constraint failed") {
+                        DomainError::AlreadyExists(user.email.clone())
+                    } else {
+                        DomainError::Internal(e.to_string())
+                    }
+                })?;
 
         if result.rows_affected() == 0 {
             return Err(DomainError::NotFound(format!("User with id {}", user.id)));
@@ -309,149 +192,777 @@ impl FunctionRepository for SqliteRepository {
     }
 
     async fn find_by_name(&self, name: &str) -> Result<Option<Function>, DomainError> {
-        let row = sqlx::query_as::<_, FunctionRow>("SELECT * FROM functions WHERE name = ?")
-            .bind(name)
-            .fetch_optional(&self.pool)
-            .await
-            .map_err(|e| DomainError::Internal(e.to_string()))?;
-
-        Ok(row.map(Into::into))
+        find_function(&mut *self.connection().await?, name).await
     }
 
     async fn save(&self, f: &Function) -> Result<Function, DomainError> {
-        let lang_str = format!("{:?}", f.language); // Debug format is usually Capitalized
-        sqlx::query("INSERT INTO functions (name, language, executable, cpu, memory, readonly) VALUES (?, ?, ?, ?, ?, ?)")
-            .bind(&f.name)
-            .bind(lang_str)
-            .bind(&f.executable)
-            .bind(&f.cpu)
-            .bind(&f.memory)
-            .bind(f.readonly)
-            .execute(&self.pool)
-            .await
-            .map_err(|e| {
-                if e.to_string().contains("UNIQUE constraint failed") {
-                    DomainError::AlreadyExists(f.name.clone())
-                } else {
-                    DomainError::Internal(e.to_string())
-                }
-            })?;
+        insert_function(&mut *self.connection().await?, f).await?;
         Ok(f.clone())
     }
 
     async fn update(&self, f: &Function) -> Result<Function, DomainError> {
-        // Check if readonly
-        let current = self.find_by_name(&f.name).await?;
-        if let Some(current) = current {
-            if current.readonly {
-                 return Err(DomainError::ValidationError(format!("Function '{}' is readonly", f.name)));
+        update_function(&mut *self.connection().await?, f).await?;
+        Ok(f.clone())
+    }
+
+    async fn delete(&self, name: &str) -> Result<(), DomainError> {
+        delete_function(&mut *self.connection().await?, name).await
+    }
+}
+
+#[async_trait]
+impl TriggerRepository for SqliteRepository {
+    async fn find_all(&self) -> Result<Vec<Trigger>, DomainError> {
+        let rows = sqlx::query_as::<_, TriggerRow>("SELECT * FROM triggers")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| DomainError::Internal(e.to_string()))?;
+
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
+    async fn save(&self, t: &Trigger) -> Result<Trigger, DomainError> {
+        insert_trigger(&mut *self.connection().await?, t).await?;
+        Ok(t.clone())
+    }
+
+    async fn delete(&self, name: &str) -> Result<(), DomainError> {
+        delete_trigger(&mut *self.connection().await?, name).await
+    }
+}
+
+#[async_trait]
+impl ManifestRepository for SqliteRepository {
+    async fn apply_manifest(&self, writes: &[ManifestWrite]) -> Result<(), DomainError> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| DomainError::Internal(e.to_string()))?;
+        for write in writes {
+            match write {
+                ManifestWrite::CreateFunction(f) => insert_function(&mut tx, f).await?,
+                ManifestWrite::UpdateFunction(f) => update_function(&mut tx, f).await?,
+                ManifestWrite::DeleteFunction(name) => delete_function(&mut tx, name).await?,
+                ManifestWrite::CreateTrigger(t) => insert_trigger(&mut tx, t).await?,
+                ManifestWrite::DeleteTrigger(name) => delete_trigger(&mut tx, name).await?,
             }
-        } else {
-             return Err(DomainError::NotFound(f.name.clone()));
         }
+        tx.commit()
+            .await
+            .map_err(|e| DomainError::Internal(e.to_string()))
+    }
+}
 
-        let lang_str = format!("{:?}", f.language);
-        let result = sqlx::query(
-            "UPDATE functions SET language=?, executable=?, cpu=?, memory=? WHERE name=?",
-        )
+// Function and trigger writes take a connection, so a manifest apply can make
+// them in one transaction.
+
+async fn insert_function(conn: &mut SqliteConnection, f: &Function) -> Result<(), DomainError> {
+    let lang_str = format!("{:?}", f.language); // Debug format is usually Capitalized
+    let allowed_calls = serde_json::to_string(&f.allowed_calls)
+        .map_err(|e| DomainError::Internal(e.to_string()))?;
+    let retry = f
+        .retry
+        .as_ref()
+        .map(serde_json::to_string)
+        .transpose()
+        .map_err(|e| DomainError::Internal(e.to_string()))?;
+    let env = serde_json::to_string(&f.env).map_err(|e| DomainError::Internal(e.to_string()))?;
+    sqlx::query("INSERT INTO functions (name, language, executable, cpu, memory, readonly, snapshot, max_concurrency, max_queue, allowed_calls, retry, env) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+        .bind(&f.name)
         .bind(lang_str)
         .bind(&f.executable)
         .bind(&f.cpu)
         .bind(&f.memory)
-        .bind(&f.name)
-        .execute(&self.pool)
+        .bind(f.readonly)
+        .bind(f.snapshot)
+        .bind(f.max_concurrency)
+        .bind(f.max_queue)
+        .bind(allowed_calls)
+        .bind(retry)
+        .bind(env)
+        .execute(&mut *conn)
         .await
+        .map_err(|e| {
+            if e.to_string().contains("UNIQUE constraint failed") {
+                DomainError::AlreadyExists(f.name.clone())
+            } else {
+                DomainError::Internal(e.to_string())
+            }
+        })?;
+    Ok(())
+}
+
+async fn update_function(conn: &mut SqliteConnection, f: &Function) -> Result<(), DomainError> {
+    // Check if readonly
+    let current = find_function(conn, &f.name).await?;
+    if let Some(current) = current {
+        if current.readonly {
+            return Err(DomainError::ValidationError(format!(
+                "Function '{}' is readonly",
+                f.name
+            )));
+        }
+    } else {
+        return Err(DomainError::NotFound(f.name.clone()));
+    }
+
+    let lang_str = format!("{:?}", f.language);
+    let allowed_calls = serde_json::to_string(&f.allowed_calls)
+        .map_err(|e| DomainError::Internal(e.to_string()))?;
+    let retry = f
+        .retry
+        .as_ref()
+        .map(serde_json::to_string)
+        .transpose()
         .map_err(|e| DomainError::Internal(e.to_string()))?;
+    let env = serde_json::to_string(&f.env).map_err(|e| DomainError::Internal(e.to_string()))?;
+    let result = sqlx::query(
+        "UPDATE functions SET language=?, executable=?, cpu=?, memory=?, snapshot=?, max_concurrency=?, max_queue=?, allowed_calls=?, retry=?, env=? WHERE name=?",
+    )
+    .bind(lang_str)
+    .bind(&f.executable)
+    .bind(&f.cpu)
+    .bind(&f.memory)
+    .bind(f.snapshot)
+    .bind(f.max_concurrency)
+    .bind(f.max_queue)
+    .bind(allowed_calls)
+    .bind(retry)
+    .bind(env)
+    .bind(&f.name)
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| DomainError::Internal(e.to_string()))?;
 
-        if result.rows_affected() == 0 {
-            return Err(DomainError::NotFound(f.name.clone()));
+    if result.rows_affected() == 0 {
+        return Err(DomainError::NotFound(f.name.clone()));
+    }
+
+    Ok(())
+}
+
+async fn delete_function(conn: &mut SqliteConnection, name: &str) -> Result<(), DomainError> {
+    // Check if readonly
+    let current = find_function(conn, name).await?;
+    if let Some(current) = current {
+        if current.readonly {
+            return Err(DomainError::ValidationError(format!(
+                "Function '{}' is readonly",
+                name
+            )));
         }
+    } else {
+        return Err(DomainError::NotFound(name.to_string()));
+    }
 
-        Ok(f.clone())
+    let result = sqlx::query("DELETE FROM functions WHERE name = ?")
+        .bind(name)
+        .execute(&mut *conn)
+        .await;
+    match result {
+        Ok(result) if result.rows_affected() == 0 => {
+            return Err(DomainError::NotFound(name.to_string()));
+        }
+        Ok(_) => {}
+        Err(e) if is_foreign_key_violation(&e) => {
+            let triggers: Vec<String> =
+                sqlx::query_scalar("SELECT name FROM triggers WHERE function = ? ORDER BY name")
+                    .bind(name)
+                    .fetch_all(&mut *conn)
+                    .await
+                    .map_err(|e| DomainError::Internal(e.to_string()))?;
+            return Err(DomainError::ValidationError(format!(
+                "Function '{}' is still used by triggers: {}",
+                name,
+                triggers.join(", ")
+            )));
+        }
+        Err(e) => return Err(DomainError::Internal(e.to_string())),
     }
+    Ok(())
+}
 
-    async fn delete(&self, name: &str) -> Result<(), DomainError> {
-        // Check if readonly
-        let current = self.find_by_name(name).await?;
-         if let Some(current) = current {
-            if current.readonly {
-                 return Err(DomainError::ValidationError(format!("Function '{}' is readonly", name)));
+async fn find_function(
+    conn: &mut SqliteConnection,
+    name: &str,
+) -> Result<Option<Function>, DomainError> {
+    let row = sqlx::query_as::<_, FunctionRow>("SELECT * FROM functions WHERE name = ?")
+        .bind(name)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| DomainError::Internal(e.to_string()))?;
+    Ok(row.map(Into::into))
+}
+
+async fn insert_trigger(conn: &mut SqliteConnection, t: &Trigger) -> Result<(), DomainError> {
+    let rate_limit = t
+        .rate_limit
+        .as_ref()
+        .map(serde_json::to_string)
+        .transpose()
+        .map_err(|e| DomainError::Internal(e.to_string()))?;
+    let cache = t
+        .cache
+        .as_ref()
+        .map(serde_json::to_string)
+        .transpose()
+        .map_err(|e| DomainError::Internal(e.to_string()))?;
+    let (function, workflow) = trigger_target(t);
+    sqlx::query("INSERT INTO triggers (name, method, path, function, workflow, kind, rate_limit, cache, readonly) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)")
+        .bind(&t.name)
+        .bind(&t.method)
+        .bind(&t.path)
+        .bind(function)
+        .bind(workflow)
+        .bind(trigger_kind_str(t.kind))
+        .bind(rate_limit)
+        .bind(cache)
+        .bind(t.readonly)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| {
+            if e.to_string().contains("UNIQUE constraint failed") {
+                DomainError::AlreadyExists(t.name.clone())
+            } else if is_foreign_key_violation(&e) {
+                unknown_target(t)
+            } else {
+                DomainError::Internal(e.to_string())
             }
-        } else {
-            return Err(DomainError::NotFound(name.to_string()));
+        })?;
+    Ok(())
+}
+
+async fn delete_trigger(conn: &mut SqliteConnection, name: &str) -> Result<(), DomainError> {
+    // Check if readonly
+    let row = sqlx::query_as::<_, TriggerRow>("SELECT * FROM triggers WHERE name = ?")
+        .bind(name)
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| DomainError::Internal(e.to_string()))?;
+
+    if let Some(row) = row {
+        if row.readonly {
+            return Err(DomainError::ValidationError(format!(
+                "Trigger '{}' is readonly",
+                name
+            )));
+        }
+    } else {
+        return Err(DomainError::NotFound(name.to_string()));
+    }
+
+    let result = sqlx::query("DELETE FROM triggers WHERE name = ?")
+        .bind(name)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| DomainError::Internal(e.to_string()))?;
+
+    if result.rows_affected() == 0 {
+        return Err(DomainError::NotFound(name.to_string()));
+    }
+    Ok(())
+}
+
+/// Buckets live in the database, so every API node sharing it enforces the same limits.
+#[async_trait]
+impl RateLimitStore for SqliteRepository {
+    async fn take(
+        &self,
+        key: &str,
+        capacity: f64,
+        refill_per_sec: f64,
+    ) -> Result<BucketState, DomainError> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_err(|e| DomainError::Internal(e.to_string()))?
+            .as_secs_f64();
+
+        // Refill and take in a single statement so concurrent nodes can't both spend a token
+        let taken: Option<f64> = sqlx::query_scalar(
+            "INSERT INTO rate_limit_buckets (key, tokens, updated_at, full_at)
+             VALUES (?1, ?2 - 1, ?3, ?3 + 1 / ?4)
+             ON CONFLICT(key) DO UPDATE SET
+                 tokens = MIN(?2, tokens + MAX(0, ?3 - updated_at) * ?4) - 1,
+                 updated_at = ?3,
+                 full_at = ?3 + (?2 + 1 - MIN(?2, tokens + MAX(0, ?3 - updated_at) * ?4)) / ?4
+             WHERE MIN(?2, tokens + MAX(0, ?3 - updated_at) * ?4) >= 1
+             RETURNING tokens",
+        )
+        .bind(key)
+        .bind(capacity)
+        .bind(now)
+        .bind(refill_per_sec)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DomainError::Internal(e.to_string()))?;
+
+        if let Some(tokens) = taken {
+            return Ok(BucketState {
+                allowed: true,
+                tokens,
+            });
         }
 
-        let result = sqlx::query("DELETE FROM functions WHERE name = ?")
+        let tokens: f64 = sqlx::query_scalar(
+            "SELECT MIN(?2, tokens + MAX(0, ?3 - updated_at) * ?4)
+             FROM rate_limit_buckets WHERE key = ?1",
+        )
+        .bind(key)
+        .bind(capacity)
+        .bind(now)
+        .bind(refill_per_sec)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DomainError::Internal(e.to_string()))?
+        .unwrap_or(0.0);
+
+        Ok(BucketState {
+            allowed: false,
+            tokens,
+        })
+    }
+
+    async fn prune(&self) -> Result<u64, DomainError> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_err(|e| DomainError::Internal(e.to_string()))?
+            .as_secs_f64();
+
+        let result = sqlx::query("DELETE FROM rate_limit_buckets WHERE full_at <= ?")
+            .bind(now)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DomainError::Internal(e.to_string()))?;
+        Ok(result.rows_affected())
+    }
+}
+
+impl SqliteRepository {
+    async fn load_run(&self, row: WorkflowRunRow) -> Result<WorkflowRun, DomainError> {
+        let steps = sqlx::query_as::<_, StepRunRow>(
+            "SELECT step, status, input, output, error, attempts FROM workflow_steps
+             WHERE run_id = ? ORDER BY position",
+        )
+        .bind(&row.id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DomainError::Internal(e.to_string()))?;
+
+        row.into_run(steps)
+    }
+
+    async fn load_runs(&self, rows: Vec<WorkflowRunRow>) -> Result<Vec<WorkflowRun>, DomainError> {
+        let mut runs = Vec::with_capacity(rows.len());
+        for row in rows {
+            runs.push(self.load_run(row).await?);
+        }
+        Ok(runs)
+    }
+}
+
+#[async_trait]
+impl WorkflowRepository for SqliteRepository {
+    async fn find_workflows(&self) -> Result<Vec<Workflow>, DomainError> {
+        sqlx::query_as::<_, WorkflowRow>("SELECT name, steps FROM workflows ORDER BY name")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| DomainError::Internal(e.to_string()))?
+            .into_iter()
+            .map(Workflow::try_from)
+            .collect()
+    }
+
+    async fn find_workflow(&self, name: &str) -> Result<Option<Workflow>, DomainError> {
+        sqlx::query_as::<_, WorkflowRow>("SELECT name, steps FROM workflows WHERE name = ?")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| DomainError::Internal(e.to_string()))?
+            .map(Workflow::try_from)
+            .transpose()
+    }
+
+    async fn save_workflow(&self, workflow: &Workflow) -> Result<Workflow, DomainError> {
+        let steps = serde_json::to_string(&workflow.steps)
+            .map_err(|e| DomainError::Internal(e.to_string()))?;
+        sqlx::query(
+            "INSERT INTO workflows (name, steps) VALUES (?, ?)
+             ON CONFLICT(name) DO UPDATE SET steps = excluded.steps",
+        )
+        .bind(&workflow.name)
+        .bind(steps)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DomainError::Internal(e.to_string()))?;
+        Ok(workflow.clone())
+    }
+
+    async fn delete_workflow(&self, name: &str) -> Result<(), DomainError> {
+        let result = sqlx::query("DELETE FROM workflows WHERE name = ?")
             .bind(name)
             .execute(&self.pool)
+            .await;
+        match result {
+            Ok(result) if result.rows_affected() == 0 => {
+                Err(DomainError::NotFound(name.to_string()))
+            }
+            Ok(_) => Ok(()),
+            Err(e) if is_foreign_key_violation(&e) => {
+                let triggers: Vec<String> = sqlx::query_scalar(
+                    "SELECT name FROM triggers WHERE workflow = ? ORDER BY name",
+                )
+                .bind(name)
+                .fetch_all(&self.pool)
+                .await
+                .map_err(|e| DomainError::Internal(e.to_string()))?;
+                Err(DomainError::ValidationError(format!(
+                    "Workflow '{}' is still used by triggers: {}",
+                    name,
+                    triggers.join(", ")
+                )))
+            }
+            Err(e) => Err(DomainError::Internal(e.to_string())),
+        }
+    }
+
+    async fn save_run(&self, run: &WorkflowRun) -> Result<(), DomainError> {
+        let definition = serde_json::to_string(&run.definition)
+            .map_err(|e| DomainError::Internal(e.to_string()))?;
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| DomainError::Internal(e.to_string()))?;
+
+        sqlx::query(
+            "INSERT INTO workflow_runs (id, workflow, definition, status, input, output, error, created_at, updated_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT(id) DO UPDATE SET
+                 status = excluded.status,
+                 output = excluded.output,
+                 error = excluded.error,
+                 updated_at = excluded.updated_at",
+        )
+        .bind(&run.id)
+        .bind(&run.workflow)
+        .bind(definition)
+        .bind(status_str(run.status))
+        .bind(&run.input)
+        .bind(&run.output)
+        .bind(&run.error)
+        .bind(run.created_at.to_rfc3339())
+        .bind(run.updated_at.to_rfc3339())
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| DomainError::Internal(e.to_string()))?;
+
+        for (position, step) in run.steps.iter().enumerate() {
+            sqlx::query(
+                "INSERT INTO workflow_steps (run_id, step, position, status, input, output, error, attempts)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                 ON CONFLICT(run_id, step) DO UPDATE SET
+                     status = excluded.status,
+                     input = excluded.input,
+                     output = excluded.output,
+                     error = excluded.error,
+                     attempts = excluded.attempts",
+            )
+            .bind(&run.id)
+            .bind(&step.step)
+            .bind(position as i64)
+            .bind(status_str(step.status))
+            .bind(&step.input)
+            .bind(&step.output)
+            .bind(&step.error)
+            .bind(step.attempts)
+            .execute(&mut *tx)
             .await
             .map_err(|e| DomainError::Internal(e.to_string()))?;
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| DomainError::Internal(e.to_string()))
+    }
+
+    async fn save_step(&self, run_id: &str, step: &StepRun) -> Result<(), DomainError> {
+        let result = sqlx::query(
+            "UPDATE workflow_steps SET status = ?, input = ?, output = ?, error = ?, attempts = ?
+             WHERE run_id = ? AND step = ?",
+        )
+        .bind(status_str(step.status))
+        .bind(&step.input)
+        .bind(&step.output)
+        .bind(&step.error)
+        .bind(step.attempts)
+        .bind(run_id)
+        .bind(&step.step)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DomainError::Internal(e.to_string()))?;
 
         if result.rows_affected() == 0 {
-            return Err(DomainError::NotFound(name.to_string()));
+            return Err(DomainError::NotFound(format!("{}/{}", run_id, step.step)));
         }
         Ok(())
     }
+
+    async fn find_run(&self, id: &str) -> Result<Option<WorkflowRun>, DomainError> {
+        let row = sqlx::query_as::<_, WorkflowRunRow>("SELECT * FROM workflow_runs WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| DomainError::Internal(e.to_string()))?;
+
+        match row {
+            Some(row) => Ok(Some(self.load_run(row).await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn find_runs(&self, workflow: &str) -> Result<Vec<WorkflowRun>, DomainError> {
+        let rows = sqlx::query_as::<_, WorkflowRunRow>(
+            "SELECT * FROM workflow_runs WHERE workflow = ? ORDER BY created_at DESC LIMIT 100",
+        )
+        .bind(workflow)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DomainError::Internal(e.to_string()))?;
+        self.load_runs(rows).await
+    }
+
+    async fn find_unfinished_runs(&self) -> Result<Vec<WorkflowRun>, DomainError> {
+        let rows = sqlx::query_as::<_, WorkflowRunRow>(
+            "SELECT * FROM workflow_runs WHERE status = ? ORDER BY created_at",
+        )
+        .bind(status_str(RunStatus::Running))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DomainError::Internal(e.to_string()))?;
+        self.load_runs(rows).await
+    }
 }
 
 #[async_trait]
-impl TriggerRepository for SqliteRepository {
-    async fn find_all(&self) -> Result<Vec<Trigger>, DomainError> {
-        let rows = sqlx::query_as::<_, TriggerRow>("SELECT * FROM triggers")
+impl BackgroundJobRepository for SqliteRepository {
+    async fn save_job(&self, job: &BackgroundJob) -> Result<(), DomainError> {
+        sqlx::query(
+            "INSERT INTO background_jobs (id, function, source, payload, attempts, created_at)
+             VALUES (?, ?, ?, ?, ?, ?)
+             ON CONFLICT(id) DO UPDATE SET attempts = excluded.attempts",
+        )
+        .bind(&job.id)
+        .bind(&job.function)
+        .bind(&job.source)
+        .bind(&job.payload)
+        .bind(job.attempts)
+        .bind(job.created_at.to_rfc3339())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DomainError::Internal(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn find_jobs(&self) -> Result<Vec<BackgroundJob>, DomainError> {
+        sqlx::query_as::<_, BackgroundJobRow>("SELECT * FROM background_jobs ORDER BY created_at")
             .fetch_all(&self.pool)
             .await
+            .map_err(|e| DomainError::Internal(e.to_string()))?
+            .into_iter()
+            .map(BackgroundJob::try_from)
+            .collect()
+    }
+
+    async fn delete_job(&self, id: &str) -> Result<(), DomainError> {
+        sqlx::query("DELETE FROM background_jobs WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await
             .map_err(|e| DomainError::Internal(e.to_string()))?;
+        Ok(())
+    }
+}
 
-        Ok(rows.into_iter().map(Into::into).collect())
+#[async_trait]
+impl NodeLeaseRepository for SqliteRepository {
+    async fn acquire_lease(
+        &self,
+        node: &str,
+        now: i64,
+        expires_at: i64,
+    ) -> Result<String, DomainError> {
+        sqlx::query(
+            "INSERT INTO node_lease (id, node, expires_at) VALUES (1, ?, ?)
+             ON CONFLICT (id) DO UPDATE SET node = excluded.node, expires_at = excluded.expires_at
+             WHERE node_lease.node = excluded.node OR node_lease.expires_at <= ?",
+        )
+        .bind(node)
+        .bind(expires_at)
+        .bind(now)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DomainError::Internal(e.to_string()))?;
+        sqlx::query_scalar("SELECT node FROM node_lease WHERE id = 1")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| DomainError::Internal(e.to_string()))
     }
 
-    async fn save(&self, t: &Trigger) -> Result<Trigger, DomainError> {
-        sqlx::query("INSERT INTO triggers (name, method, path, function, readonly) VALUES (?, ?, ?, ?, ?)")
-            .bind(&t.name)
-            .bind(&t.method)
-            .bind(&t.path)
-            .bind(&t.function_name)
-            .bind(t.readonly)
+    async fn release_lease(&self, node: &str) -> Result<(), DomainError> {
+        sqlx::query("DELETE FROM node_lease WHERE node = ?")
+            .bind(node)
             .execute(&self.pool)
             .await
-            .map_err(|e| {
-                if e.to_string().contains("UNIQUE constraint failed") {
-                    DomainError::AlreadyExists(t.name.clone())
-                } else {
-                    DomainError::Internal(e.to_string())
-                }
-            })?;
-        Ok(t.clone())
+            .map_err(|e| DomainError::Internal(e.to_string()))?;
+        Ok(())
     }
+}
 
-    async fn delete(&self, name: &str) -> Result<(), DomainError> {
-        // Check if readonly
-        // We need find_by_name for trigger but we haven't implemented it in repo trait yet?
-        // Wait, TriggerRepository trait usually has find_by_name. Let me check ports.rs or infer from context.
-        // Actually, looking at sqlite.rs, find_all is there, delete is there, but save is there.
-        // I need to check if find_by_name exists in TriggerRepository.
-        // Based on previous file content, it wasn't visible in the view.
-        // I will assume I need to fetch it manually here if not available in trait.
-        // But let's check ports.rs first? No I can just check sqlite.rs again.
-        // TriggerRepository impl usually follows FunctionRepository.
-        // sqlite.rs impl has find_all, save, delete. It MISSES find_by_name.
-        // I should stick to `sqlx::query_as` locally here.
-        
-        let row = sqlx::query_as::<_, TriggerRow>("SELECT * FROM triggers WHERE name = ?")
-            .bind(name)
+#[async_trait]
+impl DeadLetterRepository for SqliteRepository {
+    async fn save_dead_letter(&self, letter: &DeadLetter) -> Result<(), DomainError> {
+        sqlx::query(
+            "INSERT INTO dead_letters (id, function, source, payload, error, attempts, created_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&letter.id)
+        .bind(&letter.function)
+        .bind(&letter.source)
+        .bind(&letter.payload)
+        .bind(&letter.error)
+        .bind(letter.attempts)
+        .bind(letter.created_at.to_rfc3339())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DomainError::Internal(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn find_dead_letters<'a>(
+        &self,
+        function: Option<&'a str>,
+    ) -> Result<Vec<DeadLetter>, DomainError> {
+        sqlx::query_as::<_, DeadLetterRow>(
+            "SELECT * FROM dead_letters WHERE ?1 IS NULL OR function = ?1
+             ORDER BY created_at DESC LIMIT 1000",
+        )
+        .bind(function)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DomainError::Internal(e.to_string()))?
+        .into_iter()
+        .map(DeadLetter::try_from)
+        .collect()
+    }
+
+    async fn find_dead_letter(&self, id: &str) -> Result<Option<DeadLetter>, DomainError> {
+        sqlx::query_as::<_, DeadLetterRow>("SELECT * FROM dead_letters WHERE id = ?")
+            .bind(id)
             .fetch_optional(&self.pool)
             .await
+            .map_err(|e| DomainError::Internal(e.to_string()))?
+            .map(DeadLetter::try_from)
+            .transpose()
+    }
+
+    async fn delete_dead_letter(&self, id: &str) -> Result<(), DomainError> {
+        let result = sqlx::query("DELETE FROM dead_letters WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await
             .map_err(|e| DomainError::Internal(e.to_string()))?;
 
-        if let Some(row) = row {
-            if row.readonly {
-                return Err(DomainError::ValidationError(format!("Trigger '{}' is readonly", name)));
-            }
-        } else {
-             return Err(DomainError::NotFound(name.to_string()));
+        if result.rows_affected() == 0 {
+            return Err(DomainError::NotFound(id.to_string()));
         }
+        Ok(())
+    }
+
+    async fn purge_dead_letters<'a>(&self, function: Option<&'a str>) -> Result<u64, DomainError> {
+        let result = sqlx::query("DELETE FROM dead_letters WHERE ?1 IS NULL OR function = ?1")
+            .bind(function)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DomainError::Internal(e.to_string()))?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[async_trait]
+impl AlertRepository for SqliteRepository {
+    async fn find_alert_rules(&self) -> Result<Vec<AlertRule>, DomainError> {
+        sqlx::query_as::<_, AlertRuleRow>("SELECT * FROM alert_rules ORDER BY name")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| DomainError::Internal(e.to_string()))?
+            .into_iter()
+            .map(AlertRule::try_from)
+            .collect()
+    }
+
+    async fn find_alert_rule(&self, name: &str) -> Result<Option<AlertRule>, DomainError> {
+        sqlx::query_as::<_, AlertRuleRow>("SELECT * FROM alert_rules WHERE name = ?")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| DomainError::Internal(e.to_string()))?
+            .map(AlertRule::try_from)
+            .transpose()
+    }
+
+    async fn save_alert_rule(&self, rule: &AlertRule) -> Result<AlertRule, DomainError> {
+        let condition = serde_json::to_string(&rule.condition)
+            .map_err(|e| DomainError::Internal(e.to_string()))?;
+        let webhooks = serde_json::to_string(&rule.webhooks)
+            .map_err(|e| DomainError::Internal(e.to_string()))?;
+        sqlx::query(
+            "INSERT INTO alert_rules
+                 (name, function, condition, window_secs, webhooks, state, value, since, evaluated_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT(name) DO UPDATE SET
+                 function = excluded.function,
+                 condition = excluded.condition,
+                 window_secs = excluded.window_secs,
+                 webhooks = excluded.webhooks,
+                 state = excluded.state,
+                 value = excluded.value,
+                 since = excluded.since,
+                 evaluated_at = excluded.evaluated_at",
+        )
+        .bind(&rule.name)
+        .bind(&rule.function)
+        .bind(condition)
+        .bind(rule.window_secs as i64)
+        .bind(webhooks)
+        .bind(status_str(rule.status.state))
+        .bind(rule.status.value)
+        .bind(rule.status.since.map(|t| t.to_rfc3339()))
+        .bind(rule.status.evaluated_at.map(|t| t.to_rfc3339()))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DomainError::Internal(e.to_string()))?;
+        Ok(rule.clone())
+    }
+
+    async fn save_alert_status(&self, name: &str, status: &AlertStatus) -> Result<(), DomainError> {
+        sqlx::query(
+            "UPDATE alert_rules SET state = ?, value = ?, since = ?, evaluated_at = ?
+             WHERE name = ?",
+        )
+        .bind(status_str(status.state))
+        .bind(status.value)
+        .bind(status.since.map(|t| t.to_rfc3339()))
+        .bind(status.evaluated_at.map(|t| t.to_rfc3339()))
+        .bind(name)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DomainError::Internal(e.to_string()))?;
+        Ok(())
+    }
 
-        let result = sqlx::query("DELETE FROM triggers WHERE name = ?")
+    async fn delete_alert_rule(&self, name: &str) -> Result<(), DomainError> {
+        let result = sqlx::query("DELETE FROM alert_rules WHERE name = ?")
             .bind(name)
             .execute(&self.pool)
             .await
@@ -464,4 +975,53 @@ impl TriggerRepository for SqliteRepository {
     }
 }
 
+#[async_trait]
+impl AuditRepository for SqliteRepository {
+    async fn append_audit_entry(&self, entry: &AuditEntry) -> Result<AuditEntry, DomainError> {
+        let changes = serde_json::to_string(&entry.changes)
+            .map_err(|e| DomainError::Internal(e.to_string()))?;
+        let id: i64 = sqlx::query_scalar(
+            "INSERT INTO audit_log (at, actor, source_ip, action, target, changes)
+             VALUES (?, ?, ?, ?, ?, ?) RETURNING id",
+        )
+        .bind(entry.at.to_rfc3339())
+        .bind(&entry.actor)
+        .bind(&entry.source_ip)
+        .bind(status_str(entry.action))
+        .bind(&entry.target)
+        .bind(changes)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| DomainError::Internal(e.to_string()))?;
+        Ok(AuditEntry {
+            id,
+            ..entry.clone()
+        })
+    }
 
+    async fn find_audit_entries(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>, DomainError> {
+        sqlx::query_as::<_, AuditEntryRow>(
+            "SELECT * FROM audit_log
+             WHERE (?1 IS NULL OR actor = ?1)
+               AND (?2 IS NULL OR action = ?2 OR substr(action, 1, length(?2) + 1) = ?2 || '.')
+               AND (?3 IS NULL OR target = ?3)
+               AND (?4 IS NULL OR at >= ?4)
+               AND (?5 IS NULL OR at < ?5)
+               AND (?6 IS NULL OR id < ?6)
+             ORDER BY id DESC LIMIT ?7",
+        )
+        .bind(&query.actor)
+        .bind(&query.action)
+        .bind(&query.target)
+        .bind(query.from.map(|t| t.to_rfc3339()))
+        .bind(query.to.map(|t| t.to_rfc3339()))
+        .bind(query.before_id)
+        .bind(query.limit as i64)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DomainError::Internal(e.to_string()))?
+        .into_iter()
+        .map(AuditEntry::try_from)
+        .collect()
+    }
+}
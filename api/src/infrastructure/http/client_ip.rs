@@ -0,0 +1,121 @@
+use actix_web::{HttpRequest, web};
+use std::net::IpAddr;
+use tracing::warn;
+
+/// Proxies whose `X-Forwarded-For` is believed. Without any, the client is
+/// always the connection's peer, since anyone can send the header.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies(Vec<IpAddr>);
+
+impl TrustedProxies {
+    pub fn new(proxies: Vec<IpAddr>) -> Self {
+        Self(proxies)
+    }
+
+    /// Reads the comma-separated addresses in `TRUSTED_PROXIES`.
+    pub fn from_env() -> Self {
+        let proxies = std::env::var("TRUSTED_PROXIES").unwrap_or_default();
+        Self(
+            proxies
+                .split(',')
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+                .filter_map(|p| match p.parse() {
+                    Ok(ip) => Some(ip),
+                    Err(_) => {
+                        warn!("Ignoring invalid trusted proxy address: {}", p);
+                        None
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        self.0.contains(ip)
+    }
+}
+
+/// The address the request came from. When the peer is a trusted proxy,
+/// `X-Forwarded-For` is walked from the right past every trusted hop, so a
+/// client can't pick its own address by prepending entries.
+pub fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    let Some(trusted) = req.app_data::<web::Data<TrustedProxies>>() else {
+        return Some(peer);
+    };
+
+    let mut client = peer;
+    let forwarded = req
+        .headers()
+        .get_all("x-forwarded-for")
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .collect::<Vec<_>>();
+    for hop in forwarded.into_iter().rev() {
+        if !trusted.contains(&client) {
+            break;
+        }
+        match hop.parse() {
+            Ok(ip) => client = ip,
+            Err(_) => break,
+        }
+    }
+    Some(client)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn request(peer: &str, forwarded: Option<&str>) -> HttpRequest {
+        let mut req = TestRequest::default()
+            .peer_addr(format!("{}:4000", peer).parse().unwrap())
+            .app_data(web::Data::new(TrustedProxies::new(vec![
+                "10.0.0.1".parse().unwrap(),
+                "10.0.0.2".parse().unwrap(),
+            ])));
+        if let Some(forwarded) = forwarded {
+            req = req.insert_header(("X-Forwarded-For", forwarded));
+        }
+        req.to_http_request()
+    }
+
+    fn ip(s: &str) -> Option<IpAddr> {
+        Some(s.parse().unwrap())
+    }
+
+    #[test]
+    fn test_client_ip_ignores_untrusted_forwarding() {
+        assert_eq!(
+            client_ip(&request("198.51.100.9", Some("203.0.113.7"))),
+            ip("198.51.100.9")
+        );
+
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "203.0.113.7"))
+            .to_http_request();
+        assert_eq!(client_ip(&req), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn test_client_ip_walks_trusted_hops() {
+        assert_eq!(
+            client_ip(&request("10.0.0.1", Some("203.0.113.7"))),
+            ip("203.0.113.7")
+        );
+        // Entries left of the first untrusted hop were written by the client
+        assert_eq!(
+            client_ip(&request("10.0.0.1", Some("1.2.3.4, 203.0.113.7, 10.0.0.2"))),
+            ip("203.0.113.7")
+        );
+        assert_eq!(
+            client_ip(&request("10.0.0.1", Some("garbage"))),
+            ip("10.0.0.1")
+        );
+        assert_eq!(client_ip(&request("10.0.0.1", None)), ip("10.0.0.1"));
+    }
+}
@@ -0,0 +1,104 @@
+use super::audit::actor;
+use crate::application::alert_service::AlertService;
+use crate::application::auth_service::AuthService;
+use crate::domain::entities::{AlertRule, DomainError};
+use actix_web::{HttpRequest, HttpResponse, Responder, delete, get, post, put, web};
+use chrono::Utc;
+use std::sync::Arc;
+
+fn error_response(e: DomainError) -> HttpResponse {
+    match e {
+        DomainError::NotFound(msg) => HttpResponse::NotFound().body(msg),
+        DomainError::ValidationError(msg) => HttpResponse::BadRequest().body(msg),
+        DomainError::AlreadyExists(msg) => HttpResponse::Conflict().body(msg),
+        e => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+/// Every rule with its current state.
+#[get("/alerts")]
+async fn list_rules(service: web::Data<Arc<AlertService>>) -> impl Responder {
+    match service.list_rules().await {
+        Ok(list) => HttpResponse::Ok().json(list),
+        Err(e) => error_response(e),
+    }
+}
+
+#[post("/alerts")]
+async fn create_rule(
+    req: HttpRequest,
+    rule: web::Json<AlertRule>,
+    auth: web::Data<Arc<AuthService>>,
+    service: web::Data<Arc<AlertService>>,
+) -> impl Responder {
+    match service
+        .create_rule(rule.into_inner(), &actor(&req, &auth))
+        .await
+    {
+        Ok(created) => HttpResponse::Created().json(created),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Evaluates every rule now instead of waiting for the next round; returns the
+/// rules that fired or resolved.
+#[post("/alerts/evaluate")]
+async fn evaluate(service: web::Data<Arc<AlertService>>) -> impl Responder {
+    match service.evaluate(Utc::now()).await {
+        Ok(changed) => HttpResponse::Ok().json(changed),
+        Err(e) => error_response(e),
+    }
+}
+
+#[get("/alerts/{name}")]
+async fn get_rule(
+    path: web::Path<String>,
+    service: web::Data<Arc<AlertService>>,
+) -> impl Responder {
+    match service.get_rule(&path.into_inner()).await {
+        Ok(rule) => HttpResponse::Ok().json(rule),
+        Err(e) => error_response(e),
+    }
+}
+
+#[put("/alerts/{name}")]
+async fn update_rule(
+    req: HttpRequest,
+    path: web::Path<String>,
+    rule: web::Json<AlertRule>,
+    auth: web::Data<Arc<AuthService>>,
+    service: web::Data<Arc<AlertService>>,
+) -> impl Responder {
+    match service
+        .update_rule(&path.into_inner(), rule.into_inner(), &actor(&req, &auth))
+        .await
+    {
+        Ok(updated) => HttpResponse::Ok().json(updated),
+        Err(e) => error_response(e),
+    }
+}
+
+#[delete("/alerts/{name}")]
+async fn delete_rule(
+    req: HttpRequest,
+    path: web::Path<String>,
+    auth: web::Data<Arc<AuthService>>,
+    service: web::Data<Arc<AlertService>>,
+) -> impl Responder {
+    match service
+        .delete_rule(&path.into_inner(), &actor(&req, &auth))
+        .await
+    {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => error_response(e),
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_rules)
+        .service(create_rule)
+        .service(evaluate)
+        .service(get_rule)
+        .service(update_rule)
+        .service(delete_rule);
+}
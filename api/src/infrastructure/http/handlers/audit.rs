@@ -0,0 +1,113 @@
+use crate::application::audit_service::{AuditParams, AuditService};
+use crate::application::auth_service::AuthService;
+use crate::domain::entities::{Actor, DomainError};
+use crate::infrastructure::http::client_ip::client_ip;
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse, get, web};
+use futures_util::StreamExt;
+use serde_json::json;
+use std::sync::Arc;
+
+fn error_response(e: DomainError) -> HttpResponse {
+    match e {
+        DomainError::ValidationError(msg) => {
+            HttpResponse::BadRequest().json(json!({ "error": msg }))
+        }
+        e => HttpResponse::InternalServerError().json(json!({ "error": e.to_string() })),
+    }
+}
+
+fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    let value = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    Some(value.strip_prefix("Bearer ").unwrap_or(value).trim())
+}
+
+/// Who is making a management request: the signed-in user, or `anonymous`
+/// without a valid token.
+pub(crate) fn actor(req: &HttpRequest, auth: &AuthService) -> Actor {
+    let id = bearer_token(req)
+        .and_then(|token| auth.verify_token(token).ok())
+        .map_or_else(|| "anonymous".to_string(), |claims| claims.sub);
+    Actor::new(id, source_ip(req))
+}
+
+/// The client address, following `X-Forwarded-For` only through trusted proxies.
+pub(crate) fn source_ip(req: &HttpRequest) -> Option<String> {
+    client_ip(req).map(|ip| ip.to_string())
+}
+
+/// The audit log names users and where they connect from, so reading it takes an
+/// admin.
+async fn require_admin(req: &HttpRequest, auth: &AuthService) -> Result<(), HttpResponse> {
+    let token = bearer_token(req).ok_or_else(|| {
+        HttpResponse::Unauthorized().json(json!({ "error": "Missing Authorization header" }))
+    })?;
+    let user = auth.get_current_user(token).await.map_err(|e| match e {
+        DomainError::Internal(_) => {
+            HttpResponse::InternalServerError().json(json!({ "error": e.to_string() }))
+        }
+        e => HttpResponse::Unauthorized().json(json!({ "error": e.to_string() })),
+    })?;
+    if user.role != "admin" {
+        return Err(
+            HttpResponse::Forbidden().json(json!({ "error": "The audit log is for admins only" }))
+        );
+    }
+    Ok(())
+}
+
+/// Entries filtered by `actor`, `action`, `target`, `from` and `to`, newest
+/// first, a page at a time.
+#[get("/audit")]
+async fn search_audit_log(
+    req: HttpRequest,
+    auth: web::Data<Arc<AuthService>>,
+    service: web::Data<Arc<AuditService>>,
+    query: web::Query<AuditParams>,
+) -> HttpResponse {
+    if let Err(response) = require_admin(&req, &auth).await {
+        return response;
+    }
+    match service.search(&query).await {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Every entry matching the same filters as JSON lines, newest first.
+#[get("/audit/export")]
+async fn export_audit_log(
+    req: HttpRequest,
+    auth: web::Data<Arc<AuthService>>,
+    service: web::Data<Arc<AuditService>>,
+    query: web::Query<AuditParams>,
+) -> HttpResponse {
+    if let Err(response) = require_admin(&req, &auth).await {
+        return response;
+    }
+    let pages = match service.export(&query) {
+        Ok(pages) => pages,
+        Err(e) => return error_response(e),
+    };
+
+    let lines = pages.map(|page| {
+        let mut chunk = String::new();
+        for entry in page.map_err(actix_web::error::ErrorInternalServerError)? {
+            chunk.push_str(&serde_json::to_string(&entry).unwrap_or_default());
+            chunk.push('\n');
+        }
+        Ok::<_, actix_web::Error>(web::Bytes::from(chunk))
+    });
+
+    HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"audit.jsonl\"",
+        ))
+        .streaming(lines)
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(search_audit_log).service(export_audit_log);
+}
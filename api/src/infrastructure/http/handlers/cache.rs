@@ -0,0 +1,52 @@
+use super::audit::actor;
+use crate::application::audit_service::AuditService;
+use crate::application::auth_service::AuthService;
+use crate::application::response_cache::ResponseCache;
+use crate::domain::entities::AuditAction;
+use actix_web::{HttpRequest, HttpResponse, Responder, delete, web};
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+struct PurgeQuery {
+    trigger: Option<String>,
+    function: Option<String>,
+}
+
+#[delete("/cache")]
+async fn purge_cache(
+    req: HttpRequest,
+    query: web::Query<PurgeQuery>,
+    cache: web::Data<Arc<ResponseCache>>,
+    auth: web::Data<Arc<AuthService>>,
+    audit: web::Data<Arc<AuditService>>,
+) -> impl Responder {
+    let query = query.into_inner();
+    if query.trigger.is_none() && query.function.is_none() {
+        return HttpResponse::BadRequest().body("Specify a trigger or a function to purge");
+    }
+
+    let mut purged = 0;
+    if let Some(trigger) = &query.trigger {
+        purged += cache.purge_trigger(trigger);
+    }
+    if let Some(function) = &query.function {
+        purged += cache.purge_function(function);
+    }
+    // Named by scope, since a trigger and a function may share a name
+    let actor = actor(&req, &auth);
+    let targets = [("trigger", &query.trigger), ("function", &query.function)];
+    for (kind, name) in targets {
+        if let Some(name) = name {
+            let target = format!("{}:{}", kind, name);
+            audit
+                .record::<()>(&actor, AuditAction::CachePurge, &target, None, None)
+                .await;
+        }
+    }
+    HttpResponse::Ok().json(serde_json::json!({ "purged": purged }))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(purge_cache);
+}
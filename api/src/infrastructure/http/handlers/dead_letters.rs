@@ -0,0 +1,113 @@
+use super::audit::actor;
+use crate::application::auth_service::AuthService;
+use crate::application::background_service::BackgroundService;
+use crate::domain::entities::DomainError;
+use actix_web::{HttpRequest, HttpResponse, Responder, delete, get, post, web};
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+struct DeadLetterQuery {
+    function: Option<String>,
+}
+
+fn error_response(e: DomainError) -> HttpResponse {
+    match e {
+        DomainError::NotFound(msg) => HttpResponse::NotFound().body(msg),
+        e => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+/// Queues an invocation and returns immediately; failures are retried per the
+/// function's retry policy and end up in `/dead-letters`.
+#[post("/functions/{name}/invocations")]
+async fn invoke_async(
+    path: web::Path<String>,
+    body: String,
+    service: web::Data<Arc<BackgroundService>>,
+) -> impl Responder {
+    match service.submit(&path.into_inner(), body, "async").await {
+        Ok(()) => HttpResponse::Accepted().finish(),
+        Err(e) => error_response(e),
+    }
+}
+
+#[get("/dead-letters")]
+async fn list_dead_letters(
+    query: web::Query<DeadLetterQuery>,
+    service: web::Data<Arc<BackgroundService>>,
+) -> impl Responder {
+    match service.list_dead_letters(query.function.as_deref()).await {
+        Ok(list) => HttpResponse::Ok().json(list),
+        Err(e) => error_response(e),
+    }
+}
+
+#[get("/dead-letters/{id}")]
+async fn get_dead_letter(
+    path: web::Path<String>,
+    service: web::Data<Arc<BackgroundService>>,
+) -> impl Responder {
+    match service.get_dead_letter(&path.into_inner()).await {
+        Ok(letter) => HttpResponse::Ok().json(letter),
+        Err(e) => error_response(e),
+    }
+}
+
+#[post("/dead-letters/{id}/redrive")]
+async fn redrive_dead_letter(
+    req: HttpRequest,
+    path: web::Path<String>,
+    auth: web::Data<Arc<AuthService>>,
+    service: web::Data<Arc<BackgroundService>>,
+) -> impl Responder {
+    match service
+        .redrive(&path.into_inner(), &actor(&req, &auth))
+        .await
+    {
+        Ok(letter) => HttpResponse::Accepted().json(letter),
+        Err(e) => error_response(e),
+    }
+}
+
+#[delete("/dead-letters/{id}")]
+async fn delete_dead_letter(
+    req: HttpRequest,
+    path: web::Path<String>,
+    auth: web::Data<Arc<AuthService>>,
+    service: web::Data<Arc<BackgroundService>>,
+) -> impl Responder {
+    match service
+        .delete_dead_letter(&path.into_inner(), &actor(&req, &auth))
+        .await
+    {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Drops every dead letter, or only those of `?function=`.
+#[delete("/dead-letters")]
+async fn purge_dead_letters(
+    req: HttpRequest,
+    query: web::Query<DeadLetterQuery>,
+    auth: web::Data<Arc<AuthService>>,
+    service: web::Data<Arc<BackgroundService>>,
+) -> impl Responder {
+    match service
+        .purge_dead_letters(query.function.as_deref(), &actor(&req, &auth))
+        .await
+    {
+        Ok(purged) => HttpResponse::Ok().json(serde_json::json!({ "purged": purged })),
+        Err(e) => error_response(e),
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(invoke_async)
+        .service(list_dead_letters)
+        .service(get_dead_letter)
+        .service(redrive_dead_letter)
+        .service(delete_dead_letter)
+        .service(purge_dead_letters);
+}
@@ -1,6 +1,9 @@
+use super::audit::actor;
+use crate::application::auth_service::AuthService;
 use crate::application::function_service::FunctionService;
 use crate::domain::entities::Function;
-use actix_web::{HttpResponse, Responder, web};
+use actix_web::{HttpRequest, HttpResponse, Responder, web};
+use std::path::Path;
 use std::sync::Arc;
 
 async fn list_functions(service: web::Data<Arc<FunctionService>>) -> impl Responder {
@@ -24,15 +27,61 @@ async fn get_function(
 }
 
 // --- Helper for handling multipart ---
+/// Reads the `function` and `file` parts, writing the upload to a temp file.
+/// Uploads are cut off as soon as they exceed `max_wasm_bytes`, and the temp
+/// file is removed on any error.
 async fn handle_multipart(
+    payload: actix_multipart::Multipart,
+    max_wasm_bytes: u64,
+) -> Result<(Option<Function>, Option<String>), HttpResponse> {
+    let mut temp_path: Option<String> = None;
+    let result = read_multipart(payload, max_wasm_bytes, &mut temp_path).await;
+    if result.is_err()
+        && let Some(path) = &temp_path
+    {
+        let _ = std::fs::remove_file(path);
+    }
+    result.map(|function| (function, temp_path))
+}
+
+/// Streams a binary part to `path`, giving up as soon as it exceeds `max_bytes`.
+pub(super) async fn save_upload(
+    field: &mut actix_multipart::Field,
+    path: &Path,
+    max_bytes: u64,
+) -> Result<(), HttpResponse> {
+    use futures_util::stream::StreamExt as _;
+    use std::io::Write;
+
+    let mut f = std::fs::File::create(path).map_err(|e| {
+        HttpResponse::InternalServerError().body(format!("Failed to create temp file: {}", e))
+    })?;
+    let mut written = 0u64;
+    while let Some(chunk) = field.next().await {
+        let data = chunk.map_err(|e| HttpResponse::BadRequest().body(e.to_string()))?;
+        written += data.len() as u64;
+        if written > max_bytes {
+            return Err(HttpResponse::UnprocessableEntity().body(format!(
+                "Wasm binary is above the limit of {} bytes",
+                max_bytes
+            )));
+        }
+        f.write_all(&data).map_err(|e| {
+            HttpResponse::InternalServerError().body(format!("Failed to write: {}", e))
+        })?;
+    }
+    Ok(())
+}
+
+async fn read_multipart(
     mut payload: actix_multipart::Multipart,
-) -> Result<(Option<Function>, Option<String>), String> {
+    max_wasm_bytes: u64,
+    temp_path: &mut Option<String>,
+) -> Result<Option<Function>, HttpResponse> {
     use futures_util::TryStreamExt;
     use futures_util::stream::StreamExt as _;
-    use std::io::Write;
 
     let mut function: Option<Function> = None;
-    let mut temp_path: Option<String> = None;
 
     while let Ok(Some(mut field)) = payload.try_next().await {
         let content_disposition = field.content_disposition();
@@ -41,128 +90,183 @@ async fn handle_multipart(
                 // Read JSON part
                 let mut bytes = Vec::new();
                 while let Some(chunk) = field.next().await {
-                    let data = chunk.map_err(|e| e.to_string())?;
+                    let data = chunk.map_err(|e| HttpResponse::BadRequest().body(e.to_string()))?;
                     bytes.extend_from_slice(&data);
                 }
-                let f: Function =
-                    serde_json::from_slice(&bytes).map_err(|e| format!("Invalid JSON: {}", e))?;
+                let f: Function = serde_json::from_slice(&bytes)
+                    .map_err(|e| HttpResponse::BadRequest().body(format!("Invalid JSON: {}", e)))?;
                 function = Some(f);
             } else if field_name == "file" {
                 // Read binary part
-                let path = format!("/tmp/{}.wasm", uuid::Uuid::new_v4());
-                let mut f = std::fs::File::create(&path)
-                    .map_err(|e| format!("Failed to create temp file: {}", e))?;
-                while let Some(chunk) = field.next().await {
-                    let data = chunk.map_err(|e| e.to_string())?;
-                    f.write_all(&data)
-                        .map_err(|e| format!("Failed to write: {}", e))?;
-                }
-                temp_path = Some(path);
+                let path = temp_path
+                    .get_or_insert_with(|| format!("/tmp/{}.wasm", uuid::Uuid::new_v4()))
+                    .clone();
+                save_upload(&mut field, Path::new(&path), max_wasm_bytes).await?;
             }
         }
     }
 
-    Ok((function, temp_path))
+    Ok(function)
 }
 
 // --- Handlers ---
 
 async fn create_function_json(
+    req: HttpRequest,
     func: web::Json<Function>,
+    auth: web::Data<Arc<AuthService>>,
     service: web::Data<Arc<FunctionService>>,
 ) -> impl Responder {
-    match service.create_function(func.into_inner()).await {
+    match service
+        .create_function(func.into_inner(), &actor(&req, &auth))
+        .await
+    {
         Ok(created) => HttpResponse::Created().json(created),
         Err(crate::domain::entities::DomainError::AlreadyExists(msg)) => {
             HttpResponse::Conflict().body(msg)
         }
+        Err(crate::domain::entities::DomainError::ValidationError(msg)) => {
+            HttpResponse::UnprocessableEntity().body(msg)
+        }
         Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
     }
 }
 
 async fn create_function_multipart(
+    req: HttpRequest,
     payload: actix_multipart::Multipart,
+    auth: web::Data<Arc<AuthService>>,
     service: web::Data<Arc<FunctionService>>,
 ) -> impl Responder {
-    match handle_multipart(payload).await {
+    match handle_multipart(payload, service.max_wasm_bytes()).await {
         Ok((Some(mut func), temp_path)) => {
-            if let Some(path) = temp_path {
-                func.executable = path; // Service will handle copy
+            if let Some(path) = &temp_path {
+                func.executable = path.clone(); // Service will handle copy
+            }
+            let result = service.create_function(func, &actor(&req, &auth)).await;
+            // The service keeps its own copy of the upload
+            if let Some(path) = &temp_path {
+                let _ = std::fs::remove_file(path);
             }
-            match service.create_function(func).await {
-                Ok(created) => {
-                    // Cleanup temp if it became executable (service copied it)
-                    if let Some(_path) = created
-                        .executable
-                        .strip_suffix(".wasm")
-                        .and_then(|_| created.executable.lines().next())
-                    {
-                        // The saved path is destination. We need to clean SOURCE temp path.
-                        // Wait, handle_multipart returns temp_path.
-                        // We set func.executable = temp_path.
-                        // Service copied from func.executable to storage.
-                        // So temp_path is now garbage.
-                    }
-                    // Just clean generic pattern assuming service stored it
-                    // We don't have easy handle to exact temp path here unless we kept it separate variable...
-                    // Wait, we have `temp_path` variable in scope if we unwrap properly.
-                    // Let's refactor loop to clear.
-                    HttpResponse::Created().json(created)
+            match result {
+                Ok(created) => HttpResponse::Created().json(created),
+                Err(crate::domain::entities::DomainError::AlreadyExists(msg)) => {
+                    HttpResponse::Conflict().body(msg)
+                }
+                Err(crate::domain::entities::DomainError::ValidationError(msg)) => {
+                    HttpResponse::UnprocessableEntity().body(msg)
                 }
                 Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
             }
         }
-        Ok((None, _)) => HttpResponse::BadRequest().body("Missing 'function' field in multipart"),
-        Err(e) => HttpResponse::BadRequest().body(e),
+        Ok((None, temp_path)) => {
+            if let Some(path) = &temp_path {
+                let _ = std::fs::remove_file(path);
+            }
+            HttpResponse::BadRequest().body("Missing 'function' field in multipart")
+        }
+        Err(resp) => resp,
     }
 }
 
 async fn update_function_json(
+    req: HttpRequest,
     path: web::Path<String>,
     func: web::Json<Function>,
+    auth: web::Data<Arc<AuthService>>,
     service: web::Data<Arc<FunctionService>>,
 ) -> impl Responder {
     let mut f = func.into_inner();
     f.name = path.into_inner();
-    match service.update_function(f).await {
+    match service.update_function(f, &actor(&req, &auth)).await {
         Ok(updated) => HttpResponse::Ok().json(updated),
         Err(crate::domain::entities::DomainError::NotFound(msg)) => {
             HttpResponse::NotFound().body(msg)
         }
+        Err(crate::domain::entities::DomainError::ValidationError(msg)) => {
+            HttpResponse::UnprocessableEntity().body(msg)
+        }
         Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
     }
 }
 
 async fn update_function_multipart(
+    req: HttpRequest,
     path: web::Path<String>,
     payload: actix_multipart::Multipart,
+    auth: web::Data<Arc<AuthService>>,
     service: web::Data<Arc<FunctionService>>,
 ) -> impl Responder {
-    match handle_multipart(payload).await {
+    match handle_multipart(payload, service.max_wasm_bytes()).await {
         Ok((Some(mut func), temp_path)) => {
             func.name = path.into_inner();
-            if let Some(path) = temp_path {
-                func.executable = path;
+            if let Some(path) = &temp_path {
+                func.executable = path.clone();
+            }
+            let result = service.update_function(func, &actor(&req, &auth)).await;
+            if let Some(path) = &temp_path {
+                let _ = std::fs::remove_file(path);
             }
-            match service.update_function(func).await {
+            match result {
                 Ok(updated) => HttpResponse::Ok().json(updated),
+                Err(crate::domain::entities::DomainError::NotFound(msg)) => {
+                    HttpResponse::NotFound().body(msg)
+                }
+                Err(crate::domain::entities::DomainError::ValidationError(msg)) => {
+                    HttpResponse::UnprocessableEntity().body(msg)
+                }
                 Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
             }
         }
-        Ok((None, _)) => HttpResponse::BadRequest().body("Missing 'function' field"),
-        Err(e) => HttpResponse::BadRequest().body(e),
+        Ok((None, temp_path)) => {
+            if let Some(path) = &temp_path {
+                let _ = std::fs::remove_file(path);
+            }
+            HttpResponse::BadRequest().body("Missing 'function' field")
+        }
+        Err(resp) => resp,
     }
 }
 
 async fn delete_function(
+    req: HttpRequest,
     path: web::Path<String>,
+    auth: web::Data<Arc<AuthService>>,
     service: web::Data<Arc<FunctionService>>,
 ) -> impl Responder {
-    match service.delete_function(&path.into_inner()).await {
+    match service
+        .delete_function(&path.into_inner(), &actor(&req, &auth))
+        .await
+    {
         Ok(_) => HttpResponse::Ok().finish(),
         Err(crate::domain::entities::DomainError::NotFound(msg)) => {
             HttpResponse::NotFound().body(msg)
         }
+        // Readonly, or still the target of a trigger
+        Err(crate::domain::entities::DomainError::ValidationError(msg)) => {
+            HttpResponse::Conflict().body(msg)
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+async fn rollback_function(
+    req: HttpRequest,
+    path: web::Path<String>,
+    auth: web::Data<Arc<AuthService>>,
+    service: web::Data<Arc<FunctionService>>,
+) -> impl Responder {
+    match service
+        .rollback_function(&path.into_inner(), &actor(&req, &auth))
+        .await
+    {
+        Ok(restored) => HttpResponse::Ok().json(restored),
+        Err(crate::domain::entities::DomainError::NotFound(msg)) => {
+            HttpResponse::NotFound().body(msg)
+        }
+        Err(crate::domain::entities::DomainError::ValidationError(msg)) => {
+            HttpResponse::UnprocessableEntity().body(msg)
+        }
         Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
     }
 }
@@ -190,5 +294,6 @@ pub fn config(cfg: &mut web::ServiceConfig) {
                     .to(update_function_json),
             )
             .route(web::put().to(update_function_multipart)),
-    );
+    )
+    .service(web::resource("/functions/{name}/rollback").route(web::post().to(rollback_function)));
 }
@@ -1,20 +1,248 @@
-use crate::application::invocation_service::InvocationService;
-use actix_web::{HttpRequest, HttpResponse, Responder, web};
+use crate::application::invocation_service::{InvocationService, RoutePolicy};
+use crate::application::rate_limit_service::{Caller, RateLimitDecision, RateLimitService};
+use crate::application::response_cache::{CachedResponse, ResponseCache};
+use crate::application::workflow_service::WorkflowService;
+use crate::infrastructure::http::client_ip::client_ip;
+use crate::infrastructure::telemetry::extract_trace_context;
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::http::{Method, header};
+use actix_web::{HttpRequest, HttpResponse, HttpResponseBuilder, Responder, web};
+use opentelemetry::trace::{TraceContextExt, TraceId};
 use std::sync::Arc;
+use std::time::Duration;
+use tracing::{Instrument, warn};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
+/// Response header naming the trace an invocation was recorded under.
+pub const TRACE_ID_HEADER: HeaderName = HeaderName::from_static("x-trace-id");
+
+/// Hint sent with 429/503 responses; slots free up as soon as invocations finish.
+pub const RETRY_AFTER_SECS: u64 = 1;
+
+fn header_value(req: &HttpRequest, name: &str) -> Option<String> {
+    req.headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+fn caller(req: &HttpRequest) -> Caller {
+    Caller {
+        ip: client_ip(req).map_or_else(|| "unknown".to_string(), |ip| ip.to_string()),
+        bearer_token: header_value(req, header::AUTHORIZATION.as_str())
+            .and_then(|v| v.strip_prefix("Bearer ").map(str::to_string)),
+    }
+}
+
+/// Adds the IETF `RateLimit-*` headers describing the caller's bucket.
+fn rate_limit_headers(builder: &mut HttpResponseBuilder, decision: &RateLimitDecision) {
+    builder
+        .insert_header(("RateLimit-Limit", decision.limit))
+        .insert_header(("RateLimit-Remaining", decision.remaining))
+        .insert_header(("RateLimit-Reset", decision.reset_secs));
+}
+
+/// Returns whether the request's `Cache-Control` carries `directive`.
+fn cache_control_has(req: &HttpRequest, directive: &str) -> bool {
+    header_value(req, header::CACHE_CONTROL.as_str()).is_some_and(|v| {
+        v.split(',')
+            .any(|d| d.trim().eq_ignore_ascii_case(directive))
+    })
+}
+
+/// Cache key of the request, when its route caches responses and the request may use it.
+fn cache_key(req: &HttpRequest, body: &str, policy: &RoutePolicy) -> Option<String> {
+    let cache = policy.cache.as_ref()?;
+    if req.method() != Method::GET || !body.is_empty() || cache_control_has(req, "no-store") {
+        return None;
+    }
+
+    let values: Vec<(&str, &str)> = cache
+        .vary_headers
+        .iter()
+        .map(|name| {
+            let value = req
+                .headers()
+                .get(name.as_str())
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or("");
+            (name.as_str(), value)
+        })
+        .collect();
+
+    Some(ResponseCache::key(
+        &policy.trigger,
+        req.method().as_str(),
+        req.path(),
+        req.query_string(),
+        &values,
+    ))
+}
+
+fn cached_response(
+    req: &HttpRequest,
+    cached: CachedResponse,
+    status: &str,
+    decision: Option<&RateLimitDecision>,
+) -> HttpResponse {
+    let not_modified = header_value(req, header::IF_NONE_MATCH.as_str()).is_some_and(|v| {
+        v.split(',')
+            .any(|tag| tag.trim() == "*" || tag.trim().trim_start_matches("W/") == cached.etag)
+    });
+
+    let mut response = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .insert_header((header::ETAG, cached.etag))
+        .insert_header((
+            header::CACHE_CONTROL,
+            format!("public, max-age={}", cached.max_age),
+        ))
+        .insert_header((header::AGE, cached.age))
+        .insert_header(("X-Cache", status));
+    if let Some(d) = decision {
+        rate_limit_headers(&mut response, d);
+    }
+
+    if not_modified {
+        response.finish()
+    } else {
+        response.body(cached.body)
+    }
+}
+
+/// Serves the request inside a span continuing the caller's `traceparent`, and
+/// reports the trace it ended up in through `X-Trace-Id`.
 pub async fn gateway(
     req: HttpRequest,
     body: String,
     service: web::Data<Arc<InvocationService>>,
+    rate_limiter: web::Data<Arc<RateLimitService>>,
+    cache: web::Data<Arc<ResponseCache>>,
+    workflows: web::Data<Arc<WorkflowService>>,
 ) -> impl Responder {
+    let span = tracing::info_span!(
+        "gateway",
+        otel.kind = "server",
+        http.request.method = %req.method(),
+        url.path = req.path(),
+    );
+    // Without an OpenTelemetry layer there is no trace to continue
+    let _ = span.set_parent(extract_trace_context(req.headers()));
+
+    let trace_id = span.context().span().span_context().trace_id();
+    let mut response = route(req, body, service, rate_limiter, cache, workflows)
+        .instrument(span)
+        .await;
+    if trace_id != TraceId::INVALID
+        && let Ok(value) = HeaderValue::from_str(&trace_id.to_string())
+    {
+        response.headers_mut().insert(TRACE_ID_HEADER, value);
+    }
+    response
+}
+
+async fn route(
+    req: HttpRequest,
+    body: String,
+    service: web::Data<Arc<InvocationService>>,
+    rate_limiter: web::Data<Arc<RateLimitService>>,
+    cache: web::Data<Arc<ResponseCache>>,
+    workflows: web::Data<Arc<WorkflowService>>,
+) -> HttpResponse {
     let method = req.method().as_str();
     let path = req.path().strip_prefix("/function").unwrap_or(req.path());
+    let policy = service.route_policy(method, path);
 
-    match service.invoke_http(method, path, &body).await {
-        Ok(res) => HttpResponse::Ok().body(res),
-        Err(crate::domain::entities::DomainError::NotFound(_)) => {
-            HttpResponse::NotFound().body("Function route not found")
+    let mut decision = None;
+    if let Some(policy) = &policy
+        && let Some(limit) = &policy.rate_limit
+    {
+        match rate_limiter
+            .check(&policy.trigger, limit, &caller(&req))
+            .await
+        {
+            Ok(d) if !d.allowed => {
+                let mut response = HttpResponse::TooManyRequests();
+                rate_limit_headers(&mut response, &d);
+                return response
+                    .insert_header((header::RETRY_AFTER, d.retry_after_secs.max(1)))
+                    .body("Rate limit exceeded");
+            }
+            Ok(d) => decision = Some(d),
+            // Don't take the route down with the limiter's backing store
+            Err(e) => warn!(
+                trigger = policy.trigger,
+                "Rate limit check failed, allowing request: {}", e
+            ),
         }
-        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
     }
+
+    if let Some(workflow) = service.resolve_workflow(method, path) {
+        let (mut response, run) = match workflows.start(&workflow, &body).await {
+            Ok(run) => {
+                let mut response = HttpResponse::Accepted();
+                response.insert_header((
+                    header::LOCATION,
+                    format!("/workflows/{}/runs/{}", workflow, run.id),
+                ));
+                (response, Ok(run))
+            }
+            Err(crate::domain::entities::DomainError::NotFound(msg)) => {
+                (HttpResponse::NotFound(), Err(msg))
+            }
+            Err(e) => (HttpResponse::InternalServerError(), Err(e.to_string())),
+        };
+        if let Some(d) = &decision {
+            rate_limit_headers(&mut response, d);
+        }
+        return match run {
+            Ok(run) => response.json(run),
+            Err(msg) => response.body(msg),
+        };
+    }
+
+    let cache_key = policy
+        .as_ref()
+        .and_then(|p| cache_key(&req, &body, p).map(|key| (key, p)));
+    if let Some((key, _)) = &cache_key
+        && !cache_control_has(&req, "no-cache")
+        && let Some(cached) = cache.get(key)
+    {
+        return cached_response(&req, cached, "HIT", decision.as_ref());
+    }
+
+    let (mut response, body) = match service.invoke_http(method, path, &body).await {
+        Ok(res) => match (cache_key, policy.as_ref().and_then(|p| p.cache.as_ref())) {
+            (Some((key, policy)), Some(cache_policy)) => {
+                let ttl = Duration::from_secs(cache_policy.ttl_secs as u64);
+                let cached = cache.insert(key, &policy.trigger, &policy.function, res, ttl);
+                return cached_response(&req, cached, "MISS", decision.as_ref());
+            }
+            _ => (HttpResponse::Ok(), res),
+        },
+        Err(crate::domain::entities::DomainError::NotFound(_)) => (
+            HttpResponse::NotFound(),
+            "Function route not found".to_string(),
+        ),
+        Err(crate::domain::entities::DomainError::LimitExceeded(msg)) => {
+            let mut response = HttpResponse::TooManyRequests();
+            response.insert_header((header::RETRY_AFTER, RETRY_AFTER_SECS));
+            (response, msg)
+        }
+        Err(crate::domain::entities::DomainError::Overloaded(msg)) => {
+            let mut response = HttpResponse::ServiceUnavailable();
+            response.insert_header((header::RETRY_AFTER, RETRY_AFTER_SECS));
+            (response, msg)
+        }
+        Err(e) => (HttpResponse::InternalServerError(), e.to_string()),
+    };
+
+    if let Some(d) = &decision {
+        rate_limit_headers(&mut response, d);
+    }
+    response.body(body)
 }
@@ -0,0 +1,133 @@
+use super::audit::actor;
+use super::functions::save_upload;
+use crate::application::auth_service::AuthService;
+use crate::application::manifest_service::{Artifacts, Manifest, ManifestFormat, ManifestService};
+use crate::domain::entities::DomainError;
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse, Responder, post, web};
+use std::sync::Arc;
+
+/// Largest manifest accepted, not counting the artifacts uploaded with it.
+const MAX_MANIFEST_BYTES: usize = 256 * 1024;
+
+fn parse(body: &[u8], content_type: &str) -> Result<Manifest, HttpResponse> {
+    let source = std::str::from_utf8(body)
+        .map_err(|_| HttpResponse::BadRequest().body("Manifest is not valid UTF-8"))?;
+    Manifest::parse(source, ManifestFormat::from_content_type(content_type)).map_err(error_response)
+}
+
+/// Reads the manifest from the body, in the format named by `Content-Type`. A
+/// `multipart/form-data` body instead carries it in a `manifest` part, next to
+/// `artifact` parts whose file names are the `artifact` values they provide.
+async fn read(
+    req: &HttpRequest,
+    payload: web::Payload,
+    max_artifact_bytes: u64,
+) -> Result<(Manifest, Artifacts), HttpResponse> {
+    use futures_util::TryStreamExt;
+    use futures_util::stream::StreamExt as _;
+
+    let content_type = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    if !content_type.starts_with("multipart/form-data") {
+        let body = payload
+            .to_bytes_limited(MAX_MANIFEST_BYTES)
+            .await
+            .map_err(|_| HttpResponse::PayloadTooLarge().body("Manifest is too large"))?
+            .map_err(|e| HttpResponse::BadRequest().body(e.to_string()))?;
+        return Ok((parse(&body, content_type)?, Artifacts::default()));
+    }
+
+    let mut multipart = actix_multipart::Multipart::new(req.headers(), payload);
+    let mut manifest = None;
+    let mut artifacts = Artifacts::default();
+    while let Some(mut field) = multipart
+        .try_next()
+        .await
+        .map_err(|e| HttpResponse::BadRequest().body(e.to_string()))?
+    {
+        let content_disposition = field.content_disposition().clone();
+        match content_disposition.get_name() {
+            Some("manifest") => {
+                let format = field
+                    .content_type()
+                    .map(|mime| mime.to_string())
+                    .unwrap_or_default();
+                let mut body = Vec::new();
+                while let Some(chunk) = field.next().await {
+                    let data = chunk.map_err(|e| HttpResponse::BadRequest().body(e.to_string()))?;
+                    if body.len() + data.len() > MAX_MANIFEST_BYTES {
+                        return Err(HttpResponse::PayloadTooLarge().body("Manifest is too large"));
+                    }
+                    body.extend_from_slice(&data);
+                }
+                manifest = Some(parse(&body, &format)?);
+            }
+            Some("artifact") => {
+                let name = content_disposition.get_filename().ok_or_else(|| {
+                    HttpResponse::BadRequest().body("Artifact part is missing its file name")
+                })?;
+                let path = artifacts.create(name).map_err(error_response)?;
+                save_upload(&mut field, &path, max_artifact_bytes).await?;
+            }
+            _ => {}
+        }
+    }
+
+    let manifest = manifest
+        .ok_or_else(|| HttpResponse::BadRequest().body("Missing 'manifest' field in multipart"))?;
+    Ok((manifest, artifacts))
+}
+
+fn error_response(e: DomainError) -> HttpResponse {
+    match e {
+        DomainError::ValidationError(msg) => HttpResponse::UnprocessableEntity().body(msg),
+        DomainError::AlreadyExists(msg) => HttpResponse::Conflict().body(msg),
+        DomainError::NotFound(msg) => HttpResponse::NotFound().body(msg),
+        e => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+/// Dry run: returns the changes `/apply` would make for the manifest in the body.
+#[post("/plan")]
+async fn plan_manifest(
+    req: HttpRequest,
+    payload: web::Payload,
+    service: web::Data<Arc<ManifestService>>,
+) -> impl Responder {
+    let (manifest, artifacts) = match read(&req, payload, service.max_artifact_bytes()).await {
+        Ok(read) => read,
+        Err(resp) => return resp,
+    };
+    match service.plan(&manifest, &artifacts).await {
+        Ok(plan) => HttpResponse::Ok().json(plan),
+        Err(e) => error_response(e),
+    }
+}
+
+#[post("/apply")]
+async fn apply_manifest(
+    req: HttpRequest,
+    payload: web::Payload,
+    auth: web::Data<Arc<AuthService>>,
+    service: web::Data<Arc<ManifestService>>,
+) -> impl Responder {
+    let (manifest, artifacts) = match read(&req, payload, service.max_artifact_bytes()).await {
+        Ok(read) => read,
+        Err(resp) => return resp,
+    };
+    match service
+        .apply(&manifest, &artifacts, &actor(&req, &auth))
+        .await
+    {
+        Ok(plan) => HttpResponse::Ok().json(plan),
+        Err(e) => error_response(e),
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(plan_manifest).service(apply_manifest);
+}
@@ -0,0 +1,19 @@
+use crate::infrastructure::prometheus::{CONTENT_TYPE, PrometheusExporter};
+use actix_web::{HttpResponse, Responder, get, web};
+use tracing::error;
+
+#[get("/metrics")]
+async fn metrics(exporter: web::Data<PrometheusExporter>) -> impl Responder {
+    match exporter.render() {
+        Ok(text) => HttpResponse::Ok().content_type(CONTENT_TYPE).body(text),
+        Err(e) => {
+            error!("Failed to collect metrics: {}", e);
+            HttpResponse::InternalServerError().body("Failed to collect metrics")
+        }
+    }
+}
+
+/// Serves `exporter` for Prometheus to scrape.
+pub fn config(cfg: &mut web::ServiceConfig, exporter: PrometheusExporter) {
+    cfg.app_data(web::Data::new(exporter)).service(metrics);
+}
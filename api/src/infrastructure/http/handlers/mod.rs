@@ -1,6 +1,14 @@
+pub mod alerts;
+pub mod audit;
 pub mod auth;
+pub mod cache;
+pub mod dead_letters;
 pub mod functions;
 pub mod gateway;
+pub mod manifest;
+pub mod metrics;
 pub mod telemetry;
 pub mod triggers;
 pub mod users;
+pub mod websocket;
+pub mod workflows;
@@ -1,42 +1,166 @@
-use crate::application::telemetry_service::TelemetryService;
+use crate::application::telemetry_service::{
+    LogParams, RangeParams, TelemetryService, TopParams, TraceParams,
+};
+use crate::domain::entities::DomainError;
 use actix_web::{HttpResponse, web};
+use futures_util::StreamExt;
 use serde_json::json;
 
+fn error_response(e: DomainError) -> HttpResponse {
+    match e {
+        DomainError::ValidationError(msg) => {
+            HttpResponse::BadRequest().json(json!({ "error": msg }))
+        }
+        DomainError::NotFound(msg) => HttpResponse::NotFound().json(json!({ "error": msg })),
+        e => HttpResponse::InternalServerError().json(json!({ "error": e.to_string() })),
+    }
+}
+
+/// Invocation counts of a function over `?from=&to=&step=`, one entry per bucket.
 pub async fn get_function_metrics(
     service: web::Data<TelemetryService>,
     path: web::Path<String>,
+    query: web::Query<RangeParams>,
 ) -> HttpResponse {
     let function_name = path.into_inner();
 
-    match service.get_function_metrics(&function_name).await {
+    match service.get_function_metrics(&function_name, &query).await {
         Ok(metrics) => HttpResponse::Ok().json(metrics),
-        Err(e) => HttpResponse::InternalServerError().json(json!({ "error": e })),
+        Err(e) => error_response(e),
     }
 }
 
 pub async fn get_function_logs(
     service: web::Data<TelemetryService>,
     path: web::Path<String>,
+    query: web::Query<LogParams>,
 ) -> HttpResponse {
     let function_name = path.into_inner();
 
-    match service.get_function_logs(&function_name).await {
+    match service.get_function_logs(&function_name, &query).await {
         Ok(logs) => HttpResponse::Ok().json(logs),
-        Err(e) => HttpResponse::InternalServerError().json(json!({ "error": e })),
+        Err(e) => error_response(e),
     }
 }
 
-pub async fn get_recent_logs(service: web::Data<TelemetryService>) -> HttpResponse {
-    match service.get_recent_logs().await {
+pub async fn get_recent_logs(
+    service: web::Data<TelemetryService>,
+    query: web::Query<LogParams>,
+) -> HttpResponse {
+    match service.get_recent_logs(&query).await {
         Ok(logs) => HttpResponse::Ok().json(logs),
-        Err(e) => HttpResponse::InternalServerError().json(json!({ "error": e })),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Log lines filtered by `function`, `level`, `contains`, `trace_id`, `from` and
+/// `to`, a page at a time.
+pub async fn search_logs(
+    service: web::Data<TelemetryService>,
+    query: web::Query<LogParams>,
+) -> HttpResponse {
+    match service.search_logs(&query).await {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Tails `?function=` as Server-Sent Events, one `data:` event per log line.
+pub async fn stream_logs(
+    service: web::Data<TelemetryService>,
+    query: web::Query<LogParams>,
+) -> HttpResponse {
+    let tail = match service.tail_logs(&query) {
+        Ok(tail) => tail,
+        Err(e) => return error_response(e),
+    };
+
+    let events = tail.map(|entries| {
+        // Comments keep proxies from closing a quiet stream
+        let mut chunk = if entries.is_empty() {
+            ": keep-alive\n\n".to_string()
+        } else {
+            String::new()
+        };
+        for entry in entries {
+            let data = serde_json::to_string(&entry).unwrap_or_default();
+            chunk.push_str(&format!("data: {}\n\n", data));
+        }
+        Ok::<_, actix_web::Error>(web::Bytes::from(chunk))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(events)
+}
+
+pub async fn get_overall_metrics(
+    service: web::Data<TelemetryService>,
+    query: web::Query<RangeParams>,
+) -> HttpResponse {
+    match service.get_overall_metrics(&query).await {
+        Ok(metrics) => HttpResponse::Ok().json(metrics),
+        Err(e) => error_response(e),
+    }
+}
+
+/// p50/p90/p99 latency, error rate and throughput of a function per bucket.
+pub async fn get_function_performance(
+    service: web::Data<TelemetryService>,
+    path: web::Path<String>,
+    query: web::Query<RangeParams>,
+) -> HttpResponse {
+    match service
+        .get_function_performance(&path.into_inner(), &query)
+        .await
+    {
+        Ok(metrics) => HttpResponse::Ok().json(metrics),
+        Err(e) => error_response(e),
     }
 }
 
-pub async fn get_overall_metrics(service: web::Data<TelemetryService>) -> HttpResponse {
-    match service.get_overall_metrics().await {
+pub async fn get_overall_performance(
+    service: web::Data<TelemetryService>,
+    query: web::Query<RangeParams>,
+) -> HttpResponse {
+    match service.get_overall_performance(&query).await {
         Ok(metrics) => HttpResponse::Ok().json(metrics),
-        Err(e) => HttpResponse::InternalServerError().json(json!({ "error": e })),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Busiest, most failing or slowest functions: `?by=invocations|errors|latency&limit=`.
+pub async fn get_top_functions(
+    service: web::Data<TelemetryService>,
+    query: web::Query<TopParams>,
+) -> HttpResponse {
+    match service.get_top_functions(&query).await {
+        Ok(functions) => HttpResponse::Ok().json(functions),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Every span of a trace, nested by parent.
+pub async fn get_trace(
+    service: web::Data<TelemetryService>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    match service.get_trace(&path.into_inner()).await {
+        Ok(trace) => HttpResponse::Ok().json(trace),
+        Err(e) => error_response(e),
+    }
+}
+
+/// The function's slowest (`?filter=slowest`) or erroring (`?filter=errors`) traces.
+pub async fn list_function_traces(
+    service: web::Data<TelemetryService>,
+    path: web::Path<String>,
+    query: web::Query<TraceParams>,
+) -> HttpResponse {
+    match service.list_traces(&path.into_inner(), &query).await {
+        Ok(traces) => HttpResponse::Ok().json(traces),
+        Err(e) => error_response(e),
     }
 }
 
@@ -47,8 +171,24 @@ pub fn config(cfg: &mut web::ServiceConfig) {
                 "/functions/{name}/metrics",
                 web::get().to(get_function_metrics),
             )
+            .route("/functions/top", web::get().to(get_top_functions))
+            .route(
+                "/functions/{name}/performance",
+                web::get().to(get_function_performance),
+            )
             .route("/metrics/overall", web::get().to(get_overall_metrics))
+            .route(
+                "/performance/overall",
+                web::get().to(get_overall_performance),
+            )
             .route("/functions/{name}/logs", web::get().to(get_function_logs))
-            .route("/logs", web::get().to(get_recent_logs)),
+            .route(
+                "/functions/{name}/traces",
+                web::get().to(list_function_traces),
+            )
+            .route("/traces/{trace_id}", web::get().to(get_trace))
+            .route("/logs", web::get().to(get_recent_logs))
+            .route("/logs/search", web::get().to(search_logs))
+            .route("/logs/stream", web::get().to(stream_logs)),
     );
 }
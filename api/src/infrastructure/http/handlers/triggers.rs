@@ -1,6 +1,8 @@
+use super::audit::actor;
+use crate::application::auth_service::AuthService;
 use crate::application::trigger_service::TriggerService;
 use crate::domain::entities::Trigger;
-use actix_web::{HttpResponse, Responder, delete, get, post, web};
+use actix_web::{HttpRequest, HttpResponse, Responder, delete, get, post, web};
 use std::sync::Arc;
 
 #[get("/triggers")]
@@ -13,21 +15,37 @@ async fn list_triggers(service: web::Data<Arc<TriggerService>>) -> impl Responde
 
 #[post("/triggers")]
 async fn create_trigger(
+    req: HttpRequest,
     trigger: web::Json<Trigger>,
+    auth: web::Data<Arc<AuthService>>,
     service: web::Data<Arc<TriggerService>>,
 ) -> impl Responder {
-    match service.create_trigger(trigger.into_inner()).await {
+    match service
+        .create_trigger(trigger.into_inner(), &actor(&req, &auth))
+        .await
+    {
         Ok(created) => HttpResponse::Created().json(created),
+        Err(crate::domain::entities::DomainError::ValidationError(msg)) => {
+            HttpResponse::BadRequest().body(msg)
+        }
+        Err(crate::domain::entities::DomainError::AlreadyExists(msg)) => {
+            HttpResponse::Conflict().body(msg)
+        }
         Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
     }
 }
 
 #[delete("/triggers/{name}")]
 async fn delete_trigger(
+    req: HttpRequest,
     path: web::Path<String>,
+    auth: web::Data<Arc<AuthService>>,
     service: web::Data<Arc<TriggerService>>,
 ) -> impl Responder {
-    match service.delete_trigger(&path.into_inner()).await {
+    match service
+        .delete_trigger(&path.into_inner(), &actor(&req, &auth))
+        .await
+    {
         Ok(_) => HttpResponse::Ok().finish(),
         Err(crate::domain::entities::DomainError::NotFound(msg)) => {
             HttpResponse::NotFound().body(msg)
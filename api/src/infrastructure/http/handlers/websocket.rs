@@ -0,0 +1,112 @@
+use crate::application::websocket_service::WebSocketService;
+use crate::infrastructure::http::handlers::gateway::RETRY_AFTER_SECS;
+use actix_web::guard::GuardContext;
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse, web};
+use actix_ws::{AggregatedMessage, CloseCode, CloseReason};
+use std::sync::Arc;
+use tracing::warn;
+
+/// Matches requests asking to upgrade the connection to a WebSocket.
+pub fn is_upgrade(ctx: &GuardContext) -> bool {
+    ctx.head()
+        .headers()
+        .get(header::UPGRADE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("websocket"))
+}
+
+pub async fn websocket(
+    req: HttpRequest,
+    body: web::Payload,
+    service: web::Data<Arc<WebSocketService>>,
+) -> actix_web::Result<HttpResponse> {
+    let path = req.path().strip_prefix("/function").unwrap_or(req.path());
+
+    // Validate the handshake before running any guest code
+    let (response, mut session, stream) = actix_ws::handle(&req, body)?;
+
+    let (mut connection, mut outbound) = match service.connect(path).await {
+        Ok(c) => c,
+        Err(crate::domain::entities::DomainError::NotFound(_)) => {
+            return Ok(HttpResponse::NotFound().body("Function route not found"));
+        }
+        Err(crate::domain::entities::DomainError::LimitExceeded(msg)) => {
+            return Ok(HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, RETRY_AFTER_SECS))
+                .body(msg));
+        }
+        Err(crate::domain::entities::DomainError::Overloaded(msg)) => {
+            return Ok(HttpResponse::ServiceUnavailable()
+                .insert_header((header::RETRY_AFTER, RETRY_AFTER_SECS))
+                .body(msg));
+        }
+        Err(e) => return Ok(HttpResponse::InternalServerError().body(e.to_string())),
+    };
+
+    let limits = service.limits().clone();
+    let mut stream = stream
+        .max_frame_size(limits.max_message_bytes)
+        .aggregate_continuations()
+        .max_continuation_size(limits.max_message_bytes);
+
+    actix_web::rt::spawn(async move {
+        let close = |code: CloseCode, description: &str| {
+            Some(CloseReason {
+                code,
+                description: Some(description.to_string()),
+            })
+        };
+
+        let reason = loop {
+            if connection.is_closed() {
+                break close(CloseCode::Normal, "closed by function");
+            }
+            if connection.is_lagging() {
+                break close(CloseCode::Policy, "too slow to keep up");
+            }
+
+            tokio::select! {
+                msg = tokio::time::timeout(limits.idle_timeout, stream.recv()) => match msg {
+                    Err(_) => break close(CloseCode::Away, "idle timeout"),
+                    Ok(None) | Ok(Some(Ok(AggregatedMessage::Close(_)))) => break None,
+                    Ok(Some(Err(e))) => {
+                        warn!(connection_id = connection.id, "WebSocket protocol error: {}", e);
+                        break close(CloseCode::Protocol, "protocol error");
+                    }
+                    Ok(Some(Ok(AggregatedMessage::Text(text)))) => {
+                        if connection.on_message(&text).await.is_err() {
+                            break close(CloseCode::Error, "function error");
+                        }
+                    }
+                    Ok(Some(Ok(AggregatedMessage::Binary(_)))) => {
+                        break close(CloseCode::Unsupported, "binary messages are not supported");
+                    }
+                    Ok(Some(Ok(AggregatedMessage::Ping(bytes)))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            break None;
+                        }
+                    }
+                    Ok(Some(Ok(AggregatedMessage::Pong(_)))) => {}
+                },
+                Some(text) = outbound.recv() => {
+                    if session.text(text).await.is_err() {
+                        break None;
+                    }
+                }
+            }
+        };
+
+        connection.disconnect().await;
+
+        // Flush replies queued by the last callbacks before closing
+        while let Ok(text) = outbound.try_recv() {
+            if session.text(text).await.is_err() {
+                return;
+            }
+        }
+        let _ = session.close(reason).await;
+    });
+
+    Ok(response)
+}
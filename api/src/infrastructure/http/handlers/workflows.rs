@@ -0,0 +1,143 @@
+use super::audit::actor;
+use crate::application::auth_service::AuthService;
+use crate::application::workflow_service::WorkflowService;
+use crate::domain::entities::{DomainError, Workflow};
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse, Responder, delete, get, post, put, web};
+use std::sync::Arc;
+
+fn error_response(e: DomainError) -> HttpResponse {
+    match e {
+        DomainError::NotFound(msg) => HttpResponse::NotFound().body(msg),
+        DomainError::ValidationError(msg) => HttpResponse::BadRequest().body(msg),
+        DomainError::AlreadyExists(msg) => HttpResponse::Conflict().body(msg),
+        e => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[get("/workflows")]
+async fn list_workflows(service: web::Data<Arc<WorkflowService>>) -> impl Responder {
+    match service.list_workflows().await {
+        Ok(list) => HttpResponse::Ok().json(list),
+        Err(e) => error_response(e),
+    }
+}
+
+#[post("/workflows")]
+async fn create_workflow(
+    req: HttpRequest,
+    workflow: web::Json<Workflow>,
+    auth: web::Data<Arc<AuthService>>,
+    service: web::Data<Arc<WorkflowService>>,
+) -> impl Responder {
+    match service
+        .create_workflow(workflow.into_inner(), &actor(&req, &auth))
+        .await
+    {
+        Ok(created) => HttpResponse::Created().json(created),
+        Err(e) => error_response(e),
+    }
+}
+
+#[get("/workflows/{name}")]
+async fn get_workflow(
+    path: web::Path<String>,
+    service: web::Data<Arc<WorkflowService>>,
+) -> impl Responder {
+    match service.get_workflow(&path.into_inner()).await {
+        Ok(workflow) => HttpResponse::Ok().json(workflow),
+        Err(e) => error_response(e),
+    }
+}
+
+#[put("/workflows/{name}")]
+async fn update_workflow(
+    req: HttpRequest,
+    path: web::Path<String>,
+    workflow: web::Json<Workflow>,
+    auth: web::Data<Arc<AuthService>>,
+    service: web::Data<Arc<WorkflowService>>,
+) -> impl Responder {
+    match service
+        .update_workflow(
+            &path.into_inner(),
+            workflow.into_inner(),
+            &actor(&req, &auth),
+        )
+        .await
+    {
+        Ok(updated) => HttpResponse::Ok().json(updated),
+        Err(e) => error_response(e),
+    }
+}
+
+#[delete("/workflows/{name}")]
+async fn delete_workflow(
+    req: HttpRequest,
+    path: web::Path<String>,
+    auth: web::Data<Arc<AuthService>>,
+    service: web::Data<Arc<WorkflowService>>,
+) -> impl Responder {
+    match service
+        .delete_workflow(&path.into_inner(), &actor(&req, &auth))
+        .await
+    {
+        Ok(_) => HttpResponse::Ok().finish(),
+        // Still the target of a trigger
+        Err(DomainError::ValidationError(msg)) => HttpResponse::Conflict().body(msg),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Starts a run with the request body as input; poll the returned location for progress.
+#[post("/workflows/{name}/runs")]
+async fn start_run(
+    path: web::Path<String>,
+    body: String,
+    service: web::Data<Arc<WorkflowService>>,
+) -> impl Responder {
+    let name = path.into_inner();
+    match service.start(&name, &body).await {
+        Ok(run) => HttpResponse::Accepted()
+            .insert_header((
+                header::LOCATION,
+                format!("/workflows/{}/runs/{}", name, run.id),
+            ))
+            .json(run),
+        Err(e) => error_response(e),
+    }
+}
+
+#[get("/workflows/{name}/runs")]
+async fn list_runs(
+    path: web::Path<String>,
+    service: web::Data<Arc<WorkflowService>>,
+) -> impl Responder {
+    match service.list_runs(&path.into_inner()).await {
+        Ok(runs) => HttpResponse::Ok().json(runs),
+        Err(e) => error_response(e),
+    }
+}
+
+#[get("/workflows/{name}/runs/{id}")]
+async fn get_run(
+    path: web::Path<(String, String)>,
+    service: web::Data<Arc<WorkflowService>>,
+) -> impl Responder {
+    let (name, id) = path.into_inner();
+    match service.get_run(&name, &id).await {
+        Ok(run) => HttpResponse::Ok().json(run),
+        Err(e) => error_response(e),
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_workflows)
+        .service(create_workflow)
+        .service(get_workflow)
+        .service(update_workflow)
+        .service(delete_workflow)
+        .service(start_run)
+        .service(list_runs)
+        .service(get_run);
+}
@@ -1,4 +1,7 @@
 pub mod db;
 pub mod http;
+pub mod prometheus;
+pub mod rate_limit;
 pub mod telemetry;
 pub mod wasm;
+pub mod webhook;
@@ -0,0 +1,259 @@
+use opentelemetry::KeyValue;
+use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+use opentelemetry_sdk::metrics::data::{AggregatedMetrics, Metric, MetricData, ResourceMetrics};
+use opentelemetry_sdk::metrics::reader::MetricReader;
+use opentelemetry_sdk::metrics::{InstrumentKind, ManualReader, Pipeline, Temporality};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Write};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+/// Content type of the Prometheus text exposition format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Metric reader that keeps the process' OpenTelemetry metrics for Prometheus to
+/// scrape, next to (or instead of) the OTLP export.
+///
+/// Clones share the same reader, so one can be registered with the meter provider
+/// while another serves `/metrics`.
+#[derive(Clone, Debug, Default)]
+pub struct PrometheusExporter {
+    reader: Arc<ManualReader>,
+}
+
+impl PrometheusExporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enabled by `PROMETHEUS_METRICS=true`.
+    pub fn from_env() -> Option<Self> {
+        std::env::var("PROMETHEUS_METRICS")
+            .is_ok_and(|v| v == "true" || v == "1")
+            .then(Self::new)
+    }
+
+    /// Collects current values in the text exposition format.
+    pub fn render(&self) -> Result<String, OTelSdkError> {
+        let mut metrics = ResourceMetrics::default();
+        self.reader.collect(&mut metrics)?;
+
+        // Families are keyed by name so each gets a single TYPE line, even if it
+        // is reported by several meters
+        let mut families: BTreeMap<String, Family> = BTreeMap::new();
+        for metric in metrics.scope_metrics().flat_map(|scope| scope.metrics()) {
+            match metric.data() {
+                AggregatedMetrics::F64(data) => add_family(&mut families, metric, data),
+                AggregatedMetrics::U64(data) => add_family(&mut families, metric, data),
+                AggregatedMetrics::I64(data) => add_family(&mut families, metric, data),
+            }
+        }
+
+        let mut out = String::new();
+        for (name, family) in families {
+            if !family.help.is_empty() {
+                let _ = writeln!(out, "# HELP {} {}", name, escape_help(&family.help));
+            }
+            let _ = writeln!(out, "# TYPE {} {}", name, family.kind);
+            out.push_str(&family.samples);
+        }
+        Ok(out)
+    }
+}
+
+impl MetricReader for PrometheusExporter {
+    fn register_pipeline(&self, pipeline: Weak<Pipeline>) {
+        self.reader.register_pipeline(pipeline)
+    }
+
+    fn collect(&self, rm: &mut ResourceMetrics) -> OTelSdkResult {
+        self.reader.collect(rm)
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.reader.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.reader.shutdown_with_timeout(timeout)
+    }
+
+    fn temporality(&self, kind: InstrumentKind) -> Temporality {
+        // Prometheus expects running totals
+        self.reader.temporality(kind)
+    }
+}
+
+struct Family {
+    kind: &'static str,
+    help: String,
+    samples: String,
+}
+
+fn add_family<T: Display + Copy>(
+    families: &mut BTreeMap<String, Family>,
+    metric: &Metric,
+    data: &MetricData<T>,
+) {
+    let base = sanitize(metric.name());
+    let (name, kind) = match data {
+        MetricData::Sum(sum) if sum.is_monotonic() => (format!("{}_total", base), "counter"),
+        MetricData::Sum(_) | MetricData::Gauge(_) => (base, "gauge"),
+        MetricData::Histogram(_) => (base, "histogram"),
+        // Not produced by any instrument we create
+        MetricData::ExponentialHistogram(_) => return,
+    };
+
+    let family = families.entry(name.clone()).or_insert_with(|| Family {
+        kind,
+        help: metric.description().to_string(),
+        samples: String::new(),
+    });
+    let out = &mut family.samples;
+
+    match data {
+        MetricData::Sum(sum) => {
+            for point in sum.data_points() {
+                sample(out, &name, &labels(point.attributes(), None), point.value());
+            }
+        }
+        MetricData::Gauge(gauge) => {
+            for point in gauge.data_points() {
+                sample(out, &name, &labels(point.attributes(), None), point.value());
+            }
+        }
+        MetricData::Histogram(histogram) => {
+            for point in histogram.data_points() {
+                let mut cumulative = 0;
+                let bounds = point.bounds().map(|b| b.to_string());
+                for (bound, count) in bounds
+                    .chain(std::iter::once("+Inf".to_string()))
+                    .zip(point.bucket_counts())
+                {
+                    cumulative += count;
+                    let le = labels(point.attributes(), Some(&bound));
+                    sample(out, &format!("{}_bucket", name), &le, cumulative);
+                }
+                let attrs = labels(point.attributes(), None);
+                sample(out, &format!("{}_sum", name), &attrs, point.sum());
+                sample(out, &format!("{}_count", name), &attrs, point.count());
+            }
+        }
+        MetricData::ExponentialHistogram(_) => {}
+    }
+}
+
+fn sample(out: &mut String, name: &str, labels: &str, value: impl Display) {
+    let _ = writeln!(out, "{}{} {}", name, labels, value);
+}
+
+fn labels<'a>(attributes: impl Iterator<Item = &'a KeyValue>, le: Option<&str>) -> String {
+    let mut pairs: Vec<(String, String)> = attributes
+        .map(|kv| (sanitize(kv.key.as_str()), kv.value.to_string()))
+        .collect();
+    pairs.sort();
+    if let Some(le) = le {
+        pairs.push(("le".to_string(), le.to_string()));
+    }
+    if pairs.is_empty() {
+        return String::new();
+    }
+
+    let pairs: Vec<String> = pairs
+        .into_iter()
+        .map(|(key, value)| format!("{}=\"{}\"", key, escape_label(&value)))
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+/// Replaces characters Prometheus doesn't allow in metric and label names.
+fn sanitize(name: &str) -> String {
+    let mut out: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if out.starts_with(|c: char| c.is_ascii_digit()) {
+        out.insert(0, '_');
+    }
+    out
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn escape_help(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::metrics::MeterProvider;
+    use opentelemetry_sdk::metrics::SdkMeterProvider;
+
+    #[test]
+    fn test_render_exposition_format() {
+        let exporter = PrometheusExporter::new();
+        let provider = SdkMeterProvider::builder()
+            .with_reader(exporter.clone())
+            .build();
+        let meter = provider.meter("test");
+
+        let attrs = [
+            KeyValue::new("status", "ok"),
+            KeyValue::new("function_name", "say \"hi\""),
+        ];
+        let counter = meter
+            .u64_counter("function_invocations")
+            .with_description("Invocations")
+            .build();
+        counter.add(2, &attrs);
+        counter.add(1, &attrs);
+        meter
+            .u64_histogram("function_duration_ms")
+            .with_boundaries(vec![10.0, 100.0])
+            .build()
+            .record(42, &attrs);
+        meter
+            .i64_up_down_counter("function.active")
+            .build()
+            .add(-1, &[]);
+
+        let text = exporter.render().unwrap();
+        let labels = "{function_name=\"say \\\"hi\\\"\",status=\"ok\"}";
+
+        assert!(text.contains("# HELP function_invocations_total Invocations\n"));
+        assert!(text.contains("# TYPE function_invocations_total counter\n"));
+        assert!(text.contains(&format!("function_invocations_total{} 3\n", labels)));
+
+        assert!(text.contains("# TYPE function_duration_ms histogram\n"));
+        assert!(text.contains(
+            "function_duration_ms_bucket{function_name=\"say \\\"hi\\\"\",status=\"ok\",le=\"10\"} 0\n"
+        ));
+        assert!(text.contains(
+            "function_duration_ms_bucket{function_name=\"say \\\"hi\\\"\",status=\"ok\",le=\"100\"} 1\n"
+        ));
+        assert!(text.contains(
+            "function_duration_ms_bucket{function_name=\"say \\\"hi\\\"\",status=\"ok\",le=\"+Inf\"} 1\n"
+        ));
+        assert!(text.contains(&format!("function_duration_ms_sum{} 42\n", labels)));
+        assert!(text.contains(&format!("function_duration_ms_count{} 1\n", labels)));
+
+        assert!(text.contains("# TYPE function_active gauge\nfunction_active -1\n"));
+    }
+
+    #[test]
+    fn test_render_without_provider_fails() {
+        assert!(PrometheusExporter::new().render().is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use crate::domain::entities::{BucketState, DomainError};
+use crate::domain::ports::RateLimitStore;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Buckets kept past this size are pruned once they have fully refilled.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+struct Bucket {
+    tokens: f64,
+    capacity: f64,
+    refill_per_sec: f64,
+    updated_at: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.updated_at = now;
+    }
+}
+
+/// Per-process buckets; each API node enforces its own limits.
+#[derive(Default)]
+pub struct InMemoryRateLimitStore {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl InMemoryRateLimitStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for InMemoryRateLimitStore {
+    async fn take(
+        &self,
+        key: &str,
+        capacity: f64,
+        refill_per_sec: f64,
+    ) -> Result<BucketState, DomainError> {
+        let now = Instant::now();
+        let mut buckets = self
+            .buckets
+            .lock()
+            .map_err(|_| DomainError::Internal("Rate limit store poisoned".to_string()))?;
+
+        if buckets.len() >= PRUNE_THRESHOLD && !buckets.contains_key(key) {
+            prune_full(&mut buckets, now);
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            capacity,
+            refill_per_sec,
+            updated_at: now,
+        });
+        // Pick up limit changes made to the trigger
+        bucket.capacity = capacity;
+        bucket.refill_per_sec = refill_per_sec;
+        bucket.refill(now);
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+
+        Ok(BucketState {
+            allowed,
+            tokens: bucket.tokens,
+        })
+    }
+
+    async fn prune(&self) -> Result<u64, DomainError> {
+        let mut buckets = self
+            .buckets
+            .lock()
+            .map_err(|_| DomainError::Internal("Rate limit store poisoned".to_string()))?;
+        Ok(prune_full(&mut buckets, Instant::now()) as u64)
+    }
+}
+
+/// Drops buckets that have refilled to capacity and returns how many went.
+fn prune_full(buckets: &mut HashMap<String, Bucket>, now: Instant) -> usize {
+    let before = buckets.len();
+    buckets.retain(|_, b| {
+        b.refill(now);
+        b.tokens < b.capacity
+    });
+    before - buckets.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bucket_empties_and_refills() {
+        let store = InMemoryRateLimitStore::new();
+
+        assert!(store.take("k", 2.0, 0.001).await.unwrap().allowed);
+        assert!(store.take("k", 2.0, 0.001).await.unwrap().allowed);
+        let state = store.take("k", 2.0, 0.001).await.unwrap();
+        assert!(!state.allowed);
+        assert!(state.tokens < 1.0);
+
+        // Other keys have their own bucket
+        assert!(store.take("other", 2.0, 0.001).await.unwrap().allowed);
+
+        // A fast refill rate tops the bucket up again
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        assert!(store.take("k", 2.0, 1000.0).await.unwrap().allowed);
+    }
+
+    #[tokio::test]
+    async fn test_prune_drops_only_full_buckets() {
+        let store = InMemoryRateLimitStore::new();
+        store.take("slow", 2.0, 0.001).await.unwrap();
+        store.take("fast", 2.0, 1000.0).await.unwrap();
+
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        assert_eq!(store.prune().await.unwrap(), 1);
+        assert!(store.buckets.lock().unwrap().contains_key("slow"));
+    }
+}
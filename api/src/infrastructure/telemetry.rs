@@ -1,12 +1,30 @@
+use crate::domain::ports::TelemetryRecorder;
+use crate::infrastructure::prometheus::PrometheusExporter;
+use actix_web::http::header::HeaderMap;
 use opentelemetry::global;
+use opentelemetry::propagation::Extractor;
+use opentelemetry::trace::TraceId;
 use opentelemetry_sdk::propagation::TraceContextPropagator;
 use opentelemetry_sdk::{metrics as sdkmetrics, trace as sdktrace};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_opentelemetry::{OpenTelemetrySpanExt, OtelData};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::{Registry, layer::SubscriberExt};
 
 use tracing::info;
 
-pub fn init_telemetry() -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+/// Sets up OpenTelemetry export; with a `recorder`, function logs are also kept in-process,
+/// and with `prometheus`, metrics are also kept for scraping.
+pub fn init_telemetry(
+    recorder: Option<Arc<dyn TelemetryRecorder>>,
+    prometheus: Option<PrometheusExporter>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
     global::set_text_map_propagator(TraceContextPropagator::new());
 
     // Debugging: Log the endpoint being used
@@ -51,9 +69,11 @@ pub fn init_telemetry() -> Result<(), Box<dyn std::error::Error + Send + Sync +
 
     let reader = sdkmetrics::PeriodicReader::builder(metrics_exporter).build();
 
-    let meter_provider = sdkmetrics::SdkMeterProvider::builder()
-        .with_reader(reader)
-        .build();
+    let mut meter_provider = sdkmetrics::SdkMeterProvider::builder().with_reader(reader);
+    if let Some(prometheus) = prometheus {
+        meter_provider = meter_provider.with_reader(prometheus);
+    }
+    let meter_provider = meter_provider.build();
 
     global::set_meter_provider(meter_provider);
 
@@ -80,11 +100,111 @@ pub fn init_telemetry() -> Result<(), Box<dyn std::error::Error + Send + Sync +
         .with(env_filter)
         .with(telemetry)
         .with(log_layer)
+        .with(recorder.map(RecorderLayer::new))
         .try_init()?;
 
     Ok(())
 }
 
+/// Hands events carrying a `function_name` field to an in-process telemetry recorder,
+/// the way the OpenTelemetry bridge exports them to `otel_logs`.
+pub struct RecorderLayer {
+    recorder: Arc<dyn TelemetryRecorder>,
+}
+
+impl RecorderLayer {
+    pub fn new(recorder: Arc<dyn TelemetryRecorder>) -> Self {
+        Self { recorder }
+    }
+}
+
+impl<S> Layer<S> for RecorderLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut fields = LogFields::default();
+        event.record(&mut fields);
+        let Some(function_name) = fields.function_name else {
+            return;
+        };
+
+        let trace_id = ctx
+            .event_scope(event)
+            .and_then(|scope| {
+                scope
+                    .into_iter()
+                    .find_map(|span| span.extensions().get::<OtelData>()?.trace_id())
+            })
+            .filter(|id| *id != TraceId::INVALID)
+            .map(|id| id.to_string())
+            .unwrap_or_default();
+
+        self.recorder.record_log(
+            &function_name,
+            event.metadata().level().as_str(),
+            &fields.message,
+            &trace_id,
+        );
+    }
+}
+
+#[derive(Default)]
+struct LogFields {
+    function_name: Option<String>,
+    message: String,
+}
+
+impl Visit for LogFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "function_name" => self.function_name = Some(value.to_string()),
+            "message" => self.message = value.to_string(),
+            _ => {}
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match field.name() {
+            "function_name" => self.function_name = Some(format!("{:?}", value)),
+            "message" => self.message = format!("{:?}", value),
+            _ => {}
+        }
+    }
+}
+
+/// Continues the trace described by the `traceparent`/`tracestate` of `headers`,
+/// using the propagator installed by [`init_telemetry`].
+pub fn extract_trace_context(headers: &HeaderMap) -> opentelemetry::Context {
+    global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)))
+}
+
+/// The current span's trace context as `TRACEPARENT`/`TRACESTATE` variables, or
+/// nothing when the span isn't part of a recorded trace.
+pub fn trace_context_env() -> Vec<(String, String)> {
+    let context = tracing::Span::current().context();
+    let mut carrier = HashMap::new();
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&context, &mut carrier));
+
+    carrier
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(key, value)| (key.to_uppercase(), value))
+        .collect()
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|k| k.as_str()).collect()
+    }
+}
+
 pub fn shutdown_telemetry() {
     // In newer OTel versions, global shutdown might be handled differently or explicitly on providers.
     // If global::shutdown_tracer_provider() is gone, we might need to rely on providers being dropped or
@@ -0,0 +1,127 @@
+use bytes::{Bytes, BytesMut};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::{self, AsyncWrite};
+use wasmtime_wasi::cli::{IsTerminal, StdoutStream};
+use wasmtime_wasi::p2::{OutputStream, Pollable, StreamResult};
+
+/// Largest write a guest is offered at once; writes beyond it are split by WASI.
+const WRITE_PERMIT: usize = 64 * 1024;
+
+/// Guest stdout or stderr that is drained after each call, for instances that
+/// serve many calls. Unlike `MemoryOutputPipe` it never traps: output beyond
+/// `capacity` between two drains is dropped.
+#[derive(Clone)]
+pub struct LogPipe {
+    capacity: usize,
+    buffer: Arc<Mutex<Buffer>>,
+}
+
+#[derive(Default)]
+struct Buffer {
+    bytes: BytesMut,
+    dropped: usize,
+}
+
+impl LogPipe {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            buffer: Arc::default(),
+        }
+    }
+
+    /// Output written since the last drain, and how many bytes beyond `capacity`
+    /// were dropped.
+    pub fn drain(&self) -> (Bytes, usize) {
+        let mut buffer = self.buffer.lock().unwrap();
+        let dropped = std::mem::take(&mut buffer.dropped);
+        (buffer.bytes.split().freeze(), dropped)
+    }
+
+    fn append(&self, bytes: &[u8]) {
+        let mut buffer = self.buffer.lock().unwrap();
+        let room = self.capacity.saturating_sub(buffer.bytes.len());
+        let kept = bytes.len().min(room);
+        buffer.bytes.extend_from_slice(&bytes[..kept]);
+        buffer.dropped += bytes.len() - kept;
+    }
+}
+
+#[wasmtime_wasi::async_trait]
+impl OutputStream for LogPipe {
+    fn write(&mut self, bytes: Bytes) -> StreamResult<()> {
+        self.append(&bytes);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> StreamResult<()> {
+        Ok(())
+    }
+
+    fn check_write(&mut self) -> StreamResult<usize> {
+        Ok(WRITE_PERMIT)
+    }
+}
+
+#[wasmtime_wasi::async_trait]
+impl Pollable for LogPipe {
+    async fn ready(&mut self) {}
+}
+
+impl AsyncWrite for LogPipe {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.append(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl IsTerminal for LogPipe {
+    fn is_terminal(&self) -> bool {
+        false
+    }
+}
+
+impl StdoutStream for LogPipe {
+    fn p2_stream(&self) -> Box<dyn OutputStream> {
+        Box::new(self.clone())
+    }
+
+    fn async_stream(&self) -> Box<dyn AsyncWrite + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drain_resets_capacity() {
+        let mut pipe = LogPipe::new(8);
+        pipe.write(Bytes::from_static(b"hello ")).unwrap();
+        pipe.write(Bytes::from_static(b"world")).unwrap();
+        let (bytes, dropped) = pipe.drain();
+        assert_eq!((&bytes[..], dropped), (&b"hello wo"[..], 3));
+
+        // Each drain starts over, so a long-lived instance never runs out of room
+        for _ in 0..4 {
+            pipe.write(Bytes::from_static(b"12345678")).unwrap();
+            assert_eq!(pipe.drain(), (Bytes::from_static(b"12345678"), 0));
+        }
+        assert_eq!(pipe.drain(), (Bytes::new(), 0));
+    }
+}
@@ -1 +1,3 @@
+pub mod log_pipe;
 pub mod runtime;
+pub mod snapshot;
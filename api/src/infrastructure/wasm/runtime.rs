@@ -1,22 +1,41 @@
-use crate::domain::wasm_runtime::WasmRuntime;
+use crate::domain::wasm_runtime::{FunctionInvoker, WasmRuntime, WasmSession};
+use crate::infrastructure::telemetry::trace_context_env;
+use crate::infrastructure::wasm::log_pipe::LogPipe;
 use ahash::RandomState;
 use async_trait::async_trait;
+use opentelemetry::{KeyValue, global};
 use papaya::HashMap;
-use std::sync::Arc;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, Weak};
+use std::time::Instant;
 use tracing;
-use wasmtime::component::{Component, InstancePre, Linker};
+use wasmtime::component::types::ComponentItem;
+use wasmtime::component::{Component, HasSelf, InstancePre, Linker, Type};
 use wasmtime::{
-    Config, Engine, InstanceAllocationStrategy, OptLevel, PoolingAllocationConfig, Store,
+    Config, Engine, InstanceAllocationStrategy, Module, OptLevel, PoolingAllocationConfig, Store,
 };
+use wasmtime_wasi::cli::StdoutStream;
+use wasmtime_wasi::p1::WasiP1Ctx;
 use wasmtime_wasi::p2::add_to_linker_async;
-use wasmtime_wasi::p2::pipe::MemoryOutputPipe;
-use wasmtime_wasi::{ResourceTable, WasiCtx, WasiCtxBuilder, WasiCtxView, WasiView};
+use wasmtime_wasi::p2::pipe::{MemoryInputPipe, MemoryOutputPipe};
+use wasmtime_wasi::{I32Exit, ResourceTable, WasiCtx, WasiCtxBuilder, WasiCtxView, WasiView};
 
+// Besides `handle`, functions may export `init: func() -> result<_, string>`,
+// looked up dynamically in `WasmtimeRuntime::initialize`.
 wasmtime::component::bindgen!({
     inline: "
     package fluor:fun;
 
+    interface host {
+        /// Calls another function by name and returns its output.
+        call: func(function: string, input: string) -> result<string, string>;
+    }
+
     world function {
+        import host;
         export handle: func(input: string) -> string;
     }
     ",
@@ -28,6 +47,24 @@ wasmtime::component::bindgen!({
 struct FluorState {
     ctx: WasiCtx,
     table: ResourceTable,
+    function_name: String,
+    invoker: Option<Arc<dyn FunctionInvoker>>,
+}
+
+impl fluor::fun::host::Host for FluorState {
+    async fn call(
+        &mut self,
+        function: String,
+        input: String,
+    ) -> wasmtime::Result<Result<String, String>> {
+        let Some(invoker) = &self.invoker else {
+            return Ok(Err("Function calls are not available".to_string()));
+        };
+        Ok(invoker
+            .invoke_from(&self.function_name, &function, &input)
+            .await
+            .map_err(|e| e.to_string()))
+    }
 }
 
 impl WasiView for FluorState {
@@ -39,11 +76,48 @@ impl WasiView for FluorState {
     }
 }
 
+/// Preamble of component binaries: magic, version 0x0d and layer 1.
+pub(crate) const COMPONENT_HEADER: [u8; 8] = *b"\0asm\x0d\0\x01\0";
+
+/// Preamble of core module binaries: magic and version 1.
+const CORE_MODULE_HEADER: [u8; 8] = *b"\0asm\x01\0\0\0";
+
+/// Preview1 modules answer on stdout, so it gets room for a full response.
+const MODULE_OUTPUT_CAPACITY: usize = 16 * 1024 * 1024;
+
+/// Instance slots in the pooling allocator, shared by all functions.
+pub const POOL_INSTANCES: u32 = 5000;
+
+/// Code compiled from an artifact: a component, or a legacy WASI preview1 module.
+enum Compiled {
+    Component(Component),
+    Module(Module),
+}
+
+/// A function ready to be instantiated.
+#[derive(Clone)]
+enum Loaded {
+    Component(InstancePre<FluorState>),
+    /// Run as a command: input on stdin, response on stdout.
+    Module(wasmtime::InstancePre<WasiP1Ctx>),
+}
+
+type EnvMap = HashMap<String, Vec<(String, String)>, RandomState>;
+
 #[derive(Clone)]
 pub struct WasmtimeRuntime {
     engine: Engine,
     linker: Arc<Linker<FluorState>>,
-    cache: Arc<HashMap<String, InstancePre<FluorState>, RandomState>>,
+    module_linker: Arc<wasmtime::Linker<WasiP1Ctx>>,
+    cache: Arc<HashMap<String, Loaded, RandomState>>,
+    /// Functions registered for lazy loading: name -> artifact path.
+    pending: Arc<HashMap<String, String, RandomState>>,
+    /// Environment variables exposed to each function through WASI.
+    envs: Arc<EnvMap>,
+    /// Identifies the Wasmtime version and settings compiled code depends on.
+    engine_fingerprint: String,
+    /// Serves `fluor:fun/host` calls; weak since the invoker owns this runtime.
+    invoker: Arc<OnceLock<Weak<dyn FunctionInvoker>>>,
 }
 impl WasmtimeRuntime {
     pub fn new() -> anyhow::Result<Self> {
@@ -57,7 +131,7 @@ impl WasmtimeRuntime {
         let mut pool_config = PoolingAllocationConfig::default();
 
         pool_config.max_component_instance_size(256 * 1024 * 1024); // 256MB
-        pool_config.total_component_instances(5000);
+        pool_config.total_component_instances(POOL_INSTANCES);
         pool_config.max_tables_per_component(20);
 
         pool_config.max_unused_warm_slots(2000);
@@ -68,51 +142,206 @@ impl WasmtimeRuntime {
         let engine = Engine::new(&config)?;
         let mut linker = Linker::new(&engine);
         add_to_linker_async(&mut linker)?;
+        fluor::fun::host::add_to_linker::<_, HasSelf<_>>(&mut linker, |state| state)?;
+
+        let mut module_linker = wasmtime::Linker::new(&engine);
+        wasmtime_wasi::p1::add_to_linker_async(&mut module_linker, |ctx| ctx)?;
 
         let cache = Arc::new(HashMap::builder().hasher(RandomState::new()).build());
+        let pending = Arc::new(HashMap::builder().hasher(RandomState::new()).build());
+
+        let mut hasher = DefaultHasher::new();
+        engine.precompile_compatibility_hash().hash(&mut hasher);
+        let engine_fingerprint = format!("{:016x}", hasher.finish());
+
+        register_gauges(&engine, &cache);
 
         Ok(Self {
             engine,
             linker: Arc::new(linker),
+            module_linker: Arc::new(module_linker),
             cache,
+            pending,
+            envs: Arc::new(HashMap::builder().hasher(RandomState::new()).build()),
+            engine_fingerprint,
+            invoker: Arc::new(OnceLock::new()),
         })
     }
-}
 
-#[async_trait]
-impl WasmRuntime for WasmtimeRuntime {
-    fn load_function(&self, name: &str, path: &str) -> anyhow::Result<()> {
+    /// Routes calls made through the `fluor:fun/host` import to `invoker`.
+    pub fn set_invoker(&self, invoker: Weak<dyn FunctionInvoker>) {
+        if self.invoker.set(invoker).is_err() {
+            tracing::warn!("Function invoker already set");
+        }
+    }
+
+    /// Registers a function to be compiled on its first invocation instead of
+    /// upfront, so rarely used functions don't slow down boot.
+    pub fn register_function(&self, name: &str, path: &str) {
+        self.cache.pin().remove(name);
+        self.pending
+            .pin()
+            .insert(name.to_string(), path.to_string());
+    }
+
+    async fn instance_pre(&self, function_name: &str) -> anyhow::Result<Loaded> {
+        if let Some(loaded) = self.cache.pin().get(function_name).cloned() {
+            return Ok(loaded);
+        }
+
+        let path = self
+            .pending
+            .pin()
+            .get(function_name)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Function '{}' not found", function_name))?;
+
+        tracing::info!(function = %function_name, "Lazily loading function");
+        self.load_function(function_name, &path).await?;
+
+        self.cache
+            .pin()
+            .get(function_name)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Function '{}' not found", function_name))
+    }
+
+    /// Compiled code is stored beside the artifact, keyed by its content hash and
+    /// the engine fingerprint so that new uploads and Wasmtime upgrades miss.
+    fn compiled_path(&self, path: &str, bytes: &[u8]) -> PathBuf {
+        let digest = Sha256::digest(bytes);
+        let content_hash: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+        PathBuf::from(format!(
+            "{}.{}-{}.cwasm",
+            path, content_hash, self.engine_fingerprint
+        ))
+    }
+
+    fn compile(&self, name: &str, path: &str) -> anyhow::Result<Compiled> {
+        let start = Instant::now();
         let bytes = std::fs::read(path)?;
-        let component = Component::new(&self.engine, &bytes)?;
-        let instance_pre = self.linker.instantiate_pre(&component)?;
+        let is_module = bytes.starts_with(&CORE_MODULE_HEADER);
+        let compiled = self.compiled_path(path, &bytes);
 
-        self.cache.pin().insert(name.to_string(), instance_pre);
+        if compiled.exists() {
+            // SAFETY: the file was produced by `serialize` below for this exact
+            // artifact and engine configuration, inside our own storage directory.
+            let cached = unsafe {
+                if is_module {
+                    Module::deserialize_file(&self.engine, &compiled).map(Compiled::Module)
+                } else {
+                    Component::deserialize_file(&self.engine, &compiled).map(Compiled::Component)
+                }
+            };
+            match cached {
+                Ok(cached) => {
+                    record_compile(name, true, start.elapsed());
+                    return Ok(cached);
+                }
+                Err(e) => tracing::warn!("Discarding compiled cache {}: {}", compiled.display(), e),
+            }
+        }
 
-        // Warmup
-        let runtime = self.clone();
-        let name_cp = name.to_string();
+        let (code, serialized) = if is_module {
+            let module = Module::new(&self.engine, &bytes)?;
+            let serialized = module.serialize();
+            (Compiled::Module(module), serialized)
+        } else {
+            let component = Component::new(&self.engine, &bytes)?;
+            let serialized = component.serialize();
+            (Compiled::Component(component), serialized)
+        };
 
-        tokio::spawn(async move {
-            let _ = runtime.invoke(&name_cp, "{}").await;
-            tracing::info!(function = %name_cp, "Warmup concluído.");
-        });
+        record_compile(name, false, start.elapsed());
+        if let Err(e) = serialized.and_then(|s| self.store_compiled(path, &compiled, &s)) {
+            tracing::warn!("Failed to cache compiled {}: {}", path, e);
+        }
 
-        Ok(())
+        Ok(code)
     }
 
-    async fn invoke(&self, function_name: &str, input: &str) -> anyhow::Result<String> {
-        let instance_pre = {
-            let guard = self.cache.pin();
-            guard
-                .get(function_name)
-                .cloned()
-                .ok_or_else(|| anyhow::anyhow!("Function '{}' not found", function_name))?
+    fn store_compiled(&self, path: &str, compiled: &Path, serialized: &[u8]) -> anyhow::Result<()> {
+        let tmp = compiled.with_extension("cwasm.tmp");
+        std::fs::write(&tmp, serialized)?;
+        std::fs::rename(&tmp, compiled)?;
+
+        // Drop entries left behind by previous uploads or Wasmtime versions
+        remove_compiled(Path::new(path), Some(compiled))
+    }
+
+    /// Checks the component's exports against the `fluor:fun/function` world.
+    fn check_exports(&self, component: &Component) -> anyhow::Result<()> {
+        let ty = component.component_type();
+        let mut handle = None;
+        let mut init = None;
+        let mut names = Vec::new();
+
+        for (name, item) in ty.exports(&self.engine) {
+            match (name, item) {
+                ("handle", ComponentItem::ComponentFunc(f)) => handle = Some(f),
+                ("init", ComponentItem::ComponentFunc(f)) => init = Some(f),
+                _ => {}
+            }
+            names.push(name.to_string());
+        }
+
+        let Some(handle) = handle else {
+            if let Some(http) = names.iter().find(|n| n.starts_with("wasi:http/")) {
+                anyhow::bail!(
+                    "component exports `{}`, but functions must target the fluor:fun/function \
+                     world and export `handle: func(input: string) -> string`",
+                    http
+                );
+            }
+            anyhow::bail!(
+                "component does not export `handle: func(input: string) -> string` \
+                 (exports: [{}])",
+                names.join(", ")
+            );
         };
 
-        let stdout = MemoryOutputPipe::new(4096);
-        let stderr = MemoryOutputPipe::new(4096);
+        let params: Vec<Type> = handle.params().map(|(_, ty)| ty).collect();
+        let results: Vec<Type> = handle.results().collect();
+        if !matches!(params.as_slice(), [Type::String])
+            || !matches!(results.as_slice(), [Type::String])
+        {
+            anyhow::bail!("export `handle` must have type `func(input: string) -> string`");
+        }
+
+        if let Some(init) = init {
+            let results: Vec<Type> = init.results().collect();
+            let valid = init.params().len() == 0
+                && matches!(results.as_slice(), [Type::Result(r)]
+                    if r.ok().is_none() && matches!(r.err(), Some(Type::String)));
+            if !valid {
+                anyhow::bail!("export `init` must have type `func() -> result<_, string>`");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The function's configured variables plus `TRACEPARENT`/`TRACESTATE` of the
+    /// current span, so the guest can forward them on outbound calls and in its logs.
+    fn env_of(&self, function_name: &str) -> Vec<(String, String)> {
+        let mut env = self
+            .envs
+            .pin()
+            .get(function_name)
+            .cloned()
+            .unwrap_or_default();
+        env.extend(trace_context_env());
+        env
+    }
 
+    fn new_store<P: StdoutStream + Clone + 'static>(
+        &self,
+        function_name: &str,
+        stdout: &P,
+        stderr: &P,
+    ) -> Store<FluorState> {
         let wasi = WasiCtxBuilder::new()
+            .envs(&self.env_of(function_name))
             .stdout(stdout.clone())
             .stderr(stderr.clone())
             .build();
@@ -120,35 +349,809 @@ impl WasmRuntime for WasmtimeRuntime {
         let state = FluorState {
             ctx: wasi,
             table: ResourceTable::new(),
+            function_name: function_name.to_string(),
+            invoker: self.invoker.get().and_then(Weak::upgrade),
         };
 
-        let mut store = Store::new(&self.engine, state);
+        Store::new(&self.engine, state)
+    }
 
+    /// Calls the optional `init: func() -> result<_, string>` export on a throwaway
+    /// instance, which also warms up the pooling allocator slot. Functions without
+    /// it are not invoked at load time at all.
+    async fn initialize(
+        &self,
+        name: &str,
+        instance_pre: &InstancePre<FluorState>,
+    ) -> anyhow::Result<()> {
+        let stdout = MemoryOutputPipe::new(4096);
+        let stderr = MemoryOutputPipe::new(4096);
+        let mut store = self.new_store(name, &stdout, &stderr);
         let instance = instance_pre.instantiate_async(&mut store).await?;
-        let bindings = Function::new(&mut store, &instance)?;
 
-        let result = bindings.call_handle(&mut store, input).await?;
+        let Some(init) = instance.get_func(&mut store, "init") else {
+            tracing::debug!(function = %name, "No init export, skipping warmup");
+            return Ok(());
+        };
+        let init = init.typed::<(), (Result<(), String>,)>(&store)?;
+
+        let (result,) = init.call_async(&mut store, ()).await?;
+        init.post_return_async(&mut store).await?;
+
+        emit_logs(name, &stdout.contents(), &stderr.contents());
+
+        result.map_err(|e| anyhow::anyhow!("init failed: {}", e))?;
+        tracing::info!(function = %name, "Function initialized");
+        Ok(())
+    }
+
+    /// Runs a WASI preview1 command module: the input is its stdin and whatever
+    /// it writes to stdout is the response. Exiting with status 0 counts as success.
+    async fn run_module(
+        &self,
+        function_name: &str,
+        instance_pre: &wasmtime::InstancePre<WasiP1Ctx>,
+        input: &str,
+    ) -> anyhow::Result<String> {
+        let stdout = MemoryOutputPipe::new(MODULE_OUTPUT_CAPACITY);
+        let stderr = MemoryOutputPipe::new(4096);
+        let ctx = WasiCtxBuilder::new()
+            .envs(&self.env_of(function_name))
+            .stdin(MemoryInputPipe::new(input.to_string()))
+            .stdout(stdout.clone())
+            .stderr(stderr.clone())
+            .build_p1();
+        let mut store = Store::new(&self.engine, ctx);
+
+        let start = Instant::now();
+        let instance = instance_pre.instantiate_async(&mut store).await?;
+        let entry = instance.get_typed_func::<(), ()>(&mut store, "_start")?;
+        let cold_start_ms = start.elapsed().as_millis() as u64;
+
+        let start = Instant::now();
+        let result = entry.call_async(&mut store, ()).await;
+        let handler_ms = start.elapsed().as_millis() as u64;
 
-        let stdout_content = stdout.contents();
-        if !stdout_content.is_empty() {
-            let log_body = String::from_utf8_lossy(&stdout_content);
-            tracing::info!(function_name = %function_name, "{}", log_body);
+        record_timings(function_name, cold_start_ms, handler_ms);
+        emit_logs(function_name, &[], &stderr.contents());
+
+        if let Err(e) = result {
+            match e.downcast_ref::<I32Exit>() {
+                Some(I32Exit(0)) => {}
+                Some(I32Exit(code)) => anyhow::bail!("module exited with status {}", code),
+                None => return Err(e),
+            }
+        }
+
+        Ok(String::from_utf8(stdout.contents().to_vec())?)
+    }
+}
+
+/// The compiled code cached for `artifact` by `compiled_path`, with the
+/// `{hash}-{fingerprint}` key of each entry. Only exact
+/// `{artifact}.{hash}-{fingerprint}.cwasm` names match, so another artifact whose
+/// name starts the same is left alone.
+fn compiled_entries(artifact: &Path) -> anyhow::Result<Vec<(PathBuf, String)>> {
+    let (Some(dir), Some(prefix)) = (artifact.parent(), artifact.file_name()) else {
+        return Ok(Vec::new());
+    };
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let prefix = format!("{}.", prefix.to_string_lossy());
+    let is_hex = |s: &str| s.len() == 16 && s.bytes().all(|b| b.is_ascii_hexdigit());
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(dir)?.flatten() {
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        let key = file_name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix(".cwasm"))
+            .filter(|key| {
+                key.split_once('-')
+                    .is_some_and(|(hash, fingerprint)| is_hex(hash) && is_hex(fingerprint))
+            });
+        if let Some(key) = key {
+            entries.push((entry.path(), key.to_string()));
+        }
+    }
+    Ok(entries)
+}
+
+/// Removes the compiled code cached for `artifact`, except `keep`.
+fn remove_compiled(artifact: &Path, keep: Option<&Path>) -> anyhow::Result<()> {
+    for (entry, _) in compiled_entries(artifact)? {
+        if Some(entry.as_path()) != keep {
+            let _ = std::fs::remove_file(entry);
         }
+    }
+    Ok(())
+}
+
+/// Renames the compiled code cached for `from` after the artifact now at `to`,
+/// replacing what was cached for the artifact it overwrote.
+fn move_compiled(from: &Path, to: &Path) -> anyhow::Result<()> {
+    remove_compiled(to, None)?;
+    for (entry, key) in compiled_entries(from)? {
+        let mut moved = to.as_os_str().to_owned();
+        moved.push(format!(".{}.cwasm", key));
+        std::fs::rename(entry, moved)?;
+    }
+    Ok(())
+}
+
+/// Time to get a function's code ready, `cached` when it was read back from disk.
+fn record_compile(function_name: &str, cached: bool, elapsed: std::time::Duration) {
+    global::meter("fluor-api")
+        .u64_histogram("function_compile_ms")
+        .build()
+        .record(
+            elapsed.as_millis() as u64,
+            &[
+                KeyValue::new("function_name", function_name.to_string()),
+                KeyValue::new("cached", cached),
+            ],
+        );
+}
+
+/// Gauges observed at collection time: loaded functions and pooling allocator slots.
+/// Only weak references are kept, so a dropped runtime stops reporting.
+fn register_gauges(engine: &Engine, cache: &Arc<HashMap<String, Loaded, RandomState>>) {
+    let meter = global::meter("fluor-api");
 
-        let stderr_content = stderr.contents();
-        if !stderr_content.is_empty() {
-            let log_body = String::from_utf8_lossy(&stderr_content);
-            tracing::error!(function_name = %function_name, "{}", log_body);
+    let cache = Arc::downgrade(cache);
+    meter
+        .u64_observable_gauge("wasm_instance_pre_cached")
+        .with_description("Functions compiled and ready to instantiate")
+        .with_callback(move |observer| {
+            if let Some(cache) = cache.upgrade() {
+                observer.observe(cache.len() as u64, &[]);
+            }
+        })
+        .build();
+
+    let engine = engine.weak();
+    meter
+        .u64_observable_gauge("wasm_pool_slots_in_use")
+        .with_description("Pooling allocator slots currently allocated")
+        .with_callback(move |observer| {
+            let Some(pool) = engine
+                .upgrade()
+                .and_then(|engine| engine.pooling_allocator_metrics())
+            else {
+                return;
+            };
+            let kinds = [
+                ("component_instance", pool.component_instances()),
+                ("core_instance", pool.core_instances()),
+                ("memory", pool.memories() as u64),
+                ("table", pool.tables() as u64),
+            ];
+            for (kind, in_use) in kinds {
+                observer.observe(in_use, &[KeyValue::new("kind", kind)]);
+            }
+        })
+        .build();
+    meter
+        .u64_observable_gauge("wasm_pool_component_instance_slots")
+        .with_description("Component instance slots in the pooling allocator")
+        .with_callback(|observer| observer.observe(POOL_INSTANCES as u64, &[]))
+        .build();
+}
+
+/// Instantiation cost is tracked apart from the guest's own work so the
+/// effect of snapshots shows up on its own.
+fn record_timings(function_name: &str, cold_start_ms: u64, handler_ms: u64) {
+    let meter = global::meter("fluor-api");
+    let attrs = [KeyValue::new("function_name", function_name.to_string())];
+    meter
+        .u64_histogram("function_cold_start_ms")
+        .build()
+        .record(cold_start_ms, &attrs);
+    meter
+        .u64_histogram("function_handler_ms")
+        .build()
+        .record(handler_ms, &attrs);
+}
+
+fn emit_logs(function_name: &str, stdout: &[u8], stderr: &[u8]) {
+    if !stdout.is_empty() {
+        let log_body = String::from_utf8_lossy(stdout);
+        tracing::info!(function_name = %function_name, "{}", log_body);
+    }
+
+    if !stderr.is_empty() {
+        let log_body = String::from_utf8_lossy(stderr);
+        tracing::error!(function_name = %function_name, "{}", log_body);
+    }
+}
+
+/// Most output a session keeps per call; its pipes are drained after each one.
+const SESSION_LOG_CAPACITY: usize = 1024 * 1024;
+
+struct WasmtimeSession {
+    function_name: String,
+    store: Store<FluorState>,
+    bindings: Function,
+    stdout: LogPipe,
+    stderr: LogPipe,
+}
+
+#[async_trait]
+impl WasmSession for WasmtimeSession {
+    async fn invoke(&mut self, input: &str) -> anyhow::Result<String> {
+        let result = self.bindings.call_handle(&mut self.store, input).await;
+
+        let (stdout, stdout_dropped) = self.stdout.drain();
+        let (stderr, stderr_dropped) = self.stderr.drain();
+        emit_logs(&self.function_name, &stdout, &stderr);
+        if stdout_dropped + stderr_dropped > 0 {
+            tracing::warn!(
+                function_name = %self.function_name,
+                "Dropped {} bytes of output over the {} byte limit per call",
+                stdout_dropped + stderr_dropped,
+                SESSION_LOG_CAPACITY
+            );
         }
 
+        result
+    }
+}
+
+/// Preview1 modules keep no state between runs, so each message gets a fresh instance.
+struct ModuleSession {
+    runtime: WasmtimeRuntime,
+    function_name: String,
+    instance_pre: wasmtime::InstancePre<WasiP1Ctx>,
+}
+
+#[async_trait]
+impl WasmSession for ModuleSession {
+    async fn invoke(&mut self, input: &str) -> anyhow::Result<String> {
+        self.runtime
+            .run_module(&self.function_name, &self.instance_pre, input)
+            .await
+    }
+}
+
+#[async_trait]
+impl WasmRuntime for WasmtimeRuntime {
+    fn set_env(&self, function_name: &str, env: &BTreeMap<String, String>) {
+        let env: Vec<(String, String)> = env.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        if env.is_empty() {
+            self.envs.pin().remove(function_name);
+        } else {
+            self.envs.pin().insert(function_name.to_string(), env);
+        }
+    }
+
+    fn validate(&self, bytes: &[u8]) -> anyhow::Result<()> {
+        if bytes.starts_with(&CORE_MODULE_HEADER) {
+            let module = Module::new(&self.engine, bytes)
+                .map_err(|e| anyhow::anyhow!("invalid module: {:#}", e))?;
+            if !module
+                .exports()
+                .any(|e| e.name() == "_start" && e.ty().func().is_some())
+            {
+                anyhow::bail!(
+                    "core module does not export `_start`; build it as a WASI preview1 command"
+                );
+            }
+            self.module_linker
+                .instantiate_pre(&module)
+                .map_err(|e| anyhow::anyhow!("unsupported import: {:#}", e))?;
+            return Ok(());
+        }
+        if !bytes.starts_with(&COMPONENT_HEADER) {
+            anyhow::bail!("binary is not a WebAssembly component or module");
+        }
+
+        let component = Component::new(&self.engine, bytes)
+            .map_err(|e| anyhow::anyhow!("invalid component: {:#}", e))?;
+        self.check_exports(&component)?;
+        self.linker
+            .instantiate_pre(&component)
+            .map_err(|e| anyhow::anyhow!("unsupported import: {:#}", e))?;
+
+        Ok(())
+    }
+
+    async fn load_function(&self, name: &str, path: &str) -> anyhow::Result<()> {
+        let loaded = match self.compile(name, path)? {
+            Compiled::Component(component) => {
+                let instance_pre = self.linker.instantiate_pre(&component)?;
+                self.initialize(name, &instance_pre).await?;
+                Loaded::Component(instance_pre)
+            }
+            Compiled::Module(module) => {
+                Loaded::Module(self.module_linker.instantiate_pre(&module)?)
+            }
+        };
+
+        self.cache.pin().insert(name.to_string(), loaded);
+        self.pending.pin().remove(name);
+
+        Ok(())
+    }
+
+    fn unload_function(&self, name: &str, paths: &[String]) {
+        self.cache.pin().remove(name);
+        self.pending.pin().remove(name);
+        self.envs.pin().remove(name);
+        self.remove_compiled(paths);
+    }
+
+    fn rename_function(&self, from: &str, to: &str) {
+        let Some(loaded) = self.cache.pin().remove(from).cloned() else {
+            tracing::warn!(
+                "Function '{}' is not loaded; '{}' keeps its version",
+                from,
+                to
+            );
+            return;
+        };
+        match self.envs.pin().remove(from).cloned() {
+            Some(env) => self.envs.pin().insert(to.to_string(), env),
+            None => self.envs.pin().remove(to),
+        };
+        self.cache.pin().insert(to.to_string(), loaded);
+        self.pending.pin().remove(to);
+    }
+
+    fn move_compiled(&self, from: &str, to: &str) {
+        if let Err(e) = move_compiled(Path::new(from), Path::new(to)) {
+            tracing::warn!("Failed to move compiled code of {} to {}: {}", from, to, e);
+        }
+    }
+
+    fn remove_compiled(&self, paths: &[String]) {
+        for path in paths {
+            if let Err(e) = remove_compiled(Path::new(path), None) {
+                tracing::warn!("Failed to remove compiled code of {}: {}", path, e);
+            }
+        }
+    }
+
+    async fn invoke(&self, function_name: &str, input: &str) -> anyhow::Result<String> {
+        let instance_pre = match self.instance_pre(function_name).await? {
+            Loaded::Component(instance_pre) => instance_pre,
+            Loaded::Module(instance_pre) => {
+                return self.run_module(function_name, &instance_pre, input).await;
+            }
+        };
+        let stdout = MemoryOutputPipe::new(4096);
+        let stderr = MemoryOutputPipe::new(4096);
+        let mut store = self.new_store(function_name, &stdout, &stderr);
+
+        let start = Instant::now();
+        let instance = instance_pre.instantiate_async(&mut store).await?;
+        let bindings = Function::new(&mut store, &instance)?;
+        let cold_start_ms = start.elapsed().as_millis() as u64;
+
+        let start = Instant::now();
+        let result = bindings.call_handle(&mut store, input).await?;
+        let handler_ms = start.elapsed().as_millis() as u64;
+
+        record_timings(function_name, cold_start_ms, handler_ms);
+        emit_logs(function_name, &stdout.contents(), &stderr.contents());
+
         Ok(result)
     }
+
+    async fn open_session(&self, function_name: &str) -> anyhow::Result<Box<dyn WasmSession>> {
+        let instance_pre = match self.instance_pre(function_name).await? {
+            Loaded::Component(instance_pre) => instance_pre,
+            Loaded::Module(instance_pre) => {
+                return Ok(Box::new(ModuleSession {
+                    runtime: self.clone(),
+                    function_name: function_name.to_string(),
+                    instance_pre,
+                }));
+            }
+        };
+        let stdout = LogPipe::new(SESSION_LOG_CAPACITY);
+        let stderr = LogPipe::new(SESSION_LOG_CAPACITY);
+        let mut store = self.new_store(function_name, &stdout, &stderr);
+
+        let instance = instance_pre.instantiate_async(&mut store).await?;
+        let bindings = Function::new(&mut store, &instance)?;
+
+        Ok(Box::new(WasmtimeSession {
+            function_name: function_name.to_string(),
+            store,
+            bindings,
+            stdout,
+            stderr,
+        }))
+    }
 }
 
 impl std::fmt::Debug for WasmtimeRuntime {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("WasmtimeRuntime")
             .field("cache_size", &self.cache.pin().len())
+            .field("pending", &self.pending.pin().len())
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Component whose `handle` echoes its input and, when `init_error` is set,
+    /// whose `init` export fails with that message.
+    fn echo_component(init_error: Option<&str>) -> String {
+        let init = match init_error {
+            Some(msg) => format!(
+                r#"
+                (data (i32.const 16) "{msg}")
+                (func (export "init") (result i32)
+                    (i32.store8 (i32.const 32) (i32.const 1))
+                    (i32.store (i32.const 36) (i32.const 16))
+                    (i32.store (i32.const 40) (i32.const {len}))
+                    i32.const 32)"#,
+                len = msg.len()
+            ),
+            None => String::new(),
+        };
+        let init_export = if init_error.is_some() {
+            r#"(func (export "init") (result (result (error string)))
+                (canon lift (core func $i "init") (memory $i "mem") (realloc (func $i "realloc"))))"#
+        } else {
+            ""
+        };
+
+        format!(
+            r#"
+            (component
+                (core module $m
+                    (memory (export "mem") 1)
+                    (global $bump (mut i32) (i32.const 1024))
+                    (func (export "realloc") (param i32 i32 i32 i32) (result i32)
+                        (local $p i32)
+                        (local.set $p (global.get $bump))
+                        (global.set $bump (i32.add (global.get $bump) (local.get 3)))
+                        (local.get $p))
+                    (func (export "handle") (param i32 i32) (result i32)
+                        (i32.store (i32.const 0) (local.get 0))
+                        (i32.store (i32.const 4) (local.get 1))
+                        i32.const 0)
+                    {init}
+                )
+                (core instance $i (instantiate $m))
+                (func (export "handle") (param "input" string) (result string)
+                    (canon lift (core func $i "handle") (memory $i "mem") (realloc (func $i "realloc"))))
+                {init_export}
+            )"#
+        )
+    }
+
+    /// WASI preview1 command that copies stdin to stdout, then exits with `status`.
+    fn echo_module(status: i32) -> Vec<u8> {
+        wat::parse_str(format!(
+            r#"
+            (module
+                (import "wasi_snapshot_preview1" "fd_read"
+                    (func $fd_read (param i32 i32 i32 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "fd_write"
+                    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+                (memory (export "memory") 1)
+                (func (export "_start")
+                    (i32.store (i32.const 0) (i32.const 64))
+                    (i32.store (i32.const 4) (i32.const 1024))
+                    (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+                    (i32.store (i32.const 4) (i32.load (i32.const 8)))
+                    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))
+                    (call $proc_exit (i32.const {status}))))"#
+        ))
+        .unwrap()
+    }
+
+    fn write_component(dir: &tempfile::TempDir, name: &str, wat: &str) -> String {
+        let path = dir.path().join(format!("{}.wat", name));
+        std::fs::write(&path, wat).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_load_without_init_and_invoke() {
+        let runtime = WasmtimeRuntime::new().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_component(&dir, "echo", &echo_component(None));
+
+        runtime.load_function("echo", &path).await.unwrap();
+
+        let output = runtime.invoke("echo", "ping").await.unwrap();
+        assert_eq!(output, "ping");
+    }
+
+    #[tokio::test]
+    async fn test_compiled_cache_is_reused_and_invalidated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_component(&dir, "echo", &echo_component(None));
+        let cached = |dir: &tempfile::TempDir| -> Vec<PathBuf> {
+            std::fs::read_dir(dir.path())
+                .unwrap()
+                .map(|e| e.unwrap().path())
+                .filter(|p| p.extension().is_some_and(|ext| ext == "cwasm"))
+                .collect()
+        };
+
+        let runtime = WasmtimeRuntime::new().unwrap();
+        runtime.load_function("echo", &path).await.unwrap();
+        let first = cached(&dir);
+        assert_eq!(first.len(), 1);
+
+        // A fresh runtime (as after a restart) picks up the serialized component
+        let runtime = WasmtimeRuntime::new().unwrap();
+        runtime.load_function("echo", &path).await.unwrap();
+        assert_eq!(cached(&dir), first);
+        assert_eq!(runtime.invoke("echo", "ping").await.unwrap(), "ping");
+
+        // Corrupt entries are recompiled instead of failing the load. The live entry
+        // is mmapped, so replace the file rather than writing over it.
+        std::fs::remove_file(&first[0]).unwrap();
+        std::fs::write(&first[0], b"garbage").unwrap();
+        runtime.load_function("echo", &path).await.unwrap();
+        assert_ne!(std::fs::read(&first[0]).unwrap(), b"garbage");
+
+        // A new upload replaces the stale entry
+        std::fs::write(&path, echo_component(Some("changed"))).unwrap();
+        assert!(runtime.load_function("echo", &path).await.is_err());
+        let second = cached(&dir);
+        assert_eq!(second.len(), 1);
+        assert_ne!(second, first);
+    }
+
+    #[tokio::test]
+    async fn test_compiled_cache_is_kept_per_artifact() {
+        let dir = tempfile::tempdir().unwrap();
+        // `echo.wat` and `echo.wat.wat` share a prefix, but not a cache entry
+        let short = write_component(&dir, "echo", &echo_component(None));
+        let long = write_component(&dir, "echo.wat", &echo_component(None));
+        let cached_of = |path: &str| -> usize {
+            std::fs::read_dir(dir.path())
+                .unwrap()
+                .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+                .filter(|name| {
+                    name.ends_with(".cwasm")
+                        && name
+                            .strip_prefix(&format!("{}.", path.rsplit('/').next().unwrap()))
+                            .is_some_and(|rest| !rest.starts_with("wat."))
+                })
+                .count()
+        };
+
+        let runtime = WasmtimeRuntime::new().unwrap();
+        runtime.load_function("long", &long).await.unwrap();
+        runtime.load_function("short", &short).await.unwrap();
+        assert_eq!((cached_of(&short), cached_of(&long)), (1, 1));
+
+        runtime.unload_function("short", std::slice::from_ref(&short));
+        assert_eq!((cached_of(&short), cached_of(&long)), (0, 1));
+        assert!(runtime.invoke("short", "ping").await.is_err());
+        assert_eq!(runtime.invoke("long", "ping").await.unwrap(), "ping");
+    }
+
+    #[tokio::test]
+    async fn test_compiled_cache_moves_with_its_artifact() {
+        let dir = tempfile::tempdir().unwrap();
+        let staging = write_component(&dir, "echo.staging", &echo_component(None));
+        let stored = write_component(&dir, "echo", &echo_component(Some("old")));
+        let cached = || -> Vec<String> {
+            let mut names: Vec<String> = std::fs::read_dir(dir.path())
+                .unwrap()
+                .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+                .filter(|name| name.ends_with(".cwasm"))
+                .collect();
+            names.sort();
+            names
+        };
+
+        let runtime = WasmtimeRuntime::new().unwrap();
+        runtime.load_function("echo", &staging).await.unwrap();
+        let key = cached()[0]
+            .strip_prefix("echo.staging.wat.")
+            .unwrap()
+            .to_string();
+        std::fs::rename(&staging, &stored).unwrap();
+        runtime.move_compiled(&staging, &stored);
+        assert_eq!(cached(), [format!("echo.wat.{}", key)]);
+
+        // A restart finds the moved entry for the stored artifact
+        let runtime = WasmtimeRuntime::new().unwrap();
+        runtime.load_function("echo", &stored).await.unwrap();
+        assert_eq!(cached(), [format!("echo.wat.{}", key)]);
+
+        runtime.remove_compiled(std::slice::from_ref(&stored));
+        assert!(cached().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_staged_function_goes_live_when_renamed() {
+        let runtime = WasmtimeRuntime::new().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_component(&dir, "echo", &echo_component(None));
+
+        runtime.load_function("echo.staging", &path).await.unwrap();
+        assert!(runtime.invoke("echo", "ping").await.is_err());
+
+        runtime.rename_function("echo.staging", "echo");
+        assert_eq!(runtime.invoke("echo", "ping").await.unwrap(), "ping");
+        assert!(runtime.invoke("echo.staging", "ping").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_lazy_registration() {
+        let runtime = WasmtimeRuntime::new().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_component(&dir, "echo", &echo_component(None));
+
+        runtime.register_function("echo", &path);
+        assert_eq!(runtime.cache.pin().len(), 0);
+
+        assert_eq!(runtime.invoke("echo", "ping").await.unwrap(), "ping");
+        assert_eq!(runtime.cache.pin().len(), 1);
+        assert_eq!(runtime.pending.pin().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_load_reports_init_failure() {
+        let runtime = WasmtimeRuntime::new().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_component(&dir, "broken", &echo_component(Some("no config")));
+
+        let err = runtime.load_function("broken", &path).await.unwrap_err();
+        assert!(err.to_string().contains("no config"));
+        assert!(runtime.invoke("broken", "ping").await.is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let runtime = WasmtimeRuntime::new().unwrap();
+        let validate = |wat: &str| runtime.validate(&wat::parse_str(wat).unwrap());
+
+        assert!(validate(&echo_component(None)).is_ok());
+
+        let err = runtime.validate(b"dummy content").unwrap_err();
+        assert!(err.to_string().contains("not a WebAssembly component"));
+
+        let err = validate("(component)").unwrap_err();
+        assert!(err.to_string().contains("does not export `handle"));
+
+        let err = validate(
+            r#"(component
+                (instance $h)
+                (export "wasi:http/incoming-handler@0.2.0" (instance $h)))"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("wasi:http/incoming-handler"));
+
+        let err = validate(
+            r#"(component
+                (core module $m (func (export "handle") (param i32) (result i32) local.get 0))
+                (core instance $i (instantiate $m))
+                (func (export "handle") (param "input" u32) (result u32)
+                    (canon lift (core func $i "handle"))))"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("func(input: string) -> string"));
+
+        let with_import = echo_component(None).replacen(
+            "(component",
+            r#"(component (import "host-log" (func))"#,
+            1,
+        );
+        let err = validate(&with_import).unwrap_err();
+        assert!(err.to_string().contains("unsupported import"));
+        assert!(format!("{:#}", err).contains("host-log"));
+    }
+
+    #[tokio::test]
+    async fn test_preview1_module() {
+        let runtime = WasmtimeRuntime::new().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+
+        let path = dir.path().join("echo.wasm");
+        std::fs::write(&path, echo_module(0)).unwrap();
+        let path = path.to_str().unwrap();
+        runtime.load_function("echo", path).await.unwrap();
+        assert_eq!(runtime.invoke("echo", "ping").await.unwrap(), "ping");
+
+        // Each session message runs the command again
+        let mut session = runtime.open_session("echo").await.unwrap();
+        assert_eq!(session.invoke("a").await.unwrap(), "a");
+        assert_eq!(session.invoke("b").await.unwrap(), "b");
+
+        let failing = dir.path().join("failing.wasm");
+        std::fs::write(&failing, echo_module(3)).unwrap();
+        runtime
+            .load_function("failing", failing.to_str().unwrap())
+            .await
+            .unwrap();
+        let err = runtime.invoke("failing", "ping").await.unwrap_err();
+        assert!(err.to_string().contains("exited with status 3"));
+    }
+
+    #[test]
+    fn test_validate_preview1_module() {
+        let runtime = WasmtimeRuntime::new().unwrap();
+        assert!(runtime.validate(&echo_module(0)).is_ok());
+
+        let err = runtime
+            .validate(&wat::parse_str("(module)").unwrap())
+            .unwrap_err();
+        assert!(err.to_string().contains("does not export `_start`"));
+
+        let err = runtime
+            .validate(
+                &wat::parse_str(
+                    r#"(module (import "env" "host" (func)) (func (export "_start")))"#,
+                )
+                .unwrap(),
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("unsupported import"));
+    }
+
+    #[tokio::test]
+    async fn test_host_call() {
+        use crate::domain::wasm_runtime::MockFunctionInvoker;
+
+        // `handle` forwards its input to the function named "target"
+        let wat = r#"
+            (component
+                (import "fluor:fun/host" (instance $host
+                    (export "call" (func (param "function" string) (param "input" string)
+                        (result (result string (error string)))))))
+                (core module $mem
+                    (memory (export "mem") 1)
+                    (global $bump (mut i32) (i32.const 1024))
+                    (func (export "realloc") (param i32 i32 i32 i32) (result i32)
+                        (local $p i32)
+                        (local.set $p (global.get $bump))
+                        (global.set $bump (i32.add (global.get $bump) (local.get 3)))
+                        (local.get $p)))
+                (core instance $mem (instantiate $mem))
+                (alias export $host "call" (func $call))
+                (core func $call (canon lower (func $call)
+                    (memory $mem "mem") (realloc (func $mem "realloc"))))
+                (core module $m
+                    (import "host" "mem" (memory 1))
+                    (import "host" "call" (func $call (param i32 i32 i32 i32 i32)))
+                    (data (i32.const 16) "target")
+                    (func (export "handle") (param i32 i32) (result i32)
+                        (call $call (i32.const 16) (i32.const 6)
+                            (local.get 0) (local.get 1) (i32.const 32))
+                        i32.const 36))
+                (core instance $i (instantiate $m (with "host" (instance
+                    (export "mem" (memory $mem "mem"))
+                    (export "call" (func $call))))))
+                (func (export "handle") (param "input" string) (result string)
+                    (canon lift (core func $i "handle")
+                        (memory $mem "mem") (realloc (func $mem "realloc")))))"#;
+
+        let mut invoker = MockFunctionInvoker::new();
+        invoker
+            .expect_invoke_from()
+            .withf(|caller, target, input| {
+                caller == "caller" && target == "target" && input == "ping"
+            })
+            .returning(|_, _, input| Ok(format!("target got {}", input)));
+        let invoker: Arc<dyn FunctionInvoker> = Arc::new(invoker);
+
+        let runtime = WasmtimeRuntime::new().unwrap();
+        runtime.set_invoker(Arc::downgrade(&invoker));
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_component(&dir, "caller", wat);
+
+        runtime.load_function("caller", &path).await.unwrap();
+        assert_eq!(
+            runtime.invoke("caller", "ping").await.unwrap(),
+            "target got ping"
+        );
+    }
+}
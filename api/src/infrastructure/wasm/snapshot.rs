@@ -0,0 +1,118 @@
+use super::runtime::COMPONENT_HEADER;
+use crate::domain::wasm_runtime::Snapshotter;
+use async_trait::async_trait;
+use tokio::io::AsyncReadExt;
+use tokio::process::Command;
+
+/// Pre-initializes core modules with the `wizer` CLI, which runs the guest's
+/// `wizer.initialize` export and writes out the resulting memory as a new binary.
+/// Wizer can't take components apart, so those are turned away up front.
+#[derive(Debug, Clone)]
+pub struct WizerSnapshotter {
+    binary: String,
+}
+
+impl WizerSnapshotter {
+    pub fn new(binary: impl Into<String>) -> Self {
+        Self {
+            binary: binary.into(),
+        }
+    }
+
+    /// Snapshots are opt-in server-wide: only enabled when `SNAPSHOT_WIZER_PATH` is set.
+    pub fn from_env() -> Option<Self> {
+        std::env::var("SNAPSHOT_WIZER_PATH").ok().map(Self::new)
+    }
+}
+
+#[async_trait]
+impl Snapshotter for WizerSnapshotter {
+    async fn snapshot(&self, input_path: &str, output_path: &str) -> anyhow::Result<()> {
+        if is_component(input_path).await? {
+            anyhow::bail!(
+                "only core modules can be snapshotted; pre-initialize components when building them"
+            );
+        }
+
+        let output = Command::new(&self.binary)
+            .args(["--allow-wasi", "-o", output_path, input_path])
+            .output()
+            .await?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "{} exited with {}: {}",
+                self.binary,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(())
+    }
+}
+
+async fn is_component(path: &str) -> anyhow::Result<bool> {
+    let mut header = [0u8; 8];
+    let mut file = tokio::fs::File::open(path).await?;
+    let read = file.read(&mut header).await?;
+    Ok(read == header.len() && header == COMPONENT_HEADER)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+    use wasmtime::{Engine, Instance, Module, Store};
+
+    #[tokio::test]
+    async fn test_rejects_components() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("component.wasm");
+        let output = dir.path().join("component.snapshot.wasm");
+        std::fs::write(&input, wat::parse_str("(component)").unwrap()).unwrap();
+
+        // The binary is never run, so it doesn't have to exist
+        let snapshotter = WizerSnapshotter::new("/nonexistent/wizer");
+        let err = snapshotter
+            .snapshot(input.to_str().unwrap(), output.to_str().unwrap())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("only core modules"), "{:#}", err);
+        assert!(!output.exists());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a wizer binary: set SNAPSHOT_WIZER_PATH and run with --ignored"]
+    async fn test_wizer_snapshots_module() {
+        let snapshotter =
+            WizerSnapshotter::from_env().expect("SNAPSHOT_WIZER_PATH must point at a wizer binary");
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("module.wasm");
+        let output = dir.path().join("module.snapshot.wasm");
+        let module = r#"
+            (module
+                (memory (export "memory") 1)
+                (func (export "wizer.initialize")
+                    (i32.store (i32.const 0) (i32.const 42)))
+                (func (export "get") (result i32)
+                    (i32.load (i32.const 0))))"#;
+        std::fs::write(&input, wat::parse_str(module).unwrap()).unwrap();
+
+        snapshotter
+            .snapshot(input.to_str().unwrap(), output.to_str().unwrap())
+            .await
+            .unwrap();
+
+        // The snapshot starts out with what the initializer wrote
+        let engine = Engine::default();
+        let module = Module::from_file(&engine, &output).unwrap();
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new(&mut store, &module, &[]).unwrap();
+        let get = instance
+            .get_typed_func::<(), i32>(&mut store, "get")
+            .unwrap();
+        assert_eq!(get.call(&mut store, ()).unwrap(), 42);
+        assert!(instance.get_func(&mut store, "wizer.initialize").is_none());
+    }
+}
@@ -0,0 +1,130 @@
+use crate::domain::entities::{AlertCondition, AlertRule, AlertState, DomainError};
+use crate::domain::ports::AlertNotifier;
+use async_trait::async_trait;
+use serde_json::{Value, json};
+use std::time::Duration;
+
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Posts alert state changes as Slack incoming-webhook messages, which other chat
+/// tools and generic receivers accept as well.
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+}
+
+impl WebhookNotifier {
+    pub fn new() -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(NOTIFY_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl Default for WebhookNotifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl AlertNotifier for WebhookNotifier {
+    async fn notify(&self, webhook: &str, rule: &AlertRule) -> Result<(), DomainError> {
+        let response = self
+            .client
+            .post(webhook)
+            .json(&payload(rule))
+            .send()
+            .await
+            .map_err(|e| DomainError::Internal(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(DomainError::Internal(format!(
+                "Webhook responded with {}",
+                response.status()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Slack message for `rule`'s current state: a one-line `text` for notifications,
+/// and an attachment colored by state with the details.
+pub fn payload(rule: &AlertRule) -> Value {
+    let (label, color) = match rule.status.state {
+        AlertState::Firing => ("FIRING", "danger"),
+        AlertState::Ok => ("RESOLVED", "good"),
+    };
+    let window = format!("{}m", rule.window_secs.div_ceil(60));
+    let observed = match (&rule.condition, rule.status.value) {
+        (_, None) => "no data".to_string(),
+        (AlertCondition::ErrorRate { .. }, Some(v)) => format!("{:.1}%", v * 100.0),
+        (AlertCondition::LatencyP99 { .. }, Some(v)) => format!("{:.0}ms", v),
+        (AlertCondition::Absence, Some(v)) => format!("{} invocations", v),
+    };
+    let condition = match &rule.condition {
+        AlertCondition::ErrorRate { threshold, .. } => {
+            format!("error rate above {:.1}%", threshold * 100.0)
+        }
+        AlertCondition::LatencyP99 { threshold_ms } => format!("p99 above {}ms", threshold_ms),
+        AlertCondition::Absence => "no invocations".to_string(),
+    };
+
+    json!({
+        "text": format!(
+            "[{}] {}: {} of `{}` over the last {}",
+            label, rule.name, condition, rule.function, window
+        ),
+        "attachments": [{
+            "color": color,
+            "fields": [
+                { "title": "Function", "value": rule.function, "short": true },
+                { "title": "Observed", "value": observed, "short": true },
+                { "title": "Condition", "value": condition, "short": true },
+                { "title": "Window", "value": window, "short": true },
+            ],
+            "ts": rule.status.since.map(|t| t.timestamp()),
+        }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::entities::AlertStatus;
+
+    #[test]
+    fn test_payload() {
+        let mut rule = AlertRule {
+            name: "checkout-errors".to_string(),
+            function: "checkout".to_string(),
+            condition: AlertCondition::ErrorRate {
+                threshold: 0.05,
+                min_invocations: 1,
+            },
+            window_secs: 300,
+            webhooks: Vec::new(),
+            status: AlertStatus {
+                state: AlertState::Firing,
+                value: Some(0.25),
+                ..Default::default()
+            },
+        };
+
+        let firing = payload(&rule);
+        assert_eq!(
+            firing["text"],
+            "[FIRING] checkout-errors: error rate above 5.0% of `checkout` over the last 5m"
+        );
+        assert_eq!(firing["attachments"][0]["color"], "danger");
+        assert_eq!(firing["attachments"][0]["fields"][1]["value"], "25.0%");
+
+        rule.status.state = AlertState::Ok;
+        rule.status.value = None;
+        let resolved = payload(&rule);
+        assert!(resolved["text"].as_str().unwrap().starts_with("[RESOLVED]"));
+        assert_eq!(resolved["attachments"][0]["color"], "good");
+        assert_eq!(resolved["attachments"][0]["fields"][1]["value"], "no data");
+    }
+}
@@ -1,15 +1,33 @@
 use actix_cors::Cors;
-use actix_web::{App, HttpServer, web};
-use api::domain::wasm_runtime::WasmRuntime;
+use actix_web::{App, HttpServer, guard, web};
+use api::domain::wasm_runtime::{FunctionInvoker, WasmRuntime};
 use std::sync::Arc;
+use std::time::Duration;
 use tracing::{error, info};
 
 use api::application::{
-    auth_service::AuthService, function_service::FunctionService,
-    invocation_service::InvocationService, trigger_service::TriggerService,
+    admission::AdmissionController,
+    alert_service::AlertService,
+    audit_service::AuditService,
+    auth_service::AuthService,
+    background_service::BackgroundService,
+    function_service::FunctionService,
+    invocation_service::InvocationService,
+    manifest_service::ManifestService,
+    node_lease::NodeLease,
+    rate_limit_service::RateLimitService,
+    response_cache::ResponseCache,
+    trigger_service::TriggerService,
+    websocket_service::{WebSocketLimits, WebSocketService},
+    workflow_service::WorkflowService,
 };
-use api::infrastructure::db::sqlite::SqliteRepository;
-use api::infrastructure::wasm::runtime::WasmtimeRuntime;
+use api::domain::ports::RateLimitStore;
+use api::infrastructure::http::client_ip::TrustedProxies;
+use api::infrastructure::prometheus::PrometheusExporter;
+use api::infrastructure::rate_limit::InMemoryRateLimitStore;
+use api::infrastructure::wasm::runtime::{POOL_INSTANCES, WasmtimeRuntime};
+use api::infrastructure::wasm::snapshot::WizerSnapshotter;
+use api::infrastructure::webhook::WebhookNotifier;
 use api::{application, infrastructure};
 
 use mimalloc::MiMalloc;
@@ -23,26 +41,36 @@ async fn main() -> std::io::Result<()> {
     let port_str = std::env::var("PORT").unwrap_or("8080".to_string());
 
     // 0. Observability
-    infrastructure::telemetry::init_telemetry().expect("Failed to init telemetry");
+    let telemetry_backend = infrastructure::db::init_telemetry_backend();
+    let prometheus = PrometheusExporter::from_env();
+    infrastructure::telemetry::init_telemetry(
+        telemetry_backend.recorder.clone(),
+        prometheus.clone(),
+    )
+    .expect("Failed to init telemetry");
+    if prometheus.is_some() {
+        info!("Prometheus metrics served at /metrics");
+    }
     info!("Starting Fluor API at http://0.0.0.0:{}", port_str);
 
     // 1. infrastructure / Adapters
-    let pool = infrastructure::db::sqlite::init_db().await;
-    let repo = Arc::new(SqliteRepository::new(pool.clone()));
-
-    let clickhouse_url =
-        std::env::var("CLICKHOUSE_URL").unwrap_or("http://localhost:8123".to_string());
-    let clickhouse_user = std::env::var("CLICKHOUSE_USER").unwrap_or("default".to_string());
-    let clickhouse_password =
-        std::env::var("CLICKHOUSE_PASSWORD").unwrap_or("password".to_string());
-    let clickhouse_db = std::env::var("CLICKHOUSE_DB").unwrap_or("default".to_string());
-
-    let clickhouse_repo = Arc::new(infrastructure::db::clickhouse::ClickHouseRepository::new(
-        &clickhouse_url,
-        &clickhouse_user,
-        &clickhouse_password,
-        &clickhouse_db,
-    ));
+    let repo = infrastructure::db::init_repository().await;
+
+    // One node serves from a database at a time; others wait here as standbys
+    let node_id = std::env::var("NODE_ID")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .unwrap_or_else(|_| uuid::Uuid::new_v4().to_string());
+    let mut node_lease = NodeLease::new(repo.clone(), node_id);
+    if let Some(secs) = std::env::var("NODE_LEASE_TTL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&secs| secs > 0)
+    {
+        node_lease = node_lease.with_ttl(Duration::from_secs(secs));
+    }
+    let node_lease = Arc::new(node_lease);
+    node_lease.acquire().await;
+    info!("Node {} holds the node lease", node_lease.node());
 
     let runtime = Arc::new(WasmtimeRuntime::new().expect("Failed to init Wasmtime"));
     let wasm_storage_path = std::env::var("WASM_STORAGE_PATH").unwrap_or("wasm_data".to_string());
@@ -51,33 +79,163 @@ async fn main() -> std::io::Result<()> {
     let jwt_secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
 
     // 2. Application / Services
-    let auth_service = Arc::new(AuthService::new(repo.clone(), password_pepper, jwt_secret));
-    let function_service = Arc::new(FunctionService::new(
-        repo.clone(),
-        runtime.clone(),
-        wasm_storage_path,
+    let response_cache_bytes = std::env::var("RESPONSE_CACHE_MAX_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(64 * 1024 * 1024);
+    let response_cache = Arc::new(ResponseCache::new(response_cache_bytes));
+    let audit_service = Arc::new(AuditService::new(repo.clone()));
+    let auth_service = Arc::new(
+        AuthService::new(repo.clone(), password_pepper, jwt_secret)
+            .with_audit_log(audit_service.clone()),
+    );
+    // Keep a share of the instance pool free for WebSocket sessions and init calls
+    let max_in_flight = std::env::var("MAX_IN_FLIGHT_INVOCATIONS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(POOL_INSTANCES as usize * 9 / 10);
+    // WebSocket sessions keep an instance each while open, so they are held to the
+    // share left over, with some room kept for init calls
+    let reserved = (POOL_INSTANCES as usize).saturating_sub(max_in_flight);
+    let max_sessions = std::env::var("WS_MAX_SESSIONS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(reserved * 4 / 5)
+        .min(reserved);
+    let queue_timeout_ms = std::env::var("INVOCATION_QUEUE_TIMEOUT_MS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(5000);
+    let mut invocation_service =
+        InvocationService::new(repo.clone(), repo.clone(), runtime.clone()).with_admission(
+            AdmissionController::new(max_in_flight, Duration::from_millis(queue_timeout_ms))
+                .with_max_sessions(max_sessions),
+        );
+    if let Some(max_call_depth) = std::env::var("MAX_CALL_DEPTH")
+        .ok()
+        .and_then(|v| v.parse().ok())
+    {
+        invocation_service = invocation_service.with_max_call_depth(max_call_depth);
+    }
+    if let Some(recorder) = telemetry_backend.recorder {
+        info!("Embedded telemetry backend enabled");
+        invocation_service = invocation_service.with_recorder(recorder);
+    }
+    let invocation_service = Arc::new(invocation_service);
+    let invoker: Arc<dyn FunctionInvoker> = invocation_service.clone();
+    runtime.set_invoker(Arc::downgrade(&invoker));
+    let mut function_service =
+        FunctionService::new(repo.clone(), runtime.clone(), wasm_storage_path)
+            .with_response_cache(response_cache.clone())
+            .with_audit_log(audit_service.clone())
+            .with_invocation_service(invocation_service.clone());
+    if let Some(max_wasm_bytes) = std::env::var("MAX_WASM_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+    {
+        function_service = function_service.with_max_wasm_bytes(max_wasm_bytes);
+    }
+    if let Some(snapshotter) = WizerSnapshotter::from_env() {
+        info!("Function snapshots enabled");
+        function_service = function_service.with_snapshotter(Arc::new(snapshotter));
+    }
+    let function_service = Arc::new(function_service);
+    let trigger_service = Arc::new(
+        TriggerService::new(repo.clone(), invocation_service.clone())
+            .with_audit_log(audit_service.clone())
+            .with_response_cache(response_cache.clone()),
+    );
+    let manifest_service = Arc::new(
+        ManifestService::new(
+            repo.clone(),
+            function_service.clone(),
+            trigger_service.clone(),
+        )
+        .with_audit_log(audit_service.clone()),
+    );
+    let websocket_service = Arc::new(WebSocketService::new(
+        invocation_service.clone(),
+        WebSocketLimits::from_env(),
     ));
-    let invocation_service = Arc::new(InvocationService::new(
-        repo.clone(),
+    // Buckets in the database outlive the node, and carry over to a standby
+    let rate_limit_store: Arc<dyn RateLimitStore> =
+        match std::env::var("RATE_LIMIT_STORE").as_deref() {
+            Ok("database" | "sqlite") => repo.clone(),
+            _ => Arc::new(InMemoryRateLimitStore::new()),
+        };
+    let mut rate_limit_service = RateLimitService::new(rate_limit_store, auth_service.clone());
+    if let Some(secs) = std::env::var("RATE_LIMIT_PRUNE_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&secs| secs > 0)
+    {
+        rate_limit_service = rate_limit_service.with_prune_interval(Duration::from_secs(secs));
+    }
+    let rate_limit_service = Arc::new(rate_limit_service);
+    rate_limit_service.clone().start();
+    // Only these peers may report the client address in X-Forwarded-For
+    let trusted_proxies = TrustedProxies::from_env();
+    let background_service = Arc::new(
+        BackgroundService::new(
+            invocation_service.clone(),
+            repo.clone(),
+            repo.clone(),
+            repo.clone(),
+        )
+        .with_audit_log(audit_service.clone()),
+    );
+    let workflow_service = Arc::new(
+        WorkflowService::new(repo.clone(), repo.clone(), invocation_service.clone())
+            .with_audit_log(audit_service.clone()),
+    );
+    let mut alert_service = AlertService::new(
         repo.clone(),
-        runtime.clone(),
-    ));
-    let trigger_service = Arc::new(TriggerService::new(
         repo.clone(),
-        invocation_service.clone(),
-    ));
+        telemetry_backend.repository.clone(),
+        Arc::new(WebhookNotifier::new()),
+    )
+    .with_audit_log(audit_service.clone());
+    if let Ok(webhooks) = std::env::var("ALERT_WEBHOOK_URLS") {
+        alert_service = alert_service.with_default_webhooks(
+            webhooks
+                .split(',')
+                .map(str::trim)
+                .filter(|url| !url.is_empty())
+                .map(str::to_string)
+                .collect(),
+        );
+    }
+    // Zero leaves evaluation to `POST /alerts/evaluate`
+    let alert_interval_secs = std::env::var("ALERT_EVALUATION_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(60);
+    if alert_interval_secs > 0 {
+        alert_service = alert_service.with_interval(Duration::from_secs(alert_interval_secs));
+    }
+    let alert_service = Arc::new(alert_service);
+    if alert_interval_secs > 0 {
+        alert_service.clone().start();
+    }
     let telemetry_service =
-        application::telemetry_service::TelemetryService::new(clickhouse_repo.clone());
+        application::telemetry_service::TelemetryService::new(telemetry_backend.repository);
 
     // 3. Bootstrap (Preload)
     if let Err(e) = invocation_service.load_routes().await {
         error!("Failed to load routes: {}", e);
     }
 
+    // Lazy mode defers compilation (or loading compiled code from disk) to the first call
+    let lazy_load = std::env::var("FUNCTION_PRELOAD").is_ok_and(|v| v == "lazy");
+
     if let Ok(funcs) = function_service.list_functions().await {
         for f in funcs {
+            runtime.set_env(&f.name, &f.env);
             if !f.executable.is_empty() {
-                if let Err(e) = runtime.load_function(&f.name, &f.executable) {
+                let artifact = function_service.artifact_path(&f);
+                if lazy_load {
+                    runtime.register_function(&f.name, &artifact);
+                } else if let Err(e) = runtime.load_function(&f.name, &artifact).await {
                     error!("Failed to preload function {}: {}", f.name, e);
                 } else {
                     info!("Preloaded function {}", f.name);
@@ -86,13 +244,25 @@ async fn main() -> std::io::Result<()> {
         }
     }
 
+    // Runs interrupted by a previous shutdown continue once their functions are loaded
+    match workflow_service.resume().await {
+        Ok(0) => {}
+        Ok(n) => info!("Resumed {} workflow runs", n),
+        Err(e) => error!("Failed to resume workflow runs: {}", e),
+    }
+    match background_service.recover().await {
+        Ok(0) => {}
+        Ok(n) => info!("Resumed {} background invocations", n),
+        Err(e) => error!("Failed to resume background invocations: {}", e),
+    }
+
     let port = std::env::var("PORT")
         .unwrap_or("8080".to_string())
         .parse::<u16>()
         .unwrap_or(8080);
     let host = std::env::var("HOST").unwrap_or("0.0.0.0".to_string());
 
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         let cors = Cors::default()
             .allow_any_origin()
             .allow_any_method()
@@ -103,21 +273,58 @@ async fn main() -> std::io::Result<()> {
             .app_data(web::Data::new(function_service.clone()))
             .app_data(web::Data::new(trigger_service.clone()))
             .app_data(web::Data::new(invocation_service.clone()))
+            .app_data(web::Data::new(manifest_service.clone()))
+            .app_data(web::Data::new(websocket_service.clone()))
+            .app_data(web::Data::new(rate_limit_service.clone()))
+            .app_data(web::Data::new(response_cache.clone()))
+            .app_data(web::Data::new(background_service.clone()))
+            .app_data(web::Data::new(workflow_service.clone()))
             .app_data(web::Data::new(telemetry_service.clone()))
+            .app_data(web::Data::new(alert_service.clone()))
+            .app_data(web::Data::new(audit_service.clone()))
+            .app_data(web::Data::new(trusted_proxies.clone()))
             .wrap(cors)
+            .configure(infrastructure::http::handlers::alerts::config)
+            .configure(infrastructure::http::handlers::audit::config)
             .configure(infrastructure::http::handlers::auth::config)
+            .configure(infrastructure::http::handlers::cache::config)
+            .configure(infrastructure::http::handlers::dead_letters::config)
             .configure(infrastructure::http::handlers::functions::config)
+            .configure(infrastructure::http::handlers::manifest::config)
+            .configure(|cfg| {
+                if let Some(exporter) = &prometheus {
+                    infrastructure::http::handlers::metrics::config(cfg, exporter.clone());
+                }
+            })
             .configure(infrastructure::http::handlers::triggers::config)
             .configure(infrastructure::http::handlers::telemetry::config)
             .configure(infrastructure::http::handlers::users::config)
+            .configure(infrastructure::http::handlers::workflows::config)
             .service(
                 web::scope("/function")
+                    .route(
+                        "/{tail:.*}",
+                        web::get()
+                            .guard(guard::fn_guard(
+                                infrastructure::http::handlers::websocket::is_upgrade,
+                            ))
+                            .to(infrastructure::http::handlers::websocket::websocket),
+                    )
                     .default_service(web::to(infrastructure::http::handlers::gateway::gateway)),
             )
     })
     .bind((host, port))?
-    .run()
-    .await?;
+    .run();
+
+    // A node that loses the lease stops serving, so a standby can take over alone
+    let handle = server.handle();
+    let lease = node_lease.clone();
+    tokio::spawn(async move {
+        lease.hold().await;
+        handle.stop(false).await;
+    });
+    server.await?;
+    node_lease.release().await;
 
     infrastructure::telemetry::shutdown_telemetry();
     Ok(())
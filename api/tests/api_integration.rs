@@ -1,15 +1,28 @@
+use actix_web::dev::{Service, ServiceResponse};
 use actix_web::{App, test, web};
+use api::application::audit_service::AuditService;
 use api::application::auth_service::AuthService;
+use api::application::background_service::BackgroundService;
 use api::application::function_service::FunctionService;
 use api::application::invocation_service::InvocationService;
+use api::application::manifest_service::ManifestService;
+use api::application::rate_limit_service::RateLimitService;
+use api::application::response_cache::ResponseCache;
 use api::application::telemetry_service::TelemetryService;
 use api::application::trigger_service::TriggerService;
-use api::domain::wasm_runtime::WasmRuntime;
-use api::infrastructure::db::clickhouse::ClickHouseRepository;
+use api::application::websocket_service::{WebSocketLimits, WebSocketService};
+use api::application::workflow_service::WorkflowService;
+use api::domain::wasm_runtime::{WasmRuntime, WasmSession};
+use api::infrastructure::db::embedded_telemetry::EmbeddedTelemetryRepository;
 use api::infrastructure::db::sqlite::{SqliteRepository, create_pool};
+use api::infrastructure::http::client_ip::TrustedProxies;
 use api::infrastructure::http::handlers;
+use api::infrastructure::prometheus::PrometheusExporter;
+use api::infrastructure::rate_limit::InMemoryRateLimitStore;
+use api::infrastructure::telemetry::{RecorderLayer, trace_context_env};
 use async_trait::async_trait;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use tempfile::tempdir;
 
@@ -27,25 +40,81 @@ impl TestRuntime {
     }
 }
 
+impl Default for TestRuntime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+pub struct EchoSession;
+
+#[async_trait]
+impl WasmSession for EchoSession {
+    async fn invoke(&mut self, input: &str) -> anyhow::Result<String> {
+        Ok(input.to_string())
+    }
+}
+
 #[async_trait]
 impl WasmRuntime for TestRuntime {
-    fn load_function(&self, name: &str, wasm_path: &str) -> anyhow::Result<()> {
+    fn validate(&self, bytes: &[u8]) -> anyhow::Result<()> {
+        anyhow::ensure!(!bytes.starts_with(b"not wasm"), "not a component");
+        Ok(())
+    }
+
+    fn set_env(&self, _function_name: &str, _env: &BTreeMap<String, String>) {}
+
+    async fn load_function(&self, name: &str, wasm_path: &str) -> anyhow::Result<()> {
         let mut functions = self.functions.lock().unwrap();
         functions.insert(name.to_string(), wasm_path.to_string());
         Ok(())
     }
 
+    fn unload_function(&self, name: &str, _paths: &[String]) {
+        self.functions.lock().unwrap().remove(name);
+    }
+
+    fn rename_function(&self, from: &str, to: &str) {
+        let mut functions = self.functions.lock().unwrap();
+        if let Some(loaded) = functions.remove(from) {
+            functions.insert(to.to_string(), loaded);
+        }
+    }
+
+    fn move_compiled(&self, _from: &str, _to: &str) {}
+
+    fn remove_compiled(&self, _paths: &[String]) {}
+
     async fn invoke(&self, name: &str, _params: &str) -> anyhow::Result<String> {
         let functions = self.functions.lock().unwrap();
-        if functions.contains_key(name) {
+        if name.starts_with("failing-") {
+            Err(anyhow::anyhow!("Function {} failed", name))
+        } else if name.starts_with("traced-") {
+            // What a guest finds in its environment
+            let env: HashMap<_, _> = trace_context_env().into_iter().collect();
+            Ok(serde_json::to_string(&env)?)
+        } else if functions.contains_key(name) {
             let resp = serde_json::json!({ "message": format!("Hello from {}", name) });
             Ok(serde_json::to_string(&resp)?)
         } else {
             Err(anyhow::anyhow!("Function {} not loaded", name))
         }
     }
+
+    async fn open_session(&self, name: &str) -> anyhow::Result<Box<dyn WasmSession>> {
+        let functions = self.functions.lock().unwrap();
+        if functions.contains_key(name) {
+            Ok(Box::new(EchoSession))
+        } else {
+            Err(anyhow::anyhow!("Function {} not loaded", name))
+        }
+    }
 }
 
+/// The reverse proxy `spawn_app` trusts to report client addresses.
+const PROXY_IP: &str = "192.0.2.1";
+
 async fn spawn_app() -> (
     impl actix_web::dev::Service<
         actix_http::Request,
@@ -53,6 +122,19 @@ async fn spawn_app() -> (
         Error = actix_web::Error,
     >,
     tempfile::TempDir,
+) {
+    spawn_app_with_telemetry(Arc::new(EmbeddedTelemetryRepository::new())).await
+}
+
+async fn spawn_app_with_telemetry(
+    telemetry: Arc<EmbeddedTelemetryRepository>,
+) -> (
+    impl actix_web::dev::Service<
+        actix_http::Request,
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+    >,
+    tempfile::TempDir,
 ) {
     // 1. Setup Data
     let temp_dir = tempdir().unwrap();
@@ -62,40 +144,65 @@ async fn spawn_app() -> (
     let pool = create_pool(db_url).await;
     let repo = Arc::new(SqliteRepository::new(pool.clone()));
 
-    // Dummy ClickHouse (won't be used for critical path)
-    let clickhouse_repo = Arc::new(ClickHouseRepository::new(
-        "http://localhost:8123",
-        "default",
-        "password",
-        "default",
-    ));
-
     // Use Mock Runtime
     let runtime = Arc::new(TestRuntime::new());
     let wasm_storage_path = temp_dir.path().join("wasm").to_str().unwrap().to_string();
     std::fs::create_dir_all(&wasm_storage_path).unwrap();
 
     // 2. Services
-    let auth_service = Arc::new(AuthService::new(
-        repo.clone(),
-        "secret".to_string(),
-        "jwt_secret".to_string(),
-    ));
-    let function_service = Arc::new(FunctionService::new(
-        repo.clone(),
-        runtime.clone(),
-        wasm_storage_path,
-    ));
-    let invocation_service = Arc::new(InvocationService::new(
-        repo.clone(),
-        repo.clone(),
-        runtime.clone(),
-    ));
-    let trigger_service = Arc::new(TriggerService::new(
-        repo.clone(),
+    let audit_service = Arc::new(AuditService::new(repo.clone()));
+    let auth_service = Arc::new(
+        AuthService::new(repo.clone(), "secret".to_string(), "jwt_secret".to_string())
+            .with_audit_log(audit_service.clone()),
+    );
+    let response_cache = Arc::new(ResponseCache::new(1024 * 1024));
+    let invocation_service = Arc::new(
+        InvocationService::new(repo.clone(), repo.clone(), runtime.clone())
+            .with_recorder(telemetry.clone()),
+    );
+    let function_service = Arc::new(
+        FunctionService::new(repo.clone(), runtime.clone(), wasm_storage_path)
+            .with_max_wasm_bytes(1024)
+            .with_response_cache(response_cache.clone())
+            .with_audit_log(audit_service.clone())
+            .with_invocation_service(invocation_service.clone()),
+    );
+    let trigger_service = Arc::new(
+        TriggerService::new(repo.clone(), invocation_service.clone())
+            .with_audit_log(audit_service.clone())
+            .with_response_cache(response_cache.clone()),
+    );
+    let manifest_service = Arc::new(
+        ManifestService::new(
+            repo.clone(),
+            function_service.clone(),
+            trigger_service.clone(),
+        )
+        .with_audit_log(audit_service.clone()),
+    );
+    let websocket_service = Arc::new(WebSocketService::new(
         invocation_service.clone(),
+        WebSocketLimits::default(),
+    ));
+    let rate_limit_service = Arc::new(RateLimitService::new(
+        Arc::new(InMemoryRateLimitStore::new()),
+        auth_service.clone(),
     ));
-    let telemetry_service = TelemetryService::new(clickhouse_repo);
+    let background_service = Arc::new(
+        BackgroundService::new(
+            invocation_service.clone(),
+            repo.clone(),
+            repo.clone(),
+            repo.clone(),
+        )
+        .with_audit_log(audit_service.clone()),
+    );
+    let workflow_service = Arc::new(
+        WorkflowService::new(repo.clone(), repo.clone(), invocation_service.clone())
+            .with_audit_log(audit_service.clone()),
+    );
+    let telemetry_service =
+        TelemetryService::new(telemetry).with_tail_interval(std::time::Duration::from_millis(20));
 
     // 3. Init Service
     // Call seed functions
@@ -108,18 +215,86 @@ async fn spawn_app() -> (
             .app_data(web::Data::new(function_service))
             .app_data(web::Data::new(trigger_service))
             .app_data(web::Data::new(invocation_service))
+            .app_data(web::Data::new(manifest_service))
+            .app_data(web::Data::new(websocket_service))
+            .app_data(web::Data::new(rate_limit_service))
+            .app_data(web::Data::new(response_cache))
+            .app_data(web::Data::new(background_service))
+            .app_data(web::Data::new(workflow_service))
             .app_data(web::Data::new(telemetry_service))
+            .app_data(web::Data::new(audit_service))
+            .app_data(web::Data::new(TrustedProxies::new(vec![
+                PROXY_IP.parse().unwrap(),
+            ])))
+            .configure(handlers::audit::config)
             .configure(handlers::auth::config)
+            .configure(handlers::cache::config)
+            .configure(handlers::dead_letters::config)
             .configure(handlers::functions::config)
+            .configure(handlers::manifest::config)
             .configure(handlers::triggers::config)
             .configure(handlers::telemetry::config)
-            .service(web::scope("/function").default_service(web::to(handlers::gateway::gateway))),
+            .configure(handlers::users::config)
+            .configure(handlers::workflows::config)
+            .service(
+                web::scope("/function")
+                    .route(
+                        "/{tail:.*}",
+                        web::get()
+                            .guard(actix_web::guard::fn_guard(handlers::websocket::is_upgrade))
+                            .to(handlers::websocket::websocket),
+                    )
+                    .default_service(web::to(handlers::gateway::gateway)),
+            ),
     )
     .await;
 
     (app, temp_dir)
 }
 
+/// Writes a stand-in binary into `dir`; the test runtime never looks inside.
+fn placeholder_wasm(dir: &Path) -> PathBuf {
+    let path = dir.join("placeholder.wasm");
+    std::fs::write(&path, "dummy wasm content").unwrap();
+    path
+}
+
+/// Deploys `name` from a placeholder binary, with `settings` added to the
+/// definition.
+async fn create_function<S>(app: &S, dir: &Path, name: &str, settings: serde_json::Value)
+where
+    S: Service<actix_http::Request, Response = ServiceResponse, Error = actix_web::Error>,
+{
+    let mut payload = serde_json::json!({
+        "name": name,
+        "language": "rust",
+        "executable": placeholder_wasm(dir).to_str().unwrap(),
+        "cpu": "0.1",
+        "memory": "128"
+    });
+    if let serde_json::Value::Object(settings) = settings {
+        payload.as_object_mut().unwrap().extend(settings);
+    }
+    let req = test::TestRequest::post()
+        .uri("/functions")
+        .set_json(&payload)
+        .to_request();
+    let resp = test::call_service(app, req).await;
+    assert!(resp.status().is_success(), "creating {}", name);
+}
+
+async fn create_trigger<S>(app: &S, trigger: serde_json::Value)
+where
+    S: Service<actix_http::Request, Response = ServiceResponse, Error = actix_web::Error>,
+{
+    let req = test::TestRequest::post()
+        .uri("/triggers")
+        .set_json(&trigger)
+        .to_request();
+    let resp = test::call_service(app, req).await;
+    assert!(resp.status().is_success(), "creating {}", trigger["name"]);
+}
+
 #[actix_rt::test]
 async fn test_auth_flow() {
     let (app, _td) = spawn_app().await;
@@ -186,6 +361,27 @@ async fn test_functions_full_crud() {
     let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
     assert_eq!(resp["cpu"], "0.2");
 
+    // A trigger still routing to the function holds it back
+    create_trigger(
+        &app,
+        serde_json::json!({
+            "name": "crud-trig",
+            "function": "crud-func",
+            "method": "GET",
+            "path": "/crud"
+        }),
+    )
+    .await;
+    let req = test::TestRequest::delete()
+        .uri("/functions/crud-func")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::CONFLICT);
+    let req = test::TestRequest::delete()
+        .uri("/triggers/crud-trig")
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+
     // Delete
     let req = test::TestRequest::delete()
         .uri("/functions/crud-func")
@@ -201,72 +397,1348 @@ async fn test_functions_full_crud() {
     assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
 }
 
+/// A `multipart/form-data` upload of `function` with `file` as its binary.
+fn multipart_upload(function: &serde_json::Value, file: &[u8]) -> (String, Vec<u8>) {
+    let boundary = "fluor-test-boundary";
+    let mut body = format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"function\"\r\n\
+         Content-Type: application/json\r\n\r\n{function}\r\n\
+         --{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"f.wasm\"\r\n\
+         Content-Type: application/wasm\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend_from_slice(file);
+    body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+    (format!("multipart/form-data; boundary={boundary}"), body)
+}
+
 #[actix_rt::test]
-async fn test_invocation() {
+async fn test_upload_rejects_oversized_and_invalid_binaries() {
     let (app, _td) = spawn_app().await;
-
-    // Create a persistent dummy wasm file
-    let temp_dir = std::env::temp_dir();
-    let path = temp_dir.join(format!("test-{}.wasm", uuid::Uuid::new_v4()));
-    std::fs::write(&path, "dummy wasm content").unwrap();
-    let path_str = path.to_str().unwrap().to_string();
-
-    // 1. Create Function (this triggers runtime.load_function in mock)
-    let payload = serde_json::json!({
-        "name": "invoke-func",
+    let function = serde_json::json!({
+        "name": "upload",
         "language": "rust",
-        "executable": path_str,
+        "executable": "",
         "cpu": "0.1",
         "memory": "128"
     });
+
+    for file in [vec![0u8; 4096], b"not wasm".to_vec()] {
+        let (content_type, body) = multipart_upload(&function, &file);
+        let req = test::TestRequest::post()
+            .uri("/functions")
+            .insert_header(("content-type", content_type))
+            .set_payload(body)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.status(),
+            actix_web::http::StatusCode::UNPROCESSABLE_ENTITY
+        );
+    }
+    let req = test::TestRequest::get()
+        .uri("/functions/upload")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+
+    let (content_type, body) = multipart_upload(&function, b"dummy wasm content");
     let req = test::TestRequest::post()
         .uri("/functions")
-        .set_json(&payload)
+        .insert_header(("content-type", content_type))
+        .set_payload(body)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::CREATED);
+
+    // Replacing the binary goes through the same limit
+    let (content_type, body) = multipart_upload(&function, &[0u8; 4096]);
+    let req = test::TestRequest::put()
+        .uri("/functions/upload")
+        .insert_header(("content-type", content_type))
+        .set_payload(body)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(
+        resp.status(),
+        actix_web::http::StatusCode::UNPROCESSABLE_ENTITY
+    );
+}
+
+#[actix_rt::test]
+async fn test_invocation() {
+    let (app, td) = spawn_app().await;
+
+    create_function(&app, td.path(), "invoke-func", serde_json::json!({})).await;
+    create_trigger(
+        &app,
+        serde_json::json!({
+            "name": "invoke-trig",
+            "function": "invoke-func",
+            "method": "POST",
+            "path": "/my-func"
+        }),
+    )
+    .await;
+
+    // Invoke via Gateway
+    let req = test::TestRequest::post()
+        .uri("/function/my-func")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert!(resp.status().is_success());
+
+    let body_bytes = test::read_body(resp).await;
+    let resp: serde_json::Value = serde_json::from_slice(&body_bytes).unwrap();
+
+    assert_eq!(resp["message"], "Hello from invoke-func");
+}
+
+#[actix_rt::test]
+async fn test_concurrency_limit_backpressure() {
+    let (app, td) = spawn_app().await;
+
+    // No slots and no queue: every invocation is turned away
+    create_function(
+        &app,
+        td.path(),
+        "limited-func",
+        serde_json::json!({ "max_concurrency": 0 }),
+    )
+    .await;
+    create_trigger(
+        &app,
+        serde_json::json!({
+            "name": "limited-trig",
+            "function": "limited-func",
+            "method": "POST",
+            "path": "/limited"
+        }),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/function/limited")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(
+        resp.status(),
+        actix_web::http::StatusCode::TOO_MANY_REQUESTS
+    );
+    assert!(resp.headers().contains_key("retry-after"));
+
+    // Lifting the limit takes effect on the route without touching the trigger
+    let req = test::TestRequest::put()
+        .uri("/functions/limited-func")
+        .set_json(serde_json::json!({
+            "name": "limited-func",
+            "language": "rust",
+            "executable": placeholder_wasm(td.path()).to_str().unwrap(),
+            "cpu": "0.1",
+            "memory": "128"
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let req = test::TestRequest::post()
+        .uri("/function/limited")
         .to_request();
     let resp = test::call_service(&app, req).await;
     assert!(resp.status().is_success());
 
-    // 2. Create Trigger to register route
+    // The function can't be deleted from under its trigger, which keeps serving
+    let req = test::TestRequest::delete()
+        .uri("/functions/limited-func")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::CONFLICT);
+    let req = test::TestRequest::post()
+        .uri("/function/limited")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+}
+
+#[actix_rt::test]
+async fn test_trigger_rate_limit() {
+    let (app, td) = spawn_app().await;
+
+    create_function(&app, td.path(), "throttled-func", serde_json::json!({})).await;
+    create_trigger(
+        &app,
+        serde_json::json!({
+            "name": "throttled-trig",
+            "function": "throttled-func",
+            "method": "POST",
+            "path": "/throttled",
+            "rate_limit": { "requests": 2, "period_secs": 3600, "scope": "ip" }
+        }),
+    )
+    .await;
+
+    let call = |ip: &str| {
+        test::TestRequest::post()
+            .uri("/function/throttled")
+            .peer_addr(format!("{}:5000", ip).parse().unwrap())
+            .to_request()
+    };
+
+    for remaining in ["1", "0"] {
+        let resp = test::call_service(&app, call("10.0.0.1")).await;
+        assert!(resp.status().is_success());
+        assert_eq!(resp.headers().get("ratelimit-limit").unwrap(), "2");
+        assert_eq!(
+            resp.headers().get("ratelimit-remaining").unwrap(),
+            remaining
+        );
+    }
+
+    let resp = test::call_service(&app, call("10.0.0.1")).await;
+    assert_eq!(
+        resp.status(),
+        actix_web::http::StatusCode::TOO_MANY_REQUESTS
+    );
+    assert!(resp.headers().contains_key("retry-after"));
+    assert!(resp.headers().contains_key("ratelimit-reset"));
+
+    // Each client IP has its own bucket
+    let resp = test::call_service(&app, call("10.0.0.2")).await;
+    assert!(resp.status().is_success());
+
+    // Invalid limits are refused
     let trig_payload = serde_json::json!({
-        "name": "invoke-trig",
-        "function": "invoke-func",
-        "method": "POST",
-        "path": "/my-func"
+        "name": "bad-trig",
+        "function": "throttled-func",
+        "method": "GET",
+        "path": "/bad",
+        "rate_limit": { "requests": 0 }
     });
     let req = test::TestRequest::post()
         .uri("/triggers")
         .set_json(&trig_payload)
         .to_request();
     let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+}
+
+#[actix_rt::test]
+async fn test_trigger_response_cache() {
+    let (app, td) = spawn_app().await;
+
+    create_function(&app, td.path(), "cached-func", serde_json::json!({})).await;
+    create_trigger(
+        &app,
+        serde_json::json!({
+            "name": "cached-trig",
+            "function": "cached-func",
+            "method": "GET",
+            "path": "/cached",
+            "cache": { "ttl_secs": 60, "vary_headers": ["accept-language"] }
+        }),
+    )
+    .await;
+
+    let get = || test::TestRequest::get().uri("/function/cached?page=1");
+
+    let resp = test::call_service(&app, get().to_request()).await;
     assert!(resp.status().is_success());
+    assert_eq!(resp.headers().get("x-cache").unwrap(), "MISS");
+    let etag = resp.headers().get("etag").unwrap().clone();
+    assert!(
+        resp.headers()
+            .get("cache-control")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .contains("max-age=")
+    );
+
+    let resp = test::call_service(&app, get().to_request()).await;
+    assert_eq!(resp.headers().get("x-cache").unwrap(), "HIT");
+    assert_eq!(resp.headers().get("etag").unwrap(), &etag);
+
+    // Vary headers and query strings get their own entries
+    let req = get().insert_header(("Accept-Language", "fr")).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.headers().get("x-cache").unwrap(), "MISS");
+
+    let req = get().insert_header(("If-None-Match", etag)).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_MODIFIED);
+
+    let req = test::TestRequest::delete()
+        .uri("/cache?function=cached-func")
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["purged"], 2);
+
+    let resp = test::call_service(&app, get().to_request()).await;
+    assert_eq!(resp.headers().get("x-cache").unwrap(), "MISS");
 
-    // 3. Invoke via Gateway
+    let req = test::TestRequest::delete().uri("/cache").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+}
+
+#[actix_rt::test]
+async fn test_workflow_run() {
+    let (app, td) = spawn_app().await;
+
+    for name in ["wf-fetch", "wf-resize", "wf-tag", "wf-store"] {
+        create_function(&app, td.path(), name, serde_json::json!({})).await;
+    }
+
+    let workflow = serde_json::json!({
+        "name": "images",
+        "steps": [
+            { "name": "fetch", "function": "wf-fetch" },
+            { "name": "resize", "function": "wf-resize", "after": ["fetch"], "retries": 1 },
+            {
+                "name": "tag",
+                "function": "wf-tag",
+                "after": ["fetch"],
+                "when": { "step": "fetch", "path": "/message", "equals": "Hello from wf-fetch" }
+            },
+            { "name": "store", "function": "wf-store", "after": ["resize", "tag"] }
+        ]
+    });
     let req = test::TestRequest::post()
-        .uri("/function/my-func")
+        .uri("/workflows")
+        .set_json(&workflow)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::CREATED);
+
+    let cyclic = serde_json::json!({
+        "name": "cyclic",
+        "steps": [
+            { "name": "a", "function": "wf-fetch", "after": ["b"] },
+            { "name": "b", "function": "wf-fetch", "after": ["a"] }
+        ]
+    });
+    let req = test::TestRequest::post()
+        .uri("/workflows")
+        .set_json(&cyclic)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+    // Runs can be started through a trigger as well as the API
+    create_trigger(
+        &app,
+        serde_json::json!({
+            "name": "images-trig",
+            "function": "images",
+            "method": "POST",
+            "path": "/images",
+            "kind": "workflow"
+        }),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/function/images")
+        .set_payload("cat.png")
         .to_request();
     let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::ACCEPTED);
+    let location = resp
+        .headers()
+        .get("location")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
 
+    let mut run = serde_json::Value::Null;
+    for _ in 0..50 {
+        let req = test::TestRequest::get().uri(&location).to_request();
+        run = test::call_and_read_body_json(&app, req).await;
+        if run["status"] != "running" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+
+    assert_eq!(run["status"], "succeeded");
+    assert_eq!(run["input"], "cat.png");
+    let steps = run["steps"].as_array().unwrap();
+    assert_eq!(steps.len(), 4);
+    assert!(steps.iter().all(|s| s["status"] == "succeeded"));
+    assert_eq!(steps[0]["input"], "cat.png");
+    let joined: serde_json::Value =
+        serde_json::from_str(steps[3]["input"].as_str().unwrap()).unwrap();
+    assert_eq!(joined["tag"]["message"], "Hello from wf-tag");
+    assert_eq!(run["output"], r#"{"message":"Hello from wf-store"}"#);
+
+    let req = test::TestRequest::get()
+        .uri("/workflows/images/runs")
+        .to_request();
+    let runs: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(runs.as_array().unwrap().len(), 1);
+}
+
+#[actix_rt::test]
+async fn test_async_invocation_dead_letters() {
+    let (app, td) = spawn_app().await;
+
+    for name in ["async-ok", "failing-job"] {
+        let retry = serde_json::json!({
+            "retry": { "max_attempts": 2, "initial_backoff_ms": 1, "max_backoff_ms": 1 }
+        });
+        create_function(&app, td.path(), name, retry).await;
+    }
+
+    let req = test::TestRequest::get()
+        .uri("/functions/failing-job")
+        .to_request();
+    let func: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(func["retry"]["max_attempts"], 2);
+
+    for name in ["async-ok", "failing-job"] {
+        let req = test::TestRequest::post()
+            .uri(&format!("/functions/{}/invocations", name))
+            .set_payload("job-input")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::ACCEPTED);
+    }
+
+    let req = test::TestRequest::post()
+        .uri("/functions/missing/invocations")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+
+    let mut letters = serde_json::Value::Null;
+    for _ in 0..50 {
+        let req = test::TestRequest::get().uri("/dead-letters").to_request();
+        letters = test::call_and_read_body_json(&app, req).await;
+        if !letters.as_array().unwrap().is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    let letters = letters.as_array().unwrap();
+    assert_eq!(letters.len(), 1);
+    let letter = &letters[0];
+    assert_eq!(letter["function"], "failing-job");
+    assert_eq!(letter["payload"], "job-input");
+    assert_eq!(letter["attempts"], 2);
+    assert!(letter["error"].as_str().unwrap().contains("failed"));
+    let id = letter["id"].as_str().unwrap();
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/dead-letters/{}", id))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
     assert!(resp.status().is_success());
 
-    let body_bytes = test::read_body(resp).await;
-    let resp: serde_json::Value = serde_json::from_slice(&body_bytes).unwrap();
+    // Redriving removes the letter; failing again produces a new one
+    let req = test::TestRequest::post()
+        .uri(&format!("/dead-letters/{}/redrive", id))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::ACCEPTED);
+    let req = test::TestRequest::get()
+        .uri(&format!("/dead-letters/{}", id))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
 
-    assert_eq!(resp["message"], "Hello from invoke-func");
+    let mut purged = serde_json::Value::Null;
+    for _ in 0..50 {
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        let req = test::TestRequest::delete()
+            .uri("/dead-letters?function=failing-job")
+            .to_request();
+        purged = test::call_and_read_body_json(&app, req).await;
+        if purged["purged"] != 0 {
+            break;
+        }
+    }
+    assert_eq!(purged["purged"], 1);
 }
 
 #[actix_rt::test]
-async fn test_telemetry() {
+async fn test_manifest_plan_and_apply() {
+    let (app, td) = spawn_app().await;
+
+    let manifest = "
+functions:
+  - name: m-hello
+    language: rust
+    artifact: build/hello.wasm
+    cpu: '0.1'
+    memory: '128'
+    env: { GREETING: hi }
+  - name: m-other
+    language: rust
+    artifact: build/hello.wasm
+    cpu: '0.1'
+    memory: '128'
+triggers:
+  - { name: m-hello, method: GET, path: /m-hello, function: m-hello }
+"
+    .to_string();
+    // The manifest goes in a multipart body with the binaries it names
+    let post = |uri: &str, manifest: String| {
+        let boundary = "fluor-test-boundary";
+        let body = format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"manifest\"\r\n\
+             Content-Type: application/yaml\r\n\r\n{manifest}\r\n\
+             --{boundary}\r\nContent-Disposition: form-data; name=\"artifact\"; \
+             filename=\"build/hello.wasm\"\r\n\r\ndummy wasm content\r\n\
+             --{boundary}--\r\n"
+        );
+        test::TestRequest::post()
+            .uri(uri)
+            .insert_header((
+                "content-type",
+                format!("multipart/form-data; boundary={boundary}"),
+            ))
+            .set_payload(body)
+            .to_request()
+    };
+
+    // Artifacts are never read from the API host, even when the path exists there
+    let host_path = placeholder_wasm(td.path());
+    let req = test::TestRequest::post()
+        .uri("/plan")
+        .insert_header(("content-type", "application/yaml"))
+        .set_payload(manifest.replace("build/hello.wasm", host_path.to_str().unwrap()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(
+        resp.status(),
+        actix_web::http::StatusCode::UNPROCESSABLE_ENTITY
+    );
+
+    let plan: serde_json::Value =
+        test::call_and_read_body_json(&app, post("/plan", manifest.clone())).await;
+    assert_eq!(plan["applied"], false);
+    assert_eq!(plan["changes"].as_array().unwrap().len(), 3);
+    let req = test::TestRequest::get()
+        .uri("/functions/m-hello")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+
+    let applied: serde_json::Value =
+        test::call_and_read_body_json(&app, post("/apply", manifest.clone())).await;
+    assert_eq!(applied["applied"], true);
+    let req = test::TestRequest::get()
+        .uri("/functions/m-hello")
+        .to_request();
+    let func: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(func["env"]["GREETING"], "hi");
+    let req = test::TestRequest::get()
+        .uri("/function/m-hello")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    // Applying again is a no-op, and the readonly healthz entries are never touched
+    let plan: serde_json::Value =
+        test::call_and_read_body_json(&app, post("/plan", manifest.clone())).await;
+    assert_eq!(plan["changes"], serde_json::json!([]));
+
+    // A change that fails its checks stores none of the others
+    let broken = manifest.replace("m-other", "m-third").replace(
+        "function: m-hello }",
+        "function: m-hello, rate_limit: { requests: 0 } }",
+    );
+    let req = post("/apply", broken);
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(
+        resp.status(),
+        actix_web::http::StatusCode::UNPROCESSABLE_ENTITY
+    );
+    for (name, status) in [
+        ("m-other", actix_web::http::StatusCode::OK),
+        ("m-third", actix_web::http::StatusCode::NOT_FOUND),
+        ("healthz", actix_web::http::StatusCode::OK),
+    ] {
+        let req = test::TestRequest::get()
+            .uri(&format!("/functions/{}", name))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), status, "{}", name);
+    }
+    let req = test::TestRequest::get()
+        .uri("/function/m-hello")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    // A change the database rejects, stored after the others, keeps them all out
+    let dangling = format!(
+        "{}  - {{ name: m-flow, method: POST, path: /m-flow, function: nowhere, kind: workflow }}\n",
+        manifest.replace("m-other", "m-third")
+    );
+    let resp = test::call_service(&app, post("/apply", dangling)).await;
+    assert_eq!(
+        resp.status(),
+        actix_web::http::StatusCode::UNPROCESSABLE_ENTITY
+    );
+    for (name, status) in [
+        ("m-other", actix_web::http::StatusCode::OK),
+        ("m-third", actix_web::http::StatusCode::NOT_FOUND),
+    ] {
+        let req = test::TestRequest::get()
+            .uri(&format!("/functions/{}", name))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), status, "{}", name);
+    }
+    let plan: serde_json::Value =
+        test::call_and_read_body_json(&app, post("/plan", manifest.clone())).await;
+    assert_eq!(plan["changes"], serde_json::json!([]));
+}
+
+#[actix_rt::test]
+async fn test_websocket_trigger() {
     let (app, _td) = spawn_app().await;
 
-    // Test endpoint reachability (Might return 500 if ClickHouse is down, but shouldn't be 404)
+    let trig_payload = serde_json::json!({
+        "name": "chat-trig",
+        "function": "healthz",
+        "method": "GET",
+        "path": "/chat",
+        "kind": "websocket"
+    });
+    let req = test::TestRequest::post()
+        .uri("/triggers")
+        .set_json(&trig_payload)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+
+    let req = test::TestRequest::get().uri("/triggers").to_request();
+    let triggers: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let chat = triggers
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|t| t["name"] == "chat-trig")
+        .unwrap();
+    assert_eq!(chat["kind"], "websocket");
+
+    // Plain HTTP requests don't reach WebSocket triggers
+    let req = test::TestRequest::get().uri("/function/chat").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+
+    // Upgrades on unknown paths are rejected before the handshake completes
+    let req = test::TestRequest::get()
+        .uri("/function/unknown")
+        .insert_header(("upgrade", "websocket"))
+        .insert_header(("connection", "upgrade"))
+        .insert_header(("sec-websocket-version", "13"))
+        .insert_header(("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ=="))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+}
+
+#[actix_rt::test]
+async fn test_telemetry() {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let telemetry = Arc::new(EmbeddedTelemetryRepository::new());
+    let _subscriber = tracing::subscriber::set_default(
+        tracing_subscriber::registry().with(RecorderLayer::new(telemetry.clone())),
+    );
+    let (app, td) = spawn_app_with_telemetry(telemetry).await;
+
+    create_function(&app, td.path(), "observed", serde_json::json!({})).await;
+    create_trigger(
+        &app,
+        serde_json::json!({
+            "name": "observed-trig",
+            "function": "observed",
+            "method": "GET",
+            "path": "/observed"
+        }),
+    )
+    .await;
+
+    for _ in 0..2 {
+        let req = test::TestRequest::get()
+            .uri("/function/observed")
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+    }
+
+    let req = test::TestRequest::get()
+        .uri("/telemetry/functions/observed/metrics")
+        .to_request();
+    let metrics: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let total: u64 = metrics
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| m["count"].as_u64().unwrap())
+        .sum();
+    assert_eq!(total, 2);
+    // The last hour by minute, with empty minutes filled in
+    assert!(metrics.as_array().unwrap().len() >= 60);
+
+    let req = test::TestRequest::get()
+        .uri("/telemetry/functions/observed/metrics?from=now-1d&step=hour")
+        .to_request();
+    let hourly: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let hourly = hourly.as_array().unwrap();
+    assert!(hourly.len() >= 24);
+    assert_eq!(hourly.last().unwrap()["count"], 2);
+
+    let req = test::TestRequest::get()
+        .uri("/telemetry/metrics/overall?from=now-30d&step=minute")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::get()
+        .uri("/telemetry/metrics/overall")
+        .to_request();
+    let overall: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert!(!overall.as_array().unwrap().is_empty());
+
     let req = test::TestRequest::get()
-        .uri("/telemetry/functions/any-func/metrics")
+        .uri("/telemetry/functions/observed/performance?from=now-10m")
+        .to_request();
+    let performance: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let current = performance.as_array().unwrap().last().unwrap();
+    assert_eq!(current["invocations"], 2);
+    assert_eq!(current["errors"], 0);
+    assert_eq!(current["error_rate"], 0.0);
+
+    let req = test::TestRequest::get()
+        .uri("/telemetry/functions/top?by=latency&limit=5")
+        .to_request();
+    let top: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(top[0]["function_name"], "observed");
+    assert_eq!(top[0]["invocations"], 2);
+
+    let req = test::TestRequest::get()
+        .uri("/telemetry/functions/top?limit=1000")
         .to_request();
     let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
 
-    assert_ne!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+    let req = test::TestRequest::get()
+        .uri("/telemetry/functions/observed/logs")
+        .to_request();
+    let logs: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let logs = logs.as_array().unwrap();
+    assert!(!logs.is_empty());
+    assert!(logs.iter().all(|l| l["function_name"] == "observed"));
+    assert_eq!(logs[0]["body"], "Function observed exited with status ok");
 
     let req = test::TestRequest::get().uri("/telemetry/logs").to_request();
+    let recent: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(recent.as_array().unwrap().len(), logs.len());
+
+    // Two invocations log "started" and "exited" each
+    let req = test::TestRequest::get()
+        .uri("/telemetry/logs/search?function=observed&contains=EXITED&level=info&limit=1")
+        .to_request();
+    let page: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page["entries"].as_array().unwrap().len(), 1);
+    let cursor = page["next_cursor"].as_str().unwrap().to_string();
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/telemetry/logs/search?function=observed&contains=exited&limit=1&cursor={}",
+            cursor
+        ))
+        .to_request();
+    let page: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page["entries"].as_array().unwrap().len(), 1);
+    let req = test::TestRequest::get()
+        .uri(&format!(
+            "/telemetry/logs/search?function=observed&contains=exited&limit=1&cursor={}",
+            page["next_cursor"].as_str().unwrap()
+        ))
+        .to_request();
+    let page: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert!(page["entries"].as_array().unwrap().is_empty());
+    assert!(page["next_cursor"].is_null());
+
+    // The embedded backend keeps no spans
+    let req = test::TestRequest::get()
+        .uri("/telemetry/functions/observed/traces?filter=errors")
+        .to_request();
+    let traces: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert!(traces.as_array().unwrap().is_empty());
+    let req = test::TestRequest::get()
+        .uri("/telemetry/traces/0af7651916cd43dd8448eb211c80319c")
+        .to_request();
     let resp = test::call_service(&app, req).await;
-    assert_ne!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+    assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+    let req = test::TestRequest::get()
+        .uri("/telemetry/traces/xyz")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::get()
+        .uri("/telemetry/logs/search?level=verbose")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+    // The live tail only carries lines logged after it was opened
+    let req = test::TestRequest::get()
+        .uri("/telemetry/logs/stream?function=observed")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "text/event-stream"
+    );
+    let mut body = Box::pin(resp.into_body());
+
+    let req = test::TestRequest::get()
+        .uri("/function/observed")
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+
+    let mut events = String::new();
+    while events.matches("data: ").count() < 2 {
+        let chunk = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            std::future::poll_fn(|cx| actix_web::body::MessageBody::poll_next(body.as_mut(), cx)),
+        )
+        .await
+        .expect("no log events on the stream")
+        .unwrap()
+        .unwrap();
+        events.push_str(std::str::from_utf8(&chunk).unwrap());
+    }
+    let data: Vec<serde_json::Value> = events
+        .lines()
+        .filter_map(|l| l.strip_prefix("data: "))
+        .map(|d| serde_json::from_str(d).unwrap())
+        .collect();
+    assert_eq!(data[0]["body"], "Function observed started");
+    assert_eq!(data[1]["body"], "Function observed exited with status ok");
+}
+
+#[actix_rt::test]
+async fn test_trace_context_propagation() {
+    use opentelemetry::trace::TracerProvider;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    opentelemetry::global::set_text_map_propagator(
+        opentelemetry_sdk::propagation::TraceContextPropagator::new(),
+    );
+    let tracer = opentelemetry_sdk::trace::SdkTracerProvider::builder()
+        .build()
+        .tracer("test");
+    let telemetry = Arc::new(EmbeddedTelemetryRepository::new());
+    let _subscriber = tracing::subscriber::set_default(
+        tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(tracer))
+            .with(RecorderLayer::new(telemetry.clone())),
+    );
+    let (app, td) = spawn_app_with_telemetry(telemetry).await;
+
+    create_function(&app, td.path(), "traced-fn", serde_json::json!({})).await;
+    create_trigger(
+        &app,
+        serde_json::json!({
+            "name": "traced-trig",
+            "function": "traced-fn",
+            "method": "GET",
+            "path": "/traced"
+        }),
+    )
+    .await;
+
+    // The caller's trace is continued, and handed to the guest
+    let trace_id = "0af7651916cd43dd8448eb211c80319c";
+    let req = test::TestRequest::get()
+        .uri("/function/traced")
+        .insert_header((
+            "traceparent",
+            format!("00-{}-b7ad6b7169203331-01", trace_id),
+        ))
+        .insert_header(("tracestate", "vendor=value"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    assert_eq!(resp.headers().get("x-trace-id").unwrap(), trace_id);
+    let env: HashMap<String, String> = test::read_body_json(resp).await;
+    let traceparent = &env["TRACEPARENT"];
+    assert!(traceparent.starts_with(&format!("00-{}-", trace_id)));
+    assert!(!traceparent.contains("b7ad6b7169203331"));
+    assert_eq!(env["TRACESTATE"], "vendor=value");
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/telemetry/logs/search?trace_id={}", trace_id))
+        .to_request();
+    let page: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert!(!page["entries"].as_array().unwrap().is_empty());
+
+    // Without a traceparent, every request starts a trace of its own
+    let req = test::TestRequest::get()
+        .uri("/function/traced")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let own = resp.headers().get("x-trace-id").unwrap().to_str().unwrap();
+    assert_eq!(own.len(), 32);
+    assert_ne!(own, trace_id);
+}
+
+#[actix_rt::test]
+async fn test_prometheus_metrics() {
+    let exporter = PrometheusExporter::new();
+    opentelemetry::global::set_meter_provider(
+        opentelemetry_sdk::metrics::SdkMeterProvider::builder()
+            .with_reader(exporter.clone())
+            .build(),
+    );
+    let (app, td) = spawn_app().await;
+
+    create_function(&app, td.path(), "scraped", serde_json::json!({})).await;
+    create_trigger(
+        &app,
+        serde_json::json!({
+            "name": "scraped-trig",
+            "function": "scraped",
+            "method": "GET",
+            "path": "/scraped"
+        }),
+    )
+    .await;
+    let req = test::TestRequest::get()
+        .uri("/function/scraped")
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+
+    let metrics = test::init_service(
+        App::new().configure(|cfg| handlers::metrics::config(cfg, exporter.clone())),
+    )
+    .await;
+    let resp = test::call_service(
+        &metrics,
+        test::TestRequest::get().uri("/metrics").to_request(),
+    )
+    .await;
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        api::infrastructure::prometheus::CONTENT_TYPE
+    );
+    let body = test::read_body(resp).await;
+    let text = std::str::from_utf8(&body).unwrap();
+
+    assert!(text.contains("# TYPE function_invocations_total counter"));
+    assert!(text.contains("function_invocations_total{function_name=\"scraped\",status=\"ok\"} 1"));
+    assert!(text.contains("function_duration_ms_count{function_name=\"scraped\",status=\"ok\"} 1"));
+    assert!(text.contains("function_active_invocations{function_name=\"scraped\"} 0"));
+}
+
+#[actix_rt::test]
+async fn test_alerts_notify_webhook() {
+    use api::application::alert_service::AlertService;
+    use api::domain::entities::Function;
+    use api::domain::ports::{FunctionRepository, TelemetryRecorder};
+    use api::infrastructure::webhook::WebhookNotifier;
+
+    // Stand-in for a Slack incoming webhook, recording what it receives
+    let received = Arc::new(Mutex::new(Vec::<serde_json::Value>::new()));
+    let hook_received = received.clone();
+    let hook = actix_test::start(move || {
+        let received = hook_received.clone();
+        App::new().route(
+            "/hook",
+            web::post().to(move |body: web::Json<serde_json::Value>| {
+                let received = received.clone();
+                async move {
+                    received.lock().unwrap().push(body.into_inner());
+                    actix_web::HttpResponse::Ok().body("ok")
+                }
+            }),
+        )
+    });
+
+    let temp_dir = tempdir().unwrap();
+    let db_url = format!(
+        "sqlite:{}?mode=rwc",
+        temp_dir.path().join("test.db").to_str().unwrap()
+    );
+    let repo = Arc::new(SqliteRepository::new(create_pool(db_url).await));
+    FunctionRepository::save(
+        repo.as_ref(),
+        &Function {
+            name: "checkout".to_string(),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let telemetry = Arc::new(EmbeddedTelemetryRepository::new());
+    let service = Arc::new(AlertService::new(
+        repo.clone(),
+        repo.clone(),
+        telemetry.clone(),
+        Arc::new(WebhookNotifier::new()),
+    ));
+    let auth = Arc::new(AuthService::new(
+        repo.clone(),
+        "secret".to_string(),
+        "jwt_secret".to_string(),
+    ));
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(auth))
+            .app_data(web::Data::new(service))
+            .configure(handlers::alerts::config),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/alerts")
+        .set_json(serde_json::json!({
+            "name": "checkout-errors",
+            "function": "checkout",
+            "condition": "error_rate",
+            "threshold": 0.2,
+            "webhooks": [hook.url("/hook")]
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::CREATED);
+    let req = test::TestRequest::post()
+        .uri("/alerts")
+        .set_json(serde_json::json!({
+            "name": "bad",
+            "function": "checkout",
+            "condition": "latency_p99",
+            "threshold_ms": -1
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+    for status in ["ok", "error", "error", "ok"] {
+        telemetry.record_invocation("checkout", status, 10);
+    }
+    let req = test::TestRequest::post()
+        .uri("/alerts/evaluate")
+        .to_request();
+    let changed: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(changed.as_array().unwrap().len(), 1);
+    assert_eq!(changed[0]["status"]["state"], "firing");
+
+    // The state is persisted, and a second evaluation doesn't notify again
+    let req = test::TestRequest::post()
+        .uri("/alerts/evaluate")
+        .to_request();
+    let changed: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert!(changed.as_array().unwrap().is_empty());
+    let req = test::TestRequest::get()
+        .uri("/alerts/checkout-errors")
+        .to_request();
+    let rule: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(rule["status"]["state"], "firing");
+    assert_eq!(rule["status"]["value"], 0.5);
+    assert_eq!(rule["window_secs"], 300);
+
+    let received = received.lock().unwrap().clone();
+    assert_eq!(received.len(), 1);
+    assert!(
+        received[0]["text"]
+            .as_str()
+            .unwrap()
+            .starts_with("[FIRING] checkout-errors")
+    );
+    assert_eq!(received[0]["attachments"][0]["color"], "danger");
+}
+
+#[actix_rt::test]
+async fn test_audit_log() {
+    let (app, _td) = spawn_app().await;
+
+    let req = test::TestRequest::post()
+        .uri("/login")
+        .set_json(serde_json::json!({ "email": "admin@fluor.com", "password": "admin" }))
+        .to_request();
+    let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let bearer = format!("Bearer {}", resp["token"].as_str().unwrap());
+    let auth = || ("Authorization", bearer.clone());
+    let from_ip = || ("X-Forwarded-For", "203.0.113.7");
+    let proxy = format!("{}:443", PROXY_IP).parse().unwrap();
+
+    let function = |memory: &str| {
+        serde_json::json!({
+            "name": "audited",
+            "language": "python",
+            "executable": "",
+            "cpu": "0.1",
+            "memory": memory,
+            "env": { "API_KEY": "hunter2" }
+        })
+    };
+    let req = test::TestRequest::post()
+        .uri("/functions")
+        .insert_header(auth())
+        .insert_header(from_ip())
+        .peer_addr(proxy)
+        .set_json(function("128"))
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+    let req = test::TestRequest::put()
+        .uri("/functions/audited")
+        .insert_header(auth())
+        .insert_header(from_ip())
+        .peer_addr(proxy)
+        .set_json(function("256"))
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+
+    // Without a token the change is still recorded, as anonymous
+    let req = test::TestRequest::post()
+        .uri("/triggers")
+        .set_json(serde_json::json!({
+            "name": "audited", "method": "GET", "path": "/audited", "function": "audited"
+        }))
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+    // Forwarding headers from anyone but the proxy are ignored
+    let req = test::TestRequest::delete()
+        .uri("/triggers/audited")
+        .insert_header(auth())
+        .insert_header(from_ip())
+        .peer_addr("198.51.100.9:5000".parse().unwrap())
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+
+    let req = test::TestRequest::put()
+        .uri("/me/password")
+        .insert_header(auth())
+        .insert_header(from_ip())
+        .peer_addr(proxy)
+        .set_json(serde_json::json!({ "current_password": "admin", "new_password": "changed" }))
+        .to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+
+    let req = test::TestRequest::get().uri("/audit").to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        actix_web::http::StatusCode::UNAUTHORIZED
+    );
+
+    let get = |uri: &str| {
+        test::TestRequest::get()
+            .uri(uri)
+            .insert_header(auth())
+            .to_request()
+    };
+    let page: serde_json::Value = test::call_and_read_body_json(&app, get("/audit")).await;
+    let entries = page["entries"].as_array().unwrap();
+    let actions: Vec<&str> = entries
+        .iter()
+        .map(|e| e["action"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        actions,
+        [
+            "user.password_change",
+            "trigger.delete",
+            "trigger.create",
+            "function.update",
+            "function.create",
+        ]
+    );
+    assert_eq!(entries[0]["actor"], "admin@fluor.com");
+    assert_eq!(entries[0]["source_ip"], "203.0.113.7");
+    assert_eq!(entries[0]["changes"], serde_json::json!({}));
+    assert_eq!(entries[2]["actor"], "anonymous");
+    assert_eq!(entries[1]["source_ip"], "198.51.100.9");
+    assert_eq!(entries[1]["changes"]["path"]["before"], "/audited");
+    assert_eq!(
+        entries[1]["changes"]["path"]["after"],
+        serde_json::Value::Null
+    );
+
+    let update = &entries[3];
+    assert_eq!(update["target"], "audited");
+    assert_eq!(update["source_ip"], "203.0.113.7");
+    assert_eq!(
+        update["changes"],
+        serde_json::json!({ "memory": { "before": "128", "after": "256" } })
+    );
+    // Environment values never reach the log
+    assert_eq!(
+        entries[4]["changes"]["env"]["after"],
+        serde_json::json!({ "API_KEY": "[redacted]" })
+    );
+    assert!(!page.to_string().contains("hunter2"));
+
+    let page: serde_json::Value =
+        test::call_and_read_body_json(&app, get("/audit?action=function&limit=1")).await;
+    assert_eq!(page["entries"][0]["action"], "function.update");
+    let cursor = page["next_cursor"].as_str().unwrap();
+    let page: serde_json::Value = test::call_and_read_body_json(
+        &app,
+        get(&format!("/audit?action=function&limit=1&cursor={}", cursor)),
+    )
+    .await;
+    assert_eq!(page["entries"][0]["action"], "function.create");
+
+    let page: serde_json::Value =
+        test::call_and_read_body_json(&app, get("/audit?actor=anonymous")).await;
+    assert_eq!(page["entries"].as_array().unwrap().len(), 1);
+
+    let resp = test::call_service(&app, get("/audit/export?target=audited")).await;
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "application/x-ndjson"
+    );
+    let body = test::read_body(resp).await;
+    let lines: Vec<serde_json::Value> = std::str::from_utf8(&body)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[3]["action"], "function.create");
+}
+
+#[actix_rt::test]
+async fn test_audit_log_records_operational_changes() {
+    let (app, _td) = spawn_app().await;
+
+    let req = test::TestRequest::post()
+        .uri("/login")
+        .set_json(serde_json::json!({ "email": "admin@fluor.com", "password": "admin" }))
+        .to_request();
+    let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    let bearer = format!("Bearer {}", resp["token"].as_str().unwrap());
+    let send = |req: test::TestRequest| {
+        req.insert_header(("Authorization", bearer.clone()))
+            .to_request()
+    };
+
+    let req = test::TestRequest::post()
+        .uri("/apply")
+        .insert_header(("content-type", "application/yaml"))
+        .set_payload("functions: []\n");
+    assert!(
+        test::call_service(&app, send(req))
+            .await
+            .status()
+            .is_success()
+    );
+    let req = test::TestRequest::post()
+        .uri("/functions")
+        .set_json(serde_json::json!({
+            "name": "ops", "language": "python", "executable": "", "cpu": "0.1", "memory": "128"
+        }));
+    assert!(
+        test::call_service(&app, send(req))
+            .await
+            .status()
+            .is_success()
+    );
+    let workflow = |function: &str| serde_json::json!({ "name": "ops-flow", "steps": [{ "name": "run", "function": function }] });
+    let req = test::TestRequest::post()
+        .uri("/workflows")
+        .set_json(workflow("ops"));
+    assert!(
+        test::call_service(&app, send(req))
+            .await
+            .status()
+            .is_success()
+    );
+    let req = test::TestRequest::delete().uri("/workflows/ops-flow");
+    assert!(
+        test::call_service(&app, send(req))
+            .await
+            .status()
+            .is_success()
+    );
+    let req = test::TestRequest::delete().uri("/dead-letters?function=ops");
+    assert!(
+        test::call_service(&app, send(req))
+            .await
+            .status()
+            .is_success()
+    );
+    let req = test::TestRequest::delete().uri("/cache?function=ops&trigger=ops");
+    assert!(
+        test::call_service(&app, send(req))
+            .await
+            .status()
+            .is_success()
+    );
+
+    let req = test::TestRequest::get().uri("/audit");
+    let page: serde_json::Value = test::call_and_read_body_json(&app, send(req)).await;
+    let entries: Vec<(&str, &str)> = page["entries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| (e["action"].as_str().unwrap(), e["target"].as_str().unwrap()))
+        .filter(|(action, _)| !action.starts_with("function."))
+        .collect();
+    assert_eq!(
+        entries,
+        [
+            ("cache.purge", "function:ops"),
+            ("cache.purge", "trigger:ops"),
+            ("dead_letter.purge", "ops"),
+            ("workflow.delete", "ops-flow"),
+            ("workflow.create", "ops-flow"),
+            ("manifest.apply", "manifest"),
+        ]
+    );
+    assert!(
+        page["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|e| e["actor"] == "admin@fluor.com")
+    );
+}
+
+#[actix_rt::test]
+async fn test_audit_log_is_for_admins() {
+    let (app, td) = spawn_app().await;
+
+    // A second account with the default role, sharing the admin's password
+    let db_url = format!("sqlite:{}", td.path().join("test.db").to_str().unwrap());
+    sqlx::query(
+        "INSERT INTO users (email, password_hash)
+         SELECT 'dev@fluor.com', password_hash FROM users WHERE email = 'admin@fluor.com'",
+    )
+    .execute(&create_pool(db_url).await)
+    .await
+    .unwrap();
+
+    for (email, status) in [
+        ("dev@fluor.com", actix_web::http::StatusCode::FORBIDDEN),
+        ("admin@fluor.com", actix_web::http::StatusCode::OK),
+    ] {
+        let req = test::TestRequest::post()
+            .uri("/login")
+            .set_json(serde_json::json!({ "email": email, "password": "admin" }))
+            .to_request();
+        let resp: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let bearer = format!("Bearer {}", resp["token"].as_str().unwrap());
+        for uri in ["/audit", "/audit/export"] {
+            let req = test::TestRequest::get()
+                .uri(uri)
+                .insert_header(("Authorization", bearer.clone()))
+                .to_request();
+            assert_eq!(
+                test::call_service(&app, req).await.status(),
+                status,
+                "{}",
+                uri
+            );
+        }
+    }
 }
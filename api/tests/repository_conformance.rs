@@ -0,0 +1,669 @@
+//! Behaviour every control-plane database adapter must share. The SQLite
+//! adapter always runs; the PostgreSQL one is ignored unless asked for with
+//! `--features postgres -- --ignored` and `TEST_POSTGRES_URL` (see the README).
+
+use api::domain::entities::{
+    AlertCondition, AlertRule, AlertState, AlertStatus, AuditAction, AuditEntry, AuditQuery,
+    BackgroundJob, CachePolicy, DeadLetter, DomainError, FieldChange, Function, Language,
+    ManifestWrite, RateLimit, RateLimitScope, RetryPolicy, RunStatus, StepRun, StepStatus, Trigger,
+    TriggerKind, Workflow, WorkflowRun, WorkflowStep,
+};
+use api::domain::ports::{FunctionRepository, TriggerRepository, UserRepository};
+use api::infrastructure::db::Repository;
+use api::infrastructure::db::sqlite::{self, SqliteRepository};
+use chrono::{Duration, Utc};
+use tempfile::tempdir;
+
+const ADMIN_EMAIL: &str = "admin@fluor.com";
+
+async fn check_repository(repo: &dyn Repository) {
+    check_users(repo).await;
+    check_functions(repo).await;
+    check_triggers(repo).await;
+    check_manifest_writes(repo).await;
+    check_node_lease(repo).await;
+    check_rate_limits(repo).await;
+    check_workflows(repo).await;
+    check_background_jobs(repo).await;
+    check_dead_letters(repo).await;
+    check_alert_rules(repo).await;
+    check_audit_log(repo).await;
+}
+
+async fn check_users(repo: &dyn Repository) {
+    let mut admin = repo.find_by_email(ADMIN_EMAIL).await.unwrap().unwrap();
+    assert_eq!(admin.role, "admin");
+    assert!(
+        repo.find_by_email("nobody@fluor.com")
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    admin.name = "Admin".to_string();
+    UserRepository::update(repo, &admin).await.unwrap();
+    let found = repo.find_by_email(ADMIN_EMAIL).await.unwrap().unwrap();
+    assert_eq!((found.id, found.name.as_str()), (admin.id, "Admin"));
+
+    admin.id += 1000;
+    let err = UserRepository::update(repo, &admin).await.unwrap_err();
+    assert!(matches!(err, DomainError::NotFound(_)));
+}
+
+fn function(name: &str) -> Function {
+    Function {
+        name: name.to_string(),
+        language: Language::Rust,
+        executable: format!("{}.wasm", name),
+        cpu: "1".to_string(),
+        memory: "128".to_string(),
+        max_concurrency: Some(4),
+        max_queue: Some(u32::MAX),
+        allowed_calls: vec!["other".to_string()],
+        retry: Some(RetryPolicy::default()),
+        env: [("MODE".to_string(), "test".to_string())].into(),
+        ..Default::default()
+    }
+}
+
+async fn check_functions(repo: &dyn Repository) {
+    let healthz = repo.find_by_name("healthz").await.unwrap().unwrap();
+    assert!(healthz.readonly);
+
+    let hello = function("hello");
+    FunctionRepository::save(repo, &hello).await.unwrap();
+    let err = FunctionRepository::save(repo, &hello).await.unwrap_err();
+    assert!(matches!(err, DomainError::AlreadyExists(_)));
+
+    let found = repo.find_by_name("hello").await.unwrap().unwrap();
+    assert_eq!(found.language, Language::Rust);
+    assert_eq!(found.max_concurrency, Some(4));
+    assert_eq!(found.max_queue, Some(u32::MAX));
+    assert_eq!(found.allowed_calls, hello.allowed_calls);
+    assert_eq!(found.retry, hello.retry);
+    assert_eq!(found.env, hello.env);
+
+    let updated = Function {
+        memory: "256".to_string(),
+        max_concurrency: None,
+        retry: None,
+        ..hello.clone()
+    };
+    FunctionRepository::update(repo, &updated).await.unwrap();
+    let found = repo.find_by_name("hello").await.unwrap().unwrap();
+    assert_eq!(found.memory, "256");
+    assert_eq!((found.max_concurrency, found.retry), (None, None));
+
+    let err = FunctionRepository::update(repo, &function("missing"))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, DomainError::NotFound(_)));
+
+    let mut readonly = function("pinned");
+    readonly.readonly = true;
+    FunctionRepository::save(repo, &readonly).await.unwrap();
+    let err = FunctionRepository::update(repo, &readonly)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, DomainError::ValidationError(_)));
+    let err = FunctionRepository::delete(repo, "pinned")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, DomainError::ValidationError(_)));
+
+    let names: Vec<String> = FunctionRepository::find_all(repo)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|f| f.name)
+        .collect();
+    assert!(names.contains(&"hello".to_string()) && names.contains(&"pinned".to_string()));
+
+    // A function can't go while a trigger still routes to it; a workflow
+    // trigger naming a workflow of the same name doesn't hold it back
+    let hello = Workflow {
+        name: "hello".to_string(),
+        steps: vec![],
+    };
+    repo.save_workflow(&hello).await.unwrap();
+    let route = Trigger {
+        name: "hello-route".to_string(),
+        method: "GET".to_string(),
+        path: "/hello".to_string(),
+        function_name: "hello".to_string(),
+        kind: TriggerKind::Http,
+        rate_limit: None,
+        cache: None,
+        readonly: false,
+    };
+    TriggerRepository::save(repo, &route).await.unwrap();
+    let flow = Trigger {
+        name: "hello-flow".to_string(),
+        kind: TriggerKind::Workflow,
+        ..route.clone()
+    };
+    TriggerRepository::save(repo, &flow).await.unwrap();
+    let err = FunctionRepository::delete(repo, "hello").await.unwrap_err();
+    assert!(matches!(err, DomainError::ValidationError(msg) if msg.contains("hello-route")));
+    TriggerRepository::delete(repo, "hello-route")
+        .await
+        .unwrap();
+    let err = repo.delete_workflow("hello").await.unwrap_err();
+    assert!(matches!(err, DomainError::ValidationError(msg) if msg.contains("hello-flow")));
+    TriggerRepository::delete(repo, "hello-flow").await.unwrap();
+    repo.delete_workflow("hello").await.unwrap();
+
+    FunctionRepository::delete(repo, "hello").await.unwrap();
+    assert!(repo.find_by_name("hello").await.unwrap().is_none());
+    let err = FunctionRepository::delete(repo, "hello").await.unwrap_err();
+    assert!(matches!(err, DomainError::NotFound(_)));
+}
+
+async fn check_triggers(repo: &dyn Repository) {
+    let pipeline = Workflow {
+        name: "pipeline".to_string(),
+        steps: vec![],
+    };
+    repo.save_workflow(&pipeline).await.unwrap();
+    let trigger = Trigger {
+        name: "flow".to_string(),
+        method: "POST".to_string(),
+        path: "/flow".to_string(),
+        // Workflow triggers name a workflow, not a function
+        function_name: "pipeline".to_string(),
+        kind: TriggerKind::Workflow,
+        rate_limit: Some(RateLimit {
+            requests: 10,
+            period_secs: 60,
+            burst: Some(5),
+            scope: RateLimitScope::Ip,
+        }),
+        cache: Some(CachePolicy {
+            ttl_secs: 30,
+            vary_headers: vec!["accept".to_string()],
+        }),
+        readonly: false,
+    };
+    TriggerRepository::save(repo, &trigger).await.unwrap();
+    let err = TriggerRepository::save(repo, &trigger).await.unwrap_err();
+    assert!(matches!(err, DomainError::AlreadyExists(_)));
+
+    let found = TriggerRepository::find_all(repo)
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|t| t.name == "flow")
+        .unwrap();
+    assert_eq!(found.function_name, "pipeline");
+    assert_eq!(found.kind, TriggerKind::Workflow);
+    assert_eq!(found.rate_limit, trigger.rate_limit);
+    assert_eq!(found.cache, trigger.cache);
+
+    // Targets must exist
+    let stray = Trigger {
+        name: "stray".to_string(),
+        kind: TriggerKind::Http,
+        ..trigger.clone()
+    };
+    let err = TriggerRepository::save(repo, &stray).await.unwrap_err();
+    assert!(matches!(err, DomainError::ValidationError(_)));
+    let stray = Trigger {
+        name: "stray".to_string(),
+        function_name: "missing".to_string(),
+        ..trigger.clone()
+    };
+    let err = TriggerRepository::save(repo, &stray).await.unwrap_err();
+    assert!(matches!(err, DomainError::ValidationError(_)));
+
+    let pinned = Trigger {
+        name: "pinned".to_string(),
+        function_name: "pinned".to_string(),
+        kind: TriggerKind::WebSocket,
+        rate_limit: None,
+        cache: None,
+        readonly: true,
+        ..trigger
+    };
+    TriggerRepository::save(repo, &pinned).await.unwrap();
+    let err = TriggerRepository::delete(repo, "pinned").await.unwrap_err();
+    assert!(matches!(err, DomainError::ValidationError(_)));
+
+    TriggerRepository::delete(repo, "flow").await.unwrap();
+    let err = TriggerRepository::delete(repo, "flow").await.unwrap_err();
+    assert!(matches!(err, DomainError::NotFound(_)));
+    repo.delete_workflow("pipeline").await.unwrap();
+}
+
+async fn check_manifest_writes(repo: &dyn Repository) {
+    let route = |name: &str, function: &str| Trigger {
+        name: name.to_string(),
+        method: "GET".to_string(),
+        path: format!("/{}", name),
+        function_name: function.to_string(),
+        kind: TriggerKind::Http,
+        rate_limit: None,
+        cache: None,
+        readonly: false,
+    };
+    repo.apply_manifest(&[
+        ManifestWrite::CreateFunction(function("m-one")),
+        ManifestWrite::CreateTrigger(route("m-one", "m-one")),
+    ])
+    .await
+    .unwrap();
+
+    // The last write fails, so none of them is kept
+    let mut resized = function("m-one");
+    resized.memory = "256".to_string();
+    let err = repo
+        .apply_manifest(&[
+            ManifestWrite::UpdateFunction(resized.clone()),
+            ManifestWrite::DeleteTrigger("m-one".to_string()),
+            ManifestWrite::CreateFunction(function("m-two")),
+            ManifestWrite::CreateTrigger(route("m-two", "missing")),
+        ])
+        .await
+        .unwrap_err();
+    assert!(matches!(err, DomainError::ValidationError(_)));
+    assert_eq!(
+        repo.find_by_name("m-one").await.unwrap().unwrap().memory,
+        "128"
+    );
+    assert!(repo.find_by_name("m-two").await.unwrap().is_none());
+    let triggers = TriggerRepository::find_all(repo).await.unwrap();
+    assert!(triggers.iter().any(|t| t.name == "m-one"));
+    assert!(!triggers.iter().any(|t| t.name == "m-two"));
+
+    // Triggers go before the function they route to, in the same transaction
+    repo.apply_manifest(&[
+        ManifestWrite::UpdateFunction(resized),
+        ManifestWrite::DeleteTrigger("m-one".to_string()),
+        ManifestWrite::DeleteFunction("m-one".to_string()),
+    ])
+    .await
+    .unwrap();
+    assert!(repo.find_by_name("m-one").await.unwrap().is_none());
+}
+
+async fn check_node_lease(repo: &dyn Repository) {
+    assert_eq!(repo.acquire_lease("a", 0, 100).await.unwrap(), "a");
+    // Held until it expires, and renewed by its holder
+    assert_eq!(repo.acquire_lease("b", 50, 150).await.unwrap(), "a");
+    assert_eq!(repo.acquire_lease("a", 90, 190).await.unwrap(), "a");
+    assert_eq!(repo.acquire_lease("b", 150, 250).await.unwrap(), "a");
+    assert_eq!(repo.acquire_lease("b", 190, 290).await.unwrap(), "b");
+
+    // Only the holder can release it
+    repo.release_lease("a").await.unwrap();
+    assert_eq!(repo.acquire_lease("a", 200, 300).await.unwrap(), "b");
+    repo.release_lease("b").await.unwrap();
+    assert_eq!(repo.acquire_lease("a", 200, 300).await.unwrap(), "a");
+    repo.release_lease("a").await.unwrap();
+}
+
+async fn check_rate_limits(repo: &dyn Repository) {
+    assert!(repo.take("k", 2.0, 0.001).await.unwrap().allowed);
+    assert!(repo.take("k", 2.0, 0.001).await.unwrap().allowed);
+    let state = repo.take("k", 2.0, 0.001).await.unwrap();
+    assert!(!state.allowed);
+    assert!(state.tokens < 1.0);
+
+    // Other keys have their own bucket
+    assert!(repo.take("other", 2.0, 0.001).await.unwrap().allowed);
+
+    // Only buckets that have refilled are pruned; draining ones keep their state
+    repo.take("fast", 2.0, 1000.0).await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    assert_eq!(repo.prune().await.unwrap(), 1);
+    assert!(!repo.take("k", 2.0, 0.001).await.unwrap().allowed);
+}
+
+fn step_run(step: &str, status: StepStatus) -> StepRun {
+    StepRun {
+        step: step.to_string(),
+        status,
+        input: None,
+        output: None,
+        error: None,
+        attempts: 0,
+    }
+}
+
+async fn check_workflows(repo: &dyn Repository) {
+    let step = |name: &str, after: &[&str]| WorkflowStep {
+        name: name.to_string(),
+        function: "hello".to_string(),
+        after: after.iter().map(|s| s.to_string()).collect(),
+        when: None,
+        retries: 2,
+        retry_backoff_ms: 100,
+    };
+    let mut workflow = Workflow {
+        name: "pipeline".to_string(),
+        steps: vec![step("fetch", &[]), step("store", &["fetch"])],
+    };
+    repo.save_workflow(&workflow).await.unwrap();
+    workflow.steps.truncate(1);
+    repo.save_workflow(&workflow).await.unwrap();
+    assert_eq!(
+        repo.find_workflow("pipeline").await.unwrap(),
+        Some(workflow.clone())
+    );
+    assert_eq!(repo.find_workflows().await.unwrap(), vec![workflow.clone()]);
+
+    let started = Utc::now();
+    let mut run = WorkflowRun {
+        id: "run-1".to_string(),
+        workflow: "pipeline".to_string(),
+        status: RunStatus::Running,
+        input: "{}".to_string(),
+        output: None,
+        error: None,
+        created_at: started,
+        updated_at: started,
+        steps: vec![
+            step_run("fetch", StepStatus::Running),
+            step_run("store", StepStatus::Pending),
+        ],
+        definition: workflow.clone(),
+    };
+    repo.save_run(&run).await.unwrap();
+    let older = WorkflowRun {
+        id: "run-0".to_string(),
+        created_at: started - Duration::seconds(1),
+        ..run.clone()
+    };
+    repo.save_run(&older).await.unwrap();
+
+    run.steps[0] = StepRun {
+        status: StepStatus::Succeeded,
+        output: Some("done".to_string()),
+        attempts: 2,
+        ..run.steps[0].clone()
+    };
+    repo.save_step(&run.id, &run.steps[0]).await.unwrap();
+    let err = repo
+        .save_step(&run.id, &step_run("missing", StepStatus::Pending))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, DomainError::NotFound(_)));
+    assert_eq!(repo.find_run("run-1").await.unwrap(), Some(run.clone()));
+
+    run.status = RunStatus::Succeeded;
+    run.output = Some("done".to_string());
+    run.updated_at = started + Duration::seconds(1);
+    repo.save_run(&run).await.unwrap();
+    assert_eq!(repo.find_run("run-1").await.unwrap(), Some(run.clone()));
+    assert!(repo.find_run("missing").await.unwrap().is_none());
+
+    let ids = |runs: Vec<WorkflowRun>| runs.into_iter().map(|r| r.id).collect::<Vec<_>>();
+    assert_eq!(
+        ids(repo.find_runs("pipeline").await.unwrap()),
+        ["run-1", "run-0"]
+    );
+    assert_eq!(ids(repo.find_unfinished_runs().await.unwrap()), ["run-0"]);
+
+    repo.delete_workflow("pipeline").await.unwrap();
+    let err = repo.delete_workflow("pipeline").await.unwrap_err();
+    assert!(matches!(err, DomainError::NotFound(_)));
+}
+
+async fn check_background_jobs(repo: &dyn Repository) {
+    let job = |id: &str, age: i64| BackgroundJob {
+        id: id.to_string(),
+        function: "hello".to_string(),
+        source: "async".to_string(),
+        payload: "{}".to_string(),
+        attempts: 0,
+        created_at: Utc::now() - Duration::seconds(age),
+    };
+    repo.save_job(&job("new", 0)).await.unwrap();
+    repo.save_job(&job("old", 1)).await.unwrap();
+
+    // Saving again only records the attempts
+    let retried = BackgroundJob {
+        attempts: 2,
+        ..job("old", 1)
+    };
+    repo.save_job(&retried).await.unwrap();
+    let jobs = repo.find_jobs().await.unwrap();
+    assert_eq!(jobs.len(), 2);
+    assert_eq!(jobs[0].id, "old");
+    assert_eq!(jobs[0].attempts, 2);
+
+    repo.delete_job("old").await.unwrap();
+    repo.delete_job("new").await.unwrap();
+    assert!(repo.find_jobs().await.unwrap().is_empty());
+}
+
+async fn check_dead_letters(repo: &dyn Repository) {
+    let letter = |id: &str, function: &str, age: i64| DeadLetter {
+        id: id.to_string(),
+        function: function.to_string(),
+        source: "async".to_string(),
+        payload: "{}".to_string(),
+        error: "boom".to_string(),
+        attempts: 3,
+        created_at: Utc::now() - Duration::seconds(age),
+    };
+    let letters = [
+        letter("a", "hello", 2),
+        letter("b", "hello", 1),
+        letter("c", "other", 0),
+    ];
+    for letter in &letters {
+        repo.save_dead_letter(letter).await.unwrap();
+    }
+
+    assert_eq!(
+        repo.find_dead_letter("a").await.unwrap(),
+        Some(letters[0].clone())
+    );
+    let ids = |letters: Vec<DeadLetter>| letters.into_iter().map(|l| l.id).collect::<Vec<_>>();
+    assert_eq!(
+        ids(repo.find_dead_letters(None).await.unwrap()),
+        ["c", "b", "a"]
+    );
+    assert_eq!(
+        ids(repo.find_dead_letters(Some("hello")).await.unwrap()),
+        ["b", "a"]
+    );
+
+    repo.delete_dead_letter("a").await.unwrap();
+    let err = repo.delete_dead_letter("a").await.unwrap_err();
+    assert!(matches!(err, DomainError::NotFound(_)));
+    assert_eq!(repo.purge_dead_letters(Some("hello")).await.unwrap(), 1);
+    assert_eq!(repo.purge_dead_letters(None).await.unwrap(), 1);
+    assert!(repo.find_dead_letters(None).await.unwrap().is_empty());
+}
+
+async fn check_alert_rules(repo: &dyn Repository) {
+    let rule = |name: &str| AlertRule {
+        name: name.to_string(),
+        function: "hello".to_string(),
+        condition: AlertCondition::ErrorRate {
+            threshold: 0.05,
+            min_invocations: 10,
+        },
+        window_secs: 600,
+        webhooks: vec!["http://hooks.local/a".to_string()],
+        status: AlertStatus::default(),
+    };
+    repo.save_alert_rule(&rule("b")).await.unwrap();
+    repo.save_alert_rule(&rule("a")).await.unwrap();
+    assert_eq!(repo.find_alert_rule("a").await.unwrap(), Some(rule("a")));
+    assert!(repo.find_alert_rule("missing").await.unwrap().is_none());
+
+    let mut updated = rule("a");
+    updated.condition = AlertCondition::Absence;
+    updated.webhooks.clear();
+    repo.save_alert_rule(&updated).await.unwrap();
+    let status = AlertStatus {
+        state: AlertState::Firing,
+        value: Some(0.0),
+        since: Some(Utc::now() - Duration::seconds(60)),
+        evaluated_at: Some(Utc::now()),
+    };
+    repo.save_alert_status("a", &status).await.unwrap();
+    updated.status = status;
+    let names = |rules: Vec<AlertRule>| rules.into_iter().map(|r| r.name).collect::<Vec<_>>();
+    let rules = repo.find_alert_rules().await.unwrap();
+    assert_eq!(rules[0], updated);
+    assert_eq!(names(rules), ["a", "b"]);
+
+    repo.delete_alert_rule("a").await.unwrap();
+    let err = repo.delete_alert_rule("a").await.unwrap_err();
+    assert!(matches!(err, DomainError::NotFound(_)));
+    assert_eq!(names(repo.find_alert_rules().await.unwrap()), ["b"]);
+}
+
+async fn check_audit_log(repo: &dyn Repository) {
+    let entry = |action: AuditAction, target: &str, at| AuditEntry {
+        id: 0,
+        at,
+        actor: ADMIN_EMAIL.to_string(),
+        source_ip: Some("10.0.0.1".to_string()),
+        action,
+        target: target.to_string(),
+        changes: [(
+            "memory".to_string(),
+            FieldChange {
+                before: "128".into(),
+                after: serde_json::Value::Null,
+            },
+        )]
+        .into(),
+    };
+    let start = Utc::now() - Duration::minutes(10);
+    let create = repo
+        .append_audit_entry(&entry(AuditAction::FunctionCreate, "hello", start))
+        .await
+        .unwrap();
+    let trigger = repo
+        .append_audit_entry(&entry(
+            AuditAction::TriggerCreate,
+            "hello",
+            start + Duration::minutes(1),
+        ))
+        .await
+        .unwrap();
+    let mut anonymous = entry(
+        AuditAction::FunctionDelete,
+        "other",
+        start + Duration::minutes(2),
+    );
+    anonymous.actor = "anonymous".to_string();
+    anonymous.source_ip = None;
+    anonymous.changes.clear();
+    let delete = repo.append_audit_entry(&anonymous).await.unwrap();
+    assert!(create.id < trigger.id && trigger.id < delete.id);
+
+    let find = |query: AuditQuery| async move {
+        repo.find_audit_entries(&AuditQuery { limit: 10, ..query })
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|e| e.id)
+            .collect::<Vec<_>>()
+    };
+    let all = repo
+        .find_audit_entries(&AuditQuery {
+            limit: 10,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(all, [delete.clone(), trigger.clone(), create.clone()]);
+
+    let actions = |action: &str| AuditQuery {
+        action: Some(action.to_string()),
+        ..Default::default()
+    };
+    assert_eq!(find(actions("function")).await, [delete.id, create.id]);
+    assert_eq!(find(actions("function.create")).await, [create.id]);
+    assert!(find(actions("func")).await.is_empty());
+    let query = AuditQuery {
+        actor: Some(ADMIN_EMAIL.to_string()),
+        target: Some("hello".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(find(query).await, [trigger.id, create.id]);
+    let query = AuditQuery {
+        from: Some(trigger.at),
+        to: Some(delete.at),
+        ..Default::default()
+    };
+    assert_eq!(find(query).await, [trigger.id]);
+    let query = AuditQuery {
+        before_id: Some(delete.id),
+        limit: 1,
+        ..Default::default()
+    };
+    assert_eq!(repo.find_audit_entries(&query).await.unwrap(), [trigger]);
+}
+
+#[tokio::test]
+async fn test_sqlite_conformance() {
+    let dir = tempdir().unwrap();
+    let pool = sqlite::create_pool(format!(
+        "sqlite:{}?mode=rwc",
+        dir.path().join("test.db").display()
+    ))
+    .await;
+    for _ in 0..2 {
+        sqlite::seed_data(&pool).await;
+        sqlite::seed_admin(&pool, ADMIN_EMAIL, "admin", "pepper").await;
+    }
+    check_repository(&SqliteRepository::new(pool.clone())).await;
+    assert!(
+        sqlx::query("DELETE FROM audit_log")
+            .execute(&pool)
+            .await
+            .is_err()
+    );
+}
+
+#[cfg(feature = "postgres")]
+#[tokio::test]
+#[ignore = "needs a PostgreSQL server: set TEST_POSTGRES_URL and run with --ignored"]
+async fn test_postgres_conformance() {
+    use api::infrastructure::db::postgres::{self, PostgresRepository};
+
+    let url = std::env::var("TEST_POSTGRES_URL")
+        .expect("TEST_POSTGRES_URL must point at a PostgreSQL server (see the README)");
+    // A fresh schema per run keeps runs independent of each other and of real data
+    let schema = format!("conformance_{}", uuid::Uuid::new_v4().simple());
+    let admin = sqlx::PgPool::connect(&url).await.unwrap();
+    sqlx::query(&format!("CREATE SCHEMA {}", schema))
+        .execute(&admin)
+        .await
+        .unwrap();
+
+    let separator = if url.contains('?') { '&' } else { '?' };
+    let pool = postgres::create_pool(&format!(
+        "{}{}options=-c%20search_path%3D{}",
+        url, separator, schema
+    ))
+    .await;
+    // Migrating again is a no-op
+    api::infrastructure::db::migrations::migrate_postgres(&pool)
+        .await
+        .unwrap();
+    for _ in 0..2 {
+        postgres::seed_data(&pool).await;
+        postgres::seed_admin(&pool, ADMIN_EMAIL, "admin", "pepper").await;
+    }
+    check_repository(&PostgresRepository::new(pool.clone())).await;
+    assert!(
+        sqlx::query("UPDATE audit_log SET actor = 'someone'")
+            .execute(&pool)
+            .await
+            .is_err()
+    );
+
+    pool.close().await;
+    sqlx::query(&format!("DROP SCHEMA {} CASCADE", schema))
+        .execute(&admin)
+        .await
+        .unwrap();
+}
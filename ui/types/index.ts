@@ -5,6 +5,12 @@ export interface Function {
     cpu: string;
     memory: string;
     readonly?: boolean;
+    snapshot?: boolean;
+    max_concurrency?: number | null;
+    max_queue?: number | null;
+    allowed_calls?: string[];
+    retry?: RetryPolicy | null;
+    env?: Record<string, string>;
 }
 
 export interface User {
@@ -14,10 +20,78 @@ export interface User {
     role: string;
 }
 
+export interface RateLimit {
+    requests: number;
+    period_secs?: number;
+    burst?: number | null;
+    scope?: 'global' | 'ip' | 'subject';
+}
+
+export interface CachePolicy {
+    ttl_secs: number;
+    vary_headers?: string[];
+}
+
 export interface Trigger {
     name: string;
     method: string;
     path: string;
     function: string;
+    kind?: 'http' | 'websocket' | 'workflow';
+    rate_limit?: RateLimit | null;
+    cache?: CachePolicy | null;
     readonly?: boolean;
 }
+
+export interface WorkflowStep {
+    name: string;
+    function: string;
+    after?: string[];
+    when?: { step: string; path?: string | null; equals: unknown } | null;
+    retries?: number;
+    retry_backoff_ms?: number;
+}
+
+export interface Workflow {
+    name: string;
+    steps: WorkflowStep[];
+}
+
+export type StepStatus = 'pending' | 'running' | 'succeeded' | 'failed' | 'skipped';
+
+export interface StepRun {
+    step: string;
+    status: StepStatus;
+    input?: string | null;
+    output?: string | null;
+    error?: string | null;
+    attempts: number;
+}
+
+export interface WorkflowRun {
+    id: string;
+    workflow: string;
+    status: 'running' | 'succeeded' | 'failed';
+    input: string;
+    output?: string | null;
+    error?: string | null;
+    created_at: string;
+    updated_at: string;
+    steps: StepRun[];
+}
+
+export interface RetryPolicy {
+    max_attempts?: number;
+    initial_backoff_ms?: number;
+    max_backoff_ms?: number;
+}
+
+export interface DeadLetter {
+    id: string;
+    function: string;
+    source: string;
+    payload: string;
+    error: string;
+    attempts: number;
+    created_at: string;
+}